@@ -3,15 +3,18 @@
 //! Full-featured command-line interface for project management.
 //! Designed for automation, scripting, and power users.
 
-use age::secrecy::SecretString;
+use age::secrecy::{ExposeSecret, SecretString};
 use clap::{Parser, Subcommand, ValueEnum};
 use dmcore::{
-    backup_archive, backup_project_incremental_encrypted_with_message, contract_path, expand_path,
-    fetch, get_remote_status, get_remote_url, init_project_repo, list_archives,
-    project_needs_password, pull, push, recent_commits, retrieve_file_from_encrypted,
-    scan_project, set_remote_url, ArchiveFormat, Config, FileStatus, Index, Manifest, Project,
-    ProjectSummary, TrackMode, TrackedFile,
+    backup_archive, backup_project_incremental_encrypted_with_message, commit_as_of, commit_diffs, commit_info,
+    contract_path, create_tag, delete_tag, expand_path, fetch, find_archive, get_remote_status, get_remote_url,
+    init_project_repo, list_archives, list_tags, object_needs_password, project_needs_password, push,
+    entry_secrets, project_secrets, recent_commits, resolve_revision, resolve_tag, retrieve_file_from_encrypted,
+    scan_project, set_remote_url, tags_by_commit, MergeConflict, MergeResult, PullOutcome, Resolution, ArchiveFormat, ChangeKind, Config, EntryKind, ExcludeSet,
+    FileStatus, FileVersion, Index, IndexDiff, Keyring, Manifest, PasswordCheck, PathRemap, Project, ProjectSummary,
+    RestoreAction, RestoreOptions, SecretReport, SecretScan, TrackMode, TrackedFile,
 };
+use std::collections::HashMap;
 use std::io::BufRead;
 use std::path::{Path, PathBuf};
 
@@ -40,6 +43,19 @@ enum Commands {
         /// Project description
         #[arg(short, long)]
         description: Option<String>,
+
+        /// Recreate the project from the manifest entry committed in its
+        /// (cloned) repo, see `encrypt_manifest`
+        #[arg(long)]
+        from_repo: bool,
+
+        /// Read encryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read encryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
     },
 
     /// Add files to a project
@@ -57,6 +73,22 @@ enum Commands {
         /// Mark files as encrypted
         #[arg(short, long)]
         encrypted: bool,
+
+        /// For directories: how deep to walk (1 = direct children only)
+        #[arg(long)]
+        max_depth: Option<usize>,
+
+        /// For directories: include hidden files
+        #[arg(long)]
+        hidden: bool,
+
+        /// For directories: don't honor .gitignore/.ignore files
+        #[arg(long)]
+        no_gitignore: bool,
+
+        /// Allowlist files the secret scan flags instead of warning or refusing
+        #[arg(long)]
+        allow_secrets: bool,
     },
 
     /// Remove files from a project
@@ -68,6 +100,46 @@ enum Commands {
         files: Vec<String>,
     },
 
+    /// Turn encryption on or off for files and re-encrypt their stored blobs
+    Encrypt {
+        /// Project name
+        project: String,
+
+        /// File entries to change (all if not specified)
+        files: Vec<String>,
+
+        /// Turn encryption off instead
+        #[arg(long)]
+        off: bool,
+
+        /// Read encryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read encryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+
+    /// List a project's encryption recipients, or add and remove them
+    Recipients {
+        /// Project name
+        project: String,
+
+        #[command(subcommand)]
+        action: Option<RecipientAction>,
+    },
+
+    /// Scan unencrypted files for private keys, tokens and other secrets
+    Secrets {
+        /// Project name (or all if not specified)
+        project: Option<String>,
+
+        /// Allowlist files whose findings are harmless (paths or patterns)
+        #[arg(long, value_name = "PATH", requires = "project")]
+        allow: Vec<String>,
+    },
+
     /// Show project status
     Status {
         /// Project name (or all if not specified)
@@ -97,9 +169,9 @@ enum Commands {
         #[arg(short, long)]
         archive: bool,
 
-        /// Archive format (tar.gz, zip, 7z)
-        #[arg(long, value_enum, default_value = "tar-gz")]
-        format: ArchiveFormatArg,
+        /// Archive format (defaults to default_archive_format in config)
+        #[arg(long, value_enum)]
+        format: Option<ArchiveFormatArg>,
 
         /// Read encryption password from file
         #[arg(long)]
@@ -122,6 +194,59 @@ enum Commands {
         #[arg(long)]
         dry_run: bool,
 
+        /// Restore from a commit, tag or branch instead of the latest backup
+        #[arg(short, long, value_name = "REV")]
+        commit: Option<String>,
+
+        /// Restore as of a date (e.g. "2026-05-01" or "2 weeks ago")
+        #[arg(long, value_name = "DATE", conflicts_with = "commit")]
+        as_of: Option<String>,
+
+        /// Restore the snapshot made with `tag create`
+        #[arg(short, long, value_name = "NAME", conflicts_with_all = ["commit", "as_of"])]
+        tag: Option<String>,
+
+        /// Show a diff against local files instead of restoring
+        #[arg(long)]
+        diff: bool,
+
+        /// Restore from an archive backup (file name, timestamp, or "latest")
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["commit", "as_of", "tag", "diff"])]
+        archive: Option<String>,
+
+        /// Rewrite destination paths (e.g. --remap /home/old=/home/new)
+        #[arg(long, value_name = "FROM=TO")]
+        remap: Vec<String>,
+
+        /// Write files below this directory instead of their original locations
+        #[arg(long, value_name = "DIR")]
+        extract_to: Option<PathBuf>,
+
+        /// Read decryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read decryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+
+    /// Show every backed-up version of a file, with diffs between them
+    Log {
+        /// Project name
+        project: String,
+
+        /// File path
+        file: String,
+
+        /// Diff two versions (numbers from the list, or commit hashes)
+        #[arg(long, num_args = 2, value_names = ["OLD", "NEW"])]
+        diff: Vec<String>,
+
+        /// Show each version's diff against the one before it
+        #[arg(short, long, conflicts_with = "diff")]
+        patch: bool,
+
         /// Read decryption password from file
         #[arg(long)]
         password_file: Option<PathBuf>,
@@ -163,6 +288,50 @@ enum Commands {
         action: GitAction,
     },
 
+    /// Create, list and delete named snapshots (tags) of project repos
+    Tag {
+        #[command(subcommand)]
+        action: TagAction,
+    },
+
+    /// Merge another machine's backups into this one's, file by file
+    Merge {
+        /// Project name
+        project: String,
+
+        /// Branch or revision to merge (default: origin/main, else main)
+        #[arg(long, value_name = "REV")]
+        from: Option<String>,
+
+        /// Keep this machine's version of every conflicting file
+        #[arg(long, conflicts_with_all = ["theirs", "newest"])]
+        ours: bool,
+
+        /// Take the merged branch's version of every conflicting file
+        #[arg(long, conflicts_with = "newest")]
+        theirs: bool,
+
+        /// Take whichever version was modified last, per conflicting file
+        #[arg(long)]
+        newest: bool,
+
+        /// Resolve one conflicting file (repeatable)
+        #[arg(long, value_name = "PATH=ours|theirs|newest")]
+        take: Vec<String>,
+
+        /// Show what would be merged without merging
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        /// Read decryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read decryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+
     /// List archive backups for a project
     Archives {
         /// Project name
@@ -170,9 +339,48 @@ enum Commands {
     },
 
     /// Show store statistics
+    #[command(args_conflicts_with_subcommands = true)]
     Store {
         /// Project name (optional, shows global if not specified)
         project: Option<String>,
+
+        #[command(subcommand)]
+        action: Option<StoreAction>,
+    },
+
+    /// Start the passphrase agent, which keeps entered passwords so they
+    /// aren't asked for again
+    Agent {
+        /// Run in the foreground instead of detaching
+        #[arg(long)]
+        foreground: bool,
+
+        /// Seconds to keep each password (0 = until locked; default from
+        /// agent_timeout in config.toml)
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+    },
+
+    /// Wipe the passwords held by the passphrase agent
+    Lock {
+        /// Stop the agent as well
+        #[arg(long)]
+        stop: bool,
+    },
+
+    /// Unlock a project's object key on this machine (once per clone), so
+    /// backups with only recipients can store encrypted files
+    Unlock {
+        /// Project name
+        project: String,
+
+        /// Read encryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read encryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
     },
 
     /// Launch TUI
@@ -192,7 +400,7 @@ enum GitAction {
     },
     /// Push to remote
     Push,
-    /// Pull from remote
+    /// Pull from remote, merging the index file by file
     Pull,
     /// Fetch from remote
     Fetch,
@@ -201,9 +409,163 @@ enum GitAction {
         /// Number of commits to show
         #[arg(short, long, default_value = "10")]
         count: usize,
+
+        /// Show the files each commit added, removed or modified
+        #[arg(long)]
+        stat: bool,
+
+        /// Read decryption password from file (for an encrypted index)
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read decryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
     },
     /// Show ahead/behind status
     Status,
+    /// Replace the project's history with one commit of its current content,
+    /// dropping plaintext left in earlier commits (and every older version)
+    PurgeHistory {
+        /// Skip confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagAction {
+    /// Tag the latest backup of a project, or of every project
+    Create {
+        /// Tag name (e.g. pre-upgrade)
+        name: String,
+
+        /// Project name
+        #[arg(required_unless_present = "all")]
+        project: Option<String>,
+
+        /// Tag every project
+        #[arg(long, conflicts_with = "project")]
+        all: bool,
+
+        /// Tag message
+        #[arg(short, long)]
+        message: Option<String>,
+
+        /// Tag this commit instead of the latest backup
+        #[arg(short, long, value_name = "REV", conflicts_with = "all")]
+        commit: Option<String>,
+    },
+    /// List tags
+    List {
+        /// Project name (or all if not specified)
+        project: Option<String>,
+    },
+    /// Delete a tag (a pushed tag stays on the remote)
+    Delete {
+        /// Tag name
+        name: String,
+
+        /// Project name
+        #[arg(required_unless_present = "all")]
+        project: Option<String>,
+
+        /// Delete the tag from every project that has it
+        #[arg(long, conflicts_with = "project")]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum RecipientAction {
+    /// Add recipients and re-wrap stored encrypted blobs for them
+    Add {
+        /// age1... or SSH public keys, or files containing them
+        #[arg(required = true)]
+        recipients: Vec<String>,
+
+        /// Read encryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read encryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Remove recipients and re-wrap stored encrypted blobs without them
+    Remove {
+        /// age1... or SSH public keys, or files containing them
+        #[arg(required = true)]
+        recipients: Vec<String>,
+
+        /// Read encryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read encryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreAction {
+    /// Delete stored blobs no index revision refers to
+    Gc {
+        /// Project name (all projects if not specified)
+        project: Option<String>,
+
+        /// Show what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Only keep blobs referenced since this date (e.g. 2024-06-01, "3 months ago")
+        #[arg(long, value_name = "DATE")]
+        keep_since: Option<String>,
+
+        /// Read encryption password from file (to unlock an encrypted index)
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read encryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Verify stored blobs against their hashes
+    Fsck {
+        /// Project name (all projects if not specified)
+        project: Option<String>,
+
+        /// Rewrite corrupt or missing blobs from local files or git history
+        #[arg(long)]
+        repair: bool,
+
+        /// Read encryption password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read encryption password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Change the password of encrypted blobs, or re-encrypt them for the
+    /// project recipients (resumes an interrupted run)
+    Rotate {
+        /// Project name
+        project: String,
+
+        /// Read the current password from file
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+
+        /// Read the current password from stdin
+        #[arg(long)]
+        password_stdin: bool,
+
+        /// Read the new password from file
+        #[arg(long)]
+        new_password_file: Option<PathBuf>,
+    },
 }
 
 #[derive(Clone, Copy, ValueEnum)]
@@ -245,14 +607,58 @@ fn main() -> anyhow::Result<()> {
 
     match cli.command {
         Commands::Init => cmd_init(cli.json)?,
-        Commands::New { name, description } => cmd_new(name, description, cli.json)?,
+        Commands::New {
+            name,
+            description,
+            from_repo,
+            password_file,
+            password_stdin,
+        } => {
+            if from_repo {
+                cmd_new_from_repo(name, password_file, password_stdin, cli.json)?
+            } else {
+                cmd_new(name, description, cli.json)?
+            }
+        }
         Commands::Add {
             project,
             files,
             track,
             encrypted,
-        } => cmd_add(project, files, track.into(), encrypted, cli.json)?,
+            max_depth,
+            hidden,
+            no_gitignore,
+            allow_secrets,
+        } => {
+            let mut template = TrackedFile::with_mode("", track.into());
+            template.encrypted = encrypted;
+            template.max_depth = max_depth;
+            template.hidden = hidden;
+            template.gitignore = !no_gitignore;
+            cmd_add(project, files, template, allow_secrets, cli.json)?
+        }
         Commands::Remove { project, files } => cmd_remove(project, files, cli.json)?,
+        Commands::Encrypt {
+            project,
+            files,
+            off,
+            password_file,
+            password_stdin,
+        } => cmd_encrypt(project, files, !off, password_file, password_stdin, cli.json)?,
+        Commands::Recipients { project, action } => match action {
+            Some(RecipientAction::Add {
+                recipients,
+                password_file,
+                password_stdin,
+            }) => cmd_recipients_change(project, recipients, true, password_file, password_stdin, cli.json)?,
+            Some(RecipientAction::Remove {
+                recipients,
+                password_file,
+                password_stdin,
+            }) => cmd_recipients_change(project, recipients, false, password_file, password_stdin, cli.json)?,
+            None => cmd_recipients(project, cli.json)?,
+        },
+        Commands::Secrets { project, allow } => cmd_secrets(project, allow, cli.json)?,
         Commands::Status { project, changes } => cmd_status(project, changes, cli.json)?,
         Commands::Sync { project } => cmd_sync(project, cli.json)?,
         Commands::Backup {
@@ -262,20 +668,101 @@ fn main() -> anyhow::Result<()> {
             format,
             password_file,
             password_stdin,
-        } => cmd_backup(project, message, archive, format.into(), password_file, password_stdin, cli.json)?,
+        } => cmd_backup(project, message, archive, format.map(Into::into), password_file, password_stdin, cli.json)?,
         Commands::Restore {
             project,
             files,
             dry_run,
+            commit,
+            as_of,
+            tag,
+            diff,
+            archive,
+            remap,
+            extract_to,
+            password_file,
+            password_stdin,
+        } => {
+            let flags = RestoreFlags {
+                commit,
+                as_of,
+                tag,
+                dry_run,
+                diff,
+                remap: remap
+                    .iter()
+                    .map(|r| PathRemap::parse(r))
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+                extract_to: extract_to.map(|p| expand_path(&p.to_string_lossy())),
+            };
+            match archive {
+                Some(name) => cmd_restore_archive(project, name, files, flags, cli.json)?,
+                None => cmd_restore(project, files, flags, password_file, password_stdin, cli.json)?,
+            }
+        }
+        Commands::Log {
+            project,
+            file,
+            diff,
+            patch,
             password_file,
             password_stdin,
-        } => cmd_restore(project, files, dry_run, password_file, password_stdin, cli.json)?,
+        } => cmd_log(project, file, diff, patch, password_file, password_stdin, cli.json)?,
         Commands::List { verbose } => cmd_list(verbose, cli.json)?,
         Commands::Info { project } => cmd_info(project, cli.json)?,
         Commands::Delete { project, force } => cmd_delete(project, force, cli.json)?,
         Commands::Git { project, action } => cmd_git(project, action, cli.json)?,
+        Commands::Tag { action } => cmd_tag(action, cli.json)?,
+        Commands::Merge {
+            project,
+            from,
+            ours,
+            theirs,
+            newest,
+            take,
+            dry_run,
+            password_file,
+            password_stdin,
+        } => {
+            let default = if ours {
+                Some(Resolution::Ours)
+            } else if theirs {
+                Some(Resolution::Theirs)
+            } else if newest {
+                Some(Resolution::Newest)
+            } else {
+                None
+            };
+            cmd_merge(project, from, default, take, dry_run, password_file, password_stdin, cli.json)?
+        }
         Commands::Archives { project } => cmd_archives(project, cli.json)?,
-        Commands::Store { project } => cmd_store(project, cli.json)?,
+        Commands::Store { project, action } => match action {
+            Some(StoreAction::Gc {
+                project,
+                dry_run,
+                keep_since,
+                password_file,
+                password_stdin,
+            }) => cmd_store_gc(project, dry_run, keep_since, password_file, password_stdin, cli.json)?,
+            Some(StoreAction::Fsck {
+                project,
+                repair,
+                password_file,
+                password_stdin,
+            }) => cmd_store_fsck(project, repair, password_file, password_stdin, cli.json)?,
+            Some(StoreAction::Rotate {
+                project,
+                password_file,
+                password_stdin,
+                new_password_file,
+            }) => cmd_store_rotate(project, password_file, password_stdin, new_password_file, cli.json)?,
+            None => cmd_store(project, cli.json)?,
+        },
+        Commands::Agent { foreground, timeout } => cmd_agent(foreground, timeout, cli.json)?,
+        Commands::Lock { stop } => cmd_lock(stop, cli.json)?,
+        Commands::Unlock { project, password_file, password_stdin } => {
+            cmd_unlock(project, password_file, password_stdin, cli.json)?
+        }
         Commands::Tui => cmd_tui()?,
         Commands::Gui => cmd_gui()?,
     }
@@ -353,25 +840,113 @@ fn cmd_new(name: String, description: Option<String>, json: bool) -> anyhow::Res
     Ok(())
 }
 
-fn cmd_add(
-    project_name: String,
-    files: Vec<String>,
-    track: TrackMode,
-    encrypted: bool,
+/// Recreate a project from the sealed manifest entry in its repo
+fn cmd_new_from_repo(
+    name: String,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
     json: bool,
 ) -> anyhow::Result<()> {
+    let config = Config::load()?;
     let mut manifest = Manifest::load()?;
 
-    let project = manifest
-        .get_project_mut(&project_name)
-        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+    if manifest.get_project(&name).is_some() {
+        anyhow::bail!("Project '{}' already exists", name);
+    }
+    let project_dir = config.project_dir(&name)?;
+    if !dmcore::is_git_repo(&project_dir) {
+        anyhow::bail!("No project repo at {}", contract_path(&project_dir));
+    }
 
-    let mut added = 0;
-    let mut skipped = 0;
-    let mut added_files = Vec::new();
+    let needs_password = dmcore::unlock_needs_password(&config, &name)?;
+    let password = optional_password(needs_password, &password_file, password_stdin)?;
+    if let Some(password) = &password {
+        verify_password(&config, &name, password, false)?;
+    }
+    let keys = Keyring::load(&config, password.as_ref())?;
+    let project = dmcore::committed_project(&config, &name, &keys)?.ok_or_else(|| {
+        anyhow::anyhow!("'{}' has no committed manifest entry (see encrypt_manifest)", name)
+    })?;
+    dmcore::unlock_index(&config, &name, &keys)?;
+
+    let files = project.files.len();
+    manifest.add_project(name.clone(), project);
+    manifest.save()?;
+
+    if json {
+        let output = serde_json::json!({
+            "name": name,
+            "files": files,
+            "created": true,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("Recreated project '{}' with {} entries from its repo", name, files);
+    }
+
+    Ok(())
+}
+
+/// Add files, patterns or directories; `template` carries the entry settings
+fn cmd_add(
+    project_name: String,
+    files: Vec<String>,
+    template: TrackedFile,
+    allow_secrets: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let track = template.track;
+    let encrypted = template.encrypted;
+    let config = Config::load()?;
+    let mut manifest = Manifest::load()?;
+
+    let project = manifest
+        .get_project_mut(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+    let exclude = ExcludeSet::for_project(&config, project);
+
+    let mut added = 0;
+    let mut skipped = 0;
+    let mut added_files = Vec::new();
     let mut skipped_files = Vec::new();
+    let mut secret_files = Vec::new();
 
     for file_path in files {
+        // Glob patterns are tracked as-is and expanded at scan/backup time
+        if TrackedFile::new(file_path.as_str()).is_glob() {
+            let pattern = if file_path.starts_with("~/") || Path::new(&file_path).is_absolute() {
+                contract_path(&expand_path(&file_path))
+            } else {
+                contract_path(&std::env::current_dir()?.join(&file_path))
+            };
+
+            let mut tf = TrackedFile::with_mode(pattern.clone(), track);
+            tf.encrypted = encrypted;
+            let matches = tf.expand(&exclude).len();
+
+            if project.get_file(&tf.path).is_none()
+                && !secrets_allow_add(&config, project, &tf, allow_secrets, json, &mut secret_files)
+            {
+                skipped_files.push(serde_json::json!({"path": pattern, "reason": "possible secrets"}));
+                skipped += 1;
+                continue;
+            }
+            if project.add_file(tf) {
+                if !json {
+                    println!("  + {} ({}, pattern matching {} file(s))", pattern, track, matches);
+                }
+                added_files.push(serde_json::json!({"path": pattern, "track": track.to_string(), "encrypted": encrypted, "matches": matches}));
+                added += 1;
+            } else {
+                if !json {
+                    println!("  ~ {} (already tracked)", pattern);
+                }
+                skipped_files.push(serde_json::json!({"path": pattern, "reason": "already tracked"}));
+                skipped += 1;
+            }
+            continue;
+        }
+
         // Expand and validate path
         let abs_path = if file_path.starts_with("~/") {
             expand_path(&file_path)
@@ -393,6 +968,48 @@ fn cmd_add(
             continue;
         }
 
+        if exclude.is_excluded(&abs_path) {
+            if !json {
+                println!("  - {} (excluded)", contract_path(&abs_path));
+            }
+            skipped_files.push(serde_json::json!({"path": file_path, "reason": "excluded"}));
+            skipped += 1;
+            continue;
+        }
+
+        // Directories are tracked as a whole and walked at scan/backup time
+        if abs_path.is_dir() {
+            let stored_path = contract_path(&abs_path);
+            let tf = TrackedFile {
+                path: stored_path.clone(),
+                kind: EntryKind::Directory,
+                ..template.clone()
+            };
+            let matches = tf.expand(&exclude).len();
+
+            if project.get_file(&tf.path).is_none()
+                && !secrets_allow_add(&config, project, &tf, allow_secrets, json, &mut secret_files)
+            {
+                skipped_files.push(serde_json::json!({"path": stored_path, "reason": "possible secrets"}));
+                skipped += 1;
+                continue;
+            }
+            if project.add_file(tf) {
+                if !json {
+                    println!("  + {}/ ({}, directory with {} file(s))", stored_path, track, matches);
+                }
+                added_files.push(serde_json::json!({"path": stored_path, "track": track.to_string(), "encrypted": encrypted, "directory": true, "matches": matches}));
+                added += 1;
+            } else {
+                if !json {
+                    println!("  ~ {}/ (already tracked)", stored_path);
+                }
+                skipped_files.push(serde_json::json!({"path": stored_path, "reason": "already tracked"}));
+                skipped += 1;
+            }
+            continue;
+        }
+
         if !abs_path.is_file() {
             if !json {
                 println!("Warning: Not a file: {}", abs_path.display());
@@ -408,6 +1025,13 @@ fn cmd_add(
         let mut tf = TrackedFile::with_mode(stored_path.clone(), track);
         tf.encrypted = encrypted;
 
+        if project.get_file(&tf.path).is_none()
+            && !secrets_allow_add(&config, project, &tf, allow_secrets, json, &mut secret_files)
+        {
+            skipped_files.push(serde_json::json!({"path": stored_path, "reason": "possible secrets"}));
+            skipped += 1;
+            continue;
+        }
         if project.add_file(tf) {
             if !json {
                 println!("  + {} ({})", stored_path, track);
@@ -432,6 +1056,7 @@ fn cmd_add(
             "skipped": skipped,
             "files": added_files,
             "skipped_files": skipped_files,
+            "secrets": secret_files,
         });
         println!("{}", serde_json::to_string_pretty(&output)?);
     } else {
@@ -445,6 +1070,65 @@ fn cmd_add(
     Ok(())
 }
 
+/// Scan an entry before `add` tracks it, as `secret_scan` says
+///
+/// Returns false if the scan refuses the entry. With `allow`, flagged files
+/// are allowlisted in the project instead.
+fn secrets_allow_add(
+    config: &Config,
+    project: &mut Project,
+    entry: &TrackedFile,
+    allow: bool,
+    json: bool,
+    found: &mut Vec<serde_json::Value>,
+) -> bool {
+    if config.secret_scan == SecretScan::Off {
+        return true;
+    }
+    let reports = entry_secrets(config, project, entry);
+    if reports.is_empty() {
+        return true;
+    }
+
+    if allow {
+        for report in &reports {
+            let path = contract_path(&report.path);
+            if !json {
+                println!("  ! {} (allowlisted)", report.summary());
+            }
+            if !project.allow_secrets.contains(&path) {
+                project.allow_secrets.push(path);
+            }
+        }
+        return true;
+    }
+
+    let blocks = config.secret_scan == SecretScan::Block;
+    for report in &reports {
+        if !json {
+            println!("  ! {}", report.summary());
+        }
+        found.push(secret_report_json(report));
+    }
+    if !json {
+        if blocks {
+            println!("    Not added: use --encrypted, or --allow-secrets if this is harmless");
+        } else {
+            println!("    Possible secrets: consider --encrypted, or --allow-secrets if this is harmless");
+        }
+    }
+    !blocks
+}
+
+fn secret_report_json(report: &SecretReport) -> serde_json::Value {
+    let findings: Vec<_> = report
+        .findings
+        .iter()
+        .map(|f| serde_json::json!({"line": f.line, "kind": f.kind.to_string()}))
+        .collect();
+    serde_json::json!({"path": contract_path(&report.path), "findings": findings})
+}
+
 fn cmd_remove(project_name: String, files: Vec<String>, json: bool) -> anyhow::Result<()> {
     let mut manifest = Manifest::load()?;
 
@@ -501,6 +1185,277 @@ fn cmd_remove(project_name: String, files: Vec<String>, json: bool) -> anyhow::R
     Ok(())
 }
 
+fn cmd_encrypt(
+    project_name: String,
+    files: Vec<String>,
+    encrypted: bool,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let mut manifest = Manifest::load()?;
+
+    let project = manifest
+        .get_project_mut(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+
+    let mut changed = Vec::new();
+    let mut not_found = Vec::new();
+    if files.is_empty() {
+        for file in project.files.iter_mut().filter(|f| f.encrypted != encrypted) {
+            file.encrypted = encrypted;
+            changed.push(file.path.clone());
+        }
+    } else {
+        for file_path in files {
+            let contracted = contract_path(&expand_path(&file_path));
+            match project
+                .files
+                .iter_mut()
+                .find(|f| f.path == file_path || f.path == contracted)
+            {
+                Some(file) => {
+                    if file.encrypted != encrypted {
+                        file.encrypted = encrypted;
+                        changed.push(file.path.clone());
+                    }
+                }
+                None => not_found.push(file_path),
+            }
+        }
+    }
+    let project = project.clone();
+    manifest.save()?;
+
+    // Move already stored blobs over, so no plaintext copy is left behind
+    let stored = !Index::load_for_project(&config, &project_name)?.is_empty();
+    let migration = if stored && !changed.is_empty() {
+        let needs_password = dmcore::migration_needs_password(&config, &project);
+        let keys = load_keyring(&config, &project_name, &project, needs_password, &password_file, password_stdin)?;
+        Some(dmcore::migrate_encryption(&config, &project_name, &project, &keys)?)
+    } else {
+        None
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "project": project_name,
+            "encrypted": encrypted,
+            "changed": changed,
+            "not_found": not_found,
+            "migrated": migration.as_ref().map(|m| serde_json::json!({
+                "encrypted": m.encrypted,
+                "decrypted": m.decrypted,
+                "sealed": m.sealed,
+                "history_exposed": m.history_exposed,
+                "committed": m.committed
+            }))
+        }))?);
+        return Ok(());
+    }
+
+    let enc = if encrypted { " [E]" } else { "" };
+    for path in &changed {
+        println!("  * {}{}", path, enc);
+    }
+    for path in &not_found {
+        println!("  ? {} (not found in project)", path);
+    }
+    println!();
+    let state = if encrypted { "enabled" } else { "disabled" };
+    println!("Encryption {} for {} file(s) in '{}'", state, changed.len(), project_name);
+    if let Some(m) = migration {
+        println!(
+            "Store: {} encrypted, {} decrypted, {} plaintext blobs sealed",
+            m.encrypted,
+            m.decrypted,
+            m.sealed.len()
+        );
+        if m.history_exposed {
+            println!(
+                "Warning: earlier commits still hold the plaintext; run `dmxcli git {} purge-history` to drop them",
+                project_name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_recipients(project_name: String, json: bool) -> anyhow::Result<()> {
+    let manifest = Manifest::load()?;
+    let project = manifest
+        .get_project(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "project": project_name,
+            "recipients": project.recipients,
+        }))?);
+    } else if project.recipients.is_empty() {
+        println!("'{}' has no recipients; encrypted files use the password", project_name);
+    } else {
+        for recipient in &project.recipients {
+            println!("{}", recipient);
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_recipients_change(
+    project_name: String,
+    args: Vec<String>,
+    add: bool,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let mut manifest = Manifest::load()?;
+
+    let mut recipients = Vec::new();
+    for arg in &args {
+        if add {
+            recipients.extend(dmcore::read_recipients(arg)?);
+        } else {
+            // Removed keys may be in any form the manifest holds
+            recipients.extend(dmcore::read_recipients(arg).unwrap_or_else(|_| vec![arg.clone()]));
+        }
+    }
+
+    let mut project = manifest
+        .get_project(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?
+        .clone();
+    let changed = if add {
+        dmcore::add_recipients(&mut project, &recipients)
+    } else {
+        dmcore::remove_recipients(&mut project, &recipients)
+    };
+
+    // Re-wrap the store before saving, so a key that can't open it leaves
+    // both the store and the manifest as they were
+    let rewrap = if changed.is_empty() {
+        None
+    } else {
+        let needs_password = dmcore::rewrap_needs_password(&config, &project_name, &project)?;
+        let keys = load_keyring(&config, &project_name, &project, needs_password, &password_file, password_stdin)?;
+        let message = format!(
+            "Recipients: {} {}",
+            if add { "added" } else { "removed" },
+            changed.iter().map(|r| short_recipient(r)).collect::<Vec<_>>().join(", ")
+        );
+        let result = dmcore::rewrap_store(&config, &project_name, &keys, &message)?;
+        manifest.add_project(project_name.clone(), project);
+        manifest.save()?;
+        Some(result)
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "project": project_name,
+            "action": if add { "add" } else { "remove" },
+            "changed": changed,
+            "rewrapped": rewrap.as_ref().map(|r| r.rewrapped),
+            "committed": rewrap.as_ref().is_some_and(|r| r.committed),
+        }))?);
+        return Ok(());
+    }
+
+    let sign = if add { "+" } else { "-" };
+    for recipient in &changed {
+        println!("  {} {}", sign, recipient);
+    }
+    match rewrap {
+        Some(result) => {
+            println!();
+            println!("Re-wrapped {} encrypted blob(s) in '{}'", result.rewrapped, project_name);
+        }
+        None if add => println!("Nothing to add: all recipients already in '{}'", project_name),
+        None => println!("Nothing to remove: no such recipients in '{}'", project_name),
+    }
+
+    Ok(())
+}
+
+/// Shorten a recipient for commit messages (key type and tail, or comment)
+fn short_recipient(recipient: &str) -> String {
+    let parts: Vec<&str> = recipient.split_whitespace().collect();
+    match parts.as_slice() {
+        [_, _, comment, ..] => comment.to_string(),
+        [kind, key] => format!("{} …{}", kind, &key[key.len().saturating_sub(8)..]),
+        _ => format!("{}…", &recipient[..recipient.len().min(16)]),
+    }
+}
+
+fn cmd_secrets(project_name: Option<String>, allow: Vec<String>, json: bool) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let mut manifest = Manifest::load()?;
+
+    if !allow.is_empty() {
+        let name = project_name.as_deref().unwrap_or_default();
+        let project = manifest
+            .get_project_mut(name)
+            .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", name))?;
+        for path in &allow {
+            // Plain paths are stored like tracked files, with ~ for home
+            let pattern = if TrackedFile::new(path.as_str()).is_glob() || !path.contains('/') {
+                path.clone()
+            } else {
+                contract_path(&expand_path(path))
+            };
+            if !project.allow_secrets.contains(&pattern) {
+                project.allow_secrets.push(pattern);
+            }
+        }
+        manifest.save()?;
+    }
+
+    let names: Vec<String> = match &project_name {
+        Some(name) => {
+            manifest
+                .get_project(name)
+                .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", name))?;
+            vec![name.clone()]
+        }
+        None => {
+            let mut names: Vec<String> = manifest.projects.keys().cloned().collect();
+            names.sort();
+            names
+        }
+    };
+
+    let mut json_results = Vec::new();
+    let mut total = 0;
+    for name in &names {
+        let reports = project_secrets(&config, &manifest.projects[name]);
+        total += reports.len();
+        if json {
+            let secrets: Vec<_> = reports.iter().map(secret_report_json).collect();
+            json_results.push(serde_json::json!({"project": name, "secrets": secrets}));
+        } else if !reports.is_empty() {
+            println!("{}:", name);
+            for report in &reports {
+                println!("  {}", report.summary());
+            }
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({"projects": json_results}))?);
+    } else if total == 0 {
+        println!("No possible secrets in unencrypted files.");
+    } else {
+        println!();
+        println!("Set `encrypted = true` on these files, or allowlist harmless ones with --allow");
+    }
+
+    Ok(())
+}
+
 fn cmd_status(project_name: Option<String>, changes_only: bool, json: bool) -> anyhow::Result<()> {
     let config = Config::load()?;
     let manifest = Manifest::load()?;
@@ -533,7 +1488,7 @@ fn cmd_status(project_name: Option<String>, changes_only: bool, json: bool) -> a
     for (name, project) in projects {
         // Use project-specific index
         let index = Index::load_for_project(&config, name).unwrap_or_default();
-        let results = scan_project(project, &index);
+        let results = scan_project(project, &index, &ExcludeSet::for_project(&config, project));
         let summary = ProjectSummary::from_results(&results);
 
         if json {
@@ -575,7 +1530,7 @@ fn cmd_status(project_name: Option<String>, changes_only: bool, json: bool) -> a
 
                 let size_str = r
                     .current_size
-                    .map(|s| format_size(s))
+                    .map(format_size)
                     .unwrap_or_else(|| "-".to_string());
 
                 println!(
@@ -638,12 +1593,14 @@ fn cmd_sync(project_name: Option<String>, json: bool) -> anyhow::Result<()> {
     for (name, project) in projects {
         // Use project-specific index
         let mut index = Index::load_for_project(&config, name).unwrap_or_default();
-        let results = scan_project(project, &index);
+        let results = scan_project(project, &index, &ExcludeSet::for_project(&config, project));
         let mut synced = 0;
 
         for r in results {
             match r.status {
-                FileStatus::New | FileStatus::Drifted => {
+                // Encrypted files are only recorded by a backup, which
+                // keys their id instead of exposing the content hash
+                FileStatus::New | FileStatus::Drifted if !r.encrypted => {
                     if let Some(hash) = r.current_hash {
                         let abs_path = expand_path(&r.path);
                         let (size, modified) = dmcore::scanner::file_metadata(&abs_path)?;
@@ -691,7 +1648,7 @@ fn cmd_backup(
     project_name: Option<String>,
     message: Option<String>,
     archive: bool,
-    format: ArchiveFormat,
+    format: Option<ArchiveFormat>,
     password_file: Option<PathBuf>,
     password_stdin: bool,
     json: bool,
@@ -736,11 +1693,14 @@ fn cmd_backup(
         init_project_repo(&config, name)?;
 
         // Get password if needed for this project
-        let password = if project_needs_password(project) {
-            Some(get_password(&password_file, password_stdin)?)
-        } else {
-            None
-        };
+        let keys = load_keyring(
+            &config,
+            name,
+            project,
+            project_needs_password(project),
+            &password_file,
+            password_stdin,
+        )?;
 
         if !json {
             println!("Backing up {}...", name);
@@ -748,13 +1708,15 @@ fn cmd_backup(
 
         if archive {
             // Archive backup
+            let format = format.unwrap_or(config.default_archive_format);
             let archive_path = backup_archive(&config, name, project, format)?;
             if !json {
-                println!("  Created archive: {}", archive_path.display());
+                println!("  Created {} archive: {}", format.extension(), archive_path.display());
             }
             json_results.push(serde_json::json!({
                 "project": name,
                 "type": "archive",
+                "format": format.extension(),
                 "path": archive_path.to_string_lossy(),
                 "files": project.file_count(),
             }));
@@ -765,7 +1727,7 @@ fn cmd_backup(
                 &config,
                 name,
                 project,
-                password.as_ref(),
+                &keys,
                 message.as_deref(),
             )?;
 
@@ -776,21 +1738,34 @@ fn cmd_backup(
                 if result.unchanged > 0 {
                     println!("  {} file(s) unchanged (deduplicated)", result.unchanged);
                 }
+                if result.mirrored > 0 {
+                    println!("  {} file(s) updated in files/ mirror", result.mirrored);
+                }
                 if result.errors > 0 {
                     println!("  {} error(s)", result.errors);
                 }
                 if result.committed {
                     println!("  Committed to git");
                 }
+                if !result.secrets.is_empty() {
+                    println!("  Warning: possible secrets in unencrypted files:");
+                    for report in &result.secrets {
+                        println!("    {}", report.summary());
+                    }
+                    println!("  Set `encrypted = true` on them, or list them in `allow_secrets` if they are harmless");
+                }
             }
 
+            let secrets: Vec<_> = result.secrets.iter().map(secret_report_json).collect();
             json_results.push(serde_json::json!({
                 "project": name,
                 "type": "incremental",
                 "backed_up": result.backed_up,
                 "unchanged": result.unchanged,
+                "mirrored": result.mirrored,
                 "errors": result.errors,
                 "committed": result.committed,
+                "secrets": secrets,
             }));
 
             total_backed_up += result.backed_up;
@@ -820,10 +1795,36 @@ fn cmd_backup(
     Ok(())
 }
 
+/// Where `restore` reads from and how it writes
+struct RestoreFlags {
+    /// Commit, tag or branch to restore from
+    commit: Option<String>,
+    /// Restore as of the last commit before this date
+    as_of: Option<String>,
+    /// Tag to restore from
+    tag: Option<String>,
+    dry_run: bool,
+    /// Show a diff against local files instead of restoring
+    diff: bool,
+    remap: Vec<PathRemap>,
+    extract_to: Option<PathBuf>,
+}
+
+impl RestoreFlags {
+    /// Final destination for a restored file
+    fn destination(&self, path: &Path) -> PathBuf {
+        let dest = dmcore::remap::apply_all(&self.remap, path);
+        match &self.extract_to {
+            Some(base) => dmcore::remap::extract_path(base, &dest),
+            None => dest,
+        }
+    }
+}
+
 fn cmd_restore(
     project_name: String,
     files: Vec<String>,
-    dry_run: bool,
+    flags: RestoreFlags,
     password_file: Option<PathBuf>,
     password_stdin: bool,
     json: bool,
@@ -835,7 +1836,51 @@ fn cmd_restore(
         .get_project(&project_name)
         .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
 
-    if project.files.is_empty() {
+    // Resolve the revision to restore from, if any
+    let project_dir = config.project_dir(&project_name)?;
+    let revision = match (&flags.commit, &flags.as_of, &flags.tag) {
+        (Some(rev), _, _) => Some(commit_info(&project_dir, &resolve_revision(&project_dir, rev)?)?),
+        (None, Some(date), _) => Some(commit_info(&project_dir, &commit_as_of(&project_dir, date)?)?),
+        (None, None, Some(tag)) => Some(commit_info(&project_dir, &resolve_tag(&project_dir, tag)?)?),
+        (None, None, None) => None,
+    };
+
+    // Load the project index, current or historical, with paths from other
+    // machines mapped to this one
+    let unlocked = unlock_project_index(&config, &project_name, project, &password_file, password_stdin)?;
+    let mut index = match &revision {
+        Some(commit) => Index::load_at_revision(&config, &project_name, &commit.hash)?,
+        None => Index::load_for_project(&config, &project_name).unwrap_or_default(),
+    };
+    for path in index.localize(&config.path_remap) {
+        if !json {
+            println!(
+                "Warning: several recorded paths map to {}; restoring the newest",
+                contract_path(&path)
+            );
+        }
+    }
+    let store_dir = config.project_store_dir(&project_name)?;
+
+    // A historical index is restored as it was; otherwise follow the manifest
+    let candidates: Vec<TrackedFile> = if revision.is_some() {
+        let mut entries: Vec<_> = index
+            .iter()
+            .map(|(path, entry)| {
+                // Older blobs of files encrypted since then are sealed too
+                let mut file = TrackedFile::new(contract_path(path));
+                file.encrypted =
+                    entry.encrypted || project.entry_for(path).is_some_and(|f| f.encrypted);
+                file
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        entries
+    } else {
+        project.indexed_files(&index)
+    };
+
+    if candidates.is_empty() {
         if json {
             println!("{}", serde_json::json!({"restored": 0, "not_found": 0, "errors": 0}));
         } else {
@@ -844,23 +1889,11 @@ fn cmd_restore(
         return Ok(());
     }
 
-    // Load project-specific index
-    let index = Index::load_for_project(&config, &project_name).unwrap_or_default();
-    let store_dir = config.project_store_dir(&project_name)?;
-
-    // Get password if needed for this project
-    let password = if project_needs_password(project) {
-        Some(get_password(&password_file, password_stdin)?)
-    } else {
-        None
-    };
-
-    // Filter files if specific ones requested
+    // Filter files if specific ones requested (glob entries expand to indexed matches)
     let files_to_restore: Vec<_> = if files.is_empty() {
-        project.files.iter().collect()
+        candidates.iter().collect()
     } else {
-        project
-            .files
+        candidates
             .iter()
             .filter(|f| {
                 files.iter().any(|req| {
@@ -879,11 +1912,31 @@ fn cmd_restore(
         return Ok(());
     }
 
+    // Get password if any selected file will be read from a passphrase-encrypted
+    // blob (blobs encrypted to recipients are opened with the identity files)
+    let dry_run = flags.dry_run || flags.diff;
+    let needs_password = (!flags.dry_run || flags.diff)
+        && files_to_restore.iter().any(|f| {
+            f.encrypted
+                && index
+                    .get(&f.absolute_path())
+                    .is_some_and(|e| object_needs_password(&store_dir, &e.hash))
+        });
+    let keys = match unlocked {
+        Some(keys) if !needs_password || keys.password().is_some() => keys,
+        _ => load_keyring(&config, &project_name, project, needs_password, &password_file, password_stdin)?,
+    };
+
     if !json {
+        let at = revision
+            .as_ref()
+            .map(|c| format!(" at {} ({})", c.short_hash, c.date))
+            .unwrap_or_default();
         println!(
-            "Restoring {} file(s) from project '{}'{}",
+            "Restoring {} file(s) from project '{}'{}{}",
             files_to_restore.len(),
             project_name,
+            at,
             if dry_run { " (dry run)" } else { "" }
         );
         println!();
@@ -896,6 +1949,7 @@ fn cmd_restore(
 
     for file in files_to_restore {
         let abs_path = file.absolute_path();
+        let dest = flags.destination(&abs_path);
 
         // Look up in index to get hash
         let entry = match index.get(&abs_path) {
@@ -910,16 +1964,40 @@ fn cmd_restore(
             }
         };
 
-        if dry_run {
-            let exists = abs_path.exists();
+        if flags.diff {
+            let diff = diff_with_stored(&store_dir, &entry.hash, &dest, &keys, file.encrypted)?;
+            if !json {
+                match &diff {
+                    Some(d) if d.is_empty() => println!("  = {} (identical)", file.path),
+                    Some(d) => {
+                        println!("  ~ {}", file.path);
+                        println!("{}", d);
+                    }
+                    None => println!("  + {} (would create)", file.path),
+                }
+            }
+            json_files.push(serde_json::json!({
+                "path": file.path,
+                "status": "diff",
+                "hash": &entry.hash[..8],
+                "diff": diff,
+            }));
+            restored += 1;
+        } else if dry_run {
+            let exists = dest.exists();
             let status = if exists { "overwrite" } else { "create" };
             if !json {
-                println!("  {} {} (would {})", &entry.hash[..8], file.path, status);
+                if dest == abs_path {
+                    println!("  {} {} (would {})", &entry.hash[..8], file.path, status);
+                } else {
+                    println!("  {} {} -> {} (would {})", &entry.hash[..8], file.path, contract_path(&dest), status);
+                }
             }
             json_files.push(serde_json::json!({
                 "path": file.path,
                 "status": "would_restore",
                 "action": status,
+                "dest": contract_path(&dest),
                 "hash": &entry.hash[..8],
             }));
             restored += 1;
@@ -928,15 +2006,19 @@ fn cmd_restore(
             match retrieve_file_from_encrypted(
                 &store_dir,
                 &entry.hash,
-                &abs_path,
-                password.as_ref(),
+                &dest,
+                &keys,
                 file.encrypted,
             ) {
                 Ok(true) => {
                     if !json {
-                        println!("  ✓ {}", file.path);
+                        if dest == abs_path {
+                            println!("  ✓ {}", file.path);
+                        } else {
+                            println!("  ✓ {} -> {}", file.path, contract_path(&dest));
+                        }
                     }
-                    json_files.push(serde_json::json!({"path": file.path, "status": "restored"}));
+                    json_files.push(serde_json::json!({"path": file.path, "status": "restored", "dest": contract_path(&dest)}));
                     restored += 1;
                 }
                 Ok(false) => {
@@ -960,6 +2042,7 @@ fn cmd_restore(
     if json {
         let output = serde_json::json!({
             "project": project_name,
+            "commit": revision.as_ref().map(|c| &c.hash),
             "dry_run": dry_run,
             "restored": restored,
             "not_found": not_found,
@@ -969,7 +2052,9 @@ fn cmd_restore(
         println!("{}", serde_json::to_string_pretty(&output)?);
     } else {
         println!();
-        if dry_run {
+        if flags.diff {
+            println!("Compared: {} file(s)", restored);
+        } else if dry_run {
             println!("Dry run: {} file(s) would be restored", restored);
         } else {
             println!("Restored: {} file(s)", restored);
@@ -985,6 +2070,268 @@ fn cmd_restore(
     Ok(())
 }
 
+/// Unified diff of a local file against a stored blob
+///
+/// Returns `None` if the local file does not exist, and an empty string if
+/// the contents are identical.
+fn diff_with_stored(
+    store_dir: &Path,
+    hash: &str,
+    local: &Path,
+    keys: &Keyring,
+    encrypted: bool,
+) -> anyhow::Result<Option<String>> {
+    if !local.exists() {
+        return Ok(None);
+    }
+
+    // Compared in memory, so decrypted content never touches the disk
+    let stored = dmcore::read_object(store_dir, hash, keys, encrypted)?
+        .ok_or_else(|| anyhow::anyhow!("{} not in store", &hash[..8]))?;
+    let current = std::fs::read(local)?;
+
+    let local_label = contract_path(local);
+    let backup_label = format!("backup:{}", local_label);
+    if stored.contains(&0) || current.contains(&0) {
+        if stored == current {
+            return Ok(Some(String::new()));
+        }
+        return Ok(Some(format!("Binary files {} and {} differ", local_label, backup_label)));
+    }
+    let diff = dmcore::unified_diff(
+        &String::from_utf8_lossy(&current),
+        &String::from_utf8_lossy(&stored),
+        &local_label,
+        &backup_label,
+    );
+    Ok(Some(diff.trim_end().to_string()))
+}
+
+fn cmd_restore_archive(
+    project_name: String,
+    archive_name: String,
+    files: Vec<String>,
+    flags: RestoreFlags,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let archive = find_archive(&config, &project_name, &archive_name)?;
+    let dry_run = flags.dry_run;
+
+    let options = RestoreOptions {
+        dry_run,
+        files,
+        remap: flags.remap,
+        extract_to: flags.extract_to,
+    };
+    let restored = dmcore::archive::restore(&archive.path, &options)?;
+
+    let written = restored
+        .iter()
+        .filter(|r| r.action != RestoreAction::Unchanged)
+        .count();
+
+    if json {
+        let json_files: Vec<_> = restored
+            .iter()
+            .map(|r| {
+                serde_json::json!({
+                    "name": r.name,
+                    "path": contract_path(&r.path),
+                    "action": r.action.as_str(),
+                })
+            })
+            .collect();
+        let output = serde_json::json!({
+            "project": project_name,
+            "archive": archive.name,
+            "dry_run": dry_run,
+            "restored": written,
+            "unchanged": restored.len() - written,
+            "files": json_files,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    if restored.is_empty() {
+        println!("No matching files found in {}.", archive.name);
+        return Ok(());
+    }
+
+    println!(
+        "Restoring {} file(s) from {}{}",
+        restored.len(),
+        archive.name,
+        if dry_run { " (dry run)" } else { "" }
+    );
+    println!();
+
+    for r in &restored {
+        let path = contract_path(&r.path);
+        match (r.action, dry_run) {
+            (RestoreAction::Unchanged, _) => println!("  = {} (unchanged)", path),
+            (action, true) => println!("  {} (would {})", path, action.as_str()),
+            (_, false) => println!("  ✓ {}", path),
+        }
+    }
+
+    println!();
+    if dry_run {
+        println!("Dry run: {} file(s) would be restored", written);
+    } else {
+        println!("Restored: {} file(s)", written);
+    }
+    if restored.len() > written {
+        println!("Unchanged: {} file(s)", restored.len() - written);
+    }
+
+    Ok(())
+}
+
+fn cmd_log(
+    project_name: String,
+    file: String,
+    diff: Vec<String>,
+    patch: bool,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let manifest = Manifest::load()?;
+    let project = manifest
+        .get_project(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+
+    let path = if file.starts_with('~') || Path::new(&file).is_absolute() {
+        expand_path(&file)
+    } else {
+        let path = std::env::current_dir()?.join(&file);
+        path.canonicalize().unwrap_or(path)
+    };
+
+    let unlocked = unlock_project_index(&config, &project_name, project, &password_file, password_stdin)?;
+    let versions = dmcore::file_history(&config, &project_name, &path)?;
+    if versions.is_empty() {
+        anyhow::bail!("No backed-up versions of {} in '{}'", contract_path(&path), project_name);
+    }
+    // Versions are numbered from the oldest, and listed newest first
+    let number = |i: usize| versions.len() - i;
+
+    // Pairs of (old, new) positions in `versions` to diff
+    let pairs: Vec<(usize, usize)> = if !diff.is_empty() {
+        vec![(find_version(&versions, &diff[0])?, find_version(&versions, &diff[1])?)]
+    } else if patch {
+        (1..versions.len()).map(|i| (i, i - 1)).collect()
+    } else {
+        Vec::new()
+    };
+
+    let mut needs_password = false;
+    for &(old, new) in &pairs {
+        needs_password |= dmcore::version_needs_password(&config, &project_name, &versions[old])?
+            || dmcore::version_needs_password(&config, &project_name, &versions[new])?;
+    }
+    let keys = match unlocked {
+        Some(keys) if !needs_password || keys.password().is_some() => keys,
+        _ if pairs.is_empty() => Keyring::default(),
+        _ => load_keyring(&config, &project_name, project, needs_password, &password_file, password_stdin)?,
+    };
+    let diff_pair = |(old, new): (usize, usize)| {
+        dmcore::diff_versions(&config, &project_name, &path, &versions[old], &versions[new], &keys)
+    };
+
+    if json {
+        let json_versions: Vec<_> = versions
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                serde_json::json!({
+                    "version": number(i),
+                    "commit": v.commit.hash,
+                    "date": v.commit.date,
+                    "message": v.commit.message,
+                    "hash": v.hash,
+                    "size": v.size,
+                    "encrypted": v.encrypted,
+                })
+            })
+            .collect();
+        let mut json_diffs = Vec::new();
+        for &(old, new) in &pairs {
+            json_diffs.push(serde_json::json!({
+                "old": number(old),
+                "new": number(new),
+                "diff": diff_pair((old, new))?,
+            }));
+        }
+        let output = serde_json::json!({
+            "project": project_name,
+            "path": contract_path(&path),
+            "versions": json_versions,
+            "diffs": json_diffs,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+
+    if pairs.is_empty() {
+        println!("{} version(s) of {}:", versions.len(), contract_path(&path));
+        println!();
+        for (i, v) in versions.iter().enumerate() {
+            println!(
+                "  v{:<3} {}  {}  {:>9}  {}{}",
+                number(i),
+                v.commit.short_hash,
+                v.commit.date,
+                format_size(v.size),
+                v.commit.message,
+                if v.encrypted { " [encrypted]" } else { "" }
+            );
+        }
+        return Ok(());
+    }
+
+    for &(old, new) in &pairs {
+        if patch {
+            let v = &versions[new];
+            println!("v{} {} {} {}", number(new), v.commit.short_hash, v.commit.date, v.commit.message);
+        }
+        match diff_pair((old, new)) {
+            Ok(text) if text.is_empty() => println!("(v{} and v{} are identical)", number(old), number(new)),
+            Ok(text) => print!("{}", text),
+            // One unreadable version shouldn't end the whole log
+            Err(e) if patch => println!("({})", e),
+            Err(e) => return Err(e),
+        }
+        if patch {
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+/// Find a file version by its number in `dmxcli log` (1 = oldest) or by a
+/// commit hash prefix, as a position in the newest-first list
+fn find_version(versions: &[FileVersion], spec: &str) -> anyhow::Result<usize> {
+    let number = spec.strip_prefix('v').unwrap_or(spec);
+    if let Ok(n) = number.parse::<usize>() {
+        if (1..=versions.len()).contains(&n) {
+            return Ok(versions.len() - n);
+        }
+    }
+    let matches: Vec<usize> = (0..versions.len())
+        .filter(|&i| versions[i].commit.hash.starts_with(spec))
+        .collect();
+    match matches.len() {
+        1 => Ok(matches[0]),
+        0 => anyhow::bail!("No version '{}' (use a number from the list or a commit hash)", spec),
+        n => anyhow::bail!("'{}' matches {} versions, be more specific", spec, n),
+    }
+}
+
 fn cmd_list(verbose: bool, json: bool) -> anyhow::Result<()> {
     let config = Config::load()?;
     let manifest = Manifest::load()?;
@@ -1006,7 +2353,7 @@ fn cmd_list(verbose: bool, json: bool) -> anyhow::Result<()> {
     for (name, project) in projects {
         if json || verbose {
             let index = Index::load_for_project(&config, name).unwrap_or_default();
-            let results = scan_project(project, &index);
+            let results = scan_project(project, &index, &ExcludeSet::for_project(&config, project));
             let summary = ProjectSummary::from_results(&results);
 
             if json {
@@ -1069,7 +2416,7 @@ fn cmd_info(project_name: String, json: bool) -> anyhow::Result<()> {
 
     // Use project-specific index
     let index = Index::load_for_project(&config, &project_name).unwrap_or_default();
-    let results = scan_project(project, &index);
+    let results = scan_project(project, &index, &ExcludeSet::for_project(&config, project));
     let summary = ProjectSummary::from_results(&results);
 
     // Get git status for project
@@ -1087,12 +2434,38 @@ fn cmd_info(project_name: String, json: bool) -> anyhow::Result<()> {
                     .find(|r| r.path == f.path)
                     .map(|r| r.status.description())
                     .unwrap_or("unknown");
-                serde_json::json!({
-                    "path": f.path,
-                    "track": f.track.to_string(),
-                    "encrypted": f.encrypted,
-                    "status": status,
-                })
+                let matches: Vec<_> = results
+                    .iter()
+                    .filter(|r| r.pattern.as_deref() == Some(f.path.as_str()))
+                    .map(|r| serde_json::json!({"path": r.path, "status": r.status.description()}))
+                    .collect();
+                if f.is_directory() {
+                    serde_json::json!({
+                        "path": f.path,
+                        "track": f.track.to_string(),
+                        "encrypted": f.encrypted,
+                        "directory": true,
+                        "max_depth": f.max_depth,
+                        "hidden": f.hidden,
+                        "gitignore": f.gitignore,
+                        "matches": matches,
+                    })
+                } else if f.is_glob() {
+                    serde_json::json!({
+                        "path": f.path,
+                        "track": f.track.to_string(),
+                        "encrypted": f.encrypted,
+                        "pattern": true,
+                        "matches": matches,
+                    })
+                } else {
+                    serde_json::json!({
+                        "path": f.path,
+                        "track": f.track.to_string(),
+                        "encrypted": f.encrypted,
+                        "status": status,
+                    })
+                }
             })
             .collect();
 
@@ -1100,6 +2473,8 @@ fn cmd_info(project_name: String, json: bool) -> anyhow::Result<()> {
             "name": project_name,
             "description": project.description,
             "remote": git_remote,
+            "exclude": project.exclude,
+            "recipients": project.recipients,
             "file_count": project.file_count(),
             "status": {
                 "synced": summary.synced,
@@ -1127,6 +2502,14 @@ fn cmd_info(project_name: String, json: bool) -> anyhow::Result<()> {
             println!("Git remote: {}", remote);
         }
 
+        if !project.exclude.is_empty() {
+            println!("Exclude: {}", project.exclude.join(", "));
+        }
+
+        for recipient in &project.recipients {
+            println!("Recipient: {}", recipient);
+        }
+
         if let Some(status) = git_status {
             if status.has_remote {
                 if status.ahead > 0 || status.behind > 0 {
@@ -1150,12 +2533,20 @@ fn cmd_info(project_name: String, json: bool) -> anyhow::Result<()> {
             println!();
             println!("Files:");
             for file in project.list_files() {
+                let enc = if file.encrypted { " [E]" } else { "" };
+                if file.expands() {
+                    let suffix = if file.is_directory() { "/" } else { "" };
+                    println!("  * {}{} ({}){}", file.path, suffix, file.track, enc);
+                    for r in results.iter().filter(|r| r.pattern.as_deref() == Some(file.path.as_str())) {
+                        println!("      {} {}", r.status.symbol(), r.path);
+                    }
+                    continue;
+                }
                 let status = results
                     .iter()
                     .find(|r| r.path == file.path)
                     .map(|r| r.status.symbol())
                     .unwrap_or("?");
-                let enc = if file.encrypted { " [E]" } else { "" };
                 println!("  {} {} ({}){}", status, file.path, file.track, enc);
             }
         }
@@ -1252,15 +2643,32 @@ fn cmd_git(project_name: String, action: GitAction, json: bool) -> anyhow::Resul
             }
         }
         GitAction::Pull => {
-            let result = pull(&project_dir)?;
-            if json {
-                println!("{}", serde_json::json!({
-                    "project": project_name,
-                    "action": "pull",
-                    "message": result
-                }));
-            } else {
-                println!("{}", result);
+            // Never rebase: fetch, then merge like `dmxcli merge`
+            let project = manifest.get_project(&project_name).unwrap();
+            let keys = match unlock_project_index(&config, &project_name, project, &None, false)? {
+                Some(keys) => keys,
+                None => {
+                    let needs_password =
+                        (project.encrypt_index || project.encrypt_manifest) && project_needs_password(project);
+                    load_keyring(&config, &project_name, project, needs_password, &None, false)?
+                }
+            };
+            let from = dmcore::pull_source(&config, &project_name)?;
+            match dmcore::pull_project(&config, &project_name, project, &keys)? {
+                PullOutcome::UpToDate if json => {
+                    println!("{}", serde_json::json!({
+                        "project": project_name,
+                        "from": from,
+                        "up_to_date": true,
+                        "merged": false,
+                    }));
+                }
+                PullOutcome::UpToDate => println!("Already up to date with {}", from),
+                PullOutcome::Merged(result) => print_merge_result(&project_name, &from, &result, Vec::new(), json),
+                // Lists the conflicts and how to settle them
+                PullOutcome::Conflicts(_) => {
+                    cmd_merge(project_name, Some(from), None, Vec::new(), false, None, false, json)?
+                }
             }
         }
         GitAction::Fetch => {
@@ -1275,18 +2683,33 @@ fn cmd_git(project_name: String, action: GitAction, json: bool) -> anyhow::Resul
                 println!("Fetched from remote");
             }
         }
-        GitAction::Log { count } => {
+        GitAction::Log { count, stat, password_file, password_stdin } => {
             let commits = recent_commits(&project_dir, count)?;
+            let tags = tags_by_commit(&project_dir)?;
+            let stats = if stat {
+                let project = manifest.get_project(&project_name).unwrap();
+                unlock_project_index(&config, &project_name, project, &password_file, password_stdin)?;
+                let hashes: Vec<&str> = commits.iter().map(|c| c.hash.as_str()).collect();
+                commit_diffs(&config, &project_name, &hashes)?
+            } else {
+                Vec::new()
+            };
             if json {
                 let json_commits: Vec<_> = commits
                     .iter()
-                    .map(|c| {
-                        serde_json::json!({
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let mut commit = serde_json::json!({
                             "hash": c.hash,
                             "short_hash": c.short_hash,
                             "message": c.message,
-                            "date": c.date
-                        })
+                            "date": c.date,
+                            "tags": tags.get(&c.hash).cloned().unwrap_or_default()
+                        });
+                        if stat {
+                            commit["stat"] = stats[i].as_ref().map(index_diff_json).into();
+                        }
+                        commit
                     })
                     .collect();
                 println!("{}", serde_json::to_string_pretty(&serde_json::json!({
@@ -1296,8 +2719,33 @@ fn cmd_git(project_name: String, action: GitAction, json: bool) -> anyhow::Resul
             } else if commits.is_empty() {
                 println!("No commits yet");
             } else {
-                for c in commits {
-                    println!("{} {} ({})", c.short_hash, c.message, c.date);
+                for (i, c) in commits.iter().enumerate() {
+                    let tag_note = match tags.get(&c.hash) {
+                        Some(names) => format!("(tag: {}) ", names.join(", tag: ")),
+                        None => String::new(),
+                    };
+                    println!("{} {}{} ({})", c.short_hash, tag_note, c.message, c.date);
+                    if !stat {
+                        continue;
+                    }
+                    let Some(diff) = &stats[i] else {
+                        println!("    (index unreadable)");
+                        continue;
+                    };
+                    for file in diff.changed() {
+                        let mark = match file.kind {
+                            ChangeKind::Added => "A",
+                            ChangeKind::Removed => "D",
+                            _ => "M",
+                        };
+                        println!("    {} {} ({})", mark, contract_path(&file.path), format_size_delta(file.size_delta()));
+                    }
+                    println!(
+                        "    {}, {} unchanged ({})",
+                        diff.summary(),
+                        diff.count(ChangeKind::Unchanged),
+                        format_size_delta(diff.size_delta())
+                    );
                 }
             }
         }
@@ -1327,11 +2775,337 @@ fn cmd_git(project_name: String, action: GitAction, json: bool) -> anyhow::Resul
                 }
             }
         }
+        GitAction::PurgeHistory { force } => {
+            if !force {
+                if json {
+                    println!("{}", serde_json::json!({
+                        "project": project_name,
+                        "purged": false,
+                        "message": "use --force to confirm"
+                    }));
+                } else {
+                    println!("Purge the history of '{}'? Older versions and tags are lost for good.", project_name);
+                    println!("Use --force to confirm.");
+                }
+                return Ok(());
+            }
+
+            let commit = dmcore::purge_history(&config, &project_name)?;
+            if json {
+                println!("{}", serde_json::json!({
+                    "project": project_name,
+                    "purged": true,
+                    "commit": commit
+                }));
+            } else {
+                println!("History of '{}' replaced by {}", project_name, &commit[..8]);
+                if get_remote_url(&project_dir)?.is_some() {
+                    println!("The remote still has the old history; replace it with:");
+                    println!("  git -C {} push --force --all origin", project_dir.display());
+                    println!("and clone the project afresh on other machines.");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_tag(action: TagAction, json: bool) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let manifest = Manifest::load()?;
+
+    // One named project, or every project for --all and bare `list`
+    let project_names = |project: &Option<String>| -> anyhow::Result<Vec<String>> {
+        match project {
+            Some(name) => {
+                manifest
+                    .get_project(name)
+                    .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", name))?;
+                Ok(vec![name.clone()])
+            }
+            None => {
+                let mut names: Vec<String> = manifest.projects.keys().cloned().collect();
+                names.sort();
+                Ok(names)
+            }
+        }
+    };
+
+    match action {
+        TagAction::Create { name, project, all: _, message, commit } => {
+            let names = project_names(&project)?;
+            let mut json_results = Vec::new();
+            let mut failed = 0;
+            for project_name in &names {
+                let project_dir = config.project_dir(project_name)?;
+                // Projects that were never backed up have nothing to tag
+                if project.is_none() && !dmcore::is_git_repo(&project_dir) {
+                    continue;
+                }
+                match create_tag(&project_dir, &name, commit.as_deref(), message.as_deref()) {
+                    Ok(tag) => {
+                        if json {
+                            json_results.push(serde_json::json!({
+                                "project": project_name,
+                                "tag": tag.name,
+                                "commit": tag.commit,
+                                "success": true
+                            }));
+                        } else {
+                            println!("Tagged {} at {} as '{}'", project_name, &tag.commit[..7], tag.name);
+                        }
+                    }
+                    Err(e) if project.is_some() => return Err(e),
+                    Err(e) => {
+                        failed += 1;
+                        if json {
+                            json_results.push(serde_json::json!({
+                                "project": project_name,
+                                "tag": name,
+                                "success": false,
+                                "error": e.to_string()
+                            }));
+                        } else {
+                            eprintln!("{}: {}", project_name, e);
+                        }
+                    }
+                }
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&json_results)?);
+            }
+            if failed > 0 {
+                anyhow::bail!("Failed to tag {} project(s)", failed);
+            }
+        }
+        TagAction::List { project } => {
+            let mut json_results = Vec::new();
+            let mut any = false;
+            for project_name in project_names(&project)? {
+                let tags = list_tags(&config.project_dir(&project_name)?)?;
+                if json {
+                    let json_tags: Vec<_> = tags
+                        .iter()
+                        .map(|t| {
+                            serde_json::json!({
+                                "name": t.name,
+                                "commit": t.commit,
+                                "date": t.date,
+                                "message": t.message
+                            })
+                        })
+                        .collect();
+                    json_results.push(serde_json::json!({
+                        "project": project_name,
+                        "tags": json_tags
+                    }));
+                } else if !tags.is_empty() {
+                    any = true;
+                    println!("{}:", project_name);
+                    for t in tags {
+                        println!("  {:<20} {} {} {}", t.name, &t.commit[..7], t.date, t.message);
+                    }
+                }
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&json_results)?);
+            } else if !any {
+                println!("No tags");
+            }
+        }
+        TagAction::Delete { name, project, all: _ } => {
+            let mut deleted = Vec::new();
+            for project_name in project_names(&project)? {
+                let project_dir = config.project_dir(&project_name)?;
+                if project.is_none() && resolve_tag(&project_dir, &name).is_err() {
+                    continue;
+                }
+                delete_tag(&project_dir, &name)?;
+                deleted.push(project_name);
+            }
+            if json {
+                println!("{}", serde_json::json!({
+                    "tag": name,
+                    "deleted": deleted
+                }));
+            } else if deleted.is_empty() {
+                println!("No project has tag '{}'", name);
+            } else {
+                for project_name in deleted {
+                    println!("Deleted tag '{}' from {}", name, project_name);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_merge(
+    project_name: String,
+    from: Option<String>,
+    default: Option<Resolution>,
+    take: Vec<String>,
+    dry_run: bool,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let manifest = Manifest::load()?;
+    let project = manifest
+        .get_project(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+
+    let project_dir = config.project_dir(&project_name)?;
+    if !dmcore::is_git_repo(&project_dir) {
+        anyhow::bail!("Project '{}' has no backups yet", project_name);
+    }
+
+    let unlocked = unlock_project_index(&config, &project_name, project, &password_file, password_stdin)?;
+    let from = match from {
+        Some(from) => from,
+        None => dmcore::default_merge_source(&config, &project_name)?,
+    };
+    let plan = dmcore::plan_merge(&config, &project_name, &from)?;
+
+    // --take wins over --ours/--theirs for its file
+    let mut resolutions: HashMap<PathBuf, Resolution> = HashMap::new();
+    if let Some(default) = default {
+        for conflict in &plan.merge.conflicts {
+            resolutions.insert(conflict.path.clone(), default);
+        }
+    }
+    for spec in &take {
+        let (path, side) = spec
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Expected PATH=ours|theirs|newest, got '{}'", spec))?;
+        let resolution = match side {
+            "ours" => Resolution::Ours,
+            "theirs" => Resolution::Theirs,
+            "newest" => Resolution::Newest,
+            _ => anyhow::bail!("Expected 'ours', 'theirs' or 'newest' for {}, got '{}'", path, side),
+        };
+        let path = expand_path(path);
+        if !plan.merge.conflicts.iter().any(|c| c.path == path) {
+            anyhow::bail!("{} is not a conflicting file", contract_path(&path));
+        }
+        resolutions.insert(path, resolution);
+    }
+    let unresolved: Vec<&MergeConflict> = plan
+        .merge
+        .conflicts
+        .iter()
+        .filter(|c| !resolutions.contains_key(&c.path))
+        .collect();
+
+    let conflict_json = |c: &MergeConflict| {
+        serde_json::json!({
+            "path": c.path,
+            "ours": c.ours.as_ref().map(|e| e.size),
+            "theirs": c.theirs.as_ref().map(|e| e.size),
+            "resolution": resolutions.get(&c.path).map(|r| match c.side(*r) {
+                Resolution::Theirs => "theirs",
+                _ => "ours",
+            }),
+        })
+    };
+
+    if plan.up_to_date || dry_run || !unresolved.is_empty() {
+        if json {
+            println!("{}", serde_json::json!({
+                "project": project_name,
+                "from": from,
+                "up_to_date": plan.up_to_date,
+                "fast_forward": plan.fast_forward,
+                "from_theirs": plan.merge.from_theirs,
+                "text_merged": plan.text_merges.iter().map(|m| &m.path).collect::<Vec<_>>(),
+                "conflicts": plan.merge.conflicts.iter().map(conflict_json).collect::<Vec<_>>(),
+                "merged": false,
+            }));
+        } else if plan.up_to_date {
+            println!("Already up to date with {}", from);
+        } else {
+            println!(
+                "Merging {} into {}: {} file(s) from there, {} merged line by line, {} conflict(s)",
+                from,
+                project_name,
+                plan.merge.from_theirs.len(),
+                plan.text_merges.len(),
+                plan.merge.conflicts.len()
+            );
+            for path in &plan.merge.from_theirs {
+                println!("    {}", contract_path(path));
+            }
+            for text_merge in &plan.text_merges {
+                println!("  M {}", contract_path(&text_merge.path));
+            }
+            for conflict in &plan.merge.conflicts {
+                let side = match resolutions.get(&conflict.path).map(|r| conflict.side(*r)) {
+                    Some(Resolution::Theirs) => "take theirs",
+                    Some(_) => "keep ours",
+                    None => "unresolved",
+                };
+                println!("  C {} ({})", contract_path(&conflict.path), side);
+            }
+        }
+        if !plan.up_to_date && !dry_run && !unresolved.is_empty() {
+            anyhow::bail!(
+                "{} conflicting file(s); choose with `dmxcli merge {} --from {}` and --ours, --theirs, --newest \
+                 or --take PATH=ours|theirs|newest",
+                unresolved.len(),
+                project_name,
+                from
+            );
+        }
+        return Ok(());
     }
 
+    let keys = match unlocked {
+        Some(keys) => keys,
+        None => {
+            let needs_password =
+                (project.encrypt_index || project.encrypt_manifest) && project_needs_password(project);
+            load_keyring(&config, &project_name, project, needs_password, &password_file, password_stdin)?
+        }
+    };
+    let result = dmcore::apply_merge(&config, &project_name, project, &plan, &resolutions, &keys)?;
+    let conflicts = plan.merge.conflicts.iter().map(conflict_json).collect();
+    print_merge_result(&project_name, &from, &result, conflicts, json);
     Ok(())
 }
 
+fn print_merge_result(
+    project_name: &str,
+    from: &str,
+    result: &MergeResult,
+    conflicts: Vec<serde_json::Value>,
+    json: bool,
+) {
+    if json {
+        println!("{}", serde_json::json!({
+            "project": project_name,
+            "from": from,
+            "fast_forward": result.fast_forward,
+            "from_theirs": result.from_theirs,
+            "conflicts": conflicts,
+            "merged": result.merged,
+            "main_updated": result.main_updated,
+        }));
+    } else {
+        let how = if result.fast_forward { "Fast-forwarded to" } else { "Merged" };
+        println!("{} {} ({} file(s) changed)", how, from, result.from_theirs.len());
+        if result.main_updated {
+            println!("Moved {} to the merge; push to share it", dmcore::MAIN_BRANCH);
+        }
+        if !result.from_theirs.is_empty() {
+            println!("Run `dmxcli restore {}` to apply the merged files here", project_name);
+        }
+    }
+}
+
 fn cmd_archives(project_name: String, json: bool) -> anyhow::Result<()> {
     let config = Config::load()?;
     let manifest = Manifest::load()?;
@@ -1350,6 +3124,7 @@ fn cmd_archives(project_name: String, json: bool) -> anyhow::Result<()> {
                 serde_json::json!({
                     "name": a.name,
                     "path": a.path.to_string_lossy(),
+                    "format": a.format.extension(),
                     "size": a.size,
                 })
             })
@@ -1365,7 +3140,7 @@ fn cmd_archives(project_name: String, json: bool) -> anyhow::Result<()> {
         println!("Archive backups for '{}':", project_name);
         println!();
         for a in archives {
-            println!("  {:>10}  {}", format_size(a.size), a.name);
+            println!("  {:>10}  {:7} {}", format_size(a.size), a.format.extension(), a.name);
         }
     }
 
@@ -1404,7 +3179,7 @@ fn cmd_store(project_name: Option<String>, json: bool) -> anyhow::Result<()> {
         let mut total_count = 0usize;
         let mut json_stores = Vec::new();
 
-        for (name, _) in &manifest.projects {
+        for name in manifest.projects.keys() {
             let store_dir = config.project_store_dir(name)?;
             let (size, count) = calculate_store_size(&store_dir)?;
             total_size += size;
@@ -1436,6 +3211,378 @@ fn cmd_store(project_name: Option<String>, json: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn cmd_store_gc(
+    project_name: Option<String>,
+    dry_run: bool,
+    keep_since: Option<String>,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let manifest = Manifest::load()?;
+
+    let names: Vec<String> = match project_name {
+        Some(name) => {
+            if manifest.get_project(&name).is_none() {
+                anyhow::bail!("Project '{}' not found", name);
+            }
+            vec![name]
+        }
+        None => manifest.projects.keys().cloned().collect(),
+    };
+
+    let mut total_removed = 0usize;
+    let mut total_freed = 0u64;
+    let mut json_projects = Vec::new();
+
+    for name in &names {
+        // Every revision of the index has to be read to know what is live
+        if let Some(project) = manifest.get_project(name) {
+            unlock_project_index(&config, name, project, &password_file, password_stdin)?;
+        }
+        let result = dmcore::store::gc(&config, name, keep_since.as_deref(), dry_run)?;
+        total_removed += result.removed.len();
+        total_freed += result.bytes_freed;
+
+        if json {
+            json_projects.push(serde_json::json!({
+                "project": name,
+                "revisions": result.revisions,
+                "live": result.live,
+                "removed": result.removed,
+                "bytes_freed": result.bytes_freed,
+                "committed": result.committed
+            }));
+        } else {
+            let verb = if dry_run { "would remove" } else { "removed" };
+            println!(
+                "{:20} {} {} blobs ({}), {} live across {} revisions",
+                name,
+                verb,
+                result.removed.len(),
+                format_size(result.bytes_freed),
+                result.live,
+                result.revisions
+            );
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "dry_run": dry_run,
+            "keep_since": keep_since,
+            "removed": total_removed,
+            "bytes_freed": total_freed,
+            "projects": json_projects
+        }))?);
+    } else {
+        if names.len() > 1 {
+            println!();
+            let verb = if dry_run { "Would free" } else { "Freed" };
+            println!("{} {} in {} blobs", verb, format_size(total_freed), total_removed);
+        }
+        if total_removed > 0 {
+            println!("Earlier commits still hold removed blobs; `dmxcli git <project> purge-history` drops them");
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_store_fsck(
+    project_name: Option<String>,
+    repair: bool,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let manifest = Manifest::load()?;
+
+    let names: Vec<String> = match project_name {
+        Some(name) => {
+            if manifest.get_project(&name).is_none() {
+                anyhow::bail!("Project '{}' not found", name);
+            }
+            vec![name]
+        }
+        None => manifest.projects.keys().cloned().collect(),
+    };
+
+    // Encrypted blobs can only be verified (or repaired) with the password
+    // or an identity file. Projects without a password from the agent or a
+    // password command share one prompted password
+    let mut password = optional_password(false, &password_file, password_stdin)?;
+    let supplied = password.is_some();
+
+    let mut problems = 0usize;
+    let mut json_projects = Vec::new();
+
+    for name in &names {
+        let keys = match manifest.get_project(name) {
+            Some(project) => {
+                let needs_password = !supplied && project_needs_password(project);
+                let own = if needs_password {
+                    unattended_password(&config, name, project)?
+                } else {
+                    None
+                };
+                if needs_password && own.is_none() && password.is_none() {
+                    password = Some(get_password(&password_file, password_stdin)?);
+                }
+                Keyring::for_project(&config, project, own.as_ref().or(password.as_ref()))?
+            }
+            None => Keyring::load(&config, password.as_ref())?,
+        };
+        let report = dmcore::fsck(&config, name, &keys, repair)?;
+        problems += report.unrepaired_corrupt().count() + report.unrepaired_missing().count();
+
+        if json {
+            json_projects.push(serde_json::json!({
+                "project": name,
+                "revisions": report.revisions,
+                "verified": report.verified,
+                "skipped_encrypted": report.skipped_encrypted,
+                "corrupt": report.corrupt.iter().map(|c| serde_json::json!({
+                    "hash": c.hash,
+                    "reason": c.reason
+                })).collect::<Vec<_>>(),
+                "missing": report.missing.iter().map(|m| serde_json::json!({
+                    "hash": m.hash,
+                    "paths": m.paths
+                })).collect::<Vec<_>>(),
+                "orphans": report.orphans.iter().map(|o| &o.hash).collect::<Vec<_>>(),
+                "repaired": report.repaired.iter().map(|r| serde_json::json!({
+                    "hash": r.hash,
+                    "source": r.source.to_string()
+                })).collect::<Vec<_>>(),
+                "clean": report.is_clean()
+            }));
+            continue;
+        }
+
+        let status = if report.is_clean() { "✓" } else { "✗" };
+        println!(
+            "{} {}: {} verified across {} revisions",
+            status, name, report.verified, report.revisions
+        );
+        if report.skipped_encrypted > 0 {
+            println!("    {} encrypted blobs not verified (no password or identity)", report.skipped_encrypted);
+        }
+        for c in &report.corrupt {
+            println!("    corrupt  {} ({})", &c.hash[..12.min(c.hash.len())], c.reason);
+        }
+        for m in &report.missing {
+            let paths: Vec<String> = m.paths.iter().map(|p| contract_path(p)).collect();
+            println!("    missing  {} ({})", &m.hash[..12.min(m.hash.len())], paths.join(", "));
+        }
+        for r in &report.repaired {
+            println!("    repaired {} from {}", &r.hash[..12.min(r.hash.len())], r.source);
+        }
+        if !report.orphans.is_empty() {
+            let size: u64 = report.orphans.iter().map(|o| o.size).sum();
+            println!(
+                "    {} orphan blobs ({}), remove with `dmxcli store gc {}`",
+                report.orphans.len(),
+                format_size(size),
+                name
+            );
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "repair": repair,
+            "problems": problems,
+            "projects": json_projects
+        }))?);
+    }
+
+    if problems > 0 {
+        if repair {
+            anyhow::bail!("{} blobs could not be repaired", problems);
+        }
+        anyhow::bail!("{} corrupt or missing blobs (try --repair)", problems);
+    }
+
+    Ok(())
+}
+
+fn cmd_store_rotate(
+    project_name: String,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    new_password_file: Option<PathBuf>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let manifest = Manifest::load()?;
+    let project = manifest
+        .get_project(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+
+    let resuming = dmcore::rotation_in_progress(&config, &project_name)?;
+    if resuming && !json {
+        println!("Resuming interrupted rotation of '{}'", project_name);
+    }
+
+    let needs_password = dmcore::has_passphrase_blobs(&config, &project_name)?;
+    let old = load_keyring(&config, &project_name, project, needs_password, &password_file, password_stdin)?;
+
+    // With recipients, blobs are re-encrypted for them; the password is only
+    // used by projects without any
+    let new_password = if project.recipients.is_empty() {
+        Some(get_new_password(&new_password_file)?)
+    } else {
+        None
+    };
+    let new = Keyring::for_project(&config, project, new_password.as_ref())?;
+
+    let result = dmcore::rotate_store(&config, &project_name, &old, &new, |p| {
+        if !json {
+            eprint!("\r  {}/{} blobs", p.done, p.total);
+            if p.done == p.total {
+                eprintln!();
+            }
+        }
+    })?;
+    if let Some(password) = &new_password {
+        dmcore::agent::put_password(&config, &project_name, password);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "project": project_name,
+            "rotated": result.rotated,
+            "resumed": result.resumed,
+            "object_key": result.object_key,
+            "committed": result.committed
+        }))?);
+        return Ok(());
+    }
+
+    let target = if project.recipients.is_empty() {
+        "the new password".to_string()
+    } else {
+        format!("{} recipient(s)", project.recipients.len())
+    };
+    println!(
+        "Re-encrypted {} blob(s) in '{}' for {}",
+        result.rotated + result.resumed,
+        project_name,
+        target
+    );
+
+    Ok(())
+}
+
+fn cmd_agent(foreground: bool, timeout: Option<u64>, json: bool) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let socket = config.agent_socket_path()?;
+    let timeout = timeout.unwrap_or(config.agent_timeout);
+
+    if foreground {
+        let ttl = (timeout > 0).then(|| std::time::Duration::from_secs(timeout));
+        return dmcore::agent::serve(&socket, ttl);
+    }
+
+    let started = !dmcore::agent::is_running(&config);
+    if started {
+        let mut agent = std::process::Command::new(std::env::current_exe()?);
+        agent
+            .args(["agent", "--foreground", "--timeout", &timeout.to_string()])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null());
+        // Its own process group, so it outlives the terminal's job
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut agent, 0);
+        let mut child = agent.spawn()?;
+
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !dmcore::agent::is_running(&config) {
+            if child.try_wait()?.is_some() || std::time::Instant::now() > deadline {
+                anyhow::bail!("The agent did not start (try `dmxcli agent --foreground`)");
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "socket": socket,
+            "started": started,
+            "timeout": timeout
+        }))?);
+    } else if started {
+        println!("Agent started on {}", contract_path(&socket));
+    } else {
+        println!("Agent already running on {}", contract_path(&socket));
+    }
+
+    Ok(())
+}
+
+fn cmd_lock(stop: bool, json: bool) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let wiped = if stop {
+        dmcore::agent::stop(&config)
+    } else {
+        dmcore::agent::lock(&config)
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+            "running": wiped.is_some(),
+            "wiped": wiped.unwrap_or(0),
+            "stopped": stop && wiped.is_some()
+        }))?);
+        return Ok(());
+    }
+
+    match wiped {
+        None => println!("No agent running"),
+        Some(n) if stop => println!("Agent stopped, {} password(s) wiped", n),
+        Some(n) => println!("Agent locked, {} password(s) wiped", n),
+    }
+
+    Ok(())
+}
+
+fn cmd_unlock(
+    project_name: String,
+    password_file: Option<PathBuf>,
+    password_stdin: bool,
+    json: bool,
+) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let manifest = Manifest::load()?;
+    let project = manifest
+        .get_project(&project_name)
+        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project_name))?;
+
+    let needs_password = dmcore::unlock_needs_password(&config, &project_name)?;
+    let keys = load_keyring(&config, &project_name, project, needs_password, &password_file, password_stdin)?;
+    let unlocked = dmcore::ObjectKey::load(&config, &project_name, &keys)?.is_some();
+    if unlocked {
+        dmcore::unlock_index(&config, &project_name, &keys)?;
+    }
+
+    if json {
+        println!("{}", serde_json::json!({
+            "project": project_name,
+            "unlocked": unlocked
+        }));
+    } else if unlocked {
+        println!("Unlocked the object key of '{}' on this machine", project_name);
+    } else {
+        println!("'{}' has no object key yet; the first encrypted backup creates it", project_name);
+    }
+
+    Ok(())
+}
+
 fn cmd_tui() -> anyhow::Result<()> {
     println!("TUI not yet integrated. Run dotmatrix-tui separately.");
     Ok(())
@@ -1446,6 +3593,129 @@ fn cmd_gui() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Load the identity files and the project's recipients, adding the
+/// password if `needs_password` or one was supplied
+///
+/// The password is checked against the project's password check first.
+/// Without one supplied, the agent and the password command are asked before
+/// prompting, and a prompted one is handed to the agent.
+fn load_keyring(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    needs_password: bool,
+    password_file: &Option<PathBuf>,
+    password_stdin: bool,
+) -> anyhow::Result<Keyring> {
+    let prompted = !password_supplied(password_file, password_stdin);
+    let unattended = if needs_password && prompted {
+        unattended_password(config, project_name, project)?
+    } else {
+        None
+    };
+
+    let password = match unattended {
+        Some(password) => Some(password),
+        None => {
+            let password = optional_password(needs_password, password_file, password_stdin)?;
+            if let Some(password) = &password {
+                verify_password(config, project_name, password, prompted)?;
+                dmcore::agent::put_password(config, project_name, password);
+            }
+            password
+        }
+    };
+    Keyring::for_project(config, project, password.as_ref())
+}
+
+/// Unlock a project's encrypted index if it is locked, returning the keys
+/// that did it
+fn unlock_project_index(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    password_file: &Option<PathBuf>,
+    password_stdin: bool,
+) -> anyhow::Result<Option<Keyring>> {
+    if !dmcore::index_locked(config, project_name)? {
+        return Ok(None);
+    }
+    let needs_password = dmcore::unlock_needs_password(config, project_name)?;
+    let keys = load_keyring(config, project_name, project, needs_password, password_file, password_stdin)?;
+    dmcore::unlock_index(config, project_name, &keys)?;
+    Ok(Some(keys))
+}
+
+/// Get a project's password without prompting: from the agent, or else from
+/// its password command
+fn unattended_password(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+) -> anyhow::Result<Option<SecretString>> {
+    // A cached password that no longer passes the check (changed elsewhere)
+    // is skipped
+    let cached = dmcore::agent::get_password(config, project_name)
+        .filter(|p| matches!(dmcore::check_password(config, project_name, p), Ok(PasswordCheck::Correct)));
+    if cached.is_some() {
+        return Ok(cached);
+    }
+
+    let Some(password) = dmcore::command_password(config, project_name, project)? else {
+        return Ok(None);
+    };
+    verify_password(config, project_name, &password, false)?;
+    dmcore::agent::put_password(config, project_name, &password);
+    Ok(Some(password))
+}
+
+/// Get the password if `needs_password`, or if one was supplied anyway
+fn optional_password(
+    needs_password: bool,
+    password_file: &Option<PathBuf>,
+    password_stdin: bool,
+) -> anyhow::Result<Option<SecretString>> {
+    if needs_password || password_supplied(password_file, password_stdin) {
+        Ok(Some(get_password(password_file, password_stdin)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Check if a password was given by file, stdin or environment rather than
+/// being prompted for
+fn password_supplied(password_file: &Option<PathBuf>, password_stdin: bool) -> bool {
+    password_stdin || password_file.is_some() || std::env::var_os("DOTMATRIX_PASSWORD").is_some()
+}
+
+/// Check a password against a project's password check
+///
+/// The first time a password is set for a project, a typed one has to be
+/// entered twice before the check is written.
+fn verify_password(
+    config: &Config,
+    project_name: &str,
+    password: &SecretString,
+    prompted: bool,
+) -> anyhow::Result<()> {
+    match dmcore::check_password(config, project_name, password)? {
+        PasswordCheck::Correct => Ok(()),
+        PasswordCheck::Wrong => anyhow::bail!(
+            "Wrong password: '{}' is encrypted with a different one",
+            project_name
+        ),
+        PasswordCheck::Unset => {
+            if prompted {
+                let confirm = rpassword::prompt_password("Confirm encryption password: ")?;
+                if confirm != password.expose_secret() {
+                    anyhow::bail!("Passwords do not match");
+                }
+            }
+            dmcore::set_password_check(config, project_name, password)
+        }
+    }
+}
+
 /// Get password from file, stdin, environment, or interactive prompt
 fn get_password(
     password_file: &Option<PathBuf>,
@@ -1472,6 +3742,27 @@ fn get_password(
     Ok(SecretString::from(pass))
 }
 
+/// Get a new password from file, environment, or a confirmed interactive prompt
+fn get_new_password(password_file: &Option<PathBuf>) -> anyhow::Result<SecretString> {
+    if let Some(path) = password_file {
+        let pass = std::fs::read_to_string(path)?;
+        return Ok(SecretString::from(pass.trim().to_string()));
+    }
+
+    if let Ok(pass) = std::env::var("DOTMATRIX_NEW_PASSWORD") {
+        return Ok(SecretString::from(pass));
+    }
+
+    let pass = rpassword::prompt_password("New encryption password: ")?;
+    if pass.is_empty() {
+        anyhow::bail!("The new password must not be empty");
+    }
+    if rpassword::prompt_password("Confirm new password: ")? != pass {
+        anyhow::bail!("Passwords do not match");
+    }
+    Ok(SecretString::from(pass))
+}
+
 /// Calculate total size and file count for a store directory
 fn calculate_store_size(store_dir: &Path) -> anyhow::Result<(u64, usize)> {
     if !store_dir.exists() {
@@ -1502,6 +3793,36 @@ fn calculate_store_size(store_dir: &Path) -> anyhow::Result<(u64, usize)> {
     Ok((total_size, file_count))
 }
 
+/// A size change as `+1.2K` or `-300B`
+fn format_size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(delta.unsigned_abs()))
+}
+
+/// JSON for a commit's index diff, listing the files that changed
+fn index_diff_json(diff: &IndexDiff) -> serde_json::Value {
+    let files: Vec<_> = diff
+        .changed()
+        .map(|f| {
+            serde_json::json!({
+                "path": f.path,
+                "change": f.kind.as_str(),
+                "old_size": f.old_size,
+                "new_size": f.new_size,
+                "size_delta": f.size_delta()
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "added": diff.count(ChangeKind::Added),
+        "removed": diff.count(ChangeKind::Removed),
+        "modified": diff.count(ChangeKind::Modified),
+        "unchanged": diff.count(ChangeKind::Unchanged),
+        "size_delta": diff.size_delta(),
+        "files": files
+    })
+}
+
 fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
@@ -0,0 +1,240 @@
+//! Passphrase agent
+//!
+//! A small per-user server, like ssh-agent, that keeps unlocked project
+//! passwords in memory for `agent_timeout` seconds so the CLI, TUI and GUI
+//! don't each prompt again. It listens on a Unix socket only the user can
+//! open (`$XDG_RUNTIME_DIR/dotmatrix/agent.sock` by default, or
+//! `DOTMATRIX_AGENT_SOCK`).
+//!
+//! Requests and responses are single JSON lines. Frontends only cache
+//! passwords that passed the project's password check, and check cached ones
+//! again before use, so a password changed elsewhere falls back to a prompt.
+//!
+//! Without a running agent every call here is a no-op.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use age::secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Request {
+    Get { project: String },
+    Put { project: String, secret: String },
+    Lock,
+    Stop,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Response {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secret: Option<String>,
+    /// Secrets wiped by a lock or stop
+    #[serde(default)]
+    wiped: usize,
+}
+
+/// Passwords held by the agent, each with its expiry
+#[derive(Default)]
+struct Cache {
+    secrets: HashMap<String, (SecretString, Option<Instant>)>,
+}
+
+impl Cache {
+    fn expire(&mut self, now: Instant) {
+        self.secrets
+            .retain(|_, (_, expires)| expires.is_none_or(|at| at > now));
+    }
+
+    fn handle(&mut self, request: Request, ttl: Option<Duration>, now: Instant) -> Response {
+        self.expire(now);
+        let mut response = Response::default();
+        match request {
+            Request::Get { project } => {
+                response.secret = self
+                    .secrets
+                    .get(&project)
+                    .map(|(secret, _)| secret.expose_secret().to_string());
+            }
+            Request::Put { project, secret } => {
+                let expires = ttl.map(|ttl| now + ttl);
+                self.secrets.insert(project, (SecretString::from(secret), expires));
+            }
+            Request::Lock | Request::Stop => {
+                response.wiped = self.secrets.len();
+                self.secrets.clear();
+            }
+        }
+        response
+    }
+}
+
+/// Check if an agent is listening
+#[cfg(unix)]
+pub fn is_running(config: &Config) -> bool {
+    config
+        .agent_socket_path()
+        .is_ok_and(|socket| std::os::unix::net::UnixStream::connect(socket).is_ok())
+}
+
+#[cfg(not(unix))]
+pub fn is_running(_: &Config) -> bool {
+    false
+}
+
+/// Get a project's password from the agent, if it has one
+pub fn get_password(config: &Config, project_name: &str) -> Option<SecretString> {
+    let get = Request::Get {
+        project: project_name.to_string(),
+    };
+    request(config, &get)
+        .ok()
+        .and_then(|r| r.secret)
+        .map(SecretString::from)
+}
+
+/// Hand a checked password to the agent; does nothing without one
+pub fn put_password(config: &Config, project_name: &str, password: &SecretString) {
+    let put = Request::Put {
+        project: project_name.to_string(),
+        secret: password.expose_secret().to_string(),
+    };
+    let _ = request(config, &put);
+}
+
+/// Wipe every password the agent holds, returning how many there were, or
+/// `None` if no agent is running
+pub fn lock(config: &Config) -> Option<usize> {
+    request(config, &Request::Lock).ok().map(|r| r.wiped)
+}
+
+/// Wipe the agent's passwords and stop it
+pub fn stop(config: &Config) -> Option<usize> {
+    request(config, &Request::Stop).ok().map(|r| r.wiped)
+}
+
+#[cfg(unix)]
+fn request(config: &Config, request: &Request) -> anyhow::Result<Response> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(config.agent_socket_path()?)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(not(unix))]
+fn request(_: &Config, _: &Request) -> anyhow::Result<Response> {
+    anyhow::bail!("The passphrase agent needs Unix sockets")
+}
+
+/// Run the agent until it is stopped
+///
+/// Passwords expire `ttl` after they were added (never with `None`).
+#[cfg(unix)]
+pub fn serve(socket: &Path, ttl: Option<Duration>) -> anyhow::Result<()> {
+    use std::fs;
+    use std::io::{BufRead, BufReader, ErrorKind, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    // A directory of our own is closed to other users; an existing one (from
+    // DOTMATRIX_AGENT_SOCK) is left alone and the socket's mode protects it
+    if let Some(parent) = socket.parent().filter(|p| !p.exists()) {
+        fs::create_dir_all(parent)?;
+        fs::set_permissions(parent, fs::Permissions::from_mode(0o700))?;
+    }
+    if socket.exists() {
+        if UnixStream::connect(socket).is_ok() {
+            anyhow::bail!("An agent is already running at {}", socket.display());
+        }
+        // Left behind by an agent that didn't shut down cleanly
+        fs::remove_file(socket)?;
+    }
+
+    let listener = UnixListener::bind(socket)?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    // Poll, so passwords are dropped when they expire rather than on the
+    // next request
+    listener.set_nonblocking(true)?;
+
+    let mut cache = Cache::default();
+    loop {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                cache.expire(Instant::now());
+                std::thread::sleep(Duration::from_millis(200));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let answered = (|| -> anyhow::Result<bool> {
+            stream.set_nonblocking(false)?;
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            let mut line = String::new();
+            BufReader::new(&stream).read_line(&mut line)?;
+            let request: Request = serde_json::from_str(&line)?;
+            let stop = matches!(request, Request::Stop);
+            let response = cache.handle(request, ttl, Instant::now());
+            writeln!(&stream, "{}", serde_json::to_string(&response)?)?;
+            Ok(stop)
+        })();
+        // A bad client only loses its own connection
+        if let Ok(true) = answered {
+            break;
+        }
+    }
+
+    let _ = fs::remove_file(socket);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_: &Path, _: Option<Duration>) -> anyhow::Result<()> {
+    anyhow::bail!("The passphrase agent needs Unix sockets")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(cache: &mut Cache, project: &str, now: Instant) -> Option<String> {
+        let request = Request::Get {
+            project: project.to_string(),
+        };
+        cache.handle(request, None, now).secret
+    }
+
+    #[test]
+    fn test_cache_expiry_and_lock() {
+        let mut cache = Cache::default();
+        let start = Instant::now();
+        let put = |project: &str| Request::Put {
+            project: project.to_string(),
+            secret: format!("{}-pw", project),
+        };
+
+        cache.handle(put("short"), Some(Duration::from_secs(60)), start);
+        cache.handle(put("forever"), None, start);
+        assert_eq!(get(&mut cache, "short", start).as_deref(), Some("short-pw"));
+        assert_eq!(get(&mut cache, "other", start), None);
+
+        let later = start + Duration::from_secs(61);
+        assert_eq!(get(&mut cache, "short", later), None);
+        assert_eq!(get(&mut cache, "forever", later).as_deref(), Some("forever-pw"));
+
+        assert_eq!(cache.handle(Request::Lock, None, later).wiped, 1);
+        assert_eq!(get(&mut cache, "forever", later), None);
+    }
+}
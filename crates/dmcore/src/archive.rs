@@ -0,0 +1,484 @@
+//! Reading archive backups
+//!
+//! Archives are written by `backup::backup_archive` in tar.gz, zip or 7z
+//! format, with entry names relative to home (`.config/nvim/init.lua`).
+//! Files outside home are stored under `_root/` (`_root/etc/hosts`), the same
+//! layout as the git mirror, including its escaping of home paths that start
+//! with `_root`.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::config::{expand_path, ArchiveFormat};
+use crate::mirror::{self, ROOT_DIR};
+use crate::remap::{self, PathRemap};
+
+/// A file stored in an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// Name inside the archive
+    pub name: String,
+    /// Real path the entry maps back to
+    pub path: PathBuf,
+    pub size: u64,
+    /// SHA256 of the content
+    pub hash: String,
+}
+
+/// Options for restoring from an archive
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// Report what would happen without writing anything
+    pub dry_run: bool,
+    /// Only restore entries matching one of these (all if empty)
+    pub files: Vec<String>,
+    /// Prefix rewrites applied to destination paths
+    pub remap: Vec<PathRemap>,
+    /// Extract below this directory instead of restoring in place
+    pub extract_to: Option<PathBuf>,
+}
+
+/// What restoring an entry did (or would do)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreAction {
+    Create,
+    Overwrite,
+    /// Destination already has identical content
+    Unchanged,
+}
+
+impl RestoreAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestoreAction::Create => "create",
+            RestoreAction::Overwrite => "overwrite",
+            RestoreAction::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// A restored archive entry
+#[derive(Debug, Clone)]
+pub struct RestoredEntry {
+    pub name: String,
+    /// Destination after remapping
+    pub path: PathBuf,
+    pub action: RestoreAction,
+}
+
+/// Archive entry name for a stored manifest path
+pub fn entry_name(stored_path: &str) -> String {
+    if let Some(rest) = stored_path.strip_prefix("~/") {
+        mirror::escape_home_relative(Path::new(rest)).to_string_lossy().to_string()
+    } else if stored_path.starts_with('/') {
+        format!("{}/{}", ROOT_DIR, stored_path.trim_start_matches('/'))
+    } else {
+        stored_path.to_string()
+    }
+}
+
+/// Real path for an archive entry name (inverse of `entry_name`)
+pub fn entry_path(name: &str) -> anyhow::Result<PathBuf> {
+    let rel = safe_relative(name)?;
+    if let Ok(rest) = rel.strip_prefix(ROOT_DIR) {
+        return Ok(Path::new("/").join(rest));
+    }
+    if name.starts_with('/') {
+        return Ok(Path::new("/").join(rel));
+    }
+    let home =
+        dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Cannot determine home directory"))?;
+    Ok(home.join(mirror::unescape_home_relative(&rel)))
+}
+
+/// Call `f` with the name and content of every file entry in an archive
+pub fn for_each_entry<F>(archive_path: &Path, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut(&str, &mut dyn Read) -> anyhow::Result<()>,
+{
+    let format = ArchiveFormat::from_path(archive_path)
+        .ok_or_else(|| anyhow::anyhow!("Unknown archive format: {}", archive_path.display()))?;
+
+    match format {
+        ArchiveFormat::TarGz => {
+            let decoder = flate2::read::GzDecoder::new(File::open(archive_path)?);
+            let mut archive = tar::Archive::new(decoder);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().to_string();
+                f(&name, &mut entry)?;
+            }
+        }
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(archive_path)?)?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                f(&name, &mut entry)?;
+            }
+        }
+        ArchiveFormat::SevenZ => {
+            let mut archive =
+                sevenz_rust::SevenZReader::open(archive_path, sevenz_rust::Password::empty())?;
+            let mut result = Ok(());
+            archive.for_each_entries(|entry, reader| {
+                if entry.is_directory() {
+                    return Ok(true);
+                }
+                result = f(entry.name(), reader);
+                // Entries share one decompressed stream, so skip whatever
+                // the callback left unread before moving to the next
+                io::copy(reader, &mut io::sink())?;
+                Ok(result.is_ok())
+            })?;
+            result?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Extract every file in an archive below `dest`, returning the written paths
+pub fn extract_to(archive_path: &Path, dest: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+
+    for_each_entry(archive_path, |name, reader| {
+        let target = dest.join(safe_relative(name)?);
+        let tmp = write_tmp(reader, &target)?;
+        replace_with(&tmp, &target)?;
+        written.push(target);
+        Ok(())
+    })?;
+
+    Ok(written)
+}
+
+/// Read the content of a single entry, or `None` if the archive lacks it
+pub fn read_entry(archive_path: &Path, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut content = None;
+
+    for_each_entry(archive_path, |entry, reader| {
+        if content.is_none() && entry == name {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            content = Some(buf);
+        }
+        Ok(())
+    })?;
+
+    Ok(content)
+}
+
+/// Write the entries named in `dests` to their paths in one pass over the
+/// archive, returning the names written
+///
+/// As with `read_entry`, the first of duplicate names wins.
+pub fn write_entries(archive_path: &Path, dests: &HashMap<String, PathBuf>) -> anyhow::Result<Vec<String>> {
+    let mut written: Vec<String> = Vec::new();
+
+    for_each_entry(archive_path, |name, reader| {
+        if let Some(dest) = dests.get(name) {
+            if !written.iter().any(|w| w == name) {
+                let tmp = write_tmp(reader, dest)?;
+                replace_with(&tmp, dest)?;
+                written.push(name.to_string());
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(written)
+}
+
+/// List the files in an archive with the paths they restore to
+pub fn list_entries(archive_path: &Path) -> anyhow::Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+
+    for_each_entry(archive_path, |name, reader| {
+        let mut hasher = Sha256::new();
+        let size = io::copy(reader, &mut hasher)?;
+        entries.push(ArchiveEntry {
+            name: name.to_string(),
+            path: entry_path(name)?,
+            size,
+            hash: format!("{:x}", hasher.finalize()),
+        });
+        Ok(())
+    })?;
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Restore files from an archive to their original (or remapped) locations
+///
+/// Each entry is streamed to a temporary file next to its destination and
+/// only renamed over it if the content differs, so neither is ever loaded
+/// whole and an interrupted restore leaves no half-written file.
+pub fn restore(
+    archive_path: &Path,
+    options: &RestoreOptions,
+) -> anyhow::Result<Vec<RestoredEntry>> {
+    let mut restored = Vec::new();
+
+    for_each_entry(archive_path, |name, reader| {
+        let path = entry_path(name)?;
+        if !matches_filter(name, &path, &options.files) {
+            return Ok(());
+        }
+        let mut dest = remap::apply_all(&options.remap, &path);
+        if let Some(base) = &options.extract_to {
+            dest = remap::extract_path(base, &dest);
+        }
+
+        let action = if options.dry_run {
+            match File::open(&dest) {
+                Ok(existing) if reader_matches(reader, &existing)? => RestoreAction::Unchanged,
+                Ok(_) => RestoreAction::Overwrite,
+                Err(_) => RestoreAction::Create,
+            }
+        } else {
+            let tmp = write_tmp(reader, &dest)?;
+            let action = match mirror::same_content(&tmp, &dest) {
+                Ok(true) => RestoreAction::Unchanged,
+                Ok(false) if dest.exists() => RestoreAction::Overwrite,
+                Ok(false) => RestoreAction::Create,
+                Err(e) => {
+                    let _ = fs::remove_file(&tmp);
+                    return Err(e);
+                }
+            };
+            if action == RestoreAction::Unchanged {
+                fs::remove_file(&tmp)?;
+            } else {
+                replace_with(&tmp, &dest)?;
+            }
+            action
+        };
+
+        restored.push(RestoredEntry {
+            name: name.to_string(),
+            path: dest,
+            action,
+        });
+        Ok(())
+    })?;
+
+    restored.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(restored)
+}
+
+/// Stream an entry to a temporary file next to `dest`, returning its path
+fn write_tmp(reader: &mut dyn Read, dest: &Path) -> anyhow::Result<PathBuf> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = dest.with_file_name(format!(".{}.dotmatrix-tmp", name));
+    let copied = File::create(&tmp).and_then(|out| {
+        let mut out = BufWriter::new(out);
+        io::copy(reader, &mut out)?;
+        out.flush()
+    });
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(tmp)
+}
+
+/// Move a file written by `write_tmp` over `dest`, keeping the permissions
+/// of a file it replaces and writing through a symlink to its target
+fn replace_with(tmp: &Path, dest: &Path) -> anyhow::Result<()> {
+    let dest = match fs::canonicalize(dest) {
+        Ok(target) if dest.is_symlink() => target,
+        _ => dest.to_path_buf(),
+    };
+    let moved = match fs::metadata(&dest) {
+        Ok(meta) => fs::set_permissions(tmp, meta.permissions()),
+        Err(_) => Ok(()),
+    }
+    .and_then(|_| fs::rename(tmp, &dest));
+    if let Err(e) = moved {
+        let _ = fs::remove_file(tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Compare an entry's content with a file block by block, without loading
+/// either
+fn reader_matches(reader: &mut dyn Read, file: &File) -> anyhow::Result<bool> {
+    let mut file = BufReader::new(file);
+    let (mut buf_a, mut buf_b) = ([0u8; 8192], [0u8; 8192]);
+    loop {
+        let n = mirror::read_full(reader, &mut buf_a)?;
+        if n != mirror::read_full(&mut file, &mut buf_b)? || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Check an entry against a restore filter (`~/.bashrc`, `.bashrc`, `nvim/init.lua`)
+fn matches_filter(name: &str, path: &Path, files: &[String]) -> bool {
+    files.is_empty()
+        || files.iter().any(|req| {
+            name == req.trim_start_matches("~/") || *path == expand_path(req) || path.ends_with(req)
+        })
+}
+
+/// Turn an entry name into a relative path that cannot escape its root
+fn safe_relative(name: &str) -> anyhow::Result<PathBuf> {
+    let mut rel = PathBuf::new();
+    for component in Path::new(name).components() {
+        match component {
+            Component::Normal(part) => rel.push(part),
+            Component::CurDir | Component::RootDir => {}
+            _ => anyhow::bail!("Unsafe path in archive: {}", name),
+        }
+    }
+    if rel.as_os_str().is_empty() {
+        anyhow::bail!("Empty path in archive");
+    }
+    Ok(rel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_relative() {
+        assert_eq!(
+            safe_relative(".config/nvim/init.lua").unwrap(),
+            PathBuf::from(".config/nvim/init.lua")
+        );
+        assert_eq!(
+            safe_relative("/etc/hosts").unwrap(),
+            PathBuf::from("etc/hosts")
+        );
+        assert!(safe_relative("../outside").is_err());
+    }
+
+    #[test]
+    fn test_entry_name_round_trip() {
+        let home = dirs::home_dir().unwrap();
+
+        assert_eq!(
+            entry_name("~/.config/nvim/init.lua"),
+            ".config/nvim/init.lua"
+        );
+        assert_eq!(entry_name("/etc/hosts"), "_root/etc/hosts");
+        assert_eq!(
+            entry_path(".config/nvim/init.lua").unwrap(),
+            home.join(".config/nvim/init.lua")
+        );
+        assert_eq!(
+            entry_path("_root/etc/hosts").unwrap(),
+            PathBuf::from("/etc/hosts")
+        );
+        assert_eq!(entry_name("~/_root/notes"), "_root_/notes");
+        assert_eq!(entry_path("_root_/notes").unwrap(), home.join("_root/notes"));
+    }
+
+    #[test]
+    fn test_matches_filter() {
+        let home = dirs::home_dir().unwrap();
+        let path = home.join(".config/nvim/init.lua");
+        let name = ".config/nvim/init.lua";
+
+        assert!(matches_filter(name, &path, &[]));
+        assert!(matches_filter(
+            name,
+            &path,
+            &["~/.config/nvim/init.lua".to_string()]
+        ));
+        assert!(matches_filter(name, &path, &["nvim/init.lua".to_string()]));
+        assert!(!matches_filter(name, &path, &["it.lua".to_string()]));
+    }
+
+    #[test]
+    fn test_extract_7z() {
+        use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+        let dir = std::env::temp_dir().join(format!("dmcore-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("init.lua");
+        fs::write(&source, "vim.opt.number = true\n").unwrap();
+
+        let archive_path = dir.join("test.7z");
+        let mut writer = SevenZWriter::create(&archive_path).unwrap();
+        let entry = SevenZArchiveEntry::from_path(&source, ".config/nvim/init.lua".to_string());
+        writer
+            .push_archive_entry(entry, Some(File::open(&source).unwrap()))
+            .unwrap();
+        let vimrc = dir.join("vimrc");
+        fs::write(&vimrc, "set number\n").unwrap();
+        let entry = SevenZArchiveEntry::from_path(&vimrc, ".vimrc".to_string());
+        writer
+            .push_archive_entry(entry, Some(File::open(&vimrc).unwrap()))
+            .unwrap();
+        writer.finish().unwrap();
+
+        // Skipping the first entry must not corrupt the second
+        assert_eq!(
+            read_entry(&archive_path, ".vimrc").unwrap().unwrap(),
+            b"set number\n"
+        );
+
+        let dests = HashMap::from([
+            (".vimrc".to_string(), dir.join("vimrc.restored")),
+            (".missing".to_string(), dir.join("missing")),
+        ]);
+        assert_eq!(write_entries(&archive_path, &dests).unwrap(), vec![".vimrc"]);
+        assert_eq!(fs::read(dir.join("vimrc.restored")).unwrap(), b"set number\n");
+        assert!(!dir.join("missing").exists());
+
+        let written = extract_to(&archive_path, &dir.join("out")).unwrap();
+        assert_eq!(
+            written,
+            vec![dir.join("out/.config/nvim/init.lua"), dir.join("out/.vimrc")]
+        );
+        assert_eq!(
+            fs::read_to_string(&written[0]).unwrap(),
+            "vim.opt.number = true\n"
+        );
+
+        let options = RestoreOptions {
+            extract_to: Some(dir.join("restore")),
+            ..Default::default()
+        };
+        let actions = |options: &RestoreOptions| -> Vec<RestoreAction> {
+            restore(&archive_path, options).unwrap().iter().map(|r| r.action).collect()
+        };
+        assert_eq!(actions(&options), [RestoreAction::Create, RestoreAction::Create]);
+        assert_eq!(actions(&options), [RestoreAction::Unchanged, RestoreAction::Unchanged]);
+
+        let restored = restore(&archive_path, &options).unwrap()[1].path.clone();
+        fs::write(&restored, "set nonumber\n").unwrap();
+        let dry_run = RestoreOptions {
+            dry_run: true,
+            ..options.clone()
+        };
+        assert_eq!(actions(&dry_run), [RestoreAction::Unchanged, RestoreAction::Overwrite]);
+        assert_eq!(fs::read(&restored).unwrap(), b"set nonumber\n");
+        assert_eq!(actions(&options), [RestoreAction::Unchanged, RestoreAction::Overwrite]);
+        assert_eq!(fs::read(&restored).unwrap(), b"set number\n");
+        assert_eq!(fs::read_dir(restored.parent().unwrap()).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
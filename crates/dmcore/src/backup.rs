@@ -7,14 +7,21 @@
 use age::secrecy::SecretString;
 use chrono::Local;
 use std::fs::{self, File};
-use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use crate::archive;
 use crate::config::{ArchiveFormat, Config};
+use crate::crypto::Keyring;
+use crate::exclude::ExcludeSet;
 use crate::git;
 use crate::index::{FileEntry, Index};
-use crate::project::Project;
+use crate::merge;
+use crate::metadata;
+use crate::mirror;
+use crate::objectid::{self, ObjectKey};
+use crate::project::{Project, TrackedFile};
 use crate::scanner::file_metadata;
+use crate::secrets::{self, SecretReport};
 use crate::store;
 
 /// Result of a backup operation
@@ -28,8 +35,12 @@ pub struct BackupResult {
     pub errors: usize,
     /// Total bytes stored
     pub bytes_stored: u64,
+    /// Files written to the readable mirror (git-mode files)
+    pub mirrored: usize,
     /// Whether a git commit was made
     pub committed: bool,
+    /// Unencrypted files with possible secrets (when `secret_scan` warns)
+    pub secrets: Vec<SecretReport>,
 }
 
 /// Backup a project using incremental (content-addressed) mode
@@ -40,7 +51,8 @@ pub fn backup_incremental(
 ) -> anyhow::Result<BackupResult> {
     let mut result = BackupResult::default();
 
-    for file in &project.files {
+    let exclude = ExcludeSet::for_project(config, project);
+    for file in &project.resolved_files(&exclude) {
         let abs_path = file.absolute_path();
 
         if !abs_path.exists() {
@@ -73,6 +85,30 @@ pub fn backup_incremental(
     Ok(result)
 }
 
+/// Refresh the readable mirror of git-mode files in the project repo
+///
+/// Uses the index rather than the disk so files that are missing locally
+/// keep their last mirrored version instead of being deleted. Expanded
+/// files that are now excluded drop out of the mirror.
+fn update_mirror(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    index: &Index,
+) -> anyhow::Result<usize> {
+    let exclude = ExcludeSet::for_project(config, project);
+    let keep: Vec<PathBuf> = project
+        .indexed_files(index)
+        .iter()
+        .filter(|f| f.uses_git() && !f.encrypted)
+        .map(|f| f.absolute_path())
+        .filter(|p| project.entry_for(p).is_some_and(|e| !e.expands()) || !exclude.is_excluded(p))
+        .collect();
+
+    let mirror_dir = config.project_mirror_dir(project_name)?;
+    Ok(mirror::update(&mirror_dir, &keep)?.written)
+}
+
 /// Backup files to an archive
 pub fn backup_archive(
     config: &Config,
@@ -86,24 +122,19 @@ pub fn backup_archive(
     let timestamp = Local::now().format("%Y%m%d-%H%M%S");
     let archive_name = format!("{}-{}.{}", project_name, timestamp, format.extension());
     let archive_path = backups_dir.join(&archive_name);
+    let files = project.resolved_files(&ExcludeSet::for_project(config, project));
 
     match format {
-        ArchiveFormat::TarGz => create_tar_gz(&archive_path, project)?,
-        ArchiveFormat::Zip => create_zip(&archive_path, project)?,
-        ArchiveFormat::SevenZ => {
-            // For 7z, fall back to tar.gz with a note
-            // Full 7z support would require the sevenz-rust crate
-            let alt_path = backups_dir.join(format!("{}-{}.tar.gz", project_name, timestamp));
-            create_tar_gz(&alt_path, project)?;
-            return Ok(alt_path);
-        }
+        ArchiveFormat::TarGz => create_tar_gz(&archive_path, &files)?,
+        ArchiveFormat::Zip => create_zip(&archive_path, &files)?,
+        ArchiveFormat::SevenZ => create_7z(&archive_path, &files, config.sevenz_preset)?,
     }
 
     Ok(archive_path)
 }
 
 /// Create a tar.gz archive
-fn create_tar_gz(archive_path: &Path, project: &Project) -> anyhow::Result<()> {
+fn create_tar_gz(archive_path: &Path, files: &[TrackedFile]) -> anyhow::Result<()> {
     use flate2::write::GzEncoder;
     use flate2::Compression;
     use tar::Builder;
@@ -112,11 +143,10 @@ fn create_tar_gz(archive_path: &Path, project: &Project) -> anyhow::Result<()> {
     let encoder = GzEncoder::new(file, Compression::default());
     let mut builder = Builder::new(encoder);
 
-    for tracked_file in &project.files {
+    for tracked_file in files {
         let abs_path = tracked_file.absolute_path();
         if abs_path.exists() && abs_path.is_file() {
-            // Use the stored path (with ~) as the archive path
-            let archive_path = tracked_file.path.trim_start_matches("~/");
+            let archive_path = archive::entry_name(&tracked_file.path);
             builder.append_path_with_name(&abs_path, archive_path)?;
         }
     }
@@ -126,7 +156,7 @@ fn create_tar_gz(archive_path: &Path, project: &Project) -> anyhow::Result<()> {
 }
 
 /// Create a zip archive
-fn create_zip(archive_path: &Path, project: &Project) -> anyhow::Result<()> {
+fn create_zip(archive_path: &Path, files: &[TrackedFile]) -> anyhow::Result<()> {
     use zip::write::SimpleFileOptions;
     use zip::ZipWriter;
 
@@ -135,13 +165,12 @@ fn create_zip(archive_path: &Path, project: &Project) -> anyhow::Result<()> {
     let options = SimpleFileOptions::default()
         .compression_method(zip::CompressionMethod::Deflated);
 
-    for tracked_file in &project.files {
+    for tracked_file in files {
         let abs_path = tracked_file.absolute_path();
         if abs_path.exists() && abs_path.is_file() {
-            let archive_path = tracked_file.path.trim_start_matches("~/");
+            let archive_path = archive::entry_name(&tracked_file.path);
             zip.start_file(archive_path, options)?;
-            let content = fs::read(&abs_path)?;
-            zip.write_all(&content)?;
+            std::io::copy(&mut File::open(&abs_path)?, &mut zip)?;
         }
     }
 
@@ -149,6 +178,27 @@ fn create_zip(archive_path: &Path, project: &Project) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Create a 7z archive compressed with LZMA2 at the given preset (0-9)
+fn create_7z(archive_path: &Path, files: &[TrackedFile], preset: u32) -> anyhow::Result<()> {
+    use sevenz_rust::lzma::LZMA2Options;
+    use sevenz_rust::{SevenZArchiveEntry, SevenZWriter};
+
+    let mut writer = SevenZWriter::create(archive_path)?;
+    writer.set_content_methods(vec![LZMA2Options::with_preset(preset.min(9)).into()]);
+
+    for tracked_file in files {
+        let abs_path = tracked_file.absolute_path();
+        if abs_path.exists() && abs_path.is_file() {
+            let archive_path = archive::entry_name(&tracked_file.path);
+            let entry = SevenZArchiveEntry::from_path(&abs_path, archive_path);
+            writer.push_archive_entry(entry, Some(File::open(&abs_path)?))?;
+        }
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
 /// List archive backups for a project
 pub fn list_archives(config: &Config, project_name: &str) -> anyhow::Result<Vec<ArchiveInfo>> {
     let backups_dir = config.backups_dir()?;
@@ -163,11 +213,15 @@ pub fn list_archives(config: &Config, project_name: &str) -> anyhow::Result<Vec<
         let entry = entry?;
         let path = entry.path();
         if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            if name.starts_with(&prefix) {
+            if !name.starts_with(&prefix) {
+                continue;
+            }
+            if let Some(format) = ArchiveFormat::from_path(&path) {
                 let meta = entry.metadata()?;
                 archives.push(ArchiveInfo {
                     path: path.clone(),
                     name: name.to_string(),
+                    format,
                     size: meta.len(),
                     created: meta.created().ok(),
                 });
@@ -181,18 +235,49 @@ pub fn list_archives(config: &Config, project_name: &str) -> anyhow::Result<Vec<
     Ok(archives)
 }
 
+/// Find a project's archive by file name, timestamp, or `latest`
+pub fn find_archive(config: &Config, project_name: &str, name: &str) -> anyhow::Result<ArchiveInfo> {
+    let archives = list_archives(config, project_name)?;
+
+    if name == "latest" {
+        return archives
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No archives for project '{}'", project_name));
+    }
+
+    let matches: Vec<_> = archives
+        .into_iter()
+        .filter(|a| a.name == name || a.name.contains(name))
+        .collect();
+    if let Some(exact) = matches.iter().find(|a| a.name == name) {
+        return Ok(exact.clone());
+    }
+    match matches.len() {
+        0 => anyhow::bail!("Archive '{}' not found for project '{}'", name, project_name),
+        1 => Ok(matches.into_iter().next().unwrap()),
+        n => anyhow::bail!("'{}' matches {} archives, be more specific", name, n),
+    }
+}
+
 /// Information about an archive backup
 #[derive(Debug, Clone)]
 pub struct ArchiveInfo {
     pub path: PathBuf,
     pub name: String,
+    /// Format detected from the file extension
+    pub format: ArchiveFormat,
     pub size: u64,
     pub created: Option<std::time::SystemTime>,
 }
 
-/// Check if a project has any files marked for encryption
+/// Check if backing up a project needs the password
+///
+/// True if it has files marked for encryption, or encrypted metadata, and no
+/// recipients to encrypt them to.
 pub fn project_needs_password(project: &Project) -> bool {
-    project.files.iter().any(|f| f.encrypted)
+    let encrypts = project.encrypt_index || project.encrypt_manifest || project.files.iter().any(|f| f.encrypted);
+    project.recipients.is_empty() && encrypts
 }
 
 /// Backup a project using incremental mode with encryption support
@@ -207,7 +292,8 @@ pub fn backup_incremental_encrypted(
 ) -> anyhow::Result<BackupResult> {
     let mut result = BackupResult::default();
 
-    for file in &project.files {
+    let exclude = ExcludeSet::for_project(config, project);
+    for file in &project.resolved_files(&exclude) {
         let abs_path = file.absolute_path();
 
         if !abs_path.exists() {
@@ -257,12 +343,17 @@ pub fn backup_project_incremental(
     project_name: &str,
     project: &Project,
 ) -> anyhow::Result<BackupResult> {
+    merge::ensure_host_branch(config, project_name)?;
     let store_dir = config.project_store_dir(project_name)?;
     let mut index = Index::load_for_project(config, project_name)?;
 
-    let mut result = BackupResult::default();
+    let mut result = BackupResult {
+        secrets: secrets::check_project(config, project_name, project)?,
+        ..BackupResult::default()
+    };
 
-    for file in &project.files {
+    let exclude = ExcludeSet::for_project(config, project);
+    for file in &project.resolved_files(&exclude) {
         let abs_path = file.absolute_path();
 
         if !abs_path.exists() {
@@ -294,6 +385,8 @@ pub fn backup_project_incremental(
 
     // Save the project-specific index
     index.save_for_project(config, project_name)?;
+    result.mirrored = update_mirror(config, project_name, project, &index)?;
+    metadata::sync_metadata(config, project_name, project, &Keyring::default())?;
 
     // Commit to project git repo if there are changes
     let project_dir = config.project_dir(project_name)?;
@@ -314,9 +407,9 @@ pub fn backup_project_incremental_encrypted(
     config: &Config,
     project_name: &str,
     project: &Project,
-    password: Option<&SecretString>,
+    keys: &Keyring,
 ) -> anyhow::Result<BackupResult> {
-    backup_project_incremental_encrypted_with_message(config, project_name, project, password, None)
+    backup_project_incremental_encrypted_with_message(config, project_name, project, keys, None)
 }
 
 /// Backup a project with encryption support and custom commit message
@@ -327,15 +420,44 @@ pub fn backup_project_incremental_encrypted_with_message(
     config: &Config,
     project_name: &str,
     project: &Project,
-    password: Option<&SecretString>,
+    keys: &Keyring,
     custom_message: Option<&str>,
 ) -> anyhow::Result<BackupResult> {
     let store_dir = config.project_store_dir(project_name)?;
-    let mut index = Index::load_for_project(config, project_name)?;
+    let has_encrypted = project.files.iter().any(|f| f.encrypted);
 
-    let mut result = BackupResult::default();
+    // Nothing is stored or committed if the secret scan blocks
+    let secrets = secrets::check_project(config, project_name, project)?;
+
+    // Backups commit on this machine's branch when host branches are on
+    merge::ensure_host_branch(config, project_name)?;
 
-    for file in &project.files {
+    // A pulled encrypted index has to be read before it is updated
+    metadata::unlock_index(config, project_name, keys)?;
+
+    // Move files toggled since the last backup between plaintext and
+    // keyed blobs, then key this run's encrypted files the same way
+    let encryption = if keys.can_encrypt() {
+        objectid::migrate_encryption(config, project_name, project, keys)?;
+        if has_encrypted {
+            Some(ObjectKey::load_or_create(config, project_name, keys)?)
+        } else {
+            None
+        }
+    } else if has_encrypted {
+        anyhow::bail!("Password or recipients required to back up encrypted files")
+    } else {
+        None
+    };
+
+    let mut index = Index::load_for_project(config, project_name)?;
+    let mut result = BackupResult {
+        secrets,
+        ..BackupResult::default()
+    };
+
+    let exclude = ExcludeSet::for_project(config, project);
+    for file in &project.resolved_files(&exclude) {
         let abs_path = file.absolute_path();
 
         if !abs_path.exists() {
@@ -343,20 +465,21 @@ pub fn backup_project_incremental_encrypted_with_message(
             continue;
         }
 
-        // Determine if this file should be encrypted
-        let should_encrypt = file.encrypted && password.is_some();
-
-        // Store file in project-specific store
-        let store_result = if should_encrypt {
-            store::store_file_to_encrypted(&store_dir, &abs_path, password)?
-        } else {
-            store::store_file_to(&store_dir, &abs_path)?
+        // Store file in project-specific store, encrypted under its keyed id
+        // if marked for encryption
+        let keyed = encryption.as_ref().filter(|_| file.encrypted);
+        let store_result = match keyed {
+            Some(key) => store::store_file_to_encrypted(&store_dir, &abs_path, keys, key)?,
+            None => store::store_file_to(&store_dir, &abs_path)?,
         };
 
         // Update index
         let (size, modified) = file_metadata(&abs_path)?;
-        let mut entry = if should_encrypt {
-            FileEntry::with_sync_now_encrypted(store_result.hash.clone(), size, modified)
+        let mut entry = if keyed.is_some() {
+            let mut entry =
+                FileEntry::with_sync_now_encrypted(store_result.hash.clone(), size, modified);
+            entry.keyed = true;
+            entry
         } else {
             FileEntry::with_sync_now(store_result.hash.clone(), size, modified)
         };
@@ -373,6 +496,8 @@ pub fn backup_project_incremental_encrypted_with_message(
 
     // Save the project-specific index
     index.save_for_project(config, project_name)?;
+    result.mirrored = update_mirror(config, project_name, project, &index)?;
+    metadata::sync_metadata(config, project_name, project, keys)?;
 
     // Commit to project git repo if there are changes
     let project_dir = config.project_dir(project_name)?;
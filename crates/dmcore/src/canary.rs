@@ -0,0 +1,179 @@
+//! Password check for encrypted stores
+//!
+//! A project using the password keeps `password-check.age` in its repo: a
+//! known string encrypted with the password. Frontends check every entered
+//! password against it before a backup or restore, so a typo can't leave a
+//! store encrypted with two different passwords.
+//!
+//! Stores from before the check existed are checked against one of their
+//! passphrase-encrypted blobs instead (the object key first), and get the
+//! check file once a password opens it.
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use age::secrecy::SecretString;
+
+use crate::config::Config;
+use crate::crypto::Keyring;
+use crate::store;
+
+/// File name of the password check inside a project directory
+pub(crate) const PASSWORD_CHECK_FILE: &str = "password-check.age";
+
+/// Content of the password check
+const CANARY: &[u8] = b"dotmatrix password check";
+
+/// Outcome of checking a password against a project
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    /// The password opens the project's store
+    Correct,
+    /// The store is encrypted with a different password
+    Wrong,
+    /// No password has been set for the project yet
+    Unset,
+}
+
+/// Check a password against a project's password check
+///
+/// Without a check file, the first passphrase-encrypted blob decides, and a
+/// correct password gets the check file written for next time.
+pub fn check_password(
+    config: &Config,
+    project_name: &str,
+    password: &SecretString,
+) -> anyhow::Result<PasswordCheck> {
+    let keys = Keyring::from_password(password.clone());
+
+    let path = config.project_password_check_path(project_name)?;
+    if path.exists() {
+        let opened = keys.decrypt(&fs::read(&path)?).is_ok_and(|c| c == CANARY);
+        return Ok(if opened { PasswordCheck::Correct } else { PasswordCheck::Wrong });
+    }
+
+    let Some(blob) = first_passphrase_blob(config, project_name)? else {
+        return Ok(PasswordCheck::Unset);
+    };
+    // Opening the stream unwraps the file key, which is what the password
+    // decides; the blob itself needn't be read
+    if keys.decrypt_from(BufReader::new(File::open(blob)?)).is_err() {
+        return Ok(PasswordCheck::Wrong);
+    }
+    set_password_check(config, project_name, password)?;
+    Ok(PasswordCheck::Correct)
+}
+
+/// Write a project's password check for a (new) password
+pub fn set_password_check(
+    config: &Config,
+    project_name: &str,
+    password: &SecretString,
+) -> anyhow::Result<()> {
+    let path = config.project_password_check_path(project_name)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let keys = Keyring::from_password(password.clone());
+    fs::write(&path, keys.encrypt(CANARY)?)?;
+    Ok(())
+}
+
+/// Bring the password check in line with the keys a store was just
+/// re-encrypted with
+///
+/// A store moved to recipients no longer has a password, so its check is
+/// removed.
+pub(crate) fn sync_password_check(
+    config: &Config,
+    project_name: &str,
+    keys: &Keyring,
+) -> anyhow::Result<()> {
+    match keys.password() {
+        Some(password) if !keys.has_recipients() => {
+            set_password_check(config, project_name, password)
+        }
+        _ => {
+            let path = config.project_password_check_path(project_name)?;
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Find a passphrase-encrypted blob of a project, the object key first
+fn first_passphrase_blob(config: &Config, project_name: &str) -> anyhow::Result<Option<PathBuf>> {
+    let mut paths = vec![config.project_object_key_path(project_name)?];
+    let store_dir = config.project_store_dir(project_name)?;
+    paths.extend(store::stored_objects(&store_dir)?.into_iter().map(|o| o.path));
+
+    Ok(paths.into_iter().find(|p| store::is_passphrase_blob(p)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::Project;
+    use crate::scanner::hash_bytes;
+    use crate::testutil::TempData;
+
+    fn password(p: &str) -> SecretString {
+        SecretString::from(p.to_string())
+    }
+
+    #[test]
+    fn test_check_password() {
+        let data = TempData::new("canary");
+        data.project("p");
+
+        assert_eq!(check_password(&data.config, "p", &password("hunter2")).unwrap(), PasswordCheck::Unset);
+        set_password_check(&data.config, "p", &password("hunter2")).unwrap();
+        assert_eq!(check_password(&data.config, "p", &password("hunter2")).unwrap(), PasswordCheck::Correct);
+        assert_eq!(check_password(&data.config, "p", &password("hunter3")).unwrap(), PasswordCheck::Wrong);
+    }
+
+    #[test]
+    fn test_check_password_falls_back_to_blobs() {
+        let data = TempData::new("canary-legacy");
+        data.project("p");
+        let store_dir = data.config.project_store_dir("p").unwrap();
+        let check = data.config.project_password_check_path("p").unwrap();
+        let keys = Keyring::from_password(password("hunter2"));
+        store::write_object(&store_dir, &hash_bytes(b"token = a\n"), b"token = a\n", Some(&keys)).unwrap();
+
+        assert_eq!(check_password(&data.config, "p", &password("hunter3")).unwrap(), PasswordCheck::Wrong);
+        assert!(!check.exists());
+        assert_eq!(check_password(&data.config, "p", &password("hunter2")).unwrap(), PasswordCheck::Correct);
+        assert!(check.exists());
+
+        // The check file decides from now on
+        fs::remove_file(store::hash_to_path(&store_dir, &hash_bytes(b"token = a\n"))).unwrap();
+        assert_eq!(check_password(&data.config, "p", &password("hunter2")).unwrap(), PasswordCheck::Correct);
+        assert_eq!(check_password(&data.config, "p", &password("hunter3")).unwrap(), PasswordCheck::Wrong);
+    }
+
+    #[test]
+    fn test_sync_password_check() {
+        let data = TempData::new("canary-sync");
+        data.project("p");
+        let check = data.config.project_password_check_path("p").unwrap();
+
+        sync_password_check(&data.config, "p", &Keyring::from_password(password("hunter2"))).unwrap();
+        assert_eq!(check_password(&data.config, "p", &password("hunter2")).unwrap(), PasswordCheck::Correct);
+
+        // Rotated to a new password
+        sync_password_check(&data.config, "p", &Keyring::from_password(password("hunter3"))).unwrap();
+        assert_eq!(check_password(&data.config, "p", &password("hunter3")).unwrap(), PasswordCheck::Correct);
+
+        // Rotated to recipients, the password no longer applies
+        let mut project = Project::new();
+        project.recipients = vec![age::x25519::Identity::generate().to_public().to_string()];
+        let keys = Keyring::for_project(&data.config, &project, Some(&password("hunter3"))).unwrap();
+        sync_password_check(&data.config, "p", &keys).unwrap();
+        assert!(!check.exists());
+        assert_eq!(check_password(&data.config, "p", &password("hunter3")).unwrap(), PasswordCheck::Unset);
+    }
+}
@@ -0,0 +1,219 @@
+//! Changes between revisions of a project index
+//!
+//! Classifies every tracked path as added, removed, modified or unchanged,
+//! for commit lists and `diff` output.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::git;
+use crate::index::Index;
+use crate::metadata;
+
+/// How a file changed between two revisions of a project index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+    Unchanged,
+}
+
+impl ChangeKind {
+    /// Lowercase name, as used in JSON output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// One file in an index diff
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    /// Size in the old revision, 0 if the file wasn't there
+    pub old_size: u64,
+    /// Size in the new revision, 0 if the file is gone
+    pub new_size: u64,
+}
+
+impl FileChange {
+    /// Change in size in bytes
+    pub fn size_delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+}
+
+/// Every path of two project indexes, classified by how it changed
+#[derive(Debug, Clone, Default)]
+pub struct IndexDiff {
+    /// Sorted by path
+    pub files: Vec<FileChange>,
+}
+
+impl IndexDiff {
+    /// Compare two indexes by content hash
+    pub fn between(old: &Index, new: &Index) -> Self {
+        let paths: BTreeSet<&PathBuf> = old.iter().chain(new.iter()).map(|(path, _)| path).collect();
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let (before, after) = (old.get(path), new.get(path));
+                let kind = match (before, after) {
+                    (None, _) => ChangeKind::Added,
+                    (_, None) => ChangeKind::Removed,
+                    (Some(a), Some(b)) if a.hash != b.hash => ChangeKind::Modified,
+                    _ => ChangeKind::Unchanged,
+                };
+                FileChange {
+                    path: path.clone(),
+                    kind,
+                    old_size: before.map_or(0, |e| e.size),
+                    new_size: after.map_or(0, |e| e.size),
+                }
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// Number of files with a kind of change
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.files.iter().filter(|f| f.kind == kind).count()
+    }
+
+    /// Files that were added, removed or modified
+    pub fn changed(&self) -> impl Iterator<Item = &FileChange> {
+        self.files.iter().filter(|f| f.kind != ChangeKind::Unchanged)
+    }
+
+    /// Total change in size in bytes
+    pub fn size_delta(&self) -> i64 {
+        self.files.iter().map(FileChange::size_delta).sum()
+    }
+
+    /// Counts as `2 added, 1 modified`, or `no changes`
+    pub fn summary(&self) -> String {
+        let parts: Vec<String> = [ChangeKind::Added, ChangeKind::Modified, ChangeKind::Removed]
+            .into_iter()
+            .filter_map(|kind| {
+                let n = self.count(kind);
+                (n > 0).then(|| format!("{} {}", n, kind.as_str()))
+            })
+            .collect();
+        if parts.is_empty() {
+            "no changes".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// Compare a project's index between two revisions
+///
+/// A revision without an index counts as empty. Sealed indexes need the
+/// project's index unlocked.
+pub fn index_diff(config: &Config, project_name: &str, old_rev: &str, new_rev: &str) -> anyhow::Result<IndexDiff> {
+    let old = index_or_empty(config, project_name, old_rev)?;
+    let new = index_or_empty(config, project_name, new_rev)?;
+    Ok(IndexDiff::between(&old, &new))
+}
+
+/// Compare the index a commit recorded with the one of its first parent
+///
+/// The first commit is compared with an empty index.
+pub fn commit_diff(config: &Config, project_name: &str, rev: &str) -> anyhow::Result<IndexDiff> {
+    let project_dir = config.project_dir(project_name)?;
+    let new = index_or_empty(config, project_name, rev)?;
+    let old = match git::resolve_revision(&project_dir, &format!("{}^", rev)) {
+        Ok(parent) => index_or_empty(config, project_name, &parent)?,
+        Err(_) => Index::default(),
+    };
+    Ok(IndexDiff::between(&old, &new))
+}
+
+/// Diff each commit against its first parent, reading every index once
+///
+/// Used for commit lists, so `commits` are full hashes. A commit whose index
+/// can't be read gets `None`.
+pub fn commit_diffs(config: &Config, project_name: &str, commits: &[&str]) -> anyhow::Result<Vec<Option<IndexDiff>>> {
+    let project_dir = config.project_dir(project_name)?;
+    if commits.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let parents = git::first_parents(&project_dir, commits)?;
+
+    let mut indexes: HashMap<String, Option<Index>> = HashMap::new();
+    let mut load = |rev: &str| -> Option<Index> {
+        indexes
+            .entry(rev.to_string())
+            .or_insert_with(|| {
+                let mut index = metadata::index_at(config, project_name, rev).ok()?.unwrap_or_default();
+                index.localize(&config.path_remap);
+                Some(index)
+            })
+            .clone()
+    };
+
+    Ok(commits
+        .iter()
+        .map(|&commit| {
+            let new = load(commit)?;
+            let old = match parents.get(commit).cloned().flatten() {
+                Some(parent) => load(&parent)?,
+                None => Index::default(),
+            };
+            Some(IndexDiff::between(&old, &new))
+        })
+        .collect())
+}
+
+/// A project's index at a revision, localized, or empty if it had none
+fn index_or_empty(config: &Config, project_name: &str, rev: &str) -> anyhow::Result<Index> {
+    let mut index = metadata::index_at(config, project_name, rev)?.unwrap_or_default();
+    index.localize(&config.path_remap);
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::FileEntry;
+
+    #[test]
+    fn test_index_diff_between() {
+        let mut old = Index::new();
+        old.upsert(PathBuf::from("/h/kept"), FileEntry::new("a".into(), 10, 0));
+        old.upsert(PathBuf::from("/h/edited"), FileEntry::new("b".into(), 100, 0));
+        old.upsert(PathBuf::from("/h/dropped"), FileEntry::new("c".into(), 40, 0));
+        let mut new = Index::new();
+        new.upsert(PathBuf::from("/h/kept"), FileEntry::new("a".into(), 10, 5));
+        new.upsert(PathBuf::from("/h/edited"), FileEntry::new("d".into(), 130, 0));
+        new.upsert(PathBuf::from("/h/new"), FileEntry::new("e".into(), 7, 0));
+
+        let diff = IndexDiff::between(&old, &new);
+        let kinds: Vec<(&str, ChangeKind)> = diff
+            .files
+            .iter()
+            .map(|f| (f.path.to_str().unwrap(), f.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("/h/dropped", ChangeKind::Removed),
+                ("/h/edited", ChangeKind::Modified),
+                ("/h/kept", ChangeKind::Unchanged),
+                ("/h/new", ChangeKind::Added),
+            ]
+        );
+        assert_eq!(diff.size_delta(), -40 + 30 + 7);
+        assert_eq!(diff.summary(), "1 added, 1 modified, 1 removed");
+        assert_eq!(IndexDiff::between(&new, &new).summary(), "no changes");
+    }
+}
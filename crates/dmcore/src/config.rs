@@ -3,7 +3,10 @@
 //! Handles global settings that apply across all projects.
 
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::remap::{self, PathRemap};
+use crate::secrets::SecretScan;
 
 /// Preferred interface when running without arguments
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
@@ -33,23 +36,73 @@ pub struct Config {
     #[serde(default)]
     pub default_archive_format: ArchiveFormat,
 
+    /// LZMA2 preset for 7z archives (0 = fastest, 9 = smallest)
+    #[serde(default = "default_sevenz_preset")]
+    pub sevenz_preset: u32,
+
     /// Enable git tracking by default
     #[serde(default = "default_true")]
     pub git_enabled: bool,
 
-    /// Global exclude patterns
+    /// Global exclude patterns (see `exclude::ExcludeSet` for syntax)
     #[serde(default = "default_excludes")]
     pub exclude: Vec<String>,
 
     /// Preferred interface when running without arguments
     #[serde(default)]
     pub preferred_interface: PreferredInterface,
+
+    /// Rewrite rules for paths recorded on other machines (see `remap`)
+    #[serde(default = "remap::default_rules")]
+    pub path_remap: Vec<PathRemap>,
+
+    /// age identity files or unencrypted SSH private keys, for files
+    /// encrypted to project recipients
+    #[serde(default)]
+    pub identity_files: Vec<String>,
+
+    /// Seconds the passphrase agent keeps a password (0 = until locked)
+    #[serde(default = "default_agent_timeout")]
+    pub agent_timeout: u64,
+
+    /// Command printing the password, used before prompting (see `passcmd`)
+    #[serde(default)]
+    pub password_command: Option<String>,
+
+    /// Seconds a password command may run before it is killed
+    #[serde(default = "default_password_command_timeout")]
+    pub password_command_timeout: u64,
+
+    /// What possible secrets in unencrypted files do (see `secrets`)
+    #[serde(default)]
+    pub secret_scan: SecretScan,
+
+    /// Commit backups on a per-machine `host/<name>` branch and share them
+    /// through `main` by merging (see `merge`)
+    #[serde(default)]
+    pub host_branches: bool,
+
+    /// Machine name for the host branch (defaults to the system hostname)
+    #[serde(default)]
+    pub host_name: Option<String>,
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn default_sevenz_preset() -> u32 {
+    6
+}
+
+fn default_agent_timeout() -> u64 {
+    900
+}
+
+fn default_password_command_timeout() -> u64 {
+    30
+}
+
 fn default_excludes() -> Vec<String> {
     vec![
         "**/*.log".to_string(),
@@ -66,9 +119,18 @@ impl Default for Config {
             data_dir: None,
             default_backup_mode: BackupMode::default(),
             default_archive_format: ArchiveFormat::default(),
+            sevenz_preset: default_sevenz_preset(),
             git_enabled: true,
             exclude: default_excludes(),
             preferred_interface: PreferredInterface::default(),
+            path_remap: remap::default_rules(),
+            identity_files: Vec::new(),
+            agent_timeout: default_agent_timeout(),
+            password_command: None,
+            password_command_timeout: default_password_command_timeout(),
+            secret_scan: SecretScan::default(),
+            host_branches: false,
+            host_name: None,
         }
     }
 }
@@ -102,6 +164,7 @@ pub enum ArchiveFormat {
     SevenZ,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for ArchiveFormat {
     fn default() -> Self {
         #[cfg(windows)]
@@ -123,6 +186,14 @@ impl ArchiveFormat {
             ArchiveFormat::SevenZ => "7z",
         }
     }
+
+    /// Detect the format of an archive from its file name
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        [ArchiveFormat::TarGz, ArchiveFormat::Zip, ArchiveFormat::SevenZ]
+            .into_iter()
+            .find(|f| name.ends_with(&format!(".{}", f.extension())))
+    }
 }
 
 impl Config {
@@ -198,9 +269,43 @@ impl Config {
         Ok(self.project_dir(project_name)?.join("store"))
     }
 
+    /// Get the readable mirror directory for a specific project
+    pub fn project_mirror_dir(&self, project_name: &str) -> anyhow::Result<PathBuf> {
+        Ok(self.project_dir(project_name)?.join("files"))
+    }
+
+    /// Get the encrypted object key path for a specific project
+    pub fn project_object_key_path(&self, project_name: &str) -> anyhow::Result<PathBuf> {
+        Ok(self.project_dir(project_name)?.join(crate::objectid::OBJECT_KEY_FILE))
+    }
+
+    /// Get the password check file for a specific project
+    pub fn project_password_check_path(&self, project_name: &str) -> anyhow::Result<PathBuf> {
+        Ok(self.project_dir(project_name)?.join(crate::canary::PASSWORD_CHECK_FILE))
+    }
+
+    /// Get the local, uncommitted object key cache for a specific project
+    pub fn project_object_key_cache_path(&self, project_name: &str) -> anyhow::Result<PathBuf> {
+        Ok(self.project_dir(project_name)?.join(crate::objectid::OBJECT_KEY_CACHE))
+    }
+
+    /// Get the passphrase agent's socket path
+    ///
+    /// `DOTMATRIX_AGENT_SOCK` overrides it, like `SSH_AUTH_SOCK`.
+    pub fn agent_socket_path(&self) -> anyhow::Result<PathBuf> {
+        if let Some(socket) = std::env::var_os("DOTMATRIX_AGENT_SOCK") {
+            return Ok(PathBuf::from(socket));
+        }
+        let dir = match dirs::runtime_dir() {
+            Some(runtime_dir) => runtime_dir.join("dotmatrix"),
+            None => self.data_dir()?.join("agent"),
+        };
+        Ok(dir.join("agent.sock"))
+    }
+
     /// Get the index path for a specific project
     pub fn project_index_path(&self, project_name: &str) -> anyhow::Result<PathBuf> {
-        Ok(self.project_dir(project_name)?.join("index.json"))
+        Ok(self.project_dir(project_name)?.join(crate::metadata::INDEX_FILE))
     }
 }
 
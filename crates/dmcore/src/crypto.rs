@@ -2,65 +2,53 @@
 //!
 //! This module provides password-based encryption using the age-encryption.org standard.
 //! Files are encrypted with a user-provided password before being stored in the backup.
+//!
+//! Projects can instead list age recipients (`age1...` X25519 keys or SSH public keys),
+//! so backups can run with only public keys present. Restoring those files needs one of
+//! the matching identity files listed in `config.toml`. A [`Keyring`] bundles whatever a
+//! project has and picks the right key for each blob.
 
 use age::secrecy::SecretString;
 use anyhow::{Context, Result};
-use std::io::{Read, Write};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
+use std::str::FromStr;
+
+use crate::config::{expand_path, Config};
+use crate::project::Project;
 
 /// Encrypt file contents using password-based encryption.
 ///
-/// Reads the source file, encrypts it with the provided password using age,
-/// and writes the encrypted content to the destination.
+/// Streams the source file through age with the provided password into the
+/// destination, so memory use doesn't grow with the file size.
 pub fn encrypt_file(source: &Path, dest: &Path, password: &SecretString) -> Result<()> {
-    let encryptor = age::Encryptor::with_user_passphrase(password.clone());
-
-    let input = std::fs::read(source)
+    let input = File::open(source)
         .with_context(|| format!("Failed to read source file: {}", source.display()))?;
 
-    let mut output = vec![];
-    let mut writer = encryptor
-        .wrap_output(&mut output)
-        .context("Failed to create age encryptor")?;
-
-    writer.write_all(&input)
-        .context("Failed to write encrypted data")?;
-
-    writer.finish()
-        .context("Failed to finalize encryption")?;
-
     // Create parent directories if needed
     if let Some(parent) = dest.parent() {
         std::fs::create_dir_all(parent)
             .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
     }
 
-    std::fs::write(dest, output)
+    let output = File::create(dest)
         .with_context(|| format!("Failed to write encrypted file: {}", dest.display()))?;
+    Keyring::from_password(password.clone())
+        .encrypt_to(BufReader::new(input), BufWriter::new(output))?
+        .flush()?;
 
     Ok(())
 }
 
 /// Decrypt file contents using password-based encryption.
 ///
-/// Reads the encrypted source file, decrypts it with the provided password,
-/// and writes the decrypted content to the destination.
+/// Streams the encrypted source file through age with the provided password
+/// into the destination.
 pub fn decrypt_file(source: &Path, dest: &Path, password: &SecretString) -> Result<()> {
-    let encrypted = std::fs::read(source)
+    let input = File::open(source)
         .with_context(|| format!("Failed to read encrypted file: {}", source.display()))?;
-
-    let decryptor = age::Decryptor::new(&encrypted[..])
-        .context("Failed to parse encrypted file")?;
-
-    let identity = age::scrypt::Identity::new(password.clone());
-
-    let mut decrypted = vec![];
-    let mut reader = decryptor
-        .decrypt(std::iter::once(&identity as &dyn age::Identity))
-        .map_err(|e| anyhow::anyhow!("Decryption failed (wrong password?): {}", e))?;
-
-    reader.read_to_end(&mut decrypted)
-        .context("Failed to read decrypted data")?;
+    let mut reader = Keyring::from_password(password.clone()).decrypt_from(BufReader::new(input))?;
 
     // Create parent directories if needed
     if let Some(parent) = dest.parent() {
@@ -68,8 +56,13 @@ pub fn decrypt_file(source: &Path, dest: &Path, password: &SecretString) -> Resu
             .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
     }
 
-    std::fs::write(dest, decrypted)
-        .with_context(|| format!("Failed to write decrypted file: {}", dest.display()))?;
+    let mut output = BufWriter::new(
+        File::create(dest)
+            .with_context(|| format!("Failed to write decrypted file: {}", dest.display()))?,
+    );
+    std::io::copy(&mut reader, &mut output)
+        .context("Failed to read decrypted data")?;
+    output.flush()?;
 
     Ok(())
 }
@@ -111,3 +104,234 @@ pub fn decrypt_bytes(encrypted: &[u8], password: &SecretString) -> Result<Vec<u8
 
     Ok(decrypted)
 }
+
+/// Keys for encrypting and decrypting a project's files.
+///
+/// New data is encrypted to the project's recipients if it has any, and to the
+/// password otherwise (age can't mix the two in one file). Decryption uses the
+/// password for passphrase-encrypted data and the identity files for the rest,
+/// so stores holding both kinds of blob keep restoring.
+#[derive(Default)]
+pub struct Keyring {
+    password: Option<SecretString>,
+    recipients: Vec<Box<dyn age::Recipient>>,
+    identities: Vec<Box<dyn age::Identity>>,
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("password", &self.password.is_some())
+            .field("recipients", &self.recipients.len())
+            .field("identities", &self.identities.len())
+            .finish()
+    }
+}
+
+impl Keyring {
+    /// A keyring holding only a password.
+    pub fn from_password(password: SecretString) -> Self {
+        Self {
+            password: Some(password),
+            ..Self::default()
+        }
+    }
+
+    /// Load the identity files from `config.toml`, plus an optional password.
+    ///
+    /// Enough to decrypt anything; use [`Keyring::for_project`] to encrypt.
+    pub fn load(config: &Config, password: Option<&SecretString>) -> Result<Self> {
+        let mut identities = Vec::new();
+        for path in &config.identity_files {
+            identities.extend(load_identities(&expand_path(path))?);
+        }
+
+        Ok(Self {
+            password: password.cloned(),
+            recipients: Vec::new(),
+            identities,
+        })
+    }
+
+    /// Load the keys for a project: its recipients, the identity files and an
+    /// optional password.
+    pub fn for_project(
+        config: &Config,
+        project: &Project,
+        password: Option<&SecretString>,
+    ) -> Result<Self> {
+        let mut keyring = Self::load(config, password)?;
+        keyring.recipients = project
+            .recipients
+            .iter()
+            .map(|r| parse_recipient(r))
+            .collect::<Result<_>>()?;
+        Ok(keyring)
+    }
+
+    /// The password, if one was given.
+    pub fn password(&self) -> Option<&SecretString> {
+        self.password.as_ref()
+    }
+
+    /// Check if new data is encrypted to recipients rather than the password.
+    pub fn has_recipients(&self) -> bool {
+        !self.recipients.is_empty()
+    }
+
+    /// Check if there is anything to encrypt new data with.
+    pub fn can_encrypt(&self) -> bool {
+        self.has_recipients() || self.password.is_some()
+    }
+
+    /// Check if this keyring has the kind of key an encrypted blob needs.
+    ///
+    /// Only the header is inspected, so an identity may still fail to match.
+    pub fn can_decrypt(&self, encrypted: &[u8]) -> bool {
+        if is_passphrase_encrypted(encrypted) {
+            self.password.is_some()
+        } else {
+            !self.identities.is_empty()
+        }
+    }
+
+    /// Encrypt a stream to the recipients, or the password if there are none.
+    ///
+    /// Returns the output once the encryption is finalized.
+    pub fn encrypt_to<R: Read, W: Write>(&self, mut input: R, output: W) -> Result<W> {
+        let encryptor = if self.has_recipients() {
+            age::Encryptor::with_recipients(self.recipients.iter().map(|r| r.as_ref()))
+                .context("Failed to create age encryptor")?
+        } else {
+            match &self.password {
+                Some(password) => age::Encryptor::with_user_passphrase(password.clone()),
+                None => anyhow::bail!("Password or recipients required to encrypt"),
+            }
+        };
+
+        let mut writer = encryptor
+            .wrap_output(output)
+            .context("Failed to create age encryptor")?;
+
+        std::io::copy(&mut input, &mut writer)
+            .context("Failed to write encrypted data")?;
+
+        writer.finish()
+            .context("Failed to finalize encryption")
+    }
+
+    /// Encrypt data in memory to the recipients, or the password if there are none.
+    pub fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_to(data, Vec::new())
+    }
+
+    /// Open an encrypted stream with the password or an identity, whichever it needs.
+    pub fn decrypt_from<R: Read>(&self, input: R) -> Result<age::stream::StreamReader<R>> {
+        let decryptor = age::Decryptor::new(input)
+            .context("Failed to parse encrypted data")?;
+
+        if decryptor.is_scrypt() {
+            let Some(password) = &self.password else {
+                anyhow::bail!("Password required to decrypt passphrase-encrypted data");
+            };
+            let identity = age::scrypt::Identity::new(password.clone());
+            return decryptor
+                .decrypt(std::iter::once(&identity as &dyn age::Identity))
+                .map_err(|e| anyhow::anyhow!("Decryption failed (wrong password?): {}", e));
+        }
+        if self.identities.is_empty() {
+            anyhow::bail!("Data is encrypted to recipients; add an identity file to identity_files in config.toml");
+        }
+
+        decryptor
+            .decrypt(self.identities.iter().map(|i| i.as_ref()))
+            .map_err(|e| anyhow::anyhow!("Decryption failed (no matching identity?): {}", e))
+    }
+
+    /// Decrypt data in memory with the password or an identity, whichever it needs.
+    pub fn decrypt(&self, encrypted: &[u8]) -> Result<Vec<u8>> {
+        let mut decrypted = vec![];
+        self.decrypt_from(encrypted)?
+            .read_to_end(&mut decrypted)
+            .context("Failed to read decrypted data")?;
+
+        Ok(decrypted)
+    }
+}
+
+/// Parse a recipient: an `age1...` X25519 key or an SSH public key line.
+pub fn parse_recipient(recipient: &str) -> Result<Box<dyn age::Recipient>> {
+    let recipient = recipient.trim();
+    if recipient.starts_with("age1") {
+        let key = age::x25519::Recipient::from_str(recipient)
+            .map_err(|e| anyhow::anyhow!("Invalid age recipient '{}': {}", recipient, e))?;
+        return Ok(Box::new(key));
+    }
+
+    let key = age::ssh::Recipient::from_str(recipient)
+        .map_err(|e| anyhow::anyhow!("Invalid recipient '{}': {:?}", recipient, e))?;
+    Ok(Box::new(key))
+}
+
+/// Load the identities in an age identity file or an unencrypted SSH private key.
+pub fn load_identities(path: &Path) -> Result<Vec<Box<dyn age::Identity>>> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read identity file: {}", path.display()))?;
+
+    if data.trim_start().starts_with("-----BEGIN") {
+        let filename = Some(path.display().to_string());
+        let identity = age::ssh::Identity::from_buffer(BufReader::new(data.as_bytes()), filename)
+            .with_context(|| format!("Failed to parse SSH key: {}", path.display()))?;
+        return match identity {
+            age::ssh::Identity::Unencrypted(_) => Ok(vec![Box::new(identity)]),
+            age::ssh::Identity::Encrypted(_) => anyhow::bail!(
+                "SSH key {} is passphrase-protected; use an age identity file instead",
+                path.display()
+            ),
+            age::ssh::Identity::Unsupported(_) => {
+                anyhow::bail!("Unsupported SSH key type: {}", path.display())
+            }
+        };
+    }
+
+    age::IdentityFile::from_buffer(BufReader::new(data.as_bytes()))
+        .and_then(|file| {
+            file.into_identities()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))
+        })
+        .with_context(|| format!("Failed to parse identity file: {}", path.display()))
+}
+
+/// Check if age-encrypted data was encrypted to a passphrase.
+pub fn is_passphrase_encrypted(encrypted: &[u8]) -> bool {
+    age::Decryptor::new(encrypted).is_ok_and(|d| d.is_scrypt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyring_mixed_blobs() {
+        let identity = age::x25519::Identity::generate();
+        let password = SecretString::from("hunter2".to_string());
+
+        let recipients = Keyring {
+            recipients: vec![Box::new(identity.to_public())],
+            ..Keyring::default()
+        };
+        let sealed = recipients.encrypt(b"token = abc\n").unwrap();
+        let legacy = encrypt_bytes(b"set number\n", &password).unwrap();
+        assert!(!is_passphrase_encrypted(&sealed));
+        assert!(is_passphrase_encrypted(&legacy));
+
+        // Without an identity the recipient blob is unreadable
+        assert!(!recipients.can_decrypt(&sealed));
+        assert!(recipients.decrypt(&sealed).is_err());
+
+        let mut both = Keyring::from_password(password);
+        both.identities.push(Box::new(identity));
+        assert_eq!(both.decrypt(&sealed).unwrap(), b"token = abc\n");
+        assert_eq!(both.decrypt(&legacy).unwrap(), b"set number\n");
+    }
+}
@@ -0,0 +1,437 @@
+//! Line diffs between two texts
+//!
+//! A Myers diff over lines, grouped into hunks with context like
+//! `diff -u`. Used to compare stored versions of a file without shelling out
+//! to an external `diff`, and to merge two edits of one text line by line.
+
+/// Lines of context around each change in a hunk
+pub const CONTEXT_LINES: usize = 3;
+
+/// Above this many changed lines the diff gives up on finding the shortest
+/// edit and shows the differing middle as replaced
+const MAX_EDITS: i32 = 4000;
+
+/// What happened to a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffTag {
+    /// In both texts
+    Equal,
+    /// Only in the old text
+    Delete,
+    /// Only in the new text
+    Insert,
+}
+
+/// One line of a diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub tag: DiffTag,
+    pub text: String,
+}
+
+impl DiffLine {
+    /// The `diff -u` prefix for this line
+    pub fn prefix(&self) -> char {
+        match self.tag {
+            DiffTag::Equal => ' ',
+            DiffTag::Delete => '-',
+            DiffTag::Insert => '+',
+        }
+    }
+}
+
+/// A run of changes with surrounding context
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// First old line shown (1-based; the line before it for an empty range)
+    pub old_start: usize,
+    pub old_len: usize,
+    /// First new line shown, like `old_start`
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    /// The `@@ -a,b +c,d @@` header
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        )
+    }
+}
+
+/// Diff two texts line by line
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    diff_slices(&a, &b)
+}
+
+/// Diff two texts already split into lines
+fn diff_slices(a: &[&str], b: &[&str]) -> Vec<DiffLine> {
+    // Only the differing middle needs the full algorithm
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let tags = edit_script(mid_a, mid_b).unwrap_or_else(|| {
+        let mut tags = vec![DiffTag::Delete; mid_a.len()];
+        tags.extend(std::iter::repeat_n(DiffTag::Insert, mid_b.len()));
+        tags
+    });
+
+    let mut lines: Vec<DiffLine> = a[..prefix].iter().map(|l| line(DiffTag::Equal, l)).collect();
+    let (mut x, mut y) = (0, 0);
+    for tag in tags {
+        match tag {
+            DiffTag::Equal => {
+                lines.push(line(tag, mid_a[x]));
+                x += 1;
+                y += 1;
+            }
+            DiffTag::Delete => {
+                lines.push(line(tag, mid_a[x]));
+                x += 1;
+            }
+            DiffTag::Insert => {
+                lines.push(line(tag, mid_b[y]));
+                y += 1;
+            }
+        }
+    }
+    lines.extend(a[a.len() - suffix..].iter().map(|l| line(DiffTag::Equal, l)));
+    lines
+}
+
+fn line(tag: DiffTag, text: &str) -> DiffLine {
+    DiffLine {
+        tag,
+        text: text.to_string(),
+    }
+}
+
+/// Group a diff into hunks with `context` unchanged lines around changes
+pub fn hunks(lines: &[DiffLine], context: usize) -> Vec<Hunk> {
+    // Old and new line numbers before each diff line
+    let mut positions = Vec::with_capacity(lines.len());
+    let (mut old, mut new) = (0, 0);
+    for l in lines {
+        positions.push((old, new));
+        match l.tag {
+            DiffTag::Equal => {
+                old += 1;
+                new += 1;
+            }
+            DiffTag::Delete => old += 1,
+            DiffTag::Insert => new += 1,
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].tag == DiffTag::Equal {
+            i += 1;
+            continue;
+        }
+
+        // Changes closer than twice the context share a hunk
+        let mut last_change = i;
+        let mut j = i + 1;
+        while j < lines.len() {
+            if lines[j].tag != DiffTag::Equal {
+                last_change = j;
+            } else if j - last_change > 2 * context {
+                break;
+            }
+            j += 1;
+        }
+
+        let start = i.saturating_sub(context);
+        let end = (last_change + context + 1).min(lines.len());
+        let shown = &lines[start..end];
+        let old_len = shown.iter().filter(|l| l.tag != DiffTag::Insert).count();
+        let new_len = shown.iter().filter(|l| l.tag != DiffTag::Delete).count();
+        let (old_pos, new_pos) = positions[start];
+        hunks.push(Hunk {
+            old_start: if old_len == 0 { old_pos } else { old_pos + 1 },
+            old_len,
+            new_start: if new_len == 0 { new_pos } else { new_pos + 1 },
+            new_len,
+            lines: shown.to_vec(),
+        });
+        i = end;
+    }
+    hunks
+}
+
+/// Unified diff of two texts, empty if they are the same
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let hunks = hunks(&diff_lines(old, new), CONTEXT_LINES);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for hunk in &hunks {
+        out.push_str(&hunk.header());
+        out.push('\n');
+        for l in &hunk.lines {
+            out.push(l.prefix());
+            out.push_str(&l.text);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Merge two edits of a common base text, or `None` if they conflict
+///
+/// Changes to different lines of the base are combined. Changes to the same
+/// or adjacent lines conflict unless both sides made the same change.
+/// Lines keep their terminators, so CRLF endings and the presence of a final
+/// newline come from whichever side changed them.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let ours_edits = edits(&diff_slices(&base_lines, &ours_lines));
+    let theirs_edits = edits(&diff_slices(&base_lines, &theirs_lines));
+
+    let mut out: Vec<String> = Vec::new();
+    let mut pos = 0;
+    let (mut i, mut j) = (0, 0);
+    while i < ours_edits.len() || j < theirs_edits.len() {
+        // Start a group at the first remaining edit, then pull in every edit
+        // from either side that overlaps or touches it
+        let first_ours = j >= theirs_edits.len()
+            || (i < ours_edits.len() && ours_edits[i].start <= theirs_edits[j].start);
+        let (mut start, mut end) = if first_ours {
+            (ours_edits[i].start, ours_edits[i].end)
+        } else {
+            (theirs_edits[j].start, theirs_edits[j].end)
+        };
+        let (group_i, group_j) = (i, j);
+        loop {
+            if i < ours_edits.len() && ours_edits[i].start <= end {
+                start = start.min(ours_edits[i].start);
+                end = end.max(ours_edits[i].end);
+                i += 1;
+            } else if j < theirs_edits.len() && theirs_edits[j].start <= end {
+                start = start.min(theirs_edits[j].start);
+                end = end.max(theirs_edits[j].end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        out.extend(base_lines[pos..start].iter().map(|l| l.to_string()));
+        let ours_text = apply_edits(&base_lines, start, end, &ours_edits[group_i..i]);
+        let theirs_text = apply_edits(&base_lines, start, end, &theirs_edits[group_j..j]);
+        if group_i == i {
+            out.extend(theirs_text);
+        } else if group_j == j || ours_text == theirs_text {
+            out.extend(ours_text);
+        } else {
+            return None;
+        }
+        pos = end;
+    }
+    out.extend(base_lines[pos..].iter().map(|l| l.to_string()));
+    Some(out.concat())
+}
+
+/// A run of base lines `start..end` replaced by `lines`
+struct Edit {
+    start: usize,
+    end: usize,
+    lines: Vec<String>,
+}
+
+/// The changes in a diff as replacements of base line ranges
+fn edits(diff: &[DiffLine]) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = Vec::new();
+    let mut pos = 0;
+    let mut open = false;
+    for l in diff {
+        match l.tag {
+            DiffTag::Equal => {
+                open = false;
+                pos += 1;
+                continue;
+            }
+            _ if !open => {
+                edits.push(Edit {
+                    start: pos,
+                    end: pos,
+                    lines: Vec::new(),
+                });
+                open = true;
+            }
+            _ => {}
+        }
+        let edit = edits.last_mut().expect("an edit is open");
+        if l.tag == DiffTag::Delete {
+            pos += 1;
+            edit.end = pos;
+        } else {
+            edit.lines.push(l.text.clone());
+        }
+    }
+    edits
+}
+
+/// Base lines `start..end` with one side's edits in that range applied
+fn apply_edits(base: &[&str], start: usize, end: usize, edits: &[Edit]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut pos = start;
+    for edit in edits {
+        out.extend(base[pos..edit.start].iter().map(|l| l.to_string()));
+        out.extend(edit.lines.iter().cloned());
+        pos = edit.end;
+    }
+    out.extend(base[pos..end].iter().map(|l| l.to_string()));
+    out
+}
+
+/// Shortest edit script from `a` to `b` (Myers), or `None` past `MAX_EDITS`
+fn edit_script(a: &[&str], b: &[&str]) -> Option<Vec<DiffTag>> {
+    let (n, m) = (a.len() as i32, b.len() as i32);
+    let max = n + m;
+    let offset = max + 1;
+    let at = |k: i32| (k + offset) as usize;
+
+    // Furthest x reached on each diagonal k = x - y
+    let mut v = vec![0i32; (2 * max + 3) as usize];
+    // The diagonals -(d + 1)..=d + 1 of `v` as step d started
+    let mut trace: Vec<Vec<i32>> = Vec::new();
+
+    let mut done = false;
+    for d in 0..=max.min(MAX_EDITS) {
+        trace.push(v[at(-d - 1)..=at(d + 1)].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                done = true;
+                break;
+            }
+            k += 2;
+        }
+        if done {
+            break;
+        }
+    }
+    if !done {
+        return None;
+    }
+
+    // Walk back from the end through the saved steps
+    let mut tags = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, saved) in trace.iter().enumerate().rev() {
+        let d = d as i32;
+        let get = |k: i32| saved[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { get(prev_k) };
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            tags.push(DiffTag::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                tags.push(DiffTag::Insert);
+                y -= 1;
+            } else {
+                tags.push(DiffTag::Delete);
+                x -= 1;
+            }
+        }
+    }
+    tags.reverse();
+    Some(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+
+        assert_eq!(
+            unified_diff(old, new, "old", "new"),
+            "--- old\n+++ new\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -8,3 +8,4 @@\n h\n i\n j\n+k\n"
+        );
+        assert_eq!(unified_diff(old, old, "old", "new"), "");
+    }
+
+    #[test]
+    fn test_diff_lines_is_minimal() {
+        let lines = diff_lines("x\na\nb\nc\ny\n", "a\nb\nz\nc\n");
+        let tags: Vec<char> = lines.iter().map(|l| l.prefix()).collect();
+        assert_eq!(tags, vec!['-', ' ', ' ', '+', ' ', '-']);
+
+        let added = hunks(&diff_lines("", "one\n"), CONTEXT_LINES);
+        assert_eq!(added[0].header(), "@@ -0,0 +1,1 @@");
+    }
+
+    #[test]
+    fn test_merge3() {
+        let base = "a\nb\nc\nd\ne\n";
+        assert_eq!(
+            merge3(base, "A\nb\nc\nd\ne\n", "a\nb\nc\nd\nE\nf\n").as_deref(),
+            Some("A\nb\nc\nd\nE\nf\n")
+        );
+        // The same change on both sides is not a conflict
+        assert_eq!(
+            merge3(base, "a\nb\nC\nd\ne\n", "a\nb\nC\nd\ne\n").as_deref(),
+            Some("a\nb\nC\nd\ne\n")
+        );
+        assert_eq!(merge3(base, "a\nB\nc\nd\ne\n", "a\nb\nC\nd\ne\n"), None);
+        assert_eq!(merge3(base, "a\nb\nx\nd\ne\n", "a\nb\ny\nd\ne\n"), None);
+    }
+
+    #[test]
+    fn test_merge3_keeps_line_endings() {
+        assert_eq!(
+            merge3("a\r\nb\r\nc\r\n", "A\r\nb\r\nc\r\n", "a\r\nb\r\nC\r\n").as_deref(),
+            Some("A\r\nb\r\nC\r\n")
+        );
+
+        // The final newline comes from the side that changed it
+        assert_eq!(merge3("a\nb\nc\n", "A\nb\nc\n", "a\nb\nc").as_deref(), Some("A\nb\nc"));
+        assert_eq!(merge3("a\nb\nc", "A\nb\nc", "a\nb\nc\n").as_deref(), Some("A\nb\nc\n"));
+        assert_eq!(merge3("a\nb\nc", "A\nb\nc", "a\nb\nc").as_deref(), Some("A\nb\nc"));
+    }
+}
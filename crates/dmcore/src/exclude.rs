@@ -0,0 +1,139 @@
+//! Exclusion rules for expanded and recursively added files
+//!
+//! Combines the global `Config::exclude` list with a project's own
+//! `exclude` list. Patterns without a `/` match a file or directory name at
+//! any depth (`lazy-lock.json`, `*.swp`, `node_modules`). Patterns with a `/`
+//! match the full path after `~` expansion (`**/node_modules/**`,
+//! `~/.config/nvim/plugin/**`).
+//!
+//! Exclusions filter what globs and directory walks expand to, and what the
+//! frontends let you add. Files already listed explicitly in a project are
+//! never dropped.
+
+use std::path::{Component, Path};
+
+use crate::config::{expand_path, Config};
+use crate::project::Project;
+
+/// A compiled set of exclude patterns
+#[derive(Debug, Clone, Default)]
+pub struct ExcludeSet {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: glob::Pattern,
+    /// Match against individual path components instead of the full path
+    name_only: bool,
+    /// For `dir/**` patterns, a pattern matching the directory itself
+    dir: Option<glob::Pattern>,
+}
+
+impl ExcludeSet {
+    /// Compile a list of patterns, skipping any that are not valid globs
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        let rules = patterns
+            .iter()
+            .filter_map(|p| Rule::new(p.as_ref()))
+            .collect();
+        Self { rules }
+    }
+
+    /// Build the exclude set for a project: global patterns plus its own
+    pub fn for_project(config: &Config, project: &Project) -> Self {
+        let mut set = Self::new(&config.exclude);
+        set.rules
+            .extend(project.exclude.iter().filter_map(|p| Rule::new(p)));
+        set
+    }
+
+    /// Check if there are no rules at all
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check if an absolute path (file or directory) is excluded
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.rules.iter().any(|rule| rule.matches(path))
+    }
+}
+
+impl Rule {
+    fn new(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return None;
+        }
+
+        if !pattern.contains('/') {
+            return Some(Self {
+                pattern: glob::Pattern::new(pattern).ok()?,
+                name_only: true,
+                dir: None,
+            });
+        }
+
+        let full = if pattern.starts_with('~') {
+            expand_path(pattern).to_string_lossy().to_string()
+        } else {
+            pattern.to_string()
+        };
+        let dir = full
+            .strip_suffix("/**")
+            .and_then(|d| glob::Pattern::new(d).ok());
+
+        Some(Self {
+            pattern: glob::Pattern::new(&full).ok()?,
+            name_only: false,
+            dir,
+        })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        if self.name_only {
+            return path.components().any(|c| match c {
+                Component::Normal(name) => self.pattern.matches(&name.to_string_lossy()),
+                _ => false,
+            });
+        }
+
+        self.pattern.matches_path_with(path, match_options())
+            || self
+                .dir
+                .as_ref()
+                .is_some_and(|d| d.matches_path_with(path, match_options()))
+    }
+}
+
+fn match_options() -> glob::MatchOptions {
+    glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_excludes() {
+        let set = ExcludeSet::new(&Config::default().exclude);
+
+        assert!(set.is_excluded(Path::new("/home/user/app/debug.log")));
+        assert!(set.is_excluded(Path::new("/home/user/app/node_modules/x/index.js")));
+        assert!(set.is_excluded(Path::new("/home/user/app/node_modules")));
+        assert!(!set.is_excluded(Path::new("/home/user/app/package.json")));
+    }
+
+    #[test]
+    fn test_name_patterns() {
+        let set = ExcludeSet::new(&["lazy-lock.json", "*.swp"]);
+
+        assert!(set.is_excluded(Path::new("/home/user/.config/nvim/lazy-lock.json")));
+        assert!(set.is_excluded(Path::new("/home/user/.config/nvim/.init.lua.swp")));
+        assert!(!set.is_excluded(Path::new("/home/user/.config/nvim/init.lua")));
+    }
+}
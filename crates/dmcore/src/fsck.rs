@@ -0,0 +1,327 @@
+//! Store integrity checks
+//!
+//! Every blob referenced by the working index or any `index.json` revision in
+//! the project history is re-hashed and compared with its name. Encrypted
+//! blobs are only verified when the password or an identity that opens them
+//! is given. Blobs nothing refers to
+//! are reported as orphans (`dmxcli store gc` removes them).
+//!
+//! Corrupt and missing blobs can be repaired from a good copy: the tracked
+//! file itself if its content still matches, or the readable mirror in an
+//! older commit.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::crypto::Keyring;
+use crate::git;
+use crate::mirror;
+use crate::objectid::ObjectKey;
+use crate::remap;
+use crate::scanner::{hash_bytes, hash_reader};
+use crate::store::{self, hash_to_path, is_encrypted_blob, ObjectRef, StoredObject};
+
+/// A referenced blob whose content does not match its hash
+#[derive(Debug, Clone)]
+pub struct CorruptObject {
+    pub hash: String,
+    pub reason: String,
+}
+
+/// A referenced blob that is not in the store
+#[derive(Debug, Clone)]
+pub struct MissingObject {
+    pub hash: String,
+    /// Paths the index records for it
+    pub paths: Vec<PathBuf>,
+}
+
+/// Where a repaired blob was recovered from
+#[derive(Debug, Clone)]
+pub enum RepairSource {
+    /// The tracked file on disk still has the right content
+    LocalFile(PathBuf),
+    /// The readable mirror at a commit
+    History { revision: String, path: String },
+}
+
+impl fmt::Display for RepairSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepairSource::LocalFile(path) => write!(f, "{}", path.display()),
+            RepairSource::History { revision, path } => {
+                write!(f, "{}:{}", &revision[..8.min(revision.len())], path)
+            }
+        }
+    }
+}
+
+/// A corrupt or missing blob that was rewritten from a good copy
+#[derive(Debug, Clone)]
+pub struct RepairedObject {
+    pub hash: String,
+    pub source: RepairSource,
+}
+
+/// Result of checking a project store
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    /// Index revisions read to find referenced blobs
+    pub revisions: usize,
+    /// Blobs whose content matched their hash
+    pub verified: usize,
+    /// Encrypted blobs not verified for lack of the password or an identity
+    pub skipped_encrypted: usize,
+    pub corrupt: Vec<CorruptObject>,
+    pub missing: Vec<MissingObject>,
+    /// Blobs no index revision refers to
+    pub orphans: Vec<StoredObject>,
+    pub repaired: Vec<RepairedObject>,
+}
+
+impl FsckReport {
+    /// Check if every referenced blob is (now) intact
+    pub fn is_clean(&self) -> bool {
+        let broken = self.corrupt.len() + self.missing.len();
+        broken == self.repaired.len()
+    }
+
+    fn is_repaired(&self, hash: &str) -> bool {
+        self.repaired.iter().any(|r| r.hash == hash)
+    }
+
+    /// Corrupt blobs that were not repaired
+    pub fn unrepaired_corrupt(&self) -> impl Iterator<Item = &CorruptObject> {
+        self.corrupt.iter().filter(|c| !self.is_repaired(&c.hash))
+    }
+
+    /// Missing blobs that were not repaired
+    pub fn unrepaired_missing(&self) -> impl Iterator<Item = &MissingObject> {
+        self.missing.iter().filter(|m| !self.is_repaired(&m.hash))
+    }
+}
+
+/// Check every blob in a project store
+///
+/// With `repair`, corrupt and missing blobs are rewritten from a good copy
+/// when one can be found. Encrypted blobs can only be rewritten with the
+/// password or recipients.
+pub fn fsck(
+    config: &Config,
+    project_name: &str,
+    keys: &Keyring,
+    repair: bool,
+) -> anyhow::Result<FsckReport> {
+    let store_dir = config.project_store_dir(project_name)?;
+    crate::metadata::unlock_index(config, project_name, keys)?;
+    let (referenced, revisions) = store::referenced_objects(config, project_name, None)?;
+    // Without the key, keyed blobs are still checked by decrypting them
+    let key = ObjectKey::load(config, project_name, keys).unwrap_or(None);
+    let moved = store::moved_objects(config, project_name, &referenced)?;
+
+    let mut report = FsckReport {
+        revisions,
+        ..FsckReport::default()
+    };
+
+    for object in store::stored_objects(&store_dir)? {
+        if !referenced.contains_key(&object.hash) && !moved.contains(&object.hash) {
+            report.orphans.push(object);
+        }
+    }
+
+    let mut hashes: Vec<&String> = referenced.keys().collect();
+    hashes.sort();
+
+    for hash in hashes {
+        let refs = &referenced[hash];
+        let located = store::locate(&store_dir, hash)?;
+        if let Some(id) = &located {
+            let blob = BufReader::new(File::open(hash_to_path(&store_dir, id))?);
+            match check_blob(blob, hash, keys, key.as_ref()) {
+                Check::Ok => {
+                    report.verified += 1;
+                    continue;
+                }
+                Check::Skipped => {
+                    report.skipped_encrypted += 1;
+                    continue;
+                }
+                Check::Corrupt(reason) => report.corrupt.push(CorruptObject {
+                    hash: hash.clone(),
+                    reason,
+                }),
+            }
+        } else {
+            report.missing.push(MissingObject {
+                hash: hash.clone(),
+                paths: refs.iter().map(|r| r.path.clone()).collect(),
+            });
+        }
+
+        if repair {
+            // A blob moved to a keyed id is rewritten there
+            let id = located.as_deref().unwrap_or(hash);
+            let source = repair_object(config, project_name, hash, id, refs, keys, key.as_ref())?;
+            if let Some(source) = source {
+                report.repaired.push(RepairedObject {
+                    hash: hash.clone(),
+                    source,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+enum Check {
+    Ok,
+    /// Encrypted and no password or identity to verify it
+    Skipped,
+    Corrupt(String),
+}
+
+/// Compare a blob's plaintext with the hash or keyed id it is stored under
+///
+/// The blob is streamed, so large ones aren't loaded whole. Encrypted blobs
+/// are authenticated by age, so without the object key a blob that decrypts
+/// is taken as intact.
+fn check_blob<R: BufRead>(
+    mut blob: R,
+    hash: &str,
+    keys: &Keyring,
+    key: Option<&ObjectKey>,
+) -> Check {
+    let header = match blob.fill_buf() {
+        Ok(header) => header.to_vec(),
+        Err(e) => return Check::Corrupt(format!("cannot read: {}", e)),
+    };
+    if !is_encrypted_blob(&header) {
+        return match hash_reader(blob) {
+            Ok(actual) if actual == hash => Check::Ok,
+            Ok(actual) => Check::Corrupt(format!("content hashes to {}", &actual[..12])),
+            Err(e) => Check::Corrupt(format!("cannot read: {}", e)),
+        };
+    }
+
+    if !keys.can_decrypt(&header) {
+        return Check::Skipped;
+    }
+    let actual = match keys.decrypt_from(blob).and_then(|r| Ok(hash_reader(r)?)) {
+        Ok(actual) => actual,
+        Err(e) => return Check::Corrupt(format!("cannot decrypt: {}", e)),
+    };
+    match key {
+        _ if actual == hash => Check::Ok,
+        Some(key) if key.object_id(&actual) != hash => {
+            Check::Corrupt("content does not match its id".to_string())
+        }
+        _ => Check::Ok,
+    }
+}
+
+/// Rewrite a blob from the first good copy, returning where it came from
+///
+/// The blob is written under `id`, which differs from `hash` for a
+/// plaintext blob moved to a keyed id.
+fn repair_object(
+    config: &Config,
+    project_name: &str,
+    hash: &str,
+    id: &str,
+    refs: &[ObjectRef],
+    keys: &Keyring,
+    key: Option<&ObjectKey>,
+) -> anyhow::Result<Option<RepairSource>> {
+    // Anything once recorded or moved as encrypted must not be stored in
+    // the clear
+    let encrypted = id != hash || refs.iter().any(|r| r.encrypted);
+    if encrypted && !keys.can_encrypt() {
+        return Ok(None);
+    }
+    let keyed = refs.iter().any(|r| r.keyed);
+    if keyed && key.is_none() {
+        return Ok(None);
+    }
+    let store_dir = config.project_store_dir(project_name)?;
+
+    for r in refs {
+        let local = remap::localize(&config.path_remap, &r.path);
+        if let Ok(content) = fs::read(&local) {
+            let content_hash = hash_bytes(&content);
+            let content_id = match key {
+                Some(key) if keyed => key.object_id(&content_hash),
+                _ => content_hash,
+            };
+            if content_id == hash {
+                let keys = if encrypted { Some(keys) } else { None };
+                store::write_object(&store_dir, id, &content, keys)?;
+                return Ok(Some(RepairSource::LocalFile(local)));
+            }
+        }
+    }
+
+    // Encrypted files are never mirrored
+    if refs.iter().any(|r| r.encrypted) {
+        return Ok(None);
+    }
+
+    let project_dir = config.project_dir(project_name)?;
+    let mirror_dir = config.project_mirror_dir(project_name)?;
+    let mirror_rel = mirror_dir.strip_prefix(&project_dir).unwrap_or(Path::new("files"));
+
+    for r in refs {
+        let Some(revision) = &r.revision else {
+            continue;
+        };
+        let local = remap::localize(&config.path_remap, &r.path);
+        let path = mirror::mirror_path(mirror_rel, &local)
+            .to_string_lossy()
+            .to_string();
+        if let Some(content) = git::show_file_bytes(&project_dir, revision, &path)? {
+            if hash_bytes(&content) == hash {
+                let keys = if encrypted { Some(keys) } else { None };
+                store::write_object(&store_dir, id, &content, keys)?;
+                return Ok(Some(RepairSource::History {
+                    revision: revision.clone(),
+                    path,
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::SecretString;
+
+    #[test]
+    fn test_check_blob() {
+        let hash = hash_bytes(b"set number\n");
+
+        let none = Keyring::default();
+        assert!(matches!(check_blob(&b"set number\n"[..], &hash, &none, None), Check::Ok));
+        assert!(matches!(check_blob(&b"set numb"[..], &hash, &none, None), Check::Corrupt(_)));
+
+        let keys = Keyring::from_password(SecretString::from("hunter2".to_string()));
+        let encrypted = keys.encrypt(b"set number\n").unwrap();
+        assert!(matches!(check_blob(&encrypted[..], &hash, &none, None), Check::Skipped));
+        assert!(matches!(check_blob(&encrypted[..], &hash, &keys, None), Check::Ok));
+
+        let key = ObjectKey::generate();
+        let id = key.object_id(&hash);
+        assert!(matches!(check_blob(&encrypted[..], &id, &keys, Some(&key)), Check::Ok));
+        let other = key.object_id(&hash_bytes(b"other"));
+        assert!(matches!(
+            check_blob(&encrypted[..], &other, &keys, Some(&key)),
+            Check::Corrupt(_)
+        ));
+    }
+}
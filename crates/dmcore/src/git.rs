@@ -2,11 +2,20 @@
 //!
 //! Handles git initialization, commits, and status for the data directory.
 
+use std::collections::HashMap;
 use std::path::Path;
 use std::process::Command;
 
 use crate::config::Config;
 
+pub use crate::changes::{commit_diff, commit_diffs, index_diff, ChangeKind, FileChange, IndexDiff};
+
+/// The branch shared by all machines
+pub const MAIN_BRANCH: &str = "main";
+
+/// Prefix of per-machine branches
+pub const HOST_BRANCH_PREFIX: &str = "host/";
+
 /// Check if a directory is a git repository
 pub fn is_git_repo(dir: &Path) -> bool {
     dir.join(".git").exists()
@@ -22,7 +31,7 @@ pub fn init_repo(dir: &Path) -> anyhow::Result<()> {
 
     // Initialize with 'main' as the default branch
     let output = Command::new("git")
-        .args(["init", "-b", "main"])
+        .args(["init", "-b", MAIN_BRANCH])
         .current_dir(dir)
         .output()?;
 
@@ -33,9 +42,12 @@ pub fn init_repo(dir: &Path) -> anyhow::Result<()> {
         );
     }
 
-    // Create .gitignore for restore-backups
+    // Create .gitignore for restore-backups and the local object key cache
     let gitignore = dir.join(".gitignore");
-    std::fs::write(&gitignore, "restore-backups/\n")?;
+    std::fs::write(
+        &gitignore,
+        format!("restore-backups/\n{}\n", crate::objectid::OBJECT_KEY_CACHE),
+    )?;
 
     // Initial commit
     stage_all(dir)?;
@@ -44,6 +56,59 @@ pub fn init_repo(dir: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Add a pattern to a repo's `.gitignore` if it isn't listed yet
+pub fn ensure_ignored(dir: &Path, pattern: &str) -> anyhow::Result<()> {
+    let gitignore = dir.join(".gitignore");
+    let mut contents = std::fs::read_to_string(&gitignore).unwrap_or_default();
+    if contents.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
+
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(pattern);
+    contents.push('\n');
+    std::fs::write(&gitignore, contents)?;
+    Ok(())
+}
+
+/// Remove a pattern from a repo's `.gitignore`
+pub fn remove_ignored(dir: &Path, pattern: &str) -> anyhow::Result<()> {
+    let gitignore = dir.join(".gitignore");
+    let Ok(contents) = std::fs::read_to_string(&gitignore) else {
+        return Ok(());
+    };
+    if !contents.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
+
+    let kept: String = contents
+        .lines()
+        .filter(|line| line.trim() != pattern)
+        .map(|line| format!("{}\n", line))
+        .collect();
+    std::fs::write(&gitignore, kept)?;
+    Ok(())
+}
+
+/// Stop tracking a file, leaving it in the working tree
+pub fn untrack(dir: &Path, path: &str) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .args(["rm", "--cached", "--quiet", "--ignore-unmatch", "--", path])
+        .current_dir(dir)
+        .output()?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to untrack {}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(())
+}
+
 /// Stage all changes
 pub fn stage_all(dir: &Path) -> anyhow::Result<()> {
     let output = Command::new("git")
@@ -172,6 +237,212 @@ pub fn recent_commits(dir: &Path, limit: usize) -> anyhow::Result<Vec<CommitInfo
     Ok(commits)
 }
 
+/// Resolve a commit hash, tag or branch to a full commit hash
+pub fn resolve_revision(dir: &Path, rev: &str) -> anyhow::Result<String> {
+    if !is_git_repo(dir) {
+        anyhow::bail!("Not a git repository");
+    }
+
+    let output = Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .current_dir(dir)
+        .output()?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        anyhow::bail!("Unknown revision '{}'", rev);
+    }
+}
+
+/// Find the last commit made at or before a date
+///
+/// Accepts anything `git log --before` does ("2026-05-01 18:00",
+/// "2 weeks ago"). A bare `YYYY-MM-DD` date covers that whole day.
+pub fn commit_as_of(dir: &Path, date: &str) -> anyhow::Result<String> {
+    if !is_git_repo(dir) {
+        anyhow::bail!("Not a git repository");
+    }
+
+    let before = if chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() {
+        format!("{} 23:59:59", date)
+    } else {
+        date.to_string()
+    };
+
+    let output = Command::new("git")
+        .args(["rev-list", "-1", &format!("--before={}", before), "HEAD"])
+        .current_dir(dir)
+        .output()?;
+
+    let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || hash.is_empty() {
+        anyhow::bail!("No commit found as of '{}'", date);
+    }
+    Ok(hash)
+}
+
+/// Get commit details for a revision
+pub fn commit_info(dir: &Path, rev: &str) -> anyhow::Result<CommitInfo> {
+    let output = Command::new("git")
+        .args(["log", "-1", "--format=%H|%h|%ai|%s", rev])
+        .current_dir(dir)
+        .output()?;
+
+    if !output.status.success() {
+        anyhow::bail!("Unknown revision '{}'", rev);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let parts: Vec<&str> = stdout.trim_end().splitn(4, '|').collect();
+    if parts.len() < 4 {
+        anyhow::bail!("Unexpected git log output for '{}'", rev);
+    }
+
+    Ok(CommitInfo {
+        hash: parts[0].to_string(),
+        short_hash: parts[1].to_string(),
+        date: parts[2].to_string(),
+        message: parts[3].to_string(),
+    })
+}
+
+/// Read a file from the repository as of a revision
+///
+/// Returns `None` if the file did not exist at that revision.
+pub fn show_file(dir: &Path, rev: &str, path: &str) -> anyhow::Result<Option<String>> {
+    Ok(show_file_bytes(dir, rev, path)?.map(|bytes| String::from_utf8_lossy(&bytes).to_string()))
+}
+
+/// Raw content of a file at a revision, or `None` if it doesn't exist there
+pub fn show_file_bytes(dir: &Path, rev: &str, path: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let output = Command::new("git")
+        .args(["show", &format!("{}:{}", rev, path)])
+        .current_dir(dir)
+        .output()?;
+
+    if output.status.success() {
+        return Ok(Some(output.stdout));
+    }
+    // Only a path the revision doesn't have is `None`; a bad revision or a
+    // broken repo is an error
+    if resolve_revision(dir, rev).is_ok() && !path_exists_at(dir, rev, path)? {
+        return Ok(None);
+    }
+    anyhow::bail!(
+        "Failed to read {} at {}: {}",
+        path,
+        rev,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+}
+
+/// List commits on any ref that touched `path`, newest first
+///
+/// With `since`, only commits after that date are listed, plus the last one
+/// before it (which still describes the state at the start of the window).
+pub fn revisions_touching(dir: &Path, path: &str, since: Option<&str>) -> anyhow::Result<Vec<String>> {
+    revisions_touching_any(dir, &[path], since)
+}
+
+/// List commits on any ref that touched any of `paths`, newest first
+///
+/// See [`revisions_touching`] for `since`.
+pub fn revisions_touching_any(dir: &Path, paths: &[&str], since: Option<&str>) -> anyhow::Result<Vec<String>> {
+    if !is_git_repo(dir) {
+        return Ok(Vec::new());
+    }
+
+    let rev_list = |extra: &[String]| -> anyhow::Result<Vec<String>> {
+        let output = Command::new("git")
+            .arg("rev-list")
+            .args(extra)
+            .args(["--all", "--"])
+            .args(paths)
+            .current_dir(dir)
+            .output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "git rev-list failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    };
+
+    match since {
+        None => rev_list(&[]),
+        Some(date) => {
+            let mut revs = rev_list(&[format!("--since={}", date)])?;
+            revs.extend(rev_list(&["-1".to_string(), format!("--before={}", date)])?);
+            Ok(revs)
+        }
+    }
+}
+
+/// List the commits of the current branch that touched any of `paths`,
+/// newest first
+///
+/// Only first parents are followed, so commits merged in from other hosts'
+/// branches are left out; their changes show up at the merge commit.
+pub fn commits_touching(dir: &Path, paths: &[&str]) -> anyhow::Result<Vec<CommitInfo>> {
+    if !is_git_repo(dir) {
+        return Ok(Vec::new());
+    }
+
+    let output = Command::new("git")
+        .args(["log", "--first-parent", "--format=%H|%h|%ai|%s", "HEAD", "--"])
+        .args(paths)
+        .current_dir(dir)
+        .output()?;
+
+    // A repo without commits has no history
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let commits = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.splitn(4, '|').collect();
+            (parts.len() == 4).then(|| CommitInfo {
+                hash: parts[0].to_string(),
+                short_hash: parts[1].to_string(),
+                date: parts[2].to_string(),
+                message: parts[3].to_string(),
+            })
+        })
+        .collect();
+
+    Ok(commits)
+}
+
+/// Map each commit to its first parent, `None` for root commits
+pub fn first_parents(dir: &Path, commits: &[&str]) -> anyhow::Result<HashMap<String, Option<String>>> {
+    let output = Command::new("git")
+        .args(["rev-list", "--no-walk=unsorted", "--parents"])
+        .args(commits)
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git rev-list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let parents = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut hashes = line.split_whitespace();
+            Some((hashes.next()?.to_string(), hashes.next().map(str::to_string)))
+        })
+        .collect();
+    Ok(parents)
+}
+
 /// Information about a git commit
 #[derive(Debug, Clone)]
 pub struct CommitInfo {
@@ -181,6 +452,126 @@ pub struct CommitInfo {
     pub date: String,
 }
 
+/// A named snapshot of a project repo
+#[derive(Debug, Clone)]
+pub struct TagInfo {
+    pub name: String,
+    /// Full hash of the tagged commit
+    pub commit: String,
+    /// When the tag was made (the commit date for lightweight tags)
+    pub date: String,
+    pub message: String,
+}
+
+/// Tag a revision (the latest commit by default) as a named snapshot
+///
+/// Tags are annotated so they carry their own date and message, and `push`
+/// sends them along.
+pub fn create_tag(dir: &Path, name: &str, rev: Option<&str>, message: Option<&str>) -> anyhow::Result<TagInfo> {
+    if !is_git_repo(dir) {
+        anyhow::bail!("Not a git repository");
+    }
+    let valid = Command::new("git")
+        .args(["check-ref-format", &format!("refs/tags/{}", name)])
+        .current_dir(dir)
+        .status()?
+        .success();
+    if !valid || name.starts_with('-') {
+        anyhow::bail!("Invalid tag name '{}'", name);
+    }
+    if resolve_revision(dir, &format!("refs/tags/{}", name)).is_ok() {
+        anyhow::bail!("Tag '{}' already exists", name);
+    }
+    let commit = resolve_revision(dir, rev.unwrap_or("HEAD"))?;
+
+    let message = message.map_or_else(|| format!("Snapshot {}", name), str::to_string);
+    let output = Command::new("git")
+        .args(["tag", "-a", name, "-m", &message, &commit])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to create tag: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    list_tags(dir)?
+        .into_iter()
+        .find(|tag| tag.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Tag '{}' was not created", name))
+}
+
+/// List a repo's tags, newest first
+pub fn list_tags(dir: &Path) -> anyhow::Result<Vec<TagInfo>> {
+    if !is_git_repo(dir) {
+        return Ok(Vec::new());
+    }
+
+    let output = Command::new("git")
+        .args([
+            "for-each-ref",
+            "--sort=-creatordate",
+            "--format=%(refname:short)|%(objectname)|%(*objectname)|%(creatordate:iso)|%(contents:subject)",
+            "refs/tags",
+        ])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to list tags: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let tags = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.splitn(5, '|').collect();
+            (parts.len() == 5).then(|| TagInfo {
+                name: parts[0].to_string(),
+                // Annotated tags point at a tag object, which points at the commit
+                commit: if parts[2].is_empty() { parts[1] } else { parts[2] }.to_string(),
+                date: parts[3].to_string(),
+                message: parts[4].to_string(),
+            })
+        })
+        .collect();
+    Ok(tags)
+}
+
+/// Tag names by the commit they point at
+pub fn tags_by_commit(dir: &Path) -> anyhow::Result<HashMap<String, Vec<String>>> {
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for tag in list_tags(dir)? {
+        tags.entry(tag.commit).or_default().push(tag.name);
+    }
+    Ok(tags)
+}
+
+/// Resolve a tag to the commit it points at
+pub fn resolve_tag(dir: &Path, name: &str) -> anyhow::Result<String> {
+    resolve_revision(dir, &format!("refs/tags/{}", name)).map_err(|_| anyhow::anyhow!("No tag '{}'", name))
+}
+
+/// Delete a tag from the local repo
+///
+/// A tag that was already pushed stays on the remote.
+pub fn delete_tag(dir: &Path, name: &str) -> anyhow::Result<()> {
+    resolve_tag(dir, name)?;
+    let output = Command::new("git")
+        .args(["tag", "-d", name])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to delete tag: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
 /// Get the configured remote URL
 pub fn get_remote_url(dir: &Path) -> anyhow::Result<Option<String>> {
     if !is_git_repo(dir) {
@@ -245,29 +636,63 @@ pub fn set_remote_url(dir: &Path, url: &str) -> anyhow::Result<()> {
 }
 
 /// Pull from remote
+///
+/// Fetches and fast-forwards to the upstream branch. A branch with commits
+/// of its own is left alone, since its index has to be merged file by file
+/// (see `merge::pull_project`); nothing is ever rebased.
 pub fn pull(dir: &Path) -> anyhow::Result<String> {
     if !is_git_repo(dir) {
         anyhow::bail!("Not a git repository");
     }
 
+    abort_rebase(dir)?;
+    fetch(dir)?;
+    let Some(upstream) = upstream_branch(dir)? else {
+        anyhow::bail!("Pull failed: the current branch has no upstream; push it first");
+    };
+
+    if is_ancestor(dir, &upstream, "HEAD")? {
+        Ok("Already up to date".to_string())
+    } else if is_ancestor(dir, "HEAD", &upstream)? {
+        merge_fast_forward(dir, &upstream)?;
+        Ok("Pull successful".to_string())
+    } else {
+        anyhow::bail!("Pull failed: local commits and {} have diverged and need a merge", upstream);
+    }
+}
+
+/// The upstream of the current branch (like `origin/main`), if it has one
+pub fn upstream_branch(dir: &Path) -> anyhow::Result<Option<String>> {
     let output = Command::new("git")
-        .args(["pull", "--rebase"])
+        .args(["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{upstream}"])
         .current_dir(dir)
         .output()?;
+    let upstream = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.status.success() && !upstream.is_empty()).then_some(upstream))
+}
 
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        if stdout.contains("Already up to date") || stdout.contains("Already up-to-date") {
-            Ok("Already up to date".to_string())
-        } else {
-            Ok("Pull successful".to_string())
-        }
-    } else {
+/// Check if a rebase was left unfinished, like by a conflicting
+/// `git pull --rebase`
+pub fn rebase_in_progress(dir: &Path) -> bool {
+    dir.join(".git/rebase-merge").exists() || dir.join(".git/rebase-apply").exists()
+}
+
+/// Give up an unfinished rebase, restoring the branch as it was before
+pub fn abort_rebase(dir: &Path) -> anyhow::Result<()> {
+    if !rebase_in_progress(dir) {
+        return Ok(());
+    }
+    let output = Command::new("git")
+        .args(["rebase", "--abort"])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
         anyhow::bail!(
-            "Pull failed: {}",
-            String::from_utf8_lossy(&output.stderr)
+            "Failed to abort the unfinished rebase: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
         );
     }
+    Ok(())
 }
 
 /// Remote repository status
@@ -382,9 +807,31 @@ pub fn push(dir: &Path) -> anyhow::Result<String> {
         anyhow::bail!("Not a git repository");
     }
 
-    // First try regular push
+    let pushed = push_branch(dir)?;
+
+    // A host branch shares its merges through main
+    let on_host_branch = current_branch(dir)?.is_some_and(|b| b.starts_with(HOST_BRANCH_PREFIX));
+    if on_host_branch && branch_exists(dir, MAIN_BRANCH) {
+        let output = Command::new("git")
+            .args(["push", "--follow-tags", "origin", MAIN_BRANCH])
+            .current_dir(dir)
+            .output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Pushed this machine's branch, but not {}; merge first: {}",
+                MAIN_BRANCH,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+    }
+    Ok(pushed)
+}
+
+/// Push the current branch, setting its upstream on the first push
+fn push_branch(dir: &Path) -> anyhow::Result<String> {
+    // First try regular push, sending tags on pushed commits along
     let output = Command::new("git")
-        .args(["push"])
+        .args(["push", "--follow-tags"])
         .current_dir(dir)
         .output()?;
 
@@ -400,7 +847,7 @@ pub fn push(dir: &Path) -> anyhow::Result<String> {
         // Check if we need to set upstream
         if stderr.contains("no upstream branch") || stderr.contains("has no upstream") {
             let output = Command::new("git")
-                .args(["push", "-u", "origin", "HEAD"])
+                .args(["push", "--follow-tags", "-u", "origin", "HEAD"])
                 .current_dir(dir)
                 .output()?;
 
@@ -418,6 +865,253 @@ pub fn push(dir: &Path) -> anyhow::Result<String> {
     }
 }
 
+/// Name of the checked-out branch, or `None` on a detached HEAD
+pub fn current_branch(dir: &Path) -> anyhow::Result<Option<String>> {
+    let output = Command::new("git")
+        .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .current_dir(dir)
+        .output()?;
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.status.success() && !branch.is_empty()).then_some(branch))
+}
+
+/// Check if a local branch exists
+pub fn branch_exists(dir: &Path, name: &str) -> bool {
+    resolve_revision(dir, &format!("refs/heads/{}", name)).is_ok()
+}
+
+/// Check out a branch, creating it at HEAD if it doesn't exist yet
+pub fn switch_branch(dir: &Path, name: &str) -> anyhow::Result<()> {
+    if current_branch(dir)?.as_deref() == Some(name) {
+        return Ok(());
+    }
+
+    let args: &[&str] = if branch_exists(dir, name) {
+        &["checkout", "--quiet", name]
+    } else {
+        &["checkout", "--quiet", "-b", name]
+    };
+    let output = Command::new("git").args(args).current_dir(dir).output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to switch to branch {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Point a local branch at a commit
+pub fn set_branch(dir: &Path, name: &str, commit: &str) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .args(["branch", "--force", name, commit])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to move branch {}: {}",
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// The best common ancestor of two revisions, if they share history
+pub fn merge_base(dir: &Path, a: &str, b: &str) -> anyhow::Result<Option<String>> {
+    let output = Command::new("git")
+        .args(["merge-base", a, b])
+        .current_dir(dir)
+        .output()?;
+    let base = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.status.success() && !base.is_empty()).then_some(base))
+}
+
+/// Check if `ancestor` is in the history of `rev`
+pub fn is_ancestor(dir: &Path, ancestor: &str, rev: &str) -> anyhow::Result<bool> {
+    let status = Command::new("git")
+        .args(["merge-base", "--is-ancestor", ancestor, rev])
+        .current_dir(dir)
+        .status()?;
+    Ok(status.success())
+}
+
+/// Check if the working tree has no uncommitted changes (ignored files
+/// aside)
+pub fn is_clean(dir: &Path) -> anyhow::Result<bool> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git status failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout.is_empty())
+}
+
+/// Start merging a revision without committing
+///
+/// Conflicts are left in the index and working tree for the caller to
+/// resolve; other failures are errors and leave nothing behind.
+pub fn merge_no_commit(dir: &Path, rev: &str) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .args(["merge", "--no-ff", "--no-commit", "--no-edit", rev])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() && !dir.join(".git/MERGE_HEAD").exists() {
+        anyhow::bail!(
+            "Merge failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Fast-forward the current branch to a revision
+pub fn merge_fast_forward(dir: &Path, rev: &str) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .args(["merge", "--ff-only", "--quiet", rev])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Fast-forward failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Commit a merge in progress, even if it changes nothing
+pub fn commit_merge(dir: &Path, message: &str) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .args(["commit", "--quiet", "--no-edit", "-m", message])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to commit merge: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Give up a merge in progress, restoring the pre-merge state
+pub fn abort_merge(dir: &Path) -> anyhow::Result<()> {
+    if !dir.join(".git/MERGE_HEAD").exists() {
+        return Ok(());
+    }
+    let output = Command::new("git")
+        .args(["merge", "--abort"])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to abort merge: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Paths with unresolved conflicts
+pub fn unmerged_paths(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let output = Command::new("git")
+        .args(["diff", "--name-only", "--diff-filter=U"])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Check if a revision has a file
+pub fn path_exists_at(dir: &Path, rev: &str, path: &str) -> anyhow::Result<bool> {
+    let output = Command::new("git")
+        .args(["cat-file", "-e", &format!("{}:{}", rev, path)])
+        .current_dir(dir)
+        .output()?;
+    Ok(output.status.success())
+}
+
+/// Set a path in the working tree and index to its content at a revision,
+/// or remove it if the revision doesn't have it
+pub fn take_path(dir: &Path, rev: &str, path: &str) -> anyhow::Result<()> {
+    let output = if path_exists_at(dir, rev, path)? {
+        Command::new("git")
+            .args(["checkout", rev, "--", path])
+            .current_dir(dir)
+            .output()?
+    } else {
+        Command::new("git")
+            .args(["rm", "--quiet", "--force", "--ignore-unmatch", "--", path])
+            .current_dir(dir)
+            .output()?
+    };
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to take {} from {}: {}",
+            path,
+            rev,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Replace a repo's history with a single commit of its current content
+///
+/// Every local branch is pointed at the new root commit, tags and
+/// remote-tracking branches are deleted, and everything only they reached
+/// is pruned, so no earlier commit survives in this clone. The remote keeps
+/// the old history until every branch is force-pushed, and other clones
+/// keep theirs. Returns the new commit.
+pub fn purge_history(dir: &Path, message: &str) -> anyhow::Result<String> {
+    if dir.join(".git/MERGE_HEAD").exists() || rebase_in_progress(dir) {
+        anyhow::bail!("Finish or abort the merge in progress before purging history");
+    }
+    stage_all(dir)?;
+
+    let run = |args: &[&str]| -> anyhow::Result<String> {
+        let output = Command::new("git").args(args).current_dir(dir).output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to purge history (git {}): {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    let tree = run(&["write-tree"])?;
+    let commit = run(&["commit-tree", &tree, "-m", message])?;
+    let refs = run(&["for-each-ref", "--format=%(refname)", "refs/heads", "refs/tags", "refs/remotes"])?;
+    for name in refs.lines() {
+        if name.starts_with("refs/heads/") {
+            run(&["update-ref", name, &commit])?;
+        } else {
+            run(&["update-ref", "--no-deref", "-d", name])?;
+        }
+    }
+
+    run(&["reflog", "expire", "--expire=now", "--all"])?;
+    run(&["gc", "--quiet", "--prune=now"])?;
+    Ok(commit)
+}
+
 /// Initialize a git repository for a specific project
 ///
 /// Creates the project directory structure and initializes git if needed.
@@ -432,3 +1126,101 @@ pub fn init_project_repo(config: &Config, project_name: &str) -> anyhow::Result<
 
     Ok(project_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{FileEntry, Index};
+    use crate::testutil::TempData;
+    use std::path::PathBuf;
+
+    /// Commit an index holding one entry with `hash`, returning its full hash
+    fn commit_index(data: &TempData, hash: &str) -> String {
+        let project_dir = data.config.project_dir("p").unwrap();
+        let mut index = Index::new();
+        index.upsert(PathBuf::from("/h/vimrc"), FileEntry::new(hash.to_string(), 1, 0));
+        index.save_for_project(&data.config, "p").unwrap();
+        stage_all(&project_dir).unwrap();
+        commit(&project_dir, &format!("index {}", hash)).unwrap();
+        resolve_revision(&project_dir, "HEAD").unwrap()
+    }
+
+    #[test]
+    fn test_tags() {
+        let data = TempData::new("tags");
+        let dir = data.project("p");
+        let first = commit_index(&data, "a");
+        let second = commit_index(&data, "b");
+
+        assert!(create_tag(&dir, "bad..name", None, None).is_err());
+        assert!(create_tag(&dir, "-v1", None, None).is_err());
+
+        let tag = create_tag(&dir, "before-upgrade", Some(&first), Some("known good")).unwrap();
+        assert_eq!((tag.commit.as_str(), tag.message.as_str()), (first.as_str(), "known good"));
+        assert!(create_tag(&dir, "before-upgrade", None, None).is_err());
+        let tag = create_tag(&dir, "latest", None, None).unwrap();
+        assert_eq!((tag.commit.as_str(), tag.message.as_str()), (second.as_str(), "Snapshot latest"));
+
+        // Annotated tags resolve to the commit, not the tag object
+        assert_eq!(resolve_tag(&dir, "before-upgrade").unwrap(), first);
+        assert_eq!(resolve_revision(&dir, "before-upgrade").unwrap(), first);
+        assert_eq!(tags_by_commit(&dir).unwrap()[&first], vec!["before-upgrade"]);
+        let restored = Index::load_at_revision(&data.config, "p", "before-upgrade").unwrap();
+        assert_eq!(restored.get(&PathBuf::from("/h/vimrc")).unwrap().hash, "a");
+
+        delete_tag(&dir, "before-upgrade").unwrap();
+        assert!(resolve_tag(&dir, "before-upgrade").is_err());
+        assert!(delete_tag(&dir, "before-upgrade").is_err());
+        let names: Vec<String> = list_tags(&dir).unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["latest"]);
+    }
+
+    #[test]
+    fn test_resolve_revision_and_show_file() {
+        let data = TempData::new("resolve");
+        let dir = data.project("p");
+        let first = commit_index(&data, "a");
+        let second = commit_index(&data, "b");
+
+        assert_eq!(resolve_revision(&dir, "HEAD").unwrap(), second);
+        assert_eq!(resolve_revision(&dir, &first[..7]).unwrap(), first);
+        assert_eq!(resolve_revision(&dir, MAIN_BRANCH).unwrap(), second);
+        assert_eq!(resolve_revision(&dir, "HEAD~1").unwrap(), first);
+        assert!(resolve_revision(&dir, "no-such-rev").is_err());
+        assert!(resolve_revision(&dir, "HEAD^{tree}").is_err());
+        assert!(resolve_revision(&data.dir, "HEAD").is_err());
+
+        // A missing path is None, a bad revision an error
+        assert!(show_file(&dir, &first, "index.json").unwrap().unwrap().contains("\"a\""));
+        assert!(show_file_bytes(&dir, &first, "missing.json").unwrap().is_none());
+        assert!(show_file_bytes(&dir, "no-such-rev", "index.json").is_err());
+        assert!(show_file_bytes(&dir, &format!("{}~5", first), "index.json").is_err());
+    }
+
+    #[test]
+    fn test_commit_as_of() {
+        let data = TempData::new("as-of");
+        let dir = data.project("p");
+        let commit_at = |date: &str, message: &str| {
+            let status = Command::new("git")
+                .args(["commit", "--quiet", "--allow-empty", "-m", message])
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date)
+                .current_dir(&dir)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            resolve_revision(&dir, "HEAD").unwrap()
+        };
+        let may = commit_at("2024-05-01T12:00:00", "may");
+        let june = commit_at("2024-06-01T09:00:00", "june");
+
+        // A bare date covers the whole day
+        assert_eq!(commit_as_of(&dir, "2024-05-01").unwrap(), may);
+        assert_eq!(commit_as_of(&dir, "2024-05-31").unwrap(), may);
+        assert_eq!(commit_as_of(&dir, "2024-06-01 08:00").unwrap(), may);
+        assert_eq!(commit_as_of(&dir, "2024-06-01").unwrap(), june);
+        assert!(commit_as_of(&dir, "2024-04-30").is_err());
+        assert!(commit_as_of(&data.dir, "2024-06-01").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Version history of single files
+//!
+//! Every backup commit records the project index, so the versions of a file
+//! are the distinct entries it has across the project's `index.json` (or
+//! `index.json.age`) history. Their content is read back from `store/`,
+//! which keeps every blob until `store gc` drops history older than
+//! `--keep-since`.
+
+use std::path::Path;
+
+use crate::config::{contract_path, Config};
+use crate::crypto::Keyring;
+use crate::diff;
+use crate::git::{self, CommitInfo};
+use crate::index::FileEntry;
+use crate::metadata::{self, INDEX_FILE, SEALED_INDEX_FILE};
+use crate::store;
+
+/// One version of a file, as first recorded by a commit
+#[derive(Debug, Clone)]
+pub struct FileVersion {
+    /// The backup commit that recorded this version
+    pub commit: CommitInfo,
+    /// Content hash, or keyed object id for encrypted files
+    pub hash: String,
+    pub size: u64,
+    /// Modification time of the file when backed up (Unix epoch seconds)
+    pub modified: u64,
+    /// Whether the blob is stored encrypted
+    pub encrypted: bool,
+    /// Whether `hash` is a keyed object id
+    pub keyed: bool,
+}
+
+impl FileVersion {
+    fn new(commit: CommitInfo, entry: &FileEntry) -> Self {
+        Self {
+            commit,
+            hash: entry.hash.clone(),
+            size: entry.size,
+            modified: entry.modified,
+            encrypted: entry.encrypted,
+            keyed: entry.keyed,
+        }
+    }
+}
+
+/// List the distinct versions of a file in a project's history, newest first
+///
+/// `path` is the local absolute path; paths recorded on other machines are
+/// mapped with `path_remap` first. A version is listed at the commit where
+/// its content first appeared, so commits that left the file alone are
+/// skipped. A file that was dropped and re-added starts a new version even
+/// if the content is unchanged.
+pub fn file_history(config: &Config, project_name: &str, path: &Path) -> anyhow::Result<Vec<FileVersion>> {
+    let project_dir = config.project_dir(project_name)?;
+    let commits = git::commits_touching(&project_dir, &[INDEX_FILE, SEALED_INDEX_FILE])?;
+
+    let mut versions: Vec<FileVersion> = Vec::new();
+    let mut last_hash: Option<String> = None;
+    for commit in commits.into_iter().rev() {
+        let Some(mut index) = metadata::index_at(config, project_name, &commit.hash)? else {
+            continue;
+        };
+        index.localize(&config.path_remap);
+
+        match index.get(&path.to_path_buf()) {
+            Some(entry) if last_hash.as_deref() != Some(entry.hash.as_str()) => {
+                last_hash = Some(entry.hash.clone());
+                versions.push(FileVersion::new(commit, entry));
+            }
+            Some(_) => {}
+            None => last_hash = None,
+        }
+    }
+
+    versions.reverse();
+    Ok(versions)
+}
+
+/// Read the content of a file version from the project store
+pub fn read_version(
+    config: &Config,
+    project_name: &str,
+    version: &FileVersion,
+    keys: &Keyring,
+) -> anyhow::Result<Vec<u8>> {
+    let store_dir = config.project_store_dir(project_name)?;
+    store::read_object(&store_dir, &version.hash, keys, version.encrypted || version.keyed)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Version {} is no longer in the store (removed by gc?)",
+            version.commit.short_hash
+        )
+    })
+}
+
+/// Check if reading a file version needs the password rather than an
+/// identity file
+pub fn version_needs_password(config: &Config, project_name: &str, version: &FileVersion) -> anyhow::Result<bool> {
+    let store_dir = config.project_store_dir(project_name)?;
+    Ok(store::object_needs_password(&store_dir, &version.hash))
+}
+
+/// Unified diff between two versions of a file, empty if their text is the
+/// same
+///
+/// Fails for binary content.
+pub fn diff_versions(
+    config: &Config,
+    project_name: &str,
+    path: &Path,
+    old: &FileVersion,
+    new: &FileVersion,
+    keys: &Keyring,
+) -> anyhow::Result<String> {
+    let old_text = version_text(config, project_name, old, keys)?;
+    let new_text = version_text(config, project_name, new, keys)?;
+    let label = contract_path(path);
+    Ok(diff::unified_diff(
+        &old_text,
+        &new_text,
+        &format!("{}@{}", label, old.commit.short_hash),
+        &format!("{}@{}", label, new.commit.short_hash),
+    ))
+}
+
+/// Content of a version as text, refusing binary files
+pub fn version_text(
+    config: &Config,
+    project_name: &str,
+    version: &FileVersion,
+    keys: &Keyring,
+) -> anyhow::Result<String> {
+    let content = read_version(config, project_name, version, keys)?;
+    if content.contains(&0) {
+        anyhow::bail!("Version {} is a binary file", version.commit.short_hash);
+    }
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::testutil::TempData;
+
+    #[test]
+    fn test_file_history_follows_first_parents() {
+        let data = TempData::new("history");
+        let project_dir = data.project("p");
+        let vimrc = data.dir.join("home/vimrc");
+        let zshrc = data.dir.join("home/zshrc");
+        let save = |entries: &[(&Path, &str)]| {
+            let mut index = Index::new();
+            for (path, hash) in entries {
+                index.upsert(path.to_path_buf(), FileEntry::new(hash.to_string(), 1, 0));
+            }
+            index.save_for_project(&data.config, "p").unwrap();
+            git::stage_all(&project_dir).unwrap();
+        };
+        let record = |entries: &[(&Path, &str)], message: &str| {
+            save(entries);
+            git::commit(&project_dir, message).unwrap();
+        };
+
+        record(&[(&vimrc, "a")], "first");
+        let base = git::current_commit(&project_dir).unwrap().unwrap();
+        let main = git::current_branch(&project_dir).unwrap().unwrap();
+
+        // Another host's edit, merged below keeping only its other file
+        git::set_branch(&project_dir, "laptop", &base).unwrap();
+        git::switch_branch(&project_dir, "laptop").unwrap();
+        record(&[(&vimrc, "b"), (&zshrc, "y")], "laptop");
+        git::switch_branch(&project_dir, &main).unwrap();
+
+        record(&[(&vimrc, "c")], "edited");
+        record(&[(&vimrc, "c"), (&zshrc, "z")], "other file");
+        git::merge_no_commit(&project_dir, "laptop").unwrap();
+        save(&[(&vimrc, "c"), (&zshrc, "y")]);
+        git::commit_merge(&project_dir, "merge laptop").unwrap();
+        record(&[(&zshrc, "y")], "dropped");
+        record(&[(&vimrc, "c"), (&zshrc, "y")], "re-added");
+
+        let versions = file_history(&data.config, "p", &vimrc).unwrap();
+        let listed: Vec<(&str, &str)> = versions
+            .iter()
+            .map(|v| (v.hash.as_str(), v.commit.message.as_str()))
+            .collect();
+        assert_eq!(listed, vec![("c", "re-added"), ("c", "edited"), ("a", "first")]);
+    }
+}
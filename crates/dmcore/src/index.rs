@@ -2,6 +2,14 @@
 //!
 //! The index stores the last known state of each tracked file,
 //! used for drift detection and sync operations.
+//!
+//! Two on-disk formats exist: the current one keyed by `entries`, and the
+//! v1 format keyed by `files` (with `last_modified` instead of `modified`).
+//! `Index::parse` reads either, so old commits stay restorable.
+//!
+//! A project's committed index may also be sealed as `index.json.age`; the
+//! project loaders read it transparently once it is unlocked (see
+//! `metadata`).
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -9,6 +17,9 @@ use std::fs;
 use std::path::PathBuf;
 
 use crate::config::Config;
+use crate::git;
+use crate::metadata;
+use crate::remap::{self, PathRemap};
 
 /// Index of all tracked files and their backup state
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -21,7 +32,7 @@ pub struct Index {
 /// State of a tracked file in the index
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
-    /// SHA256 hash of the file contents
+    /// SHA256 hash of the file contents, or the keyed object id if `keyed`
     pub hash: String,
 
     /// File size in bytes
@@ -41,6 +52,43 @@ pub struct FileEntry {
     /// Whether the file is stored encrypted
     #[serde(default)]
     pub encrypted: bool,
+
+    /// Whether `hash` is a keyed object id rather than the content hash
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keyed: bool,
+}
+
+/// v1 index format (`files` field)
+#[derive(Debug, Deserialize)]
+struct LegacyIndex {
+    #[serde(default)]
+    files: HashMap<PathBuf, LegacyFileEntry>,
+}
+
+/// v1 file entry format
+#[derive(Debug, Deserialize)]
+struct LegacyFileEntry {
+    hash: String,
+    size: u64,
+    #[serde(default)]
+    last_modified: u64,
+    #[serde(default)]
+    encrypted: bool,
+}
+
+impl From<LegacyIndex> for Index {
+    fn from(legacy: LegacyIndex) -> Self {
+        let entries = legacy
+            .files
+            .into_iter()
+            .map(|(path, f)| {
+                let mut entry = FileEntry::new(f.hash, f.size, f.last_modified);
+                entry.encrypted = f.encrypted;
+                (path, entry)
+            })
+            .collect();
+        Self { entries }
+    }
 }
 
 impl Index {
@@ -49,12 +97,58 @@ impl Index {
         Self::default()
     }
 
+    /// Parse index JSON in the current or v1 format
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let index: Self = serde_json::from_str(content)?;
+        if !index.entries.is_empty() {
+            return Ok(index);
+        }
+        match serde_json::from_str::<LegacyIndex>(content) {
+            Ok(legacy) if !legacy.files.is_empty() => Ok(legacy.into()),
+            _ => Ok(index),
+        }
+    }
+
+    /// Rewrite entry paths recorded on another machine to local ones
+    ///
+    /// When several recorded paths map to the same local path, the entry
+    /// with the newest modification time (then backup time) is kept. Returns
+    /// the local paths that had such a collision, sorted.
+    pub fn localize(&mut self, rules: &[PathRemap]) -> Vec<PathBuf> {
+        let mut entries: HashMap<PathBuf, FileEntry> = HashMap::new();
+        let mut collisions = Vec::new();
+
+        // Recorded paths in order, so ties resolve the same way every time
+        let mut recorded: Vec<_> = std::mem::take(&mut self.entries).into_iter().collect();
+        recorded.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, entry) in recorded {
+            let local = remap::localize(rules, &path);
+            match entries.get(&local) {
+                Some(kept) => {
+                    if !collisions.contains(&local) {
+                        collisions.push(local.clone());
+                    }
+                    if (entry.modified, entry.last_backup) > (kept.modified, kept.last_backup) {
+                        entries.insert(local, entry);
+                    }
+                }
+                None => {
+                    entries.insert(local, entry);
+                }
+            }
+        }
+
+        self.entries = entries;
+        collisions.sort();
+        collisions
+    }
+
     /// Load index from the default location
     pub fn load() -> anyhow::Result<Self> {
         let path = Self::index_path()?;
         if path.exists() {
             let contents = std::fs::read_to_string(&path)?;
-            Ok(serde_json::from_str(&contents)?)
+            Self::parse(&contents)
         } else {
             Ok(Self::default())
         }
@@ -120,15 +214,26 @@ impl Index {
 
     /// Load index for a specific project
     pub fn load_for_project(config: &Config, project_name: &str) -> anyhow::Result<Self> {
+        metadata::refresh_index(config, project_name)?;
         let path = config.project_index_path(project_name)?;
         if path.exists() {
             let content = fs::read_to_string(&path)?;
-            Ok(serde_json::from_str(&content)?)
+            Self::parse(&content)
         } else {
             Ok(Self::default())
         }
     }
 
+    /// Load a project's index as it was at a commit, tag or branch
+    pub fn load_at_revision(config: &Config, project_name: &str, rev: &str) -> anyhow::Result<Self> {
+        let project_dir = config.project_dir(project_name)?;
+        let commit = git::resolve_revision(&project_dir, rev)?;
+        match metadata::index_at(config, project_name, &commit)? {
+            Some(index) => Ok(index),
+            None => anyhow::bail!("No index at revision '{}'", rev),
+        }
+    }
+
     /// Save index for a specific project
     pub fn save_for_project(&self, config: &Config, project_name: &str) -> anyhow::Result<()> {
         let path = config.project_index_path(project_name)?;
@@ -151,6 +256,7 @@ impl FileEntry {
             last_sync: None,
             last_backup: None,
             encrypted: false,
+            keyed: false,
         }
     }
 
@@ -163,6 +269,7 @@ impl FileEntry {
             last_sync: Some(chrono::Utc::now()),
             last_backup: None,
             encrypted: false,
+            keyed: false,
         }
     }
 
@@ -175,6 +282,7 @@ impl FileEntry {
             last_sync: Some(chrono::Utc::now()),
             last_backup: None,
             encrypted: true,
+            keyed: false,
         }
     }
 
@@ -188,3 +296,48 @@ impl FileEntry {
         self.last_backup = Some(chrono::Utc::now());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_current_format() {
+        let index = Index::parse(
+            r#"{"entries": {"/home/a/.bashrc": {"hash": "abc", "size": 3, "modified": 7}}}"#,
+        )
+        .unwrap();
+        let entry = index.get(&PathBuf::from("/home/a/.bashrc")).unwrap();
+        assert_eq!((entry.hash.as_str(), entry.size, entry.modified), ("abc", 3, 7));
+    }
+
+    #[test]
+    fn test_parse_legacy_format() {
+        let index = Index::parse(
+            r#"{"files": {"/home/a/.bashrc": {"path": "/home/a/.bashrc", "hash": "abc",
+                "last_modified": 7, "size": 3, "encrypted": true}}}"#,
+        )
+        .unwrap();
+        let entry = index.get(&PathBuf::from("/home/a/.bashrc")).unwrap();
+        assert_eq!((entry.hash.as_str(), entry.modified), ("abc", 7));
+        assert!(entry.encrypted);
+    }
+
+    #[test]
+    fn test_localize_keeps_newest_on_collision() {
+        let mut index = Index::new();
+        index.upsert(PathBuf::from("/home/a/.bashrc"), FileEntry::new("old".into(), 3, 5));
+        index.upsert(PathBuf::from("/Users/a/.bashrc"), FileEntry::new("new".into(), 3, 9));
+        index.upsert(PathBuf::from("/etc/hosts"), FileEntry::new("h".into(), 3, 1));
+        let rules = vec![
+            PathRemap::new("/home/a", "/home/me"),
+            PathRemap::new("/Users/a", "/home/me"),
+        ];
+
+        let collisions = index.localize(&rules);
+        assert_eq!(collisions, vec![PathBuf::from("/home/me/.bashrc")]);
+        assert_eq!(index.entries.len(), 2);
+        assert_eq!(index.get(&PathBuf::from("/home/me/.bashrc")).unwrap().hash, "new");
+        assert!(index.localize(&rules).is_empty());
+    }
+}
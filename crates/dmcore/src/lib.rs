@@ -9,6 +9,7 @@
 //! - **Drift detection**: SHA256-based change detection
 //! - **Track modes**: `git`, `backup`, or `both` per file
 //! - **Backup**: Incremental (content-addressed) or archive
+//! - **Mirror**: Git-mode files as a plain, diffable tree in the project repo
 //!
 //! # Design Rules
 //!
@@ -16,37 +17,88 @@
 //! - Return types that frontends interpret and render
 //! - All logic lives here, frontends are thin wrappers
 
+pub mod agent;
+pub mod archive;
 pub mod backup;
+pub mod canary;
+pub mod changes;
 pub mod config;
 pub mod crypto;
+pub mod diff;
+pub mod exclude;
+pub mod fsck;
 pub mod git;
+pub mod history;
 pub mod index;
 pub mod manifest;
+pub mod merge;
+pub mod metadata;
+pub mod mirror;
+pub mod objectid;
+pub mod passcmd;
 pub mod project;
+pub mod recipients;
+pub mod remap;
+pub mod rotate;
 pub mod scanner;
+pub mod secrets;
 pub mod store;
 
+#[cfg(test)]
+mod testutil;
+
+pub use archive::{ArchiveEntry, RestoreAction, RestoreOptions, RestoredEntry};
 pub use backup::{
     backup_archive, backup_incremental, backup_incremental_encrypted,
     backup_project_incremental, backup_project_incremental_encrypted,
-    backup_project_incremental_encrypted_with_message, list_archives, project_needs_password,
+    backup_project_incremental_encrypted_with_message, find_archive, list_archives, project_needs_password,
     ArchiveInfo, BackupResult,
 };
+pub use canary::{check_password, set_password_check, PasswordCheck};
+pub use changes::{commit_diff, commit_diffs, index_diff, ChangeKind, FileChange, IndexDiff};
 pub use config::{contract_path, expand_path, ArchiveFormat, BackupMode, Config};
-pub use crypto::{decrypt_bytes, decrypt_file, encrypt_bytes, encrypt_file};
+pub use crypto::{
+    decrypt_bytes, decrypt_file, encrypt_bytes, encrypt_file, is_passphrase_encrypted, parse_recipient,
+    Keyring,
+};
+pub use diff::{diff_lines, merge3, unified_diff, DiffLine, DiffTag, Hunk};
+pub use exclude::ExcludeSet;
+pub use fsck::{fsck, CorruptObject, FsckReport, MissingObject, RepairSource, RepairedObject};
 pub use git::{
-    commit, commits_ahead, commits_behind, fetch, get_remote_status, get_remote_url,
-    init_project_repo, init_repo, is_git_repo, pull, push, recent_commits, set_remote_url,
-    stage_all, CommitInfo, RemoteStatus,
+    abort_rebase, commit, commit_as_of, commit_info, commits_ahead, commits_behind, commits_touching, create_tag,
+    current_branch, delete_tag, fetch, get_remote_status, get_remote_url, init_project_repo, init_repo, is_git_repo,
+    list_tags, pull, push, rebase_in_progress, recent_commits, resolve_revision, resolve_tag, revisions_touching,
+    revisions_touching_any, set_remote_url, show_file, show_file_bytes, stage_all, tags_by_commit, upstream_branch,
+    CommitInfo, RemoteStatus, TagInfo, HOST_BRANCH_PREFIX, MAIN_BRANCH,
 };
+pub use history::{diff_versions, file_history, read_version, version_needs_password, version_text, FileVersion};
 pub use index::{FileEntry, Index};
 pub use manifest::Manifest;
-pub use project::{Project, TrackMode, TrackedFile};
+pub use merge::{
+    apply_merge, default_merge_source, ensure_host_branch, host_branch, merge_indexes, plan_merge, pull_project,
+    pull_source, IndexMerge, MergeConflict, MergePlan, MergeResult, PullOutcome, Resolution, TextMerge,
+};
+pub use metadata::{committed_project, index_locked, unlock_index, unlock_needs_password};
+pub use objectid::{
+    migrate_encryption, migration_needs_password, purge_history, MigrationResult, ObjectKey, ObjectKeyLocked,
+};
+pub use passcmd::{command_password, password_command, run_password_command};
+pub use project::{EntryKind, Project, TrackMode, TrackedFile};
+pub use recipients::{
+    add_recipients, has_passphrase_blobs, read_recipients, remove_recipients, rewrap_needs_password, rewrap_store,
+    RewrapResult,
+};
+pub use remap::PathRemap;
+pub use rotate::{rotate_store, rotation_in_progress, RotateProgress, RotateResult};
 pub use scanner::{
-    file_metadata, hash_file, scan_file, scan_project, FileStatus, ProjectSummary, ScanResult,
+    file_metadata, hash_bytes, hash_file, hash_reader, scan_file, scan_pattern, scan_project, FileStatus, ProjectSummary,
+    ScanResult, Scanner,
+};
+pub use secrets::{
+    entry_secrets, file_secrets, find_secrets, project_secrets, SecretFinding, SecretKind, SecretReport, SecretScan,
 };
 pub use store::{
-    exists_in_store, get_stored_path, retrieve_file, retrieve_file_encrypted,
+    exists_in_store, get_stored_path, object_needs_password, read_object, retrieve_file, retrieve_file_encrypted,
     retrieve_file_from, retrieve_file_from_encrypted, store_file, store_file_encrypted,
-    store_file_to, store_file_to_encrypted, StoreResult,
+    store_file_to, store_file_to_encrypted, GcResult, ObjectRef, StoreResult, StoredObject,
 };
@@ -0,0 +1,662 @@
+//! Per-host branches and index-aware merging of project repos
+//!
+//! With `host_branches = true` in `config.toml`, every machine commits its
+//! backups on its own branch, `host/<hostname>`, so two machines pushing the
+//! same project never race on one branch. `main` is the shared line: a merge
+//! brings in what other machines shared there and moves `main` to the
+//! result, which `push` then publishes.
+//!
+//! Git can't merge `index.json` (or its sealed form) line by line, so the
+//! index is merged file by file against the common ancestor. A file changed
+//! on one side takes that side, a file changed the same way on both keeps
+//! it, and only a file changed differently on both sides is a conflict for
+//! the frontends to resolve, unless it is a Git-mode file whose edits merge
+//! cleanly line by line. `store/` is content-addressed, so its objects are
+//! the union of both sides, and the `files/` mirror follows the merged index.
+//! A merge that fails is aborted, leaving the repo as it was.
+//!
+//! `pull_project` pulls the same way instead of rebasing, so a conflict
+//! never leaves the repo halfway through a rebase.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::crypto::Keyring;
+use crate::git::{self, HOST_BRANCH_PREFIX, MAIN_BRANCH};
+use crate::index::{FileEntry, Index};
+use crate::diff;
+use crate::metadata;
+use crate::mirror;
+use crate::project::Project;
+use crate::scanner::file_metadata;
+use crate::store;
+
+/// Which version of a conflicting file a merge keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// This machine's version
+    Ours,
+    /// The merged branch's version
+    Theirs,
+    /// The version backed up from the more recently modified file
+    Newest,
+}
+
+/// A file both sides changed differently since their common ancestor
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    pub path: PathBuf,
+    /// Entry in the common ancestor, `None` if the file was added since
+    pub base: Option<FileEntry>,
+    /// Our entry, `None` if we dropped the file
+    pub ours: Option<FileEntry>,
+    /// Their entry, `None` if they dropped the file
+    pub theirs: Option<FileEntry>,
+}
+
+impl MergeConflict {
+    /// The side a resolution keeps, `Ours` or `Theirs`
+    ///
+    /// For `Newest`, a side that dropped the file counts as older, and a tie
+    /// keeps ours.
+    pub fn side(&self, resolution: Resolution) -> Resolution {
+        match resolution {
+            Resolution::Newest => {
+                let modified = |entry: &Option<FileEntry>| entry.as_ref().map(|e| e.modified);
+                if modified(&self.theirs) > modified(&self.ours) {
+                    Resolution::Theirs
+                } else {
+                    Resolution::Ours
+                }
+            }
+            side => side,
+        }
+    }
+}
+
+/// A Git-mode file changed on both sides whose edits merge line by line
+#[derive(Debug, Clone)]
+pub struct TextMerge {
+    pub path: PathBuf,
+    /// The merged text, committed as a new version of the file
+    pub content: String,
+}
+
+/// Result of merging three indexes file by file
+#[derive(Debug, Clone, Default)]
+pub struct IndexMerge {
+    /// The merged index, with conflicting files still at our version
+    pub index: Index,
+    /// Files whose merged version is theirs (added, changed or dropped)
+    pub from_theirs: Vec<PathBuf>,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// What merging a branch into the current one would do
+#[derive(Debug, Clone)]
+pub struct MergePlan {
+    /// The branch or revision merged, as given
+    pub theirs_ref: String,
+    pub ours_commit: String,
+    pub theirs_commit: String,
+    /// Their common ancestor, `None` for unrelated histories
+    pub base_commit: Option<String>,
+    /// Their commits are already in our history
+    pub up_to_date: bool,
+    /// We have no commits of our own, so the merge just moves to theirs
+    pub fast_forward: bool,
+    /// The file-by-file merge; conflicts that merged as text are moved to
+    /// `text_merges`
+    pub merge: IndexMerge,
+    pub text_merges: Vec<TextMerge>,
+}
+
+/// Result of applying a merge
+#[derive(Debug, Clone, Default)]
+pub struct MergeResult {
+    /// A merge commit was made or the branch was fast-forwarded
+    pub merged: bool,
+    /// The branch was fast-forwarded
+    pub fast_forward: bool,
+    /// Files whose backed-up version changed; restore them to apply it here
+    pub from_theirs: Vec<PathBuf>,
+    /// `main` now points at the merge result
+    pub main_updated: bool,
+}
+
+/// Merge three indexes file by file
+///
+/// Entries are compared by content hash, so the same content backed up on
+/// two machines (with different mtimes) is not a change.
+pub fn merge_indexes(base: &Index, ours: &Index, theirs: &Index) -> IndexMerge {
+    let same = |a: Option<&FileEntry>, b: Option<&FileEntry>| match (a, b) {
+        (Some(a), Some(b)) => a.hash == b.hash,
+        (None, None) => true,
+        _ => false,
+    };
+
+    let paths: BTreeSet<&PathBuf> = base
+        .iter()
+        .chain(ours.iter())
+        .chain(theirs.iter())
+        .map(|(path, _)| path)
+        .collect();
+
+    let mut merge = IndexMerge::default();
+    for path in paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        let merged = if same(o, t) || same(t, b) {
+            o
+        } else if same(o, b) {
+            merge.from_theirs.push(path.clone());
+            t
+        } else {
+            merge.conflicts.push(MergeConflict {
+                path: path.clone(),
+                base: b.cloned(),
+                ours: o.cloned(),
+                theirs: t.cloned(),
+            });
+            o
+        };
+        if let Some(entry) = merged {
+            merge.index.upsert(path.clone(), entry.clone());
+        }
+    }
+    merge
+}
+
+/// This machine's branch name, `host/<hostname>`
+pub fn host_branch(config: &Config) -> String {
+    let name = config
+        .host_name
+        .clone()
+        .or_else(system_host_name)
+        .unwrap_or_else(|| "localhost".to_string());
+    // Keep to characters that are safe in a ref name
+    let name: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("{}{}", HOST_BRANCH_PREFIX, name)
+}
+
+fn system_host_name() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| {
+            let output = std::process::Command::new("hostname").output().ok()?;
+            output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+        })?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Move a project repo onto this machine's branch before a backup commits
+///
+/// Does nothing unless `host_branches` is on. The branch starts at the
+/// current commit the first time.
+pub fn ensure_host_branch(config: &Config, project_name: &str) -> anyhow::Result<()> {
+    if !config.host_branches {
+        return Ok(());
+    }
+    let project_dir = config.project_dir(project_name)?;
+    if !git::is_git_repo(&project_dir) {
+        return Ok(());
+    }
+    git::switch_branch(&project_dir, &host_branch(config))
+}
+
+/// The branch a merge takes by default: the remote `main` if there is one,
+/// else the local one
+pub fn default_merge_source(config: &Config, project_name: &str) -> anyhow::Result<String> {
+    let project_dir = config.project_dir(project_name)?;
+    let remote_main = format!("origin/{}", MAIN_BRANCH);
+    if git::resolve_revision(&project_dir, &format!("refs/remotes/{}", remote_main)).is_ok() {
+        Ok(remote_main)
+    } else {
+        Ok(MAIN_BRANCH.to_string())
+    }
+}
+
+/// Work out what merging `theirs` into the current branch would do,
+/// without touching the repo
+///
+/// Sealed indexes need the project's index unlocked.
+pub fn plan_merge(config: &Config, project_name: &str, theirs: &str) -> anyhow::Result<MergePlan> {
+    let project_dir = config.project_dir(project_name)?;
+    let ours_commit = git::resolve_revision(&project_dir, "HEAD")?;
+    let theirs_commit = git::resolve_revision(&project_dir, theirs)?;
+
+    let base_commit = git::merge_base(&project_dir, &ours_commit, &theirs_commit)?;
+    let up_to_date = base_commit.as_deref() == Some(theirs_commit.as_str());
+    let fast_forward = !up_to_date && base_commit.as_deref() == Some(ours_commit.as_str());
+
+    let load = |rev: Option<&str>| -> anyhow::Result<Index> {
+        let Some(rev) = rev else {
+            return Ok(Index::default());
+        };
+        let mut index = metadata::index_at(config, project_name, rev)?.unwrap_or_default();
+        index.localize(&config.path_remap);
+        Ok(index)
+    };
+    let mut merge = merge_indexes(
+        &load(base_commit.as_deref())?,
+        &load(Some(&ours_commit))?,
+        &load(Some(&theirs_commit))?,
+    );
+
+    let mut text_merges = Vec::new();
+    if let Some(base_commit) = &base_commit {
+        let mirror_dir = config.project_mirror_dir(project_name)?;
+        let revs = [base_commit.as_str(), ours_commit.as_str(), theirs_commit.as_str()];
+        let mut conflicts = Vec::new();
+        for conflict in std::mem::take(&mut merge.conflicts) {
+            match merge_text(&project_dir, &mirror_dir, &conflict, revs)? {
+                Some(content) => text_merges.push(TextMerge {
+                    path: conflict.path.clone(),
+                    content,
+                }),
+                None => conflicts.push(conflict),
+            }
+        }
+        merge.conflicts = conflicts;
+    }
+
+    Ok(MergePlan {
+        theirs_ref: theirs.to_string(),
+        ours_commit,
+        theirs_commit,
+        base_commit,
+        up_to_date,
+        fast_forward,
+        merge,
+        text_merges,
+    })
+}
+
+/// Merge the base, our and their text of a conflicting file line by line
+///
+/// Only plain files on all three sides have text to merge: their mirror
+/// copies in `files/` at each commit. `None` if any is missing or binary, or
+/// the edits overlap; failing to read one from git is an error.
+fn merge_text(
+    project_dir: &Path,
+    mirror_dir: &Path,
+    conflict: &MergeConflict,
+    revs: [&str; 3],
+) -> anyhow::Result<Option<String>> {
+    let entries = [&conflict.base, &conflict.ours, &conflict.theirs];
+    if entries.iter().any(|e| e.as_ref().is_none_or(|e| e.encrypted || e.keyed)) {
+        return Ok(None);
+    }
+
+    let Some(rel) = repo_path(project_dir, &mirror::mirror_path(mirror_dir, &conflict.path)) else {
+        return Ok(None);
+    };
+    let mut texts = Vec::with_capacity(3);
+    for rev in revs {
+        let Some(content) = git::show_file_bytes(project_dir, rev, &rel)? else {
+            return Ok(None);
+        };
+        let Ok(text) = String::from_utf8(content) else {
+            return Ok(None);
+        };
+        if text.contains('\0') {
+            return Ok(None);
+        }
+        texts.push(text);
+    }
+    Ok(diff::merge3(&texts[0], &texts[1], &texts[2]))
+}
+
+/// Merge as planned, keeping the resolved version of every conflict
+///
+/// Fails before touching anything if a conflict has no resolution, the
+/// repo has uncommitted changes or moved since the plan was made. Once
+/// the merge has started, any failure aborts it.
+pub fn apply_merge(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    plan: &MergePlan,
+    resolutions: &HashMap<PathBuf, Resolution>,
+    keys: &Keyring,
+) -> anyhow::Result<MergeResult> {
+    let project_dir = config.project_dir(project_name)?;
+    if plan.up_to_date {
+        return Ok(MergeResult::default());
+    }
+
+    let unresolved = plan
+        .merge
+        .conflicts
+        .iter()
+        .filter(|c| !resolutions.contains_key(&c.path))
+        .count();
+    if unresolved > 0 {
+        anyhow::bail!("{} conflicting file(s) have no resolution", unresolved);
+    }
+    if git::resolve_revision(&project_dir, "HEAD")? != plan.ours_commit {
+        anyhow::bail!("The project repo changed since the merge was planned");
+    }
+    if !git::is_clean(&project_dir)? {
+        anyhow::bail!("The project repo has uncommitted changes; back up first");
+    }
+
+    let mut result = MergeResult {
+        merged: true,
+        fast_forward: plan.fast_forward,
+        from_theirs: plan.merge.from_theirs.clone(),
+        ..MergeResult::default()
+    };
+
+    if plan.fast_forward {
+        git::merge_fast_forward(&project_dir, &plan.theirs_commit)?;
+    } else {
+        // The working index is gitignored when sealed, so an aborted merge
+        // doesn't restore it
+        let index_path = config.project_index_path(project_name)?;
+        let saved_index = fs::read(&index_path).ok();
+
+        git::merge_no_commit(&project_dir, &plan.theirs_commit)?;
+        let mut written = Vec::new();
+        let resolved = resolve_merge(config, project_name, project, plan, resolutions, keys, &mut written);
+        if let Err(e) = resolved {
+            git::abort_merge(&project_dir)?;
+            discard_written(&project_dir, &written)?;
+            if let Some(content) = saved_index {
+                fs::write(&index_path, content)?;
+            }
+            return Err(e);
+        }
+        result.from_theirs.extend(
+            plan.merge
+                .conflicts
+                .iter()
+                .filter(|c| c.side(resolutions[&c.path]) == Resolution::Theirs)
+                .map(|c| c.path.clone()),
+        );
+        result.from_theirs.extend(plan.text_merges.iter().map(|m| m.path.clone()));
+    }
+
+    result.main_updated = update_main(&project_dir)?;
+    Ok(result)
+}
+
+/// Settle a started merge: the merged index, the mirror of resolved files,
+/// and the union of store objects
+///
+/// Files it writes outside of git (text merges and their store objects) are
+/// recorded in `written`, since aborting the merge leaves them behind.
+fn resolve_merge(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    plan: &MergePlan,
+    resolutions: &HashMap<PathBuf, Resolution>,
+    keys: &Keyring,
+    written: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let project_dir = config.project_dir(project_name)?;
+    let mirror_dir = config.project_mirror_dir(project_name)?;
+    let store_dir = config.project_store_dir(project_name)?;
+
+    let mut index = plan.merge.index.clone();
+    for conflict in &plan.merge.conflicts {
+        if conflict.side(resolutions[&conflict.path]) != Resolution::Theirs {
+            continue;
+        }
+        match &conflict.theirs {
+            Some(entry) => index.upsert(conflict.path.clone(), entry.clone()),
+            None => {
+                index.remove(&conflict.path);
+            }
+        }
+    }
+
+    // Mirror copies of conflicting files follow the chosen side, even where
+    // git merged their text on its own
+    for conflict in &plan.merge.conflicts {
+        let rev = match conflict.side(resolutions[&conflict.path]) {
+            Resolution::Theirs => "MERGE_HEAD",
+            _ => "HEAD",
+        };
+        if let Some(rel) = repo_path(&project_dir, &mirror::mirror_path(&mirror_dir, &conflict.path)) {
+            git::take_path(&project_dir, rev, &rel)?;
+        }
+    }
+
+    // Whatever git couldn't merge is ours where we have it: store objects
+    // are the same content either way, and the metadata is rewritten below
+    for path in git::unmerged_paths(&project_dir)? {
+        let rev = if git::path_exists_at(&project_dir, "HEAD", &path)? {
+            "HEAD"
+        } else {
+            "MERGE_HEAD"
+        };
+        git::take_path(&project_dir, rev, &path)?;
+    }
+
+    // Text merges are new versions: the mirror copy is their content
+    for text_merge in &plan.text_merges {
+        let mirror_file = mirror::mirror_path(&mirror_dir, &text_merge.path);
+        if let Some(parent) = mirror_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        written.push(mirror_file.clone());
+        fs::write(&mirror_file, &text_merge.content)?;
+        let stored = store::store_file_to(&store_dir, &mirror_file)?;
+        if stored.was_new {
+            written.push(store::hash_to_path(&store_dir, &stored.hash));
+        }
+        let (size, modified) = file_metadata(&mirror_file)?;
+        let mut entry = FileEntry::with_sync_now(stored.hash, size, modified);
+        entry.mark_backed_up();
+        index.upsert(text_merge.path.clone(), entry);
+    }
+
+    index.save_for_project(config, project_name)?;
+    metadata::sync_metadata(config, project_name, project, keys)?;
+    git::stage_all(&project_dir)?;
+    if !git::unmerged_paths(&project_dir)?.is_empty() {
+        anyhow::bail!("Merge left unresolved paths");
+    }
+
+    let branch = git::current_branch(&project_dir)?.unwrap_or_else(|| "HEAD".to_string());
+    git::commit_merge(&project_dir, &format!("Merge {} into {}", plan.theirs_ref, branch))
+}
+
+/// Put files a failed merge wrote back the way `HEAD` has them, removing
+/// the ones it doesn't
+fn discard_written(project_dir: &Path, written: &[PathBuf]) -> anyhow::Result<()> {
+    for path in written {
+        match repo_path(project_dir, path) {
+            Some(rel) if git::path_exists_at(project_dir, "HEAD", &rel)? => {
+                git::take_path(project_dir, "HEAD", &rel)?;
+            }
+            _ if path.exists() => fs::remove_file(path)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// What a pull did
+#[derive(Debug, Clone)]
+pub enum PullOutcome {
+    /// Nothing new to pull
+    UpToDate,
+    /// Fast-forwarded, or merged without conflicts
+    Merged(MergeResult),
+    /// Files changed differently on both sides. Nothing was merged yet;
+    /// resolve them and pass the plan to `apply_merge`
+    Conflicts(MergePlan),
+}
+
+/// Where a pull takes changes from
+///
+/// With host branches that is the shared `main`, since this machine's own
+/// branch has nothing new; otherwise the current branch's upstream.
+pub fn pull_source(config: &Config, project_name: &str) -> anyhow::Result<String> {
+    let project_dir = config.project_dir(project_name)?;
+    if !config.host_branches {
+        if let Some(upstream) = git::upstream_branch(&project_dir)? {
+            return Ok(upstream);
+        }
+    }
+    default_merge_source(config, project_name)
+}
+
+/// Fetch and merge a project's remote changes without rebasing
+///
+/// A rebase left behind by an interrupted pull is aborted first. The merge
+/// goes file by file like `apply_merge`; if files conflict, nothing is
+/// changed beyond the fetch and the plan comes back for resolving. Sealed
+/// indexes need the project's index unlocked.
+pub fn pull_project(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    keys: &Keyring,
+) -> anyhow::Result<PullOutcome> {
+    let project_dir = config.project_dir(project_name)?;
+    if !git::is_git_repo(&project_dir) {
+        anyhow::bail!("Not a git repository");
+    }
+
+    git::abort_rebase(&project_dir)?;
+    git::fetch(&project_dir)?;
+    let plan = plan_merge(config, project_name, &pull_source(config, project_name)?)?;
+    if plan.up_to_date {
+        return Ok(PullOutcome::UpToDate);
+    }
+    if !plan.merge.conflicts.is_empty() {
+        return Ok(PullOutcome::Conflicts(plan));
+    }
+    let result = apply_merge(config, project_name, project, &plan, &HashMap::new(), keys)?;
+    Ok(PullOutcome::Merged(result))
+}
+
+/// Move `main` up to the current commit after a merge on a host branch
+///
+/// Only fast-forwards; returns whether `main` moved.
+fn update_main(project_dir: &Path) -> anyhow::Result<bool> {
+    let branch = git::current_branch(project_dir)?;
+    if branch.as_deref() == Some(MAIN_BRANCH) {
+        return Ok(false);
+    }
+    let head = git::resolve_revision(project_dir, "HEAD")?;
+    if git::branch_exists(project_dir, MAIN_BRANCH) {
+        let main = git::resolve_revision(project_dir, MAIN_BRANCH)?;
+        if main == head || !git::is_ancestor(project_dir, &main, &head)? {
+            return Ok(false);
+        }
+    }
+    git::set_branch(project_dir, MAIN_BRANCH, &head)?;
+    Ok(true)
+}
+
+/// A path inside the project repo, relative to it
+fn repo_path(project_dir: &Path, path: &Path) -> Option<String> {
+    Some(path.strip_prefix(project_dir).ok()?.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::TrackedFile;
+    use crate::testutil::TempData;
+
+    fn index(entries: &[(&str, &str)]) -> Index {
+        let mut index = Index::new();
+        for (path, hash) in entries {
+            index.upsert(PathBuf::from(path), FileEntry::new(hash.to_string(), 1, 0));
+        }
+        index
+    }
+
+    #[test]
+    fn test_merge_indexes() {
+        let base = index(&[("/h/a", "1"), ("/h/b", "1"), ("/h/c", "1"), ("/h/d", "1")]);
+        // We edit a and c, drop d and add e; they edit b and c and add f
+        let ours = index(&[("/h/a", "2"), ("/h/b", "1"), ("/h/c", "2"), ("/h/e", "1")]);
+        let theirs = index(&[("/h/a", "1"), ("/h/b", "3"), ("/h/c", "3"), ("/h/d", "1"), ("/h/f", "1")]);
+
+        let merge = merge_indexes(&base, &ours, &theirs);
+        let hashes: Vec<(&str, &str)> = merge
+            .index
+            .iter()
+            .map(|(p, e)| (p.to_str().unwrap(), e.hash.as_str()))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        assert_eq!(
+            hashes,
+            vec![("/h/a", "2"), ("/h/b", "3"), ("/h/c", "2"), ("/h/e", "1"), ("/h/f", "1")]
+        );
+        assert_eq!(merge.from_theirs, vec![PathBuf::from("/h/b"), PathBuf::from("/h/f")]);
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].path, PathBuf::from("/h/c"));
+
+        // Newest goes by the backed-up mtime, and a dropped file loses
+        let mut conflict = merge.conflicts[0].clone();
+        conflict.theirs.as_mut().unwrap().modified = 5;
+        assert_eq!(conflict.side(Resolution::Newest), Resolution::Theirs);
+        conflict.theirs = None;
+        assert_eq!(conflict.side(Resolution::Newest), Resolution::Ours);
+    }
+
+    #[test]
+    fn test_host_branch_name() {
+        let config = Config {
+            host_name: Some("Work Laptop.local".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(host_branch(&config), "host/work-laptop-local");
+    }
+
+    #[test]
+    fn test_failed_merge_leaves_repo_clean() {
+        let data = TempData::new("merge-abort");
+        let project_dir = data.project("p");
+        let file = data.file("init.vim", b"a\nb\nc\nd\ne\n");
+        let mut project = Project::new();
+        project.add_file(TrackedFile::new(file.to_string_lossy()));
+        let backup = |project: &Project| {
+            crate::backup::backup_project_incremental(&data.config, "p", project).unwrap();
+        };
+
+        // Both sides edit different lines, so git merges the text file by
+        // file and the index entry becomes a text merge
+        backup(&project);
+        git::set_branch(&project_dir, "other", "HEAD").unwrap();
+        fs::write(&file, b"A\nb\nc\nd\ne\n").unwrap();
+        backup(&project);
+        git::switch_branch(&project_dir, "other").unwrap();
+        fs::write(&file, b"a\nb\nc\nd\nE\n").unwrap();
+        backup(&project);
+        git::switch_branch(&project_dir, MAIN_BRANCH).unwrap();
+
+        let plan = plan_merge(&data.config, "p", "other").unwrap();
+        assert_eq!(plan.text_merges.len(), 1);
+        assert!(plan.merge.conflicts.is_empty());
+
+        // Sealing the index without a key fails after the text merge is
+        // written
+        project.encrypt_index = true;
+        let failed = apply_merge(&data.config, "p", &project, &plan, &HashMap::new(), &Keyring::default());
+        assert!(failed.is_err());
+        assert!(git::is_clean(&project_dir).unwrap());
+        assert!(!project_dir.join(".git/MERGE_HEAD").exists());
+
+        project.encrypt_index = false;
+        let result = apply_merge(&data.config, "p", &project, &plan, &HashMap::new(), &Keyring::default()).unwrap();
+        assert!(result.merged);
+        assert!(git::is_clean(&project_dir).unwrap());
+    }
+}
@@ -0,0 +1,320 @@
+//! Encrypted project metadata
+//!
+//! `index.json` records the path, size, mtime and hash of every tracked
+//! file, secret or not, and the project repo gets pushed. A project with
+//! `encrypt_index = true` commits it as `index.json.age` instead, and
+//! `encrypt_manifest = true` also commits its manifest entry as
+//! `project.toml.age`, so a clone can recreate the project elsewhere.
+//!
+//! Both are sealed with the project's object key (see `objectid`), which is
+//! cached locally once the password or an identity unlocked it. From then on
+//! the working index and every revision in history read transparently.
+//!
+//! The plaintext `index.json` stays in the project directory as a gitignored
+//! working copy. `index.json.age.id` records the hash of the sealed index it
+//! matches, so when `index.json.age` changes underneath it (as after a pull
+//! or a checkout) the working copy is replaced. Commits made before the
+//! option was turned on still hold the plain index.
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::crypto::Keyring;
+use crate::git;
+use crate::index::Index;
+use crate::objectid::ObjectKey;
+use crate::project::Project;
+use crate::scanner::hash_bytes;
+use crate::store;
+
+/// File name of the plaintext index inside a project directory
+pub(crate) const INDEX_FILE: &str = "index.json";
+
+/// File name of the sealed index inside a project directory
+pub(crate) const SEALED_INDEX_FILE: &str = "index.json.age";
+
+/// File name of the hash of the sealed index the working copy matches
+const SEALED_INDEX_ID_FILE: &str = "index.json.age.id";
+
+/// File name of the sealed manifest entry inside a project directory
+pub(crate) const SEALED_PROJECT_FILE: &str = "project.toml.age";
+
+fn locked(project_name: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "The index of '{}' is encrypted; unlock it with the password or an identity",
+        project_name
+    )
+}
+
+/// Bring the committed metadata in line with a project's settings
+///
+/// Called before every commit that may change the index.
+pub(crate) fn sync_metadata(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    keys: &Keyring,
+) -> anyhow::Result<()> {
+    let project_dir = config.project_dir(project_name)?;
+    let sealed_index = project_dir.join(SEALED_INDEX_FILE);
+    let sealed_index_id = project_dir.join(SEALED_INDEX_ID_FILE);
+    let sealed_project = project_dir.join(SEALED_PROJECT_FILE);
+    let is_repo = git::is_git_repo(&project_dir);
+
+    let key = if project.encrypt_index || project.encrypt_manifest {
+        Some(metadata_key(config, project_name, keys)?)
+    } else {
+        None
+    };
+
+    match &key {
+        Some(key) if project.encrypt_index => {
+            let index_path = config.project_index_path(project_name)?;
+            if index_path.exists() {
+                write_sealed(&sealed_index, key, &fs::read(&index_path)?)?;
+                fs::write(&sealed_index_id, hash_bytes(&fs::read(&sealed_index)?))?;
+            }
+            if is_repo {
+                git::ensure_ignored(&project_dir, INDEX_FILE)?;
+                git::ensure_ignored(&project_dir, SEALED_INDEX_ID_FILE)?;
+                git::untrack(&project_dir, INDEX_FILE)?;
+            }
+        }
+        _ if sealed_index.exists() => {
+            fs::remove_file(&sealed_index)?;
+            let _ = fs::remove_file(&sealed_index_id);
+            if is_repo {
+                git::remove_ignored(&project_dir, INDEX_FILE)?;
+                git::remove_ignored(&project_dir, SEALED_INDEX_ID_FILE)?;
+            }
+        }
+        _ => {}
+    }
+
+    match &key {
+        Some(key) if project.encrypt_manifest => {
+            let entry = toml::to_string_pretty(project)?;
+            write_sealed(&sealed_project, key, entry.as_bytes())?;
+        }
+        _ if sealed_project.exists() => fs::remove_file(&sealed_project)?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// The object key to seal metadata with, created if the project has none yet
+fn metadata_key(config: &Config, project_name: &str, keys: &Keyring) -> anyhow::Result<ObjectKey> {
+    if keys.can_encrypt() {
+        return ObjectKey::load_or_create(config, project_name, keys);
+    }
+    ObjectKey::load(config, project_name, keys)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Password or recipients required to encrypt the index of '{}'",
+            project_name
+        )
+    })
+}
+
+/// Write sealed content unless the file already holds it, so unchanged
+/// metadata doesn't show up as a change in every commit
+fn write_sealed(path: &Path, key: &ObjectKey, content: &[u8]) -> anyhow::Result<()> {
+    if let Ok(existing) = fs::read(path) {
+        if key.unseal(&existing).is_ok_and(|c| c == content) {
+            return Ok(());
+        }
+    }
+    fs::write(path, key.seal(content)?)?;
+    Ok(())
+}
+
+/// Replace the working `index.json` from `index.json.age` when the sealed
+/// index isn't the one the working copy was made from or sealed into
+///
+/// Without the key cached, an existing working copy is kept until the index
+/// is unlocked.
+pub(crate) fn refresh_index(config: &Config, project_name: &str) -> anyhow::Result<()> {
+    let project_dir = config.project_dir(project_name)?;
+    let Ok(sealed) = fs::read(project_dir.join(SEALED_INDEX_FILE)) else {
+        return Ok(());
+    };
+
+    let index_path = config.project_index_path(project_name)?;
+    let id_path = project_dir.join(SEALED_INDEX_ID_FILE);
+    let id = hash_bytes(&sealed);
+    if index_path.exists() && fs::read_to_string(&id_path).is_ok_and(|recorded| recorded.trim() == id) {
+        return Ok(());
+    }
+
+    match ObjectKey::cached(config, project_name)? {
+        Some(key) => {
+            fs::write(&index_path, key.unseal(&sealed)?)?;
+            fs::write(&id_path, id)?;
+            Ok(())
+        }
+        None if index_path.exists() => Ok(()),
+        None => Err(locked(project_name)),
+    }
+}
+
+/// Read a project's index as committed at a revision, plain or sealed
+pub(crate) fn index_at(config: &Config, project_name: &str, rev: &str) -> anyhow::Result<Option<Index>> {
+    let project_dir = config.project_dir(project_name)?;
+    if let Some(content) = git::show_file(&project_dir, rev, INDEX_FILE)? {
+        return Index::parse(&content).map(Some);
+    }
+    let Some(sealed) = git::show_file_bytes(&project_dir, rev, SEALED_INDEX_FILE)? else {
+        return Ok(None);
+    };
+
+    let key = ObjectKey::cached(config, project_name)?.ok_or_else(|| locked(project_name))?;
+    let content = String::from_utf8(key.unseal(&sealed)?)?;
+    Index::parse(&content).map(Some)
+}
+
+/// Check if a project's index, or any revision of it, can't be read until
+/// its object key is unlocked
+pub fn index_locked(config: &Config, project_name: &str) -> anyhow::Result<bool> {
+    if ObjectKey::cached(config, project_name)?.is_some() {
+        return Ok(false);
+    }
+    let project_dir = config.project_dir(project_name)?;
+    Ok(project_dir.join(SEALED_INDEX_FILE).exists()
+        || !git::revisions_touching(&project_dir, SEALED_INDEX_FILE, None)?.is_empty())
+}
+
+/// Check if unlocking a project's index needs the password rather than an
+/// identity file
+pub fn unlock_needs_password(config: &Config, project_name: &str) -> anyhow::Result<bool> {
+    Ok(store::is_passphrase_blob(&config.project_object_key_path(project_name)?))
+}
+
+/// Unlock a project's encrypted index, and its history, with a keyring
+///
+/// This caches the object key, so it is only needed once per machine; an
+/// index that isn't locked is left alone.
+pub fn unlock_index(config: &Config, project_name: &str, keys: &Keyring) -> anyhow::Result<()> {
+    if !index_locked(config, project_name)? {
+        return Ok(());
+    }
+    if ObjectKey::load(config, project_name, keys)?.is_none() {
+        anyhow::bail!("'{}' has an encrypted index but no object key", project_name);
+    }
+    refresh_index(config, project_name)
+}
+
+/// Read the manifest entry committed in a project's `project.toml.age`
+pub fn committed_project(
+    config: &Config,
+    project_name: &str,
+    keys: &Keyring,
+) -> anyhow::Result<Option<Project>> {
+    let path = config.project_dir(project_name)?.join(SEALED_PROJECT_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let key = ObjectKey::load(config, project_name, keys)?
+        .ok_or_else(|| anyhow::anyhow!("'{}' has a sealed manifest entry but no object key", project_name))?;
+    let entry = String::from_utf8(key.unseal(&fs::read(&path)?)?)?;
+    Ok(Some(toml::from_str(&entry)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::FileEntry;
+    use crate::testutil::TempData;
+    use age::secrecy::SecretString;
+    use std::path::PathBuf;
+
+    fn index_of(hash: &str) -> Index {
+        let mut index = Index::new();
+        index.upsert(PathBuf::from("/h/vimrc"), FileEntry::new(hash.to_string(), 1, 0));
+        index
+    }
+
+    fn hash_in(index: &Index) -> String {
+        index.get(&PathBuf::from("/h/vimrc")).unwrap().hash.clone()
+    }
+
+    fn commit(data: &TempData, message: &str) -> String {
+        let project_dir = data.config.project_dir("p").unwrap();
+        git::stage_all(&project_dir).unwrap();
+        git::commit(&project_dir, message).unwrap();
+        git::current_commit(&project_dir).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_sync_metadata_toggles_sealed_index() {
+        let data = TempData::new("sync-metadata");
+        let project_dir = data.project("p");
+        let keys = Keyring::from_password(SecretString::from("hunter2".to_string()));
+        let mut project = Project::new();
+
+        index_of("a").save_for_project(&data.config, "p").unwrap();
+        let plain = commit(&data, "plain");
+
+        project.encrypt_index = true;
+        sync_metadata(&data.config, "p", &project, &keys).unwrap();
+        let sealed = commit(&data, "sealed");
+        assert!(git::path_exists_at(&project_dir, &sealed, SEALED_INDEX_FILE).unwrap());
+        assert!(!git::path_exists_at(&project_dir, &sealed, INDEX_FILE).unwrap());
+        assert!(!git::path_exists_at(&project_dir, &sealed, SEALED_INDEX_ID_FILE).unwrap());
+        assert!(git::is_clean(&project_dir).unwrap());
+
+        project.encrypt_index = false;
+        sync_metadata(&data.config, "p", &project, &keys).unwrap();
+        let unsealed = commit(&data, "unsealed");
+        assert!(!project_dir.join(SEALED_INDEX_FILE).exists());
+        assert!(!project_dir.join(SEALED_INDEX_ID_FILE).exists());
+        assert!(git::path_exists_at(&project_dir, &unsealed, INDEX_FILE).unwrap());
+        assert!(!fs::read_to_string(project_dir.join(".gitignore")).unwrap().contains(INDEX_FILE));
+
+        for rev in [&plain, &sealed, &unsealed] {
+            let index = index_at(&data.config, "p", rev).unwrap().unwrap();
+            assert_eq!(hash_in(&index), "a");
+        }
+
+        // Sealed revisions need the key, plain ones don't
+        fs::remove_file(data.config.project_object_key_cache_path("p").unwrap()).unwrap();
+        assert!(index_at(&data.config, "p", &sealed).is_err());
+        assert!(index_at(&data.config, "p", &plain).unwrap().is_some());
+        assert!(index_locked(&data.config, "p").unwrap());
+    }
+
+    #[test]
+    fn test_refresh_index_follows_sealed_content() {
+        let data = TempData::new("refresh-index");
+        let project_dir = data.project("p");
+        let keys = Keyring::from_password(SecretString::from("hunter2".to_string()));
+        let mut project = Project::new();
+        project.encrypt_index = true;
+
+        index_of("a").save_for_project(&data.config, "p").unwrap();
+        sync_metadata(&data.config, "p", &project, &keys).unwrap();
+        let first = commit(&data, "first");
+        index_of("b").save_for_project(&data.config, "p").unwrap();
+        sync_metadata(&data.config, "p", &project, &keys).unwrap();
+        commit(&data, "second");
+
+        // A local change not sealed yet is kept
+        index_of("c").save_for_project(&data.config, "p").unwrap();
+        assert_eq!(hash_in(&Index::load_for_project(&data.config, "p").unwrap()), "c");
+
+        // A sealed index from elsewhere replaces it, even with an older mtime
+        git::take_path(&project_dir, &first, SEALED_INDEX_FILE).unwrap();
+        index_of("c").save_for_project(&data.config, "p").unwrap();
+        assert_eq!(hash_in(&Index::load_for_project(&data.config, "p").unwrap()), "a");
+
+        // Locked, a working copy is kept and a missing one can't be made
+        git::take_path(&project_dir, "HEAD", SEALED_INDEX_FILE).unwrap();
+        fs::remove_file(data.config.project_object_key_cache_path("p").unwrap()).unwrap();
+        assert_eq!(hash_in(&Index::load_for_project(&data.config, "p").unwrap()), "a");
+        fs::remove_file(data.config.project_index_path("p").unwrap()).unwrap();
+        assert!(refresh_index(&data.config, "p").is_err());
+
+        ObjectKey::load(&data.config, "p", &keys).unwrap().unwrap();
+        assert_eq!(hash_in(&Index::load_for_project(&data.config, "p").unwrap()), "b");
+    }
+}
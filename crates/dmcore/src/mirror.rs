@@ -0,0 +1,249 @@
+//! Readable mirror of git-tracked files
+//!
+//! Git-mode files are copied as plain files into `files/` inside the project
+//! repository, laid out by their path relative to home
+//! (`files/.config/nvim/init.lua`). Paths outside home go under `files/_root/`;
+//! a home path that itself starts with `_root` gets an extra underscore
+//! (`~/_root/x` is `files/_root_/x`) so the two can't collide. This gives `git log -p` and forge web views real, diffable history.
+//!
+//! Only non-encrypted files tracked with `git` or `both` are mirrored.
+//! Backup-only and encrypted files live in the hashed store alone.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Subdirectory used for paths outside the home directory
+pub(crate) const ROOT_DIR: &str = "_root";
+
+/// Result of updating the mirror
+#[derive(Debug, Clone, Default)]
+pub struct MirrorResult {
+    /// Files written because they were new or changed
+    pub written: usize,
+    /// Stale files removed from the mirror
+    pub removed: usize,
+}
+
+/// Map an absolute path to its location inside the mirror directory
+pub fn mirror_path(mirror_dir: &Path, abs_path: &Path) -> PathBuf {
+    if let Some(home) = dirs::home_dir() {
+        if let Ok(rel) = abs_path.strip_prefix(&home) {
+            return mirror_dir.join(escape_home_relative(rel));
+        }
+    }
+
+    let rel: PathBuf = abs_path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect();
+    mirror_dir.join(ROOT_DIR).join(rel)
+}
+
+/// Check if a name is `_root` followed only by underscores, with at least
+/// `extra` of them
+fn is_root_like(name: &str, extra: usize) -> bool {
+    name.strip_prefix(ROOT_DIR)
+        .is_some_and(|rest| rest.len() >= extra && rest.bytes().all(|b| b == b'_'))
+}
+
+/// Lay out a home-relative path so it can't start with `ROOT_DIR`: a first
+/// component of `_root`, `_root_`, ... gets one more underscore
+pub(crate) fn escape_home_relative(rel: &Path) -> PathBuf {
+    let mut components = rel.components();
+    match components.next() {
+        Some(Component::Normal(first)) if first.to_str().is_some_and(|f| is_root_like(f, 0)) => {
+            Path::new(&format!("{}_", first.to_string_lossy())).join(components.as_path())
+        }
+        _ => rel.to_path_buf(),
+    }
+}
+
+/// Inverse of `escape_home_relative`
+pub(crate) fn unescape_home_relative(rel: &Path) -> PathBuf {
+    let mut components = rel.components();
+    match components.next() {
+        Some(Component::Normal(first)) if first.to_str().is_some_and(|f| is_root_like(f, 1)) => {
+            let first = first.to_string_lossy();
+            Path::new(&first[..first.len() - 1]).join(components.as_path())
+        }
+        _ => rel.to_path_buf(),
+    }
+}
+
+/// Bring the mirror in line with a set of files
+///
+/// Each path in `keep` that exists on disk is copied in if its content
+/// differs from the mirrored copy. Paths in `keep` that are missing locally
+/// keep their last mirrored version. Anything else in the mirror is removed.
+pub fn update(mirror_dir: &Path, keep: &[PathBuf]) -> anyhow::Result<MirrorResult> {
+    let mut result = MirrorResult::default();
+    let mut wanted = HashSet::new();
+
+    for abs_path in keep {
+        let dest = mirror_path(mirror_dir, abs_path);
+        if abs_path.is_file() && copy_if_changed(abs_path, &dest)? {
+            result.written += 1;
+        }
+        wanted.insert(dest);
+    }
+
+    if mirror_dir.exists() {
+        result.removed = prune(mirror_dir, &wanted)?;
+    }
+
+    Ok(result)
+}
+
+/// Copy a file unless the destination already has identical content
+fn copy_if_changed(source: &Path, dest: &Path) -> anyhow::Result<bool> {
+    if same_content(source, dest)? {
+        return Ok(false);
+    }
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, dest)?;
+    Ok(true)
+}
+
+/// Compare two files by size, then block by block, without loading either;
+/// a missing `dest` differs
+pub(crate) fn same_content(source: &Path, dest: &Path) -> anyhow::Result<bool> {
+    let Ok(dest_meta) = fs::metadata(dest) else {
+        return Ok(false);
+    };
+    if fs::metadata(source)?.len() != dest_meta.len() {
+        return Ok(false);
+    }
+
+    let mut a = BufReader::new(File::open(source)?);
+    let mut b = BufReader::new(File::open(dest)?);
+    let (mut buf_a, mut buf_b) = ([0u8; 8192], [0u8; 8192]);
+    loop {
+        let n = read_full(&mut a, &mut buf_a)?;
+        if n != read_full(&mut b, &mut buf_b)? || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fill `buf` as far as the reader allows, returning the bytes read
+pub(crate) fn read_full(reader: &mut (impl Read + ?Sized), buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+/// Remove files not in `wanted`, and any directories left empty
+fn prune(dir: &Path, wanted: &HashSet<PathBuf>) -> anyhow::Result<usize> {
+    let mut removed = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            removed += prune(&path, wanted)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
+            }
+        } else if !wanted.contains(&path) {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror_path_outside_home() {
+        let mirror = Path::new("/data/files");
+        assert_eq!(
+            mirror_path(mirror, Path::new("/etc/hosts")),
+            PathBuf::from("/data/files/_root/etc/hosts")
+        );
+    }
+
+    #[test]
+    fn test_mirror_path_in_home() {
+        let home = dirs::home_dir().unwrap();
+        let mirror = Path::new("/data/files");
+        assert_eq!(
+            mirror_path(mirror, &home.join(".config/nvim/init.lua")),
+            PathBuf::from("/data/files/.config/nvim/init.lua")
+        );
+    }
+
+    #[test]
+    fn test_mirror_path_escapes_home_root() {
+        let home = dirs::home_dir().unwrap();
+        let mirror = Path::new("/data/files");
+        assert_eq!(
+            mirror_path(mirror, &home.join("_root/etc/hosts")),
+            PathBuf::from("/data/files/_root_/etc/hosts")
+        );
+        assert_eq!(
+            mirror_path(mirror, &home.join("_root__")),
+            PathBuf::from("/data/files/_root___")
+        );
+        assert_eq!(
+            mirror_path(mirror, &home.join("_rooted")),
+            PathBuf::from("/data/files/_rooted")
+        );
+        assert_eq!(unescape_home_relative(Path::new("_root___")), PathBuf::from("_root__"));
+        assert_eq!(unescape_home_relative(Path::new("_rooted")), PathBuf::from("_rooted"));
+    }
+
+    #[test]
+    fn test_update_copies_changes_and_prunes() {
+        let dir = std::env::temp_dir().join(format!("dmcore-mirror-{}", std::process::id()));
+        let mirror = dir.join("files");
+        let vimrc = dir.join("src/vimrc");
+        let hosts = dir.join("src/etc/hosts");
+        fs::create_dir_all(hosts.parent().unwrap()).unwrap();
+        fs::write(&vimrc, b"set number\n").unwrap();
+        fs::write(&hosts, b"127.0.0.1 localhost\n").unwrap();
+        let keep = vec![vimrc.clone(), hosts.clone()];
+
+        assert_eq!(update(&mirror, &keep).unwrap().written, 2);
+        assert_eq!(update(&mirror, &keep).unwrap().written, 0);
+
+        // Same size, different content
+        fs::write(&vimrc, b"set nonumb\n").unwrap();
+        let result = update(&mirror, &keep).unwrap();
+        assert_eq!((result.written, result.removed), (1, 0));
+        assert_eq!(fs::read(mirror_path(&mirror, &vimrc)).unwrap(), b"set nonumb\n");
+
+        // Missing locally keeps the last copy; untracked files go, with
+        // the directories they leave empty
+        fs::remove_file(&vimrc).unwrap();
+        let stray = mirror.join("stray/file");
+        fs::create_dir_all(stray.parent().unwrap()).unwrap();
+        fs::write(&stray, b"x").unwrap();
+        let result = update(&mirror, &keep[..1]).unwrap();
+        assert_eq!((result.written, result.removed), (0, 2));
+        assert!(mirror_path(&mirror, &vimrc).exists());
+        assert!(!mirror_path(&mirror, &hosts).exists());
+        assert!(!mirror_path(&mirror, &hosts).parent().unwrap().exists());
+        assert!(!mirror.join("stray").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
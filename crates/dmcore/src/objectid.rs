@@ -0,0 +1,542 @@
+//! Storage ids for encrypted blobs
+//!
+//! Plaintext blobs are addressed by the SHA256 of their content. Encrypted
+//! blobs are addressed by a keyed id instead, `HMAC-SHA256(key, sha256)`, so
+//! neither the store layout nor the committed `index.json` reveals a hash
+//! anyone could confirm a guessed file against.
+//!
+//! The key is random per project and lives in the project repo as
+//! `object-key.age`, encrypted like the blobs (to the project recipients, or
+//! the password). Ids stay stable as long as the key does, so encrypted blobs
+//! still deduplicate.
+//!
+//! Once unlocked, the key is cached in `object-key.cache`, which is
+//! gitignored and never leaves the machine. That lets backups with only
+//! recipients (no password or identity) keep storing keyed blobs. A fresh
+//! clone has to unlock the key once, with the password or an identity
+//! (`dmxcli unlock <project>`); until then those backups fail with
+//! [`ObjectKeyLocked`].
+//!
+//! Index entries with `keyed = true` record the id in `hash`. Their drift is
+//! detected from size and mtime, since the content hash can't be derived
+//! without the key.
+//!
+//! The key also seals encrypted project metadata (see `metadata`).
+//!
+//! When a file becomes encrypted, its older plaintext blobs move to the
+//! keyed id of their hash, encrypted. Old index revisions still name the
+//! plain hash, so the store looks a missing plain hash up under its keyed id
+//! (see `store::locate`). Earlier commits of the project repo still contain
+//! the plaintext blobs, though; only [`purge_history`] removes them, by
+//! replacing the repo's history with a single commit.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use age::secrecy::SecretString;
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::crypto::Keyring;
+use crate::git;
+use crate::index::{FileEntry, Index};
+use crate::project::Project;
+use crate::remap;
+use crate::scanner::hash_bytes;
+use crate::store::{self, hash_to_path, is_encrypted_blob};
+
+/// File name of the encrypted key inside a project directory
+pub(crate) const OBJECT_KEY_FILE: &str = "object-key.age";
+
+/// File name of the local plaintext key cache inside a project directory
+pub(crate) const OBJECT_KEY_CACHE: &str = "object-key.cache";
+
+/// scrypt work factor for sealed metadata; the key is random, so it needs no
+/// stretching and history reads stay fast
+const SEAL_WORK_FACTOR: u8 = 10;
+
+/// Error for a project whose object key isn't unlocked on this machine yet
+#[derive(Debug)]
+pub struct ObjectKeyLocked {
+    pub project: String,
+    /// Why the key could not be opened
+    pub reason: String,
+}
+
+impl fmt::Display for ObjectKeyLocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The object key of '{}' is not unlocked on this machine ({}). Unlock it once with the \
+             password or an identity file: dmxcli unlock {}",
+            self.project, self.reason, self.project
+        )
+    }
+}
+
+impl std::error::Error for ObjectKeyLocked {}
+
+/// Per-project secret used to derive encrypted blob ids
+#[derive(Clone)]
+pub struct ObjectKey([u8; 32]);
+
+impl std::fmt::Debug for ObjectKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ObjectKey(..)")
+    }
+}
+
+impl ObjectKey {
+    /// Generate a new random key
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Storage id for a blob with the given plaintext SHA256
+    pub fn object_id(&self, content_hash: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(content_hash.as_bytes());
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+
+    fn from_bytes(bytes: Vec<u8>, path: &Path) -> anyhow::Result<Self> {
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid object key in {}", path.display()))?;
+        Ok(Self(key))
+    }
+
+    /// Load a project's key, if it has one
+    ///
+    /// Uses the local cache when present, and otherwise decrypts
+    /// `object-key.age` with the keyring and caches the result. Fails with
+    /// [`ObjectKeyLocked`] if the keyring can't open it, as with recipients
+    /// alone.
+    pub fn load(config: &Config, project_name: &str, keys: &Keyring) -> anyhow::Result<Option<Self>> {
+        let path = config.project_object_key_path(project_name)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let cache = config.project_object_key_cache_path(project_name)?;
+        if let Ok(bytes) = fs::read(&cache) {
+            return Self::from_bytes(bytes, &cache).map(Some);
+        }
+
+        let plain = keys.decrypt(&fs::read(&path)?).map_err(|e| ObjectKeyLocked {
+            project: project_name.to_string(),
+            reason: e.to_string(),
+        })?;
+        let key = Self::from_bytes(plain, &path)?;
+        key.cache(config, project_name)?;
+        Ok(Some(key))
+    }
+
+    /// Load a project's key from the local cache only
+    pub(crate) fn cached(config: &Config, project_name: &str) -> anyhow::Result<Option<Self>> {
+        Self::cached_in(&config.project_dir(project_name)?)
+    }
+
+    /// Load the key cached in a project directory
+    pub(crate) fn cached_in(project_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let cache = project_dir.join(OBJECT_KEY_CACHE);
+        match fs::read(&cache) {
+            Ok(bytes) => Self::from_bytes(bytes, &cache).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The key as an age passphrase
+    fn passphrase(&self) -> SecretString {
+        SecretString::from(self.0.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+    }
+
+    /// Encrypt project metadata under this key
+    pub(crate) fn seal(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut recipient = age::scrypt::Recipient::new(self.passphrase());
+        recipient.set_work_factor(SEAL_WORK_FACTOR);
+        let encryptor = age::Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient))?;
+
+        let mut sealed = Vec::new();
+        let mut writer = encryptor.wrap_output(&mut sealed)?;
+        writer.write_all(data)?;
+        writer.finish()?;
+        Ok(sealed)
+    }
+
+    /// Decrypt metadata sealed with [`ObjectKey::seal`]
+    pub(crate) fn unseal(&self, sealed: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let identity = age::scrypt::Identity::new(self.passphrase());
+        let mut reader = age::Decryptor::new(sealed)?
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .map_err(|e| anyhow::anyhow!("Cannot open sealed metadata: {}", e))?;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Load a project's key, creating it on first use
+    pub fn load_or_create(
+        config: &Config,
+        project_name: &str,
+        keys: &Keyring,
+    ) -> anyhow::Result<Self> {
+        if let Some(key) = Self::load(config, project_name, keys)? {
+            return Ok(key);
+        }
+
+        let key = Self::generate();
+        let path = config.project_object_key_path(project_name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, keys.encrypt(&key.0)?)?;
+        key.cache(config, project_name)?;
+        Ok(key)
+    }
+
+    /// Write the plaintext key to the project's gitignored local cache
+    fn cache(&self, config: &Config, project_name: &str) -> anyhow::Result<()> {
+        let project_dir = config.project_dir(project_name)?;
+        if git::is_git_repo(&project_dir) {
+            git::ensure_ignored(&project_dir, OBJECT_KEY_CACHE)?;
+        }
+
+        // Created user-only, so the key is never readable by others
+        let path = config.project_object_key_cache_path(project_name)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(&path)?.write_all(&self.0)?;
+        Ok(())
+    }
+}
+
+/// Result of bringing stored blobs in line with a project's encryption flags
+#[derive(Debug, Clone, Default)]
+pub struct MigrationResult {
+    /// Entries moved to keyed, encrypted blobs
+    pub encrypted: usize,
+    /// Entries moved back to plaintext blobs
+    pub decrypted: usize,
+    /// Plaintext blobs of now-encrypted files moved to keyed, encrypted ids
+    pub sealed: Vec<String>,
+    /// Whether earlier commits still hold sealed plaintext blobs, until
+    /// [`purge_history`] drops them
+    pub history_exposed: bool,
+    /// Whether a git commit was made
+    pub committed: bool,
+}
+
+impl MigrationResult {
+    /// Check if anything changed
+    pub fn is_empty(&self) -> bool {
+        self.encrypted == 0 && self.decrypted == 0 && self.sealed.is_empty()
+    }
+}
+
+/// Check if migrating a project's blobs needs the password
+///
+/// Without it, new blobs are encrypted to the project recipients and read
+/// with the identity files, so both must be set up.
+pub fn migration_needs_password(config: &Config, project: &Project) -> bool {
+    project.recipients.is_empty() || config.identity_files.is_empty()
+}
+
+/// Re-encrypt or decrypt stored blobs after files were toggled
+///
+/// Every working index entry is compared with the project's `encrypted`
+/// flags. Entries that should be encrypted move to a keyed, encrypted blob
+/// (this also upgrades blobs encrypted before ids were keyed). Entries that
+/// should no longer be encrypted get a plaintext blob.
+///
+/// Every plaintext blob such a file ever had, in any commit, is then sealed:
+/// moved to the keyed id of its hash and encrypted, so older commits still
+/// restore (with the password or an identity) without the store naming a
+/// confirmable hash. Blobs an unencrypted file still shares are left alone.
+/// Earlier git commits of the project repo do still contain the plaintext
+/// blobs; `history_exposed` reports it, and [`purge_history`] removes them.
+///
+/// When every entry already matches its flags this returns at once, without
+/// reading the index history.
+pub fn migrate_encryption(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+    keys: &Keyring,
+) -> anyhow::Result<MigrationResult> {
+    let mut result = MigrationResult::default();
+    let store_dir = config.project_store_dir(project_name)?;
+    crate::metadata::unlock_index(config, project_name, keys)?;
+    let mut index = Index::load_for_project(config, project_name)?;
+
+    // Nothing moves unless an entry disagrees with the manifest, which spares
+    // every backup from reading the whole index history below
+    let disagrees = |path: &PathBuf, entry: &FileEntry| {
+        project
+            .entry_for(path)
+            .is_some_and(|f| f.encrypted != (entry.encrypted && entry.keyed))
+    };
+    if !index.iter().any(|(path, entry)| disagrees(path, entry)) {
+        return Ok(result);
+    }
+
+    let mut key: Option<ObjectKey> = None;
+    let mut superseded = HashSet::new();
+
+    let mut paths: Vec<_> = index.entries.keys().cloned().collect();
+    paths.sort();
+
+    for path in paths {
+        let Some(want_encrypted) = project.entry_for(&path).map(|f| f.encrypted) else {
+            continue;
+        };
+        let entry = &index.entries[&path];
+        if want_encrypted == (entry.encrypted && entry.keyed) {
+            continue;
+        }
+
+        // Entries without a blob (e.g. only synced) are stored by the next backup
+        let content = if entry.keyed {
+            let blob = hash_to_path(&store_dir, &entry.hash);
+            if !blob.exists() {
+                continue;
+            }
+            keys.decrypt(&fs::read(&blob)?)?
+        } else {
+            match store::read_object(&store_dir, &entry.hash, keys, entry.encrypted)? {
+                Some(content) => content,
+                None => continue,
+            }
+        };
+        let content_hash = hash_bytes(&content);
+
+        let entry = index.entries.get_mut(&path).unwrap();
+        if want_encrypted {
+            if key.is_none() {
+                key = Some(ObjectKey::load_or_create(config, project_name, keys)?);
+            }
+            let id = key.as_ref().unwrap().object_id(&content_hash);
+            if !hash_to_path(&store_dir, &id).exists() {
+                store::write_object(&store_dir, &id, &content, Some(keys))?;
+            }
+            superseded.insert(entry.hash.clone());
+            entry.hash = id;
+            entry.encrypted = true;
+            entry.keyed = true;
+            result.encrypted += 1;
+        } else {
+            if !hash_to_path(&store_dir, &content_hash).exists() {
+                store::write_object(&store_dir, &content_hash, &content, None)?;
+            }
+            entry.hash = content_hash;
+            entry.encrypted = false;
+            entry.keyed = false;
+            result.decrypted += 1;
+        }
+    }
+
+    if result.is_empty() {
+        return Ok(result);
+    }
+
+    // Older versions of files that are now encrypted are sealed too: old
+    // commits keep restoring them through the keyed id of their hash
+    let (referenced, _) = store::referenced_objects(config, project_name, None)?;
+    let history = referenced.iter().filter(|(_, refs)| {
+        refs.iter().any(|r| {
+            !r.encrypted
+                && project
+                    .entry_for(&remap::localize(&config.path_remap, &r.path))
+                    .is_some_and(|f| f.encrypted)
+        })
+    });
+    superseded.extend(history.map(|(hash, _)| hash.clone()));
+
+    // Move the old plaintext blobs to keyed ids, keeping any that
+    // unencrypted files still share
+    let still_plain: HashSet<&String> = index
+        .iter()
+        .filter(|(_, e)| !e.encrypted)
+        .map(|(_, e)| &e.hash)
+        .collect();
+    let mut superseded: Vec<_> = superseded.into_iter().collect();
+    superseded.sort();
+    for hash in superseded {
+        if still_plain.contains(&hash) {
+            continue;
+        }
+        // Skip blobs already gone, and leave corrupt ones to fsck. Blobs an
+        // older version sealed in place under their hash move as well
+        let content = match fs::read(hash_to_path(&store_dir, &hash)) {
+            Ok(raw) if is_encrypted_blob(&raw) => match keys.decrypt(&raw) {
+                Ok(content) => content,
+                Err(_) => continue,
+            },
+            Ok(raw) => raw,
+            Err(_) => continue,
+        };
+        if hash_bytes(&content) != hash {
+            continue;
+        }
+
+        if key.is_none() {
+            key = Some(ObjectKey::load_or_create(config, project_name, keys)?);
+        }
+        let id = key.as_ref().unwrap().object_id(&hash);
+        if !hash_to_path(&store_dir, &id).exists() {
+            store::write_object(&store_dir, &id, &content, Some(keys))?;
+        }
+        store::remove_object(&store_dir, &hash)?;
+        result.sealed.push(hash);
+    }
+
+    if result.is_empty() {
+        return Ok(result);
+    }
+
+    index.save_for_project(config, project_name)?;
+    crate::metadata::sync_metadata(config, project_name, project, keys)?;
+
+    let project_dir = config.project_dir(project_name)?;
+    let blobs: Vec<String> = result.sealed.iter().map(|hash| store::object_path(hash)).collect();
+    for chunk in blobs.chunks(256) {
+        let chunk: Vec<&str> = chunk.iter().map(String::as_str).collect();
+        if !git::revisions_touching_any(&project_dir, &chunk, None)?.is_empty() {
+            result.history_exposed = true;
+            break;
+        }
+    }
+
+    if git::is_git_repo(&project_dir) {
+        git::stage_all(&project_dir)?;
+        if git::has_staged_changes(&project_dir)? {
+            let msg = format!(
+                "Encryption: {} encrypted, {} decrypted, {} plaintext blobs sealed",
+                result.encrypted,
+                result.decrypted,
+                result.sealed.len()
+            );
+            git::commit(&project_dir, &msg)?;
+            result.committed = true;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Drop every earlier commit of a project repo, with any plaintext it holds
+///
+/// The repo's history is replaced by a single commit of its current
+/// content (see [`git::purge_history`]), so older versions can no longer be
+/// restored, tags are gone, and the remote and other clones keep the old
+/// history until the branches are force-pushed and the clones made afresh.
+/// Returns the new commit.
+pub fn purge_history(config: &Config, project_name: &str) -> anyhow::Result<String> {
+    let project_dir = config.project_dir(project_name)?;
+    if !git::is_git_repo(&project_dir) {
+        anyhow::bail!("'{}' has no project repo", project_name);
+    }
+    git::purge_history(&project_dir, "Purge history")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::{TrackMode, TrackedFile};
+    use crate::testutil::TempData;
+
+    #[test]
+    fn test_object_id_is_keyed() {
+        let hash = hash_bytes(b"aws_secret_access_key = x\n");
+        let a = ObjectKey::generate();
+        let b = ObjectKey::generate();
+
+        assert_eq!(a.object_id(&hash), a.object_id(&hash));
+        assert_ne!(a.object_id(&hash), b.object_id(&hash));
+        assert_ne!(a.object_id(&hash), hash);
+        assert_eq!(a.object_id(&hash).len(), 64);
+    }
+
+    #[test]
+    fn test_seal_roundtrip() {
+        let key = ObjectKey::generate();
+        let sealed = key.seal(b"{\"entries\": {}}").unwrap();
+
+        assert!(is_encrypted_blob(&sealed));
+        assert_eq!(key.unseal(&sealed).unwrap(), b"{\"entries\": {}}");
+        assert!(ObjectKey::generate().unseal(&sealed).is_err());
+    }
+
+    #[test]
+    fn test_migrate_moves_plaintext_blobs_to_keyed_ids() {
+        let data = TempData::new("migrate");
+        let project_dir = data.project("p");
+        let store_dir = data.config.project_store_dir("p").unwrap();
+        let file = data.file("vimrc", b"set number\n");
+        let hash = hash_bytes(b"set number\n");
+
+        let mut project = Project::new();
+        project.add_file(TrackedFile::with_mode(file.to_string_lossy(), TrackMode::Backup));
+        crate::backup::backup_project_incremental(&data.config, "p", &project).unwrap();
+
+        project.files[0].encrypted = true;
+        let keys = Keyring::from_password(SecretString::from("hunter2".to_string()));
+        let result = migrate_encryption(&data.config, "p", &project, &keys).unwrap();
+        assert_eq!(result.encrypted, 1);
+        assert_eq!(result.sealed, vec![hash.clone()]);
+        assert!(result.history_exposed);
+        assert!(!hash_to_path(&store_dir, &hash).exists());
+        assert!(migrate_encryption(&data.config, "p", &project, &keys).unwrap().is_empty());
+
+        // Old revisions still read it, through the keyed id of its hash
+        let key = ObjectKey::cached(&data.config, "p").unwrap().unwrap();
+        let raw = fs::read(hash_to_path(&store_dir, &key.object_id(&hash))).unwrap();
+        assert!(is_encrypted_blob(&raw));
+        let content = store::read_object(&store_dir, &hash, &keys, false).unwrap();
+        assert_eq!(content.unwrap(), b"set number\n");
+
+        let blob = store::object_path(&hash);
+        assert!(!git::revisions_touching(&project_dir, &blob, None).unwrap().is_empty());
+        purge_history(&data.config, "p").unwrap();
+        assert!(git::revisions_touching(&project_dir, &blob, None).unwrap().is_empty());
+        assert_eq!(git::commit_count(&project_dir).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_load_needs_unlock_without_cache() {
+        let data = TempData::new("object-key");
+        data.project("p");
+        let keys = Keyring::from_password(SecretString::from("hunter2".to_string()));
+        let key = ObjectKey::load_or_create(&data.config, "p", &keys).unwrap();
+        let cache = data.config.project_object_key_cache_path("p").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&cache).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // A fresh clone has no cache, and recipients alone can't open the key
+        fs::remove_file(&cache).unwrap();
+        let err = ObjectKey::load(&data.config, "p", &Keyring::default()).unwrap_err();
+        assert!(err.downcast_ref::<ObjectKeyLocked>().is_some());
+
+        let unlocked = ObjectKey::load(&data.config, "p", &keys).unwrap().unwrap();
+        assert_eq!(unlocked.0, key.0);
+        assert!(ObjectKey::cached(&data.config, "p").unwrap().is_some());
+    }
+}
@@ -0,0 +1,248 @@
+//! Passwords from an external command
+//!
+//! `password_command` (in `config.toml`, or per project in the manifest)
+//! names a program that prints a project's password on its first line of
+//! output, such as `pass show dotmatrix/{project}`. It lets unattended
+//! backups run without a plaintext password file.
+//!
+//! The command is split into arguments like a shell would, but never run by
+//! one: there is no variable, glob or command expansion, and `{project}` is
+//! the only substitution. The password only travels over the command's
+//! stdout.
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use age::secrecy::SecretString;
+use anyhow::Context;
+
+use crate::config::Config;
+use crate::project::Project;
+
+/// The password command for a project: its own, or the global one
+pub fn password_command<'a>(config: &'a Config, project: &'a Project) -> Option<&'a str> {
+    project
+        .password_command
+        .as_deref()
+        .or(config.password_command.as_deref())
+        .filter(|c| !c.trim().is_empty())
+}
+
+/// Run a project's password command, if it has one
+pub fn command_password(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+) -> anyhow::Result<Option<SecretString>> {
+    let Some(command) = password_command(config, project) else {
+        return Ok(None);
+    };
+    let timeout = Duration::from_secs(config.password_command_timeout);
+    run_password_command(command, project_name, timeout).map(Some)
+}
+
+/// Run a password command and return the first line it prints
+///
+/// Fails if the command can't be started, exits unsuccessfully, prints
+/// nothing, or is still running after `timeout` (it is killed then). Output
+/// pipes held open past the deadline, e.g. by a process the command left
+/// behind, also fail it.
+pub fn run_password_command(
+    command: &str,
+    project_name: &str,
+    timeout: Duration,
+) -> anyhow::Result<SecretString> {
+    let args: Vec<String> = split_command(command)?
+        .into_iter()
+        .map(|arg| arg.replace("{project}", project_name))
+        .collect();
+    let program = &args[0];
+
+    let mut child = Command::new(program)
+        .args(&args[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run password command '{}'", program))?;
+
+    // Read both pipes while waiting, so a chatty command can't fill one and
+    // block
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!(
+                "Password command '{}' timed out after {}s",
+                program,
+                timeout.as_secs_f32()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let output = finish_reading(stdout, program, deadline, timeout)?;
+    if !status.success() {
+        let errors = finish_reading(stderr, program, deadline, timeout)?;
+        let errors = String::from_utf8_lossy(&errors);
+        match errors.lines().rev().find(|l| !l.trim().is_empty()) {
+            Some(last) => anyhow::bail!("Password command '{}' failed ({}): {}", program, status, last.trim()),
+            None => anyhow::bail!("Password command '{}' failed ({})", program, status),
+        }
+    }
+
+    let output = String::from_utf8(output)
+        .map_err(|_| anyhow::anyhow!("Password command '{}' printed invalid UTF-8", program))?;
+    let password = output.lines().next().unwrap_or("").trim_end_matches('\r');
+    if password.is_empty() {
+        anyhow::bail!("Password command '{}' printed no password", program);
+    }
+    Ok(SecretString::from(password.to_string()))
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        let _ = tx.send(buf);
+    });
+    rx
+}
+
+/// Wait for a pipe read by `read_in_background` to close, until `deadline`
+///
+/// A reader still blocked then is left behind; it ends whenever whatever
+/// holds the pipe exits.
+fn finish_reading(
+    reader: Option<Receiver<Vec<u8>>>,
+    program: &str,
+    deadline: Instant,
+    timeout: Duration,
+) -> anyhow::Result<Vec<u8>> {
+    let Some(reader) = reader else {
+        return Ok(Vec::new());
+    };
+    match reader.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        Ok(buf) => Ok(buf),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Ok(Vec::new()),
+        Err(mpsc::RecvTimeoutError::Timeout) => anyhow::bail!(
+            "Password command '{}' timed out after {}s: its output was still open after it exited",
+            program,
+            timeout.as_secs_f32()
+        ),
+    }
+}
+
+/// Split a command line into arguments
+///
+/// Whitespace separates arguments; single quotes keep everything literally,
+/// double quotes allow `\"` and `\\`, and a backslash outside quotes escapes
+/// the next character.
+fn split_command(command: &str) -> anyhow::Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                args.extend(current.take());
+            }
+            '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => anyhow::bail!("Unterminated ' in password command"),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => anyhow::bail!("Unterminated \" in password command"),
+                        },
+                        Some(c) => arg.push(c),
+                        None => anyhow::bail!("Unterminated \" in password command"),
+                    }
+                }
+            }
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Trailing \\ in password command"))?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+
+    if args.is_empty() {
+        anyhow::bail!("Password command is empty");
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command(r#"pass show  'dot matrix/{project}' "a \"b\"" c\ d ''"#).unwrap(),
+            vec!["pass", "show", "dot matrix/{project}", "a \"b\"", "c d", ""]
+        );
+        assert!(split_command("echo 'open").is_err());
+        assert!(split_command("   ").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_password_command() {
+        let timeout = Duration::from_secs(10);
+
+        let password = run_password_command("printf 'pw-%s\\nsecond line' {project}", "ssh", timeout).unwrap();
+        assert_eq!(password.expose_secret(), "pw-ssh");
+
+        // Arguments aren't expanded by a shell
+        let password = run_password_command("echo $HOME", "ssh", timeout).unwrap();
+        assert_eq!(password.expose_secret(), "$HOME");
+
+        let err = run_password_command("sh -c 'echo locked >&2; exit 3'", "ssh", timeout).unwrap_err();
+        assert!(err.to_string().contains("locked"), "{}", err);
+        assert!(run_password_command("true", "ssh", timeout).is_err());
+
+        let started = Instant::now();
+        let err = run_password_command("sleep 5", "ssh", Duration::from_millis(100)).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(4));
+
+        // A process left behind holding stdout doesn't hang the caller
+        let started = Instant::now();
+        let err = run_password_command("sh -c 'sleep 5 & echo pw'", "ssh", Duration::from_millis(300)).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+}
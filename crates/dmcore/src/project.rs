@@ -4,12 +4,15 @@
 //! on disk. Each file can have its own tracking mode (git, backup, or both).
 
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::config::expand_path;
+use crate::config::{contract_path, expand_path};
+use crate::exclude::ExcludeSet;
+use crate::index::Index;
+use crate::scanner::Scanner;
 
 /// A project is a logical grouping of files that may be scattered across disk
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Project {
     /// Optional description
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -22,22 +25,42 @@ pub struct Project {
     /// Files tracked in this project
     #[serde(default)]
     pub files: Vec<TrackedFile>,
-}
 
-impl Default for Project {
-    fn default() -> Self {
-        Self {
-            description: None,
-            remote: None,
-            files: Vec::new(),
-        }
-    }
+    /// Exclude patterns for this project, on top of the global list
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    /// age recipients (`age1...` or SSH public keys) that encrypted files are
+    /// encrypted to instead of the password
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+
+    /// Command printing this project's password, overriding the global
+    /// `password_command` (see `passcmd`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_command: Option<String>,
+
+    /// Files whose possible secrets are known to be harmless, as patterns
+    /// like `exclude` (see `secrets`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_secrets: Vec<String>,
+
+    /// Commit the index encrypted, as `index.json.age` (see `metadata`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypt_index: bool,
+
+    /// Also commit this manifest entry encrypted, as `project.toml.age`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypt_manifest: bool,
 }
 
 /// A file tracked within a project
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrackedFile {
     /// Path to the file (may contain ~ for home directory)
+    ///
+    /// May also be a glob pattern such as `~/.config/nvim/lua/**`, in which
+    /// case every matching file is tracked with this entry's settings.
     pub path: String,
 
     /// How this file should be tracked
@@ -47,6 +70,47 @@ pub struct TrackedFile {
     /// Whether this file should be encrypted in backups
     #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub encrypted: bool,
+
+    /// Whether this entry is a single file or a whole directory
+    #[serde(default, skip_serializing_if = "EntryKind::is_file")]
+    pub kind: EntryKind,
+
+    /// For directories: how deep to walk (1 = direct children only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+
+    /// For directories: include hidden files and directories
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+
+    /// For directories: honor `.gitignore` and `.ignore` files
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub gitignore: bool,
+}
+
+/// What a manifest entry refers to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// A single file (or a glob pattern matching files)
+    #[default]
+    File,
+    /// A directory, walked recursively
+    Directory,
+}
+
+impl EntryKind {
+    pub fn is_file(&self) -> bool {
+        *self == EntryKind::File
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
 }
 
 /// How a file should be tracked
@@ -81,11 +145,7 @@ impl std::fmt::Display for TrackMode {
 impl TrackedFile {
     /// Create a new tracked file with default settings
     pub fn new(path: impl Into<String>) -> Self {
-        Self {
-            path: path.into(),
-            track: TrackMode::default(),
-            encrypted: false,
-        }
+        Self::with_mode(path, TrackMode::default())
     }
 
     /// Create a new tracked file with a specific track mode
@@ -94,6 +154,18 @@ impl TrackedFile {
             path: path.into(),
             track,
             encrypted: false,
+            kind: EntryKind::File,
+            max_depth: None,
+            hidden: false,
+            gitignore: true,
+        }
+    }
+
+    /// Create a directory entry, walked with default options
+    pub fn directory(path: impl Into<String>, track: TrackMode) -> Self {
+        Self {
+            kind: EntryKind::Directory,
+            ..Self::with_mode(path, track)
         }
     }
 
@@ -107,6 +179,98 @@ impl TrackedFile {
         self.absolute_path().exists()
     }
 
+    /// Check if this entry is a glob pattern rather than a single file
+    pub fn is_glob(&self) -> bool {
+        self.path.contains(['*', '?', '['])
+    }
+
+    /// Check if this entry is a whole directory
+    pub fn is_directory(&self) -> bool {
+        self.kind == EntryKind::Directory
+    }
+
+    /// Check if this entry stands for many files (a glob or a directory)
+    pub fn expands(&self) -> bool {
+        self.is_glob() || self.is_directory()
+    }
+
+    /// Check if a concrete absolute path is covered by this entry
+    ///
+    /// For directories this checks depth and hidden components, but not
+    /// ignore files, so it may accept paths a walk would skip.
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.is_directory() {
+            return self.covers_in_dir(path);
+        }
+        if !self.is_glob() {
+            return self.absolute_path() == path;
+        }
+        match glob::Pattern::new(&self.absolute_path().to_string_lossy()) {
+            Ok(pattern) => pattern.matches_path_with(path, glob_options()),
+            Err(_) => false,
+        }
+    }
+
+    /// Expand this entry into the concrete files it currently refers to
+    ///
+    /// A plain entry expands to itself (whether or not it exists). A glob or
+    /// directory entry expands to one entry per matching regular file, each
+    /// inheriting the track mode and encryption setting of the entry. Matches
+    /// caught by `exclude` are dropped.
+    pub fn expand(&self, exclude: &ExcludeSet) -> Vec<TrackedFile> {
+        if self.is_directory() {
+            let walker = Scanner::new(self.absolute_path())
+                .with_max_depth(self.max_depth)
+                .with_hidden(self.hidden)
+                .with_gitignore(self.gitignore);
+            return walker.walk(exclude).iter().map(|p| self.concrete(p)).collect();
+        }
+        if !self.is_glob() {
+            return vec![self.clone()];
+        }
+
+        let pattern = self.absolute_path().to_string_lossy().to_string();
+        let mut paths: Vec<PathBuf> = match glob::glob_with(&pattern, glob_options()) {
+            Ok(iter) => iter
+                .filter_map(|p| p.ok())
+                .filter(|p| p.is_file() && !exclude.is_excluded(p))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+
+        paths.iter().map(|p| self.concrete(p)).collect()
+    }
+
+    /// Create a concrete entry for a path matched by this entry
+    pub fn concrete(&self, path: &Path) -> TrackedFile {
+        TrackedFile {
+            path: contract_path(path),
+            kind: EntryKind::File,
+            max_depth: None,
+            hidden: false,
+            gitignore: true,
+            ..self.clone()
+        }
+    }
+
+    /// Check if a path lies inside this directory entry within its limits
+    fn covers_in_dir(&self, path: &Path) -> bool {
+        let rel = match path.strip_prefix(self.absolute_path()) {
+            Ok(rel) if rel.components().next().is_some() => rel,
+            _ => return false,
+        };
+        if let Some(depth) = self.max_depth {
+            if rel.components().count() > depth {
+                return false;
+            }
+        }
+        self.hidden
+            || !rel
+                .components()
+                .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
     /// Check if this file should be tracked via git
     pub fn uses_git(&self) -> bool {
         matches!(self.track, TrackMode::Git | TrackMode::Both)
@@ -174,6 +338,38 @@ impl Project {
         &self.files
     }
 
+    /// Get the concrete files currently on disk, with glob entries expanded
+    pub fn resolved_files(&self, exclude: &ExcludeSet) -> Vec<TrackedFile> {
+        self.files.iter().flat_map(|f| f.expand(exclude)).collect()
+    }
+
+    /// Get the concrete files recorded in an index, with glob entries expanded
+    ///
+    /// Unlike `resolved_files`, this finds files matched by a pattern even if
+    /// they no longer exist on disk, which is what restore needs.
+    pub fn indexed_files(&self, index: &Index) -> Vec<TrackedFile> {
+        let mut files = Vec::new();
+        for file in &self.files {
+            if file.expands() {
+                let mut matched: Vec<&PathBuf> =
+                    index.entries.keys().filter(|p| file.matches(p)).collect();
+                matched.sort();
+                files.extend(matched.into_iter().map(|p| file.concrete(p)));
+            } else {
+                files.push(file.clone());
+            }
+        }
+        files
+    }
+
+    /// Find the manifest entry (plain path or pattern) that covers a path
+    pub fn entry_for(&self, path: &Path) -> Option<&TrackedFile> {
+        self.files
+            .iter()
+            .find(|f| !f.expands() && f.absolute_path() == path)
+            .or_else(|| self.files.iter().find(|f| f.matches(path)))
+    }
+
     /// Get the number of tracked files
     pub fn file_count(&self) -> usize {
         self.files.len()
@@ -194,3 +390,56 @@ impl Project {
         self.files.iter().any(|f| f.uses_backup())
     }
 }
+
+/// Match options for glob entries: `*` stays within one path component
+fn glob_options() -> glob::MatchOptions {
+    glob::MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        let file = TrackedFile::new("/etc/nvim/lua/*.lua");
+
+        assert!(file.is_glob());
+        assert!(file.matches(Path::new("/etc/nvim/lua/init.lua")));
+        assert!(!file.matches(Path::new("/etc/nvim/lua/plugins/lsp.lua")));
+        assert!(!file.matches(Path::new("/etc/nvim/init.vim")));
+    }
+
+    #[test]
+    fn test_recursive_glob_matches() {
+        let file = TrackedFile::new("/etc/nvim/**/*.lua");
+
+        assert!(file.matches(Path::new("/etc/nvim/init.lua")));
+        assert!(file.matches(Path::new("/etc/nvim/lua/plugins/lsp.lua")));
+    }
+
+    #[test]
+    fn test_plain_entry_expands_to_itself() {
+        let file = TrackedFile::new("/nonexistent/file.toml");
+
+        assert!(!file.is_glob());
+        assert_eq!(file.expand(&ExcludeSet::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_directory_matches() {
+        let mut dir = TrackedFile::directory("/etc/nvim", TrackMode::Git);
+        dir.max_depth = Some(2);
+
+        assert!(dir.expands());
+        assert!(dir.matches(Path::new("/etc/nvim/init.lua")));
+        assert!(dir.matches(Path::new("/etc/nvim/lua/plugins.lua")));
+        assert!(!dir.matches(Path::new("/etc/nvim/lua/plugins/lsp.lua")));
+        assert!(!dir.matches(Path::new("/etc/nvim/.netrwhist")));
+        assert!(!dir.matches(Path::new("/etc/nvim")));
+    }
+}
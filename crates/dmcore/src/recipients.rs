@@ -0,0 +1,202 @@
+//! Managing a project's encryption recipients
+//!
+//! Every encrypted blob in a project store is wrapped for the project's
+//! `recipients` (or its password if the list is empty). Changing the list
+//! re-wraps the whole store, sealed blobs and `object-key.age` included, and
+//! commits the result. Removed recipients can't open anything stored from
+//! then on, though earlier commits of the project repo still hold the blobs
+//! as they were wrapped before.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::canary;
+use crate::config::{expand_path, Config};
+use crate::crypto::{parse_recipient, Keyring};
+use crate::git;
+use crate::project::Project;
+use crate::store::{self, is_encrypted_blob};
+
+/// Result of re-wrapping a project store
+#[derive(Debug, Clone, Default)]
+pub struct RewrapResult {
+    /// Encrypted blobs re-wrapped for the new recipients
+    pub rewrapped: usize,
+    /// Whether `object-key.age` was re-wrapped too
+    pub object_key: bool,
+    /// Whether a git commit was made
+    pub committed: bool,
+}
+
+/// Expand a recipient argument: a key itself, or a file of keys such as
+/// `~/.ssh/id_ed25519.pub` (one per line, `#` comments skipped)
+pub fn read_recipients(arg: &str) -> anyhow::Result<Vec<String>> {
+    let path = expand_path(arg);
+    let keys = if path.is_file() {
+        fs::read_to_string(&path)?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(String::from)
+            .collect()
+    } else {
+        vec![arg.trim().to_string()]
+    };
+
+    for key in &keys {
+        parse_recipient(key)?;
+    }
+    Ok(keys)
+}
+
+/// Add recipients to a project, returning the ones that were new
+pub fn add_recipients(project: &mut Project, recipients: &[String]) -> Vec<String> {
+    let mut added = Vec::new();
+    for recipient in recipients {
+        if !project.recipients.contains(recipient) {
+            project.recipients.push(recipient.clone());
+            added.push(recipient.clone());
+        }
+    }
+    added
+}
+
+/// Remove recipients from a project, returning the ones it had
+pub fn remove_recipients(project: &mut Project, recipients: &[String]) -> Vec<String> {
+    let removed: Vec<String> = project
+        .recipients
+        .iter()
+        .filter(|r| recipients.contains(r))
+        .cloned()
+        .collect();
+    project.recipients.retain(|r| !removed.contains(r));
+    removed
+}
+
+/// Check if re-wrapping a project store needs the password
+///
+/// True if the project will have no recipients, or the store (or its object
+/// key) still holds passphrase-encrypted blobs.
+pub fn rewrap_needs_password(
+    config: &Config,
+    project_name: &str,
+    project: &Project,
+) -> anyhow::Result<bool> {
+    Ok(project.recipients.is_empty() || has_passphrase_blobs(config, project_name)?)
+}
+
+/// Check if a project store (or its object key) holds passphrase-encrypted
+/// blobs
+pub fn has_passphrase_blobs(config: &Config, project_name: &str) -> anyhow::Result<bool> {
+    let store_dir = config.project_store_dir(project_name)?;
+    let mut paths: Vec<PathBuf> = store::stored_objects(&store_dir)?
+        .into_iter()
+        .map(|o| o.path)
+        .collect();
+    paths.push(config.project_object_key_path(project_name)?);
+
+    Ok(paths.iter().any(|p| store::is_passphrase_blob(p)))
+}
+
+/// Re-wrap every encrypted blob in a project store for `keys`
+///
+/// `keys` must open every encrypted blob and encrypt for the new
+/// recipients. All blobs are decrypted and re-encrypted to temporary files
+/// first, so a blob no key opens leaves the store untouched. The result is
+/// committed with `message`.
+pub fn rewrap_store(
+    config: &Config,
+    project_name: &str,
+    keys: &Keyring,
+    message: &str,
+) -> anyhow::Result<RewrapResult> {
+    let mut result = RewrapResult::default();
+    let store_dir = config.project_store_dir(project_name)?;
+
+    let mut targets: Vec<PathBuf> = store::stored_objects(&store_dir)?
+        .into_iter()
+        .map(|o| o.path)
+        .collect();
+    let key_path = config.project_object_key_path(project_name)?;
+    if key_path.exists() {
+        targets.push(key_path.clone());
+    }
+
+    let mut staged: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let prepared = (|| -> anyhow::Result<()> {
+        for path in &targets {
+            let raw = fs::read(path)?;
+            if !is_encrypted_blob(&raw) {
+                continue;
+            }
+            let content = keys
+                .decrypt(&raw)
+                .map_err(|e| anyhow::anyhow!("Cannot re-wrap {}: {}", display_name(&store_dir, path), e))?;
+
+            let tmp = path.with_extension("rewrap");
+            fs::write(&tmp, keys.encrypt(&content)?)?;
+            staged.push((tmp, path.clone()));
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = prepared {
+        for (tmp, _) in &staged {
+            let _ = fs::remove_file(tmp);
+        }
+        return Err(e);
+    }
+
+    for (tmp, path) in &staged {
+        fs::rename(tmp, path)?;
+        if *path == key_path {
+            result.object_key = true;
+        } else {
+            result.rewrapped += 1;
+        }
+    }
+
+    canary::sync_password_check(config, project_name, keys)?;
+
+    let project_dir = config.project_dir(project_name)?;
+    if git::is_git_repo(&project_dir) {
+        git::stage_all(&project_dir)?;
+        if git::has_staged_changes(&project_dir)? {
+            git::commit(&project_dir, message)?;
+            result.committed = true;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Name a store path by its (short) hash for messages
+fn display_name(store_dir: &Path, path: &Path) -> String {
+    match path.strip_prefix(store_dir) {
+        Ok(rel) => rel.to_string_lossy().replace('/', "").chars().take(12).collect(),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_remove_recipients() {
+        let alice = age::x25519::Identity::generate().to_public().to_string();
+        let bob = age::x25519::Identity::generate().to_public().to_string();
+        let both = vec![alice.clone(), bob.clone()];
+        let mut project = Project::default();
+
+        assert_eq!(add_recipients(&mut project, &both[..1]), &both[..1]);
+        assert!(add_recipients(&mut project, &both[..1]).is_empty());
+        assert_eq!(add_recipients(&mut project, &both), &both[1..]);
+        assert_eq!(project.recipients, both);
+
+        assert_eq!(remove_recipients(&mut project, &both[..1]), vec![alice]);
+        assert_eq!(project.recipients, vec![bob.clone()]);
+        assert_eq!(read_recipients(&bob).unwrap(), vec![bob]);
+        assert!(read_recipients("age1notakey").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! Path remapping for restores
+//!
+//! A remap rule rewrites a path prefix, so files backed up as
+//! `/home/olduser/.bashrc` can be restored to `/home/newuser/.bashrc`.
+//! Rules are written as `from=to` on the command line, or as
+//! `[[path_remap]]` tables in `config.toml`:
+//!
+//! ```toml
+//! [[path_remap]]
+//! from = "/home/*"
+//! to = "~"
+//!
+//! [[path_remap]]
+//! from = "/etc/nixos"
+//! to = "~/nixos"
+//! ```
+//!
+//! Either side may start with `~`. A `*` component in `from` matches any
+//! single name, so `/Users/*` covers every macOS home. The first matching
+//! rule wins.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::config::expand_path;
+
+/// A single prefix rewrite rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRemap {
+    pub from: String,
+    pub to: String,
+}
+
+impl PathRemap {
+    pub fn new(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+        }
+    }
+
+    /// Parse a `from=to` rule
+    pub fn parse(spec: &str) -> anyhow::Result<Self> {
+        let (from, to) = spec
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid remap '{}', expected FROM=TO", spec))?;
+        if from.is_empty() || to.is_empty() {
+            anyhow::bail!("Invalid remap '{}', expected FROM=TO", spec);
+        }
+        Ok(Self::new(from, to))
+    }
+
+    /// Rewrite `path` if it lies under `from`
+    pub fn apply(&self, path: &Path) -> Option<PathBuf> {
+        let from = expand_path(&self.from);
+        let mut rest = path.components();
+        for want in from.components() {
+            let got = rest.next()?;
+            if want.as_os_str() != "*" && want != got {
+                return None;
+            }
+        }
+
+        let to = expand_path(&self.to);
+        let rest = rest.as_path();
+        if rest.as_os_str().is_empty() {
+            Some(to)
+        } else {
+            Some(to.join(rest))
+        }
+    }
+}
+
+/// Rules used when `config.toml` has no `path_remap`: any Linux or macOS
+/// home maps to the current one
+pub fn default_rules() -> Vec<PathRemap> {
+    vec![PathRemap::new("/home/*", "~"), PathRemap::new("/Users/*", "~")]
+}
+
+/// Apply the first matching rule, or return the path unchanged
+pub fn apply_all(remaps: &[PathRemap], path: &Path) -> PathBuf {
+    remaps
+        .iter()
+        .find_map(|r| r.apply(path))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Map a path recorded on another machine (or as `~/...`) to this one
+pub fn localize(remaps: &[PathRemap], path: &Path) -> PathBuf {
+    apply_all(remaps, &expand_path(&path.to_string_lossy()))
+}
+
+/// Destination for `path` when extracting below `base` instead of in place
+///
+/// Uses the archive and mirror layout: home-relative, `_root/` for the rest.
+pub fn extract_path(base: &Path, path: &Path) -> PathBuf {
+    crate::mirror::mirror_path(base, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_apply() {
+        let remap = PathRemap::parse("/home/old=/home/new").unwrap();
+        assert_eq!(
+            remap.apply(Path::new("/home/old/.bashrc")),
+            Some(PathBuf::from("/home/new/.bashrc"))
+        );
+        // Component-wise prefix, not string prefix
+        assert_eq!(remap.apply(Path::new("/home/older/.bashrc")), None);
+        assert!(PathRemap::parse("/home/old").is_err());
+    }
+
+    #[test]
+    fn test_wildcard_to_home() {
+        let home = dirs::home_dir().unwrap();
+        let rules = default_rules();
+
+        assert_eq!(
+            apply_all(&rules, Path::new("/Users/alice/.zshrc")),
+            home.join(".zshrc")
+        );
+        assert_eq!(
+            apply_all(&rules, Path::new("/home/bob/.config/nvim/init.lua")),
+            home.join(".config/nvim/init.lua")
+        );
+        assert_eq!(apply_all(&rules, Path::new("/etc/hosts")), PathBuf::from("/etc/hosts"));
+        assert_eq!(localize(&rules, Path::new("~/.vimrc")), home.join(".vimrc"));
+    }
+
+    #[test]
+    fn test_apply_all_first_match() {
+        let remaps = vec![
+            PathRemap::new("/etc/nginx", "/srv/nginx"),
+            PathRemap::new("/etc", "/tmp/etc"),
+        ];
+        assert_eq!(
+            apply_all(&remaps, Path::new("/etc/nginx/nginx.conf")),
+            PathBuf::from("/srv/nginx/nginx.conf")
+        );
+        assert_eq!(
+            apply_all(&remaps, Path::new("/etc/hosts")),
+            PathBuf::from("/tmp/etc/hosts")
+        );
+        assert_eq!(
+            apply_all(&remaps, Path::new("/var/log")),
+            PathBuf::from("/var/log")
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Changing the password or recipients of an encrypted store
+//!
+//! Rotation decrypts every encrypted blob referenced by any index revision
+//! with the old keys, re-encrypts it with the new ones and commits the
+//! result. `object-key.age` is re-encrypted too; the object key itself is
+//! kept, so blob ids and the index history stay valid.
+//!
+//! Progress is journaled in `rotation/` inside the project directory
+//! (gitignored), so an interrupted rotation picks up where it stopped when
+//! run again with the same old and new keys.
+
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::canary;
+use crate::config::Config;
+use crate::crypto::Keyring;
+use crate::git;
+use crate::store::{self, hash_to_path, is_encrypted_blob};
+
+/// Directory of the rotation journal inside a project directory
+const ROTATION_DIR: &str = "rotation";
+
+/// Content of the journal's check file, encrypted with the new keys
+const CHECK: &[u8] = b"dotmatrix rotation";
+
+/// Progress of a running rotation
+#[derive(Debug, Clone)]
+pub struct RotateProgress {
+    /// Blobs handled so far, including ones done by an earlier run
+    pub done: usize,
+    /// Encrypted blobs to rotate in total
+    pub total: usize,
+    /// Hash of the blob just handled
+    pub hash: String,
+}
+
+/// Result of rotating a project store
+#[derive(Debug, Clone, Default)]
+pub struct RotateResult {
+    /// Blobs re-encrypted by this run
+    pub rotated: usize,
+    /// Blobs an interrupted earlier run had already re-encrypted
+    pub resumed: usize,
+    /// Whether `object-key.age` was re-encrypted
+    pub object_key: bool,
+    /// Whether a git commit was made
+    pub committed: bool,
+}
+
+/// Check if a project has an interrupted rotation to resume
+pub fn rotation_in_progress(config: &Config, project_name: &str) -> anyhow::Result<bool> {
+    Ok(config.project_dir(project_name)?.join(ROTATION_DIR).exists())
+}
+
+/// Re-encrypt every referenced encrypted blob from `old` to `new` keys
+///
+/// `progress` is called after each blob. If a journal from an interrupted
+/// run exists, `new` must be the same keys it used.
+pub fn rotate_store<F>(
+    config: &Config,
+    project_name: &str,
+    old: &Keyring,
+    new: &Keyring,
+    mut progress: F,
+) -> anyhow::Result<RotateResult>
+where
+    F: FnMut(&RotateProgress),
+{
+    if !new.can_encrypt() {
+        anyhow::bail!("A new password or recipients are required to rotate");
+    }
+
+    let mut result = RotateResult::default();
+    let project_dir = config.project_dir(project_name)?;
+    let store_dir = config.project_store_dir(project_name)?;
+    let journal = Journal::open(&project_dir, new)?;
+
+    crate::metadata::unlock_index(config, project_name, old)?;
+    let (referenced, _) = store::referenced_objects(config, project_name, None)?;
+    // Blobs moved to a keyed id are rotated there
+    let mut hashes = Vec::new();
+    for hash in referenced.keys() {
+        if let Some(id) = store::locate(&store_dir, hash)? {
+            if store::read_header(&hash_to_path(&store_dir, &id)).is_ok_and(|raw| is_encrypted_blob(&raw)) {
+                hashes.push(id);
+            }
+        }
+    }
+    hashes.sort();
+    hashes.dedup();
+
+    let total = hashes.len();
+    for (i, hash) in hashes.iter().enumerate() {
+        if journal.done.contains(hash) {
+            result.resumed += 1;
+        } else {
+            let raw = fs::read(hash_to_path(&store_dir, hash))?;
+            match rotate_blob(&raw, old, new, hash)? {
+                Some(content) => {
+                    store::write_object(&store_dir, hash, &content, Some(new))?;
+                    result.rotated += 1;
+                }
+                // Rotated just before an interruption, before it was journaled
+                None => result.resumed += 1,
+            }
+            journal.record(hash)?;
+        }
+
+        progress(&RotateProgress {
+            done: i + 1,
+            total,
+            hash: hash.clone(),
+        });
+    }
+
+    let key_path = config.project_object_key_path(project_name)?;
+    if key_path.exists() {
+        let raw = fs::read(&key_path)?;
+        if let Some(content) = rotate_blob(&raw, old, new, "object key")? {
+            let tmp = key_path.with_extension("tmp");
+            fs::write(&tmp, new.encrypt(&content)?)?;
+            fs::rename(&tmp, &key_path)?;
+            result.object_key = true;
+        }
+    }
+
+    canary::sync_password_check(config, project_name, new)?;
+
+    if git::is_git_repo(&project_dir) {
+        git::stage_all(&project_dir)?;
+        if git::has_staged_changes(&project_dir)? {
+            let msg = format!(
+                "Rotate encryption: {} blobs re-encrypted",
+                result.rotated + result.resumed
+            );
+            git::commit(&project_dir, &msg)?;
+            result.committed = true;
+        }
+    }
+
+    journal.finish()?;
+    Ok(result)
+}
+
+/// Decrypt a blob with the old keys, or return `None` if the new keys
+/// already open it
+fn rotate_blob(
+    raw: &[u8],
+    old: &Keyring,
+    new: &Keyring,
+    name: &str,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    match old.decrypt(raw) {
+        Ok(content) => Ok(Some(content)),
+        Err(_) if new.can_decrypt(raw) && new.decrypt(raw).is_ok() => Ok(None),
+        Err(e) => Err(anyhow::anyhow!(
+            "Cannot decrypt {} with the old keys: {}",
+            &name[..12.min(name.len())],
+            e
+        )),
+    }
+}
+
+/// Hashes already rotated, kept on disk until the rotation is committed
+struct Journal {
+    dir: PathBuf,
+    done: HashSet<String>,
+}
+
+impl Journal {
+    /// Open the journal, starting a new one or checking that a resumed one
+    /// was written for the same new keys
+    fn open(project_dir: &Path, new: &Keyring) -> anyhow::Result<Self> {
+        let dir = project_dir.join(ROTATION_DIR);
+        let check = dir.join("check.age");
+
+        if check.exists() {
+            let opened = new.decrypt(&fs::read(&check)?).is_ok_and(|c| c == CHECK);
+            if !opened {
+                anyhow::bail!(
+                    "An interrupted rotation used different new keys; resume it with the same password or recipients"
+                );
+            }
+        } else {
+            if git::is_git_repo(project_dir) {
+                git::ensure_ignored(project_dir, &format!("{}/", ROTATION_DIR))?;
+            }
+            fs::create_dir_all(&dir)?;
+            fs::write(&check, new.encrypt(CHECK)?)?;
+        }
+
+        let done = fs::read_to_string(dir.join("done"))
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect();
+        Ok(Self { dir, done })
+    }
+
+    fn record(&self, hash: &str) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join("done"))?;
+        writeln!(file, "{}", hash)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::SecretString;
+
+    #[test]
+    fn test_rotate_blob() {
+        let old = Keyring::from_password(SecretString::from("old".to_string()));
+        let new = Keyring::from_password(SecretString::from("new".to_string()));
+        let other = Keyring::from_password(SecretString::from("other".to_string()));
+
+        let blob = old.encrypt(b"PresharedKey = x\n").unwrap();
+        assert_eq!(
+            rotate_blob(&blob, &old, &new, "blob").unwrap().unwrap(),
+            b"PresharedKey = x\n"
+        );
+
+        // Already rotated by an interrupted run
+        let rotated = new.encrypt(b"PresharedKey = x\n").unwrap();
+        assert!(rotate_blob(&rotated, &old, &new, "blob").unwrap().is_none());
+
+        assert!(rotate_blob(&blob, &other, &new, "blob").is_err());
+    }
+
+    #[test]
+    fn test_rotate_store_resumes_interrupted_run() {
+        use crate::project::{Project, TrackMode, TrackedFile};
+        use crate::testutil::TempData;
+
+        let data = TempData::new("rotate-resume");
+        let project_dir = data.project("p");
+        let store_dir = data.config.project_store_dir("p").unwrap();
+        let old = Keyring::from_password(SecretString::from("old".to_string()));
+        let new = Keyring::from_password(SecretString::from("new".to_string()));
+
+        let mut project = Project::new();
+        for (name, content) in [("a.conf", "token = a\n"), ("b.conf", "token = b\n"), ("c.conf", "token = c\n")] {
+            let path = data.file(name, content.as_bytes());
+            let mut file = TrackedFile::with_mode(path.to_string_lossy(), TrackMode::Backup);
+            file.encrypted = true;
+            project.add_file(file);
+        }
+        crate::backup::backup_project_incremental_encrypted(&data.config, "p", &project, &old).unwrap();
+        let blobs = store::stored_objects(&store_dir).unwrap();
+        assert_eq!(blobs.len(), 3);
+
+        // Interrupted after journaling the first blob and rewriting the
+        // second, before the second was journaled
+        let journal = Journal::open(&project_dir, &new).unwrap();
+        for blob in &blobs[..2] {
+            let content = old.decrypt(&fs::read(&blob.path).unwrap()).unwrap();
+            store::write_object(&store_dir, &blob.hash, &content, Some(&new)).unwrap();
+        }
+        journal.record(&blobs[0].hash).unwrap();
+        drop(journal);
+        assert!(rotation_in_progress(&data.config, "p").unwrap());
+
+        let other = Keyring::from_password(SecretString::from("other".to_string()));
+        let err = rotate_store(&data.config, "p", &old, &other, |_| {}).unwrap_err();
+        assert!(err.to_string().contains("different new keys"), "{}", err);
+
+        let result = rotate_store(&data.config, "p", &old, &new, |_| {}).unwrap();
+        assert_eq!(result.rotated, 1);
+        assert_eq!(result.resumed, 2);
+        assert!(result.committed);
+        assert!(!rotation_in_progress(&data.config, "p").unwrap());
+
+        let mut contents = Vec::new();
+        for blob in &blobs {
+            let raw = fs::read(&blob.path).unwrap();
+            assert!(old.decrypt(&raw).is_err());
+            contents.push(new.decrypt(&raw).unwrap());
+        }
+        contents.sort();
+        assert_eq!(contents, [b"token = a\n", b"token = b\n", b"token = c\n"]);
+        assert!(git::is_clean(&project_dir).unwrap());
+    }
+}
@@ -4,9 +4,11 @@
 
 use sha2::{Digest, Sha256};
 use std::fs::{self, File};
-use std::io::{BufReader, Read};
-use std::path::Path;
+use std::io::{self, BufReader, Read};
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
 
+use crate::exclude::ExcludeSet;
 use crate::index::Index;
 use crate::project::{Project, TrackedFile};
 
@@ -27,6 +29,12 @@ pub struct ScanResult {
 
     /// Track mode
     pub track_mode: crate::project::TrackMode,
+
+    /// Whether the file is marked for encryption
+    pub encrypted: bool,
+
+    /// The glob pattern this file was matched by (None for plain entries)
+    pub pattern: Option<String>,
 }
 
 /// Status of a tracked file
@@ -68,21 +76,48 @@ impl FileStatus {
 
 /// Calculate SHA256 hash of a file
 pub fn hash_file(path: &Path) -> anyhow::Result<String> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-    let mut hasher = Sha256::new();
-    let mut buffer = [0u8; 8192];
-
-    loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
+    Ok(hash_reader(BufReader::new(File::open(path)?))?)
+}
+
+/// Calculate SHA256 hash of everything a reader yields
+pub fn hash_reader<R: Read>(reader: R) -> io::Result<String> {
+    let mut reader = HashingReader::new(reader);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finish())
+}
+
+/// Reader that hashes everything read through it, for hashing content
+/// while copying it elsewhere
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
         }
-        hasher.update(&buffer[..bytes_read]);
     }
 
-    let hash = hasher.finalize();
-    Ok(format!("{:x}", hash))
+    /// SHA256 of everything read so far
+    pub fn finish(self) -> String {
+        format!("{:x}", self.hasher.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Calculate SHA256 hash of in-memory content
+pub fn hash_bytes(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
 }
 
 /// Get file metadata
@@ -97,6 +132,72 @@ pub fn file_metadata(path: &Path) -> anyhow::Result<(u64, u64)> {
     Ok((size, modified))
 }
 
+/// Recursive directory walker for directory entries
+///
+/// Honors `.gitignore`, `.ignore` and `.git/info/exclude` (even outside a git
+/// repository), skips hidden files unless asked, and stops at `max_depth`.
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    root: PathBuf,
+    max_depth: Option<usize>,
+    hidden: bool,
+    gitignore: bool,
+}
+
+impl Scanner {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            max_depth: None,
+            hidden: false,
+            gitignore: true,
+        }
+    }
+
+    pub fn with_max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn with_gitignore(mut self, respect: bool) -> Self {
+        self.gitignore = respect;
+        self
+    }
+
+    /// Walk the directory and return the regular files found, sorted
+    ///
+    /// Excluded directories are pruned rather than walked.
+    pub fn walk(&self, exclude: &ExcludeSet) -> Vec<PathBuf> {
+        let mut builder = WalkBuilder::new(&self.root);
+        builder
+            .hidden(!self.hidden)
+            .git_ignore(self.gitignore)
+            .git_global(self.gitignore)
+            .git_exclude(self.gitignore)
+            .ignore(self.gitignore)
+            .parents(self.gitignore)
+            .require_git(false)
+            .max_depth(self.max_depth);
+
+        let exclude = exclude.clone();
+        builder.filter_entry(move |entry| !exclude.is_excluded(entry.path()));
+
+        let mut files: Vec<PathBuf> = builder
+            .build()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_file()))
+            .map(|entry| entry.into_path())
+            .collect();
+        files.sort();
+        files
+    }
+}
+
 /// Scan a tracked file and determine its status
 pub fn scan_file(file: &TrackedFile, index: &Index) -> ScanResult {
     let abs_path = file.absolute_path();
@@ -108,6 +209,8 @@ pub fn scan_file(file: &TrackedFile, index: &Index) -> ScanResult {
             current_hash: None,
             current_size: None,
             track_mode: file.track,
+            encrypted: file.encrypted,
+            pattern: None,
         };
     }
 
@@ -123,11 +226,21 @@ pub fn scan_file(file: &TrackedFile, index: &Index) -> ScanResult {
                 current_hash: None,
                 current_size: None,
                 track_mode: file.track,
+                encrypted: file.encrypted,
+                pattern: None,
             };
         }
     };
 
     let status = match index.get(&abs_path) {
+        // Keyed ids can't be recomputed without the key, so fall back to
+        // size and mtime
+        Some(entry) if entry.keyed => match file_metadata(&abs_path) {
+            Ok((size, modified)) if size == entry.size && modified == entry.modified => {
+                FileStatus::Synced
+            }
+            _ => FileStatus::Drifted,
+        },
         Some(entry) if Some(&entry.hash) == current_hash.as_ref() => FileStatus::Synced,
         Some(_) => FileStatus::Drifted,
         None => FileStatus::New,
@@ -139,15 +252,61 @@ pub fn scan_file(file: &TrackedFile, index: &Index) -> ScanResult {
         current_hash,
         current_size,
         track_mode: file.track,
+        encrypted: file.encrypted,
+        pattern: None,
     }
 }
 
+/// Scan a glob or directory entry, producing one result per matching file
+///
+/// Files that match on disk are scanned normally (unknown ones show up as
+/// New). Files recorded in the index that no longer exist are reported as
+/// Missing. A pattern or directory that matches nothing and does not exist
+/// yields a single Missing result for the entry itself so it stays visible.
+/// Excluded paths are skipped in both directions.
+pub fn scan_pattern(file: &TrackedFile, index: &Index, exclude: &ExcludeSet) -> Vec<ScanResult> {
+    let matches = file.expand(exclude);
+
+    let mut results: Vec<ScanResult> = matches
+        .iter()
+        .map(|f| ScanResult {
+            pattern: Some(file.path.clone()),
+            ..scan_file(f, index)
+        })
+        .collect();
+
+    let mut gone: Vec<_> = index
+        .entries
+        .keys()
+        .filter(|p| file.matches(p) && !p.exists() && !exclude.is_excluded(p))
+        .collect();
+    gone.sort();
+    for path in gone {
+        results.push(ScanResult {
+            pattern: Some(file.path.clone()),
+            ..scan_file(&file.concrete(path), index)
+        });
+    }
+
+    if results.is_empty() && !file.exists() {
+        results.push(scan_file(file, index));
+    }
+
+    results
+}
+
 /// Scan all files in a project
-pub fn scan_project(project: &Project, index: &Index) -> Vec<ScanResult> {
+pub fn scan_project(project: &Project, index: &Index, exclude: &ExcludeSet) -> Vec<ScanResult> {
     project
         .files
         .iter()
-        .map(|f| scan_file(f, index))
+        .flat_map(|f| {
+            if f.expands() {
+                scan_pattern(f, index, exclude)
+            } else {
+                vec![scan_file(f, index)]
+            }
+        })
         .collect()
 }
 
@@ -164,8 +323,10 @@ pub struct ProjectSummary {
 
 impl ProjectSummary {
     pub fn from_results(results: &[ScanResult]) -> Self {
-        let mut summary = Self::default();
-        summary.total = results.len();
+        let mut summary = Self {
+            total: results.len(),
+            ..Self::default()
+        };
         for r in results {
             match r.status {
                 FileStatus::Synced => summary.synced += 1,
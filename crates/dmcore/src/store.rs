@@ -3,14 +3,21 @@
 //! Files are stored by their SHA256 hash, enabling deduplication.
 //! Structure: store/ab/cdef1234... (first 2 chars as subdirectory)
 
-use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use age::secrecy::SecretString;
 
 use crate::config::Config;
-use crate::crypto::{decrypt_file, encrypt_file};
-use crate::scanner::hash_file;
+use crate::crypto::{encrypt_file, is_passphrase_encrypted, Keyring};
+use crate::git;
+use crate::index::Index;
+use crate::metadata;
+use crate::objectid::{ObjectKey, ObjectKeyLocked};
+use crate::scanner::{hash_bytes, hash_file, HashingReader};
 
 /// Result of storing a file
 #[derive(Debug, Clone)]
@@ -32,6 +39,29 @@ pub fn hash_to_path(store_dir: &Path, hash: &str) -> PathBuf {
     store_dir.join(prefix).join(rest)
 }
 
+/// Path of a project blob relative to the project repo, as git records it
+pub(crate) fn object_path(hash: &str) -> String {
+    let (prefix, rest) = hash.split_at(2.min(hash.len()));
+    format!("store/{}/{}", prefix, rest)
+}
+
+/// Find the id a blob is stored under
+///
+/// Plaintext blobs of files that became encrypted move to the keyed id of
+/// their hash (see `objectid::migrate_encryption`), so a hash that is not in
+/// the store is looked up under its keyed id too, once the project's object
+/// key is cached.
+pub(crate) fn locate(store_dir: &Path, hash: &str) -> anyhow::Result<Option<String>> {
+    if hash_to_path(store_dir, hash).exists() {
+        return Ok(Some(hash.to_string()));
+    }
+    let Some(key) = store_dir.parent().map(ObjectKey::cached_in).transpose()?.flatten() else {
+        return Ok(None);
+    };
+    let id = key.object_id(hash);
+    Ok(hash_to_path(store_dir, &id).exists().then_some(id))
+}
+
 /// Store a file in the content-addressed store
 pub fn store_file(config: &Config, source: &Path) -> anyhow::Result<StoreResult> {
     let store_dir = config.store_dir()?;
@@ -67,20 +97,7 @@ pub fn store_file(config: &Config, source: &Path) -> anyhow::Result<StoreResult>
 
 /// Retrieve a file from the store by hash
 pub fn retrieve_file(config: &Config, hash: &str, dest: &Path) -> anyhow::Result<bool> {
-    let store_dir = config.store_dir()?;
-    let storage_path = hash_to_path(&store_dir, hash);
-
-    if !storage_path.exists() {
-        return Ok(false);
-    }
-
-    // Create parent directory for destination
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent)?;
-    }
-
-    fs::copy(&storage_path, dest)?;
-    Ok(true)
+    retrieve_file_from(&config.store_dir()?, hash, dest)
 }
 
 /// Check if a hash exists in the store
@@ -199,77 +216,79 @@ pub fn retrieve_file_encrypted(
     password: Option<&SecretString>,
     encrypted: bool,
 ) -> anyhow::Result<bool> {
-    let store_dir = config.store_dir()?;
-    let storage_path = hash_to_path(&store_dir, hash);
-
-    if !storage_path.exists() {
-        return Ok(false);
-    }
-
-    // Create parent directory for destination
-    if let Some(parent) = dest.parent() {
-        fs::create_dir_all(parent)?;
-    }
-
-    // Retrieve with or without decryption
-    if encrypted {
-        match password {
-            Some(pwd) => {
-                decrypt_file(&storage_path, dest, pwd)?;
-            }
-            None => {
-                anyhow::bail!("Password required to retrieve encrypted file");
-            }
-        }
-    } else {
-        fs::copy(&storage_path, dest)?;
-    }
-
-    Ok(true)
+    let keys = password.cloned().map(Keyring::from_password).unwrap_or_default();
+    retrieve_file_from_encrypted(&config.store_dir()?, hash, dest, &keys, encrypted)
 }
 
 /// Store a file in a specific store directory
+///
+/// The file is hashed first, so content already in the store is read once
+/// and never copied. New content is streamed to a temporary file, hashed
+/// again on the way, and renamed into place.
 pub fn store_file_to(store_dir: &Path, source: &Path) -> anyhow::Result<StoreResult> {
     fs::create_dir_all(store_dir)?;
 
-    // Hash the file
     let hash = hash_file(source)?;
-    let size = fs::metadata(source)?.len();
-
-    // Determine storage path
     let storage_path = hash_to_path(store_dir, &hash);
 
     // Check if already stored (deduplication)
-    let was_new = if storage_path.exists() {
-        false
-    } else {
-        // Create parent directory
-        if let Some(parent) = storage_path.parent() {
-            fs::create_dir_all(parent)?;
+    if storage_path.exists() {
+        return Ok(StoreResult {
+            source: source.to_path_buf(),
+            hash,
+            size: fs::metadata(source)?.len(),
+            was_new: false,
+        });
+    }
+
+    let tmp = unique_tmp(store_dir, "incoming");
+    let mut reader = HashingReader::new(File::open(source)?);
+    let size = match File::create(&tmp).and_then(|mut out| io::copy(&mut reader, &mut out)) {
+        Ok(size) => size,
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
         }
-        // Copy file to store
-        fs::copy(source, &storage_path)?;
-        true
     };
+    if reader.finish() != hash {
+        let _ = fs::remove_file(&tmp);
+        anyhow::bail!("{} changed while it was being stored", source.display());
+    }
+
+    // Create parent directory
+    if let Some(parent) = storage_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Keep the source's permissions, as a plain copy would
+    fs::set_permissions(&tmp, fs::metadata(source)?.permissions())?;
+    fs::rename(&tmp, &storage_path)?;
 
     Ok(StoreResult {
         source: source.to_path_buf(),
         hash,
         size,
-        was_new,
+        was_new: true,
     })
 }
 
-/// Store a file with optional encryption to a specific store directory
+/// Store a file encrypted to a specific store directory
+///
+/// The blob is addressed by its keyed object id, never by the plaintext
+/// hash, so a plaintext copy of the same content is never reused for it.
+/// `StoreResult::hash` is the id. The content is encrypted to the keyring's
+/// recipients, or its password if there are none.
 pub fn store_file_to_encrypted(
     store_dir: &Path,
     source: &Path,
-    password: Option<&SecretString>,
+    keys: &Keyring,
+    key: &ObjectKey,
 ) -> anyhow::Result<StoreResult> {
     fs::create_dir_all(store_dir)?;
 
-    // Hash the original file (before encryption)
-    let hash = hash_file(source)?;
+    // Derive the id from the original file (before encryption). Hashing
+    // first reads the file twice, but spares already stored content the
+    // cost of encrypting it
+    let hash = key.object_id(&hash_file(source)?);
     let size = fs::metadata(source)?.len();
 
     // Determine storage path
@@ -279,20 +298,8 @@ pub fn store_file_to_encrypted(
     let was_new = if storage_path.exists() {
         false
     } else {
-        // Create parent directory
-        if let Some(parent) = storage_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        // Store with or without encryption
-        match password {
-            Some(pwd) => {
-                encrypt_file(source, &storage_path, pwd)?;
-            }
-            None => {
-                fs::copy(source, &storage_path)?;
-            }
-        }
+        let source = BufReader::new(File::open(source)?);
+        write_object_from(store_dir, &hash, source, Some(keys))?;
         true
     };
 
@@ -306,53 +313,630 @@ pub fn store_file_to_encrypted(
 
 /// Retrieve a file from a specific store directory
 pub fn retrieve_file_from(store_dir: &Path, hash: &str, dest: &Path) -> anyhow::Result<bool> {
-    let storage_path = hash_to_path(store_dir, hash);
+    retrieve_file_from_encrypted(store_dir, hash, dest, &Keyring::default(), false)
+}
 
-    if !storage_path.exists() {
+/// Retrieve a file from a specific store directory, optionally decrypting it
+///
+/// The content is streamed to a temporary file next to `dest`, hashed on the
+/// way, and only moved into place once it matches `hash`, so a rotted or
+/// truncated blob is refused instead of restored. Like [`read_object`],
+/// whether to decrypt is decided by the blob itself.
+///
+/// A replaced file keeps its permissions. A new one gets the stored blob's,
+/// or user-only ones if the content was decrypted.
+pub fn retrieve_file_from_encrypted(
+    store_dir: &Path,
+    hash: &str,
+    dest: &Path,
+    keys: &Keyring,
+    encrypted: bool,
+) -> anyhow::Result<bool> {
+    let Some(id) = locate(store_dir, hash)? else {
         return Ok(false);
-    }
+    };
+    let storage_path = hash_to_path(store_dir, &id);
+
+    let mut blob = BufReader::new(File::open(&storage_path)?);
+    let sealed = is_encrypted_blob(blob.fill_buf()?);
+    let reader: Box<dyn Read> = if sealed {
+        Box::new(keys.decrypt_from(blob)?)
+    } else {
+        Box::new(blob)
+    };
+
+    // Restore through symlinks (e.g. stow-managed dotfiles) to their target
+    let dest = match fs::canonicalize(dest) {
+        Ok(target) if dest.is_symlink() => target,
+        _ => dest.to_path_buf(),
+    };
 
     // Create parent directory for destination
     if let Some(parent) = dest.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    fs::copy(&storage_path, dest)?;
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = dest.with_file_name(format!(".{}.dotmatrix-tmp", name));
+    let mut reader = HashingReader::new(reader);
+    let copied = create_restore_tmp(&tmp, sealed)
+        .map_err(anyhow::Error::from)
+        .and_then(|out| {
+            let mut out = BufWriter::new(out);
+            io::copy(&mut reader, &mut out)?;
+            out.flush()?;
+            Ok(())
+        });
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    let actual = reader.finish();
+    let matches = if sealed {
+        matches_id(store_dir, hash, &actual, encrypted)
+    } else {
+        Ok(actual == hash)
+    };
+    match matches {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = fs::remove_file(&tmp);
+            return Err(mismatch(hash, &actual, encrypted));
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+    }
+
+    // Decrypted content is already user-only from creating the file
+    let permissions = match fs::metadata(&dest) {
+        Ok(meta) => Some(meta.permissions()),
+        Err(_) if sealed => None,
+        Err(_) => Some(fs::metadata(&storage_path)?.permissions()),
+    };
+    if let Some(permissions) = permissions {
+        fs::set_permissions(&tmp, permissions)?;
+    }
+    fs::rename(&tmp, &dest)?;
     Ok(true)
 }
 
-/// Retrieve a file from a specific store directory, optionally decrypting it
-pub fn retrieve_file_from_encrypted(
+/// Create the temporary file a restore is written to, user-only if it
+/// will hold decrypted content
+fn create_restore_tmp(tmp: &Path, private: bool) -> io::Result<File> {
+    // A file left by an interrupted restore would keep its own mode
+    let _ = fs::remove_file(tmp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(if private { 0o600 } else { 0o666 });
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options.open(tmp)
+}
+
+/// Read a blob's plaintext, verifying it against its hash
+///
+/// Whether to decrypt is decided by the blob itself, since older versions
+/// of a file that became encrypted are moved to encrypted blobs. For an
+/// encrypted entry, `hash` may be a keyed id, which is checked with the
+/// cached object key (see [`matches_id`]).
+///
+/// Returns `None` if the blob is not in the store.
+pub fn read_object(
     store_dir: &Path,
     hash: &str,
-    dest: &Path,
-    password: Option<&SecretString>,
+    keys: &Keyring,
     encrypted: bool,
-) -> anyhow::Result<bool> {
-    let storage_path = hash_to_path(store_dir, hash);
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(id) = locate(store_dir, hash)? else {
+        return Ok(None);
+    };
 
-    if !storage_path.exists() {
+    let raw = fs::read(hash_to_path(store_dir, &id))?;
+    if !is_encrypted_blob(&raw) {
+        return verify(hash, raw).map(Some);
+    }
+
+    let content = keys.decrypt(&raw)?;
+    let actual = hash_bytes(&content);
+    if !matches_id(store_dir, hash, &actual, encrypted)? {
+        return Err(mismatch(hash, &actual, encrypted));
+    }
+    Ok(Some(content))
+}
+
+/// Check decrypted content, hashing to `actual`, against the id it was read by
+///
+/// A plain content hash has to match. For an encrypted entry the id may be
+/// keyed instead, which is checked with the project's cached object key.
+/// Only while a project that has a key keeps it locked is a keyed id taken
+/// on trust, with age having authenticated the content.
+fn matches_id(store_dir: &Path, hash: &str, actual: &str, encrypted: bool) -> anyhow::Result<bool> {
+    if actual == hash {
+        return Ok(true);
+    }
+    let Some(project_dir) = store_dir.parent().filter(|_| encrypted) else {
         return Ok(false);
+    };
+    match ObjectKey::cached_in(project_dir)? {
+        Some(key) => Ok(key.object_id(actual) == hash),
+        None => Ok(project_dir.join(crate::objectid::OBJECT_KEY_FILE).exists()),
+    }
+}
+
+/// Error for content that doesn't match its id, without naming the plaintext
+/// hash of encrypted content
+fn mismatch(hash: &str, actual: &str, encrypted: bool) -> anyhow::Error {
+    if encrypted {
+        anyhow::anyhow!("Store object {} is corrupt (content does not match its id)", short_hash(hash))
+    } else {
+        corrupt(hash, actual)
     }
+}
 
-    // Create parent directory for destination
-    if let Some(parent) = dest.parent() {
+fn verify(hash: &str, content: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let actual = hash_bytes(&content);
+    if actual != hash {
+        return Err(corrupt(hash, &actual));
+    }
+    Ok(content)
+}
+
+fn corrupt(hash: &str, actual: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Store object {} is corrupt (content hashes to {})",
+        short_hash(hash),
+        short_hash(actual)
+    )
+}
+
+/// Write a blob into the store, replacing any existing copy
+///
+/// The blob is written next to its final path and renamed into place, and
+/// encrypted if `keys` are given.
+pub(crate) fn write_object(
+    store_dir: &Path,
+    hash: &str,
+    content: &[u8],
+    keys: Option<&Keyring>,
+) -> anyhow::Result<()> {
+    write_object_from(store_dir, hash, content, keys)
+}
+
+/// Write a blob into the store from a stream, like [`write_object`]
+pub(crate) fn write_object_from<R: Read>(
+    store_dir: &Path,
+    hash: &str,
+    mut content: R,
+    keys: Option<&Keyring>,
+) -> anyhow::Result<()> {
+    let storage_path = hash_to_path(store_dir, hash);
+    if let Some(parent) = storage_path.parent() {
         fs::create_dir_all(parent)?;
     }
 
-    // Retrieve with or without decryption
-    if encrypted {
-        match password {
-            Some(pwd) => {
-                decrypt_file(&storage_path, dest, pwd)?;
+    let name = storage_path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = unique_tmp(storage_path.parent().unwrap_or(store_dir), &name);
+    let written = File::create(&tmp)
+        .map_err(anyhow::Error::from)
+        .and_then(|out| {
+            let mut out = BufWriter::new(out);
+            match keys {
+                Some(keys) => {
+                    keys.encrypt_to(content, &mut out)?;
+                }
+                None => {
+                    io::copy(&mut content, &mut out)?;
+                }
             }
-            None => {
-                anyhow::bail!("Password required to retrieve encrypted file");
+            out.flush()?;
+            Ok(())
+        });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    fs::rename(&tmp, &storage_path)?;
+    Ok(())
+}
+
+/// A temporary file path in `dir` no other store or thread is writing to
+fn unique_tmp(dir: &Path, name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    dir.join(format!("{}-{}-{}.tmp", name, std::process::id(), n))
+}
+
+/// Whether a file in the store is a blob being written, not a blob
+fn is_temporary(name: &str) -> bool {
+    name.ends_with(".tmp") || name.ends_with(".rewrap")
+}
+
+/// Remove a blob from the store
+pub(crate) fn remove_object(store_dir: &Path, hash: &str) -> anyhow::Result<()> {
+    remove_blob(&hash_to_path(store_dir, hash))
+}
+
+/// Remove a blob file, and its prefix directory once that is empty
+fn remove_blob(path: &Path) -> anyhow::Result<()> {
+    fs::remove_file(path)?;
+    if let Some(parent) = path.parent() {
+        if fs::read_dir(parent)?.next().is_none() {
+            fs::remove_dir(parent)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check if stored bytes are age-encrypted
+pub fn is_encrypted_blob(raw: &[u8]) -> bool {
+    raw.starts_with(b"age-encryption.org/")
+}
+
+/// Read the start of a stored file, enough to tell whether (and how) it is
+/// encrypted without loading all of it
+pub(crate) fn read_header(path: &Path) -> io::Result<Vec<u8>> {
+    let mut header = Vec::new();
+    File::open(path)?.take(4096).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Check if a stored file is passphrase-encrypted
+///
+/// A passphrase header always fits in [`read_header`]; a longer header is
+/// one with recipients.
+pub(crate) fn is_passphrase_blob(path: &Path) -> bool {
+    read_header(path).is_ok_and(|raw| is_encrypted_blob(&raw) && is_passphrase_encrypted(&raw))
+}
+
+/// Check if reading a blob needs the password
+///
+/// True only for passphrase-encrypted blobs; blobs encrypted to recipients
+/// need an identity file instead.
+pub fn object_needs_password(store_dir: &Path, hash: &str) -> bool {
+    match locate(store_dir, hash) {
+        Ok(Some(id)) => is_passphrase_blob(&hash_to_path(store_dir, &id)),
+        _ => false,
+    }
+}
+
+fn short_hash(hash: &str) -> &str {
+    &hash[..12.min(hash.len())]
+}
+
+/// Result of a store garbage collection
+#[derive(Debug, Clone, Default)]
+pub struct GcResult {
+    /// Index revisions read to find live hashes
+    pub revisions: usize,
+    /// Blobs still referenced
+    pub live: usize,
+    /// Hashes of unreferenced blobs (removed unless dry run)
+    pub removed: Vec<String>,
+    /// Bytes removed from the working tree (or that would be). Earlier
+    /// commits of the project repo still hold the blobs
+    pub bytes_freed: u64,
+    /// Whether the removal was committed
+    pub committed: bool,
+}
+
+/// A blob in a project store
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub hash: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// List every blob in a store directory
+///
+/// Temporary files of writes in progress (or interrupted) are skipped.
+pub fn stored_objects(store_dir: &Path) -> anyhow::Result<Vec<StoredObject>> {
+    let mut objects = Vec::new();
+    if !store_dir.exists() {
+        return Ok(objects);
+    }
+
+    for prefix in fs::read_dir(store_dir)? {
+        let prefix = prefix?;
+        if !prefix.file_type()?.is_dir() {
+            continue;
+        }
+        let prefix_name = prefix.file_name().to_string_lossy().to_string();
+        for entry in fs::read_dir(prefix.path())? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if meta.is_file() && !is_temporary(&name) {
+                objects.push(StoredObject {
+                    hash: format!("{}{}", prefix_name, name),
+                    path: entry.path(),
+                    size: meta.len(),
+                });
             }
         }
-    } else {
-        fs::copy(&storage_path, dest)?;
     }
 
-    Ok(true)
+    objects.sort_by(|a, b| a.hash.cmp(&b.hash));
+    Ok(objects)
+}
+
+/// Where a stored object is referenced
+#[derive(Debug, Clone)]
+pub struct ObjectRef {
+    /// File path recorded in the index
+    pub path: PathBuf,
+    pub encrypted: bool,
+    /// Whether the hash is a keyed object id
+    pub keyed: bool,
+    /// Commit of the index revision, or `None` for the working index
+    pub revision: Option<String>,
+}
+
+/// Collect every object referenced by the working index and its git history
+///
+/// Each hash maps to one reference per distinct path, from the newest index
+/// that records it. With `keep_since`, history older than that date
+/// (anything `git log --since` accepts) is ignored, except the last revision
+/// before it. Also returns the number of revisions read.
+pub fn referenced_objects(
+    config: &Config,
+    project_name: &str,
+    keep_since: Option<&str>,
+) -> anyhow::Result<(HashMap<String, Vec<ObjectRef>>, usize)> {
+    let mut objects: HashMap<String, Vec<ObjectRef>> = HashMap::new();
+    let mut add = |index: &Index, revision: Option<&String>| {
+        for (path, entry) in index.iter() {
+            let refs = objects.entry(entry.hash.clone()).or_default();
+            if !refs.iter().any(|r| &r.path == path) {
+                refs.push(ObjectRef {
+                    path: path.clone(),
+                    encrypted: entry.encrypted,
+                    keyed: entry.keyed,
+                    revision: revision.cloned(),
+                });
+            }
+        }
+    };
+
+    add(&Index::load_for_project(config, project_name)?, None);
+
+    let project_dir = config.project_dir(project_name)?;
+    let paths = [metadata::INDEX_FILE, metadata::SEALED_INDEX_FILE];
+    let revisions = git::revisions_touching_any(&project_dir, &paths, keep_since)?;
+    for rev in &revisions {
+        let index = metadata::index_at(config, project_name, rev)
+            .map_err(|e| anyhow::anyhow!("Unreadable index at {}: {}", &rev[..8.min(rev.len())], e))?;
+        if let Some(index) = index {
+            add(&index, Some(rev));
+        }
+    }
+
+    Ok((objects, revisions.len()))
+}
+
+/// Keyed ids that plain hashes among `referenced` were moved to
+///
+/// Fails if the project has an object key that isn't cached, since such
+/// blobs can't be told apart from unreferenced ones without it.
+pub(crate) fn moved_objects(
+    config: &Config,
+    project_name: &str,
+    referenced: &HashMap<String, Vec<ObjectRef>>,
+) -> anyhow::Result<HashSet<String>> {
+    let Some(key) = ObjectKey::cached(config, project_name)? else {
+        if config.project_object_key_path(project_name)?.exists() {
+            return Err(ObjectKeyLocked {
+                project: project_name.to_string(),
+                reason: "needed to tell blobs moved to keyed ids apart".to_string(),
+            }
+            .into());
+        }
+        return Ok(HashSet::new());
+    };
+    Ok(referenced
+        .iter()
+        .filter(|(_, refs)| refs.iter().any(|r| !r.keyed))
+        .map(|(hash, _)| key.object_id(hash))
+        .collect())
+}
+
+/// Delete blobs no index revision refers to
+///
+/// The removal is committed, so the project repo stays clean for merges.
+/// The blobs remain in the commits that added them, so this shrinks the
+/// working tree but not the repo's history.
+pub fn gc(
+    config: &Config,
+    project_name: &str,
+    keep_since: Option<&str>,
+    dry_run: bool,
+) -> anyhow::Result<GcResult> {
+    let store_dir = config.project_store_dir(project_name)?;
+    let (live, revisions) = referenced_objects(config, project_name, keep_since)?;
+    let moved = moved_objects(config, project_name, &live)?;
+
+    let mut result = GcResult {
+        revisions,
+        ..GcResult::default()
+    };
+
+    for object in stored_objects(&store_dir)? {
+        if live.contains_key(&object.hash) || moved.contains(&object.hash) {
+            result.live += 1;
+            continue;
+        }
+        if !dry_run {
+            remove_blob(&object.path)?;
+        }
+        result.bytes_freed += object.size;
+        result.removed.push(object.hash);
+    }
+
+    let project_dir = config.project_dir(project_name)?;
+    if !dry_run && !result.removed.is_empty() && git::is_git_repo(&project_dir) {
+        git::stage_all(&project_dir)?;
+        let msg = format!("Store gc: removed {} unreferenced blobs", result.removed.len());
+        result.committed = git::commit(&project_dir, &msg)?;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempData;
+
+    #[test]
+    fn test_read_object_refuses_corrupt_blob() {
+        let dir = std::env::temp_dir().join(format!("dmcore-store-{}", std::process::id()));
+        let hash = hash_bytes(b"set number\n");
+
+        write_object(&dir, &hash, b"set number\n", None).unwrap();
+        assert_eq!(
+            read_object(&dir, &hash, &Keyring::default(), false).unwrap().unwrap(),
+            b"set number\n"
+        );
+
+        fs::write(hash_to_path(&dir, &hash), b"set numb").unwrap();
+        assert!(read_object(&dir, &hash, &Keyring::default(), false).is_err());
+        assert!(retrieve_file_from(&dir, &hash, &dir.join("out")).is_err());
+        assert!(!dir.join("out").exists());
+
+        assert!(read_object(&dir, &hash_bytes(b"other"), &Keyring::default(), false)
+            .unwrap()
+            .is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_object_checks_keyed_ids() {
+        let data = TempData::new("keyed-ids");
+        data.project("p");
+        let store_dir = data.config.project_store_dir("p").unwrap();
+        let keys = Keyring::from_password(SecretString::from("hunter2".to_string()));
+        let key = ObjectKey::load_or_create(&data.config, "p", &keys).unwrap();
+        let id = key.object_id(&hash_bytes(b"token = a\n"));
+
+        write_object(&store_dir, &id, b"token = a\n", Some(&keys)).unwrap();
+        assert_eq!(read_object(&store_dir, &id, &keys, true).unwrap().unwrap(), b"token = a\n");
+
+        // Swapped content fails its keyed id, and its plain hash
+        write_object(&store_dir, &id, b"token = b\n", Some(&keys)).unwrap();
+        assert!(read_object(&store_dir, &id, &keys, true).is_err());
+        assert!(retrieve_file_from_encrypted(&store_dir, &id, &data.dir.join("out"), &keys, true).is_err());
+        assert!(!data.dir.join("out").exists());
+        let hash = hash_bytes(b"token = a\n");
+        write_object(&store_dir, &hash, b"token = b\n", Some(&keys)).unwrap();
+        assert!(read_object(&store_dir, &hash, &keys, false).is_err());
+
+        // With the key locked, age's authentication is all there is
+        fs::remove_file(data.config.project_object_key_cache_path("p").unwrap()).unwrap();
+        assert!(read_object(&store_dir, &id, &keys, true).is_ok());
+    }
+
+    #[test]
+    fn test_gc_keeps_history_and_commits() {
+        let data = TempData::new("gc");
+        let project_dir = data.project("p");
+        let store_dir = data.config.project_store_dir("p").unwrap();
+        let file = data.file("vimrc", b"set number\n");
+        let mut project = crate::project::Project::new();
+        project.add_file(crate::project::TrackedFile::with_mode(
+            file.to_string_lossy(),
+            crate::project::TrackMode::Backup,
+        ));
+
+        crate::backup::backup_project_incremental(&data.config, "p", &project).unwrap();
+        fs::write(&file, b"set relativenumber\n").unwrap();
+        crate::backup::backup_project_incremental(&data.config, "p", &project).unwrap();
+        let orphan = hash_bytes(b"orphan");
+        write_object(&store_dir, &orphan, b"orphan", None).unwrap();
+        git::stage_all(&project_dir).unwrap();
+        git::commit(&project_dir, "orphan").unwrap();
+
+        let result = gc(&data.config, "p", None, true).unwrap();
+        assert_eq!(result.removed, vec![orphan.clone()]);
+        assert!(hash_to_path(&store_dir, &orphan).exists());
+
+        // Both versions stay live through the history
+        let result = gc(&data.config, "p", None, false).unwrap();
+        assert_eq!(result.removed, vec![orphan.clone()]);
+        assert_eq!(result.live, 2);
+        assert!(result.committed);
+        assert!(!hash_to_path(&store_dir, &orphan).exists());
+        assert!(git::is_clean(&project_dir).unwrap());
+
+        // Only the last revision before the date counts from older history
+        let result = gc(&data.config, "p", Some("2099-01-01"), false).unwrap();
+        assert_eq!(result.removed, vec![hash_bytes(b"set number\n")]);
+        assert_eq!(result.live, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_retrieve_sets_permissions_of_new_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let data = TempData::new("retrieve-mode");
+        let store_dir = data.dir.join("store");
+        let script = data.file("bin/deploy", b"#!/bin/sh\necho ok\n");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let stored = store_file_to(&store_dir, &script).unwrap();
+        let restored = data.dir.join("fresh/deploy");
+        assert!(retrieve_file_from(&store_dir, &stored.hash, &restored).unwrap());
+        assert_eq!(mode(&restored), 0o755);
+
+        // Replacing a file keeps its mode
+        fs::set_permissions(&restored, fs::Permissions::from_mode(0o700)).unwrap();
+        assert!(retrieve_file_from(&store_dir, &stored.hash, &restored).unwrap());
+        assert_eq!(mode(&restored), 0o700);
+
+        // Decrypted content is user-only
+        let keys = Keyring::from_password(SecretString::from("hunter2".to_string()));
+        let hash = hash_bytes(b"token = a\n");
+        write_object(&store_dir, &hash, b"token = a\n", Some(&keys)).unwrap();
+        let secret = data.dir.join("fresh/token");
+        assert!(retrieve_file_from_encrypted(&store_dir, &hash, &secret, &keys, false).unwrap());
+        assert_eq!(mode(&secret), 0o600);
+    }
+
+    #[test]
+    fn test_store_and_retrieve_streamed() {
+        let dir = std::env::temp_dir().join(format!("dmcore-stream-{}", std::process::id()));
+        let store_dir = dir.join("store");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("init.lua");
+        fs::write(&source, b"vim.opt.number = true\n").unwrap();
+
+        let stored = store_file_to(&store_dir, &source).unwrap();
+        assert!(stored.was_new);
+        assert_eq!(stored.hash, hash_bytes(b"vim.opt.number = true\n"));
+        assert_eq!(stored.size, 22);
+        assert!(!store_file_to(&store_dir, &source).unwrap().was_new);
+
+        fs::write(&source, b"changed").unwrap();
+        assert!(retrieve_file_from(&store_dir, &stored.hash, &source).unwrap());
+        assert_eq!(fs::read(&source).unwrap(), b"vim.opt.number = true\n");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Leftovers of interrupted writes aren't blobs
+        let blob = hash_to_path(&store_dir, &stored.hash);
+        fs::write(unique_tmp(blob.parent().unwrap(), "0123"), b"partial").unwrap();
+        fs::write(blob.with_extension("rewrap"), b"partial").unwrap();
+        let objects = stored_objects(&store_dir).unwrap();
+        assert_eq!(objects.iter().map(|o| &o.hash).collect::<Vec<_>>(), [&stored.hash]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
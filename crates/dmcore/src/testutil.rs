@@ -0,0 +1,52 @@
+//! Scratch data directories for tests that need project repos
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// A data directory under the system temp dir, removed on drop
+pub(crate) struct TempData {
+    pub dir: PathBuf,
+    pub config: Config,
+}
+
+impl TempData {
+    pub fn new(name: &str) -> Self {
+        // Commits need an identity, which a CI machine may not have
+        for var in ["GIT_AUTHOR_NAME", "GIT_COMMITTER_NAME"] {
+            std::env::set_var(var, "dotmatrix test");
+        }
+        for var in ["GIT_AUTHOR_EMAIL", "GIT_COMMITTER_EMAIL"] {
+            std::env::set_var(var, "test@dotmatrix.invalid");
+        }
+
+        let dir = std::env::temp_dir().join(format!("dmcore-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            data_dir: Some(dir.join("data").to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        Self { dir, config }
+    }
+
+    /// Create a project repo, returning its directory
+    pub fn project(&self, name: &str) -> PathBuf {
+        crate::git::init_project_repo(&self.config, name).unwrap()
+    }
+
+    /// Write a file under the scratch dir, returning its path
+    pub fn file(&self, name: &str, content: &[u8]) -> PathBuf {
+        let path = self.dir.join("home").join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempData {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
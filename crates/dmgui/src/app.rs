@@ -4,13 +4,14 @@
 
 use age::secrecy::SecretString;
 use dmcore::{
-    backup_project_incremental_encrypted_with_message, contract_path, expand_path,
-    get_remote_status, hash_file, init_project_repo, project_needs_password,
-    recent_commits, retrieve_file_from, retrieve_file_from_encrypted, scan_project,
-    Config, Index, Manifest, ProjectSummary, RemoteStatus, TrackMode,
+    backup_project_incremental_encrypted_with_message, check_password, command_password, commit_diffs, contract_path,
+    expand_path, get_remote_status, hash_file, init_project_repo, migrate_encryption, migration_needs_password,
+    object_needs_password, password_command, project_needs_password, list_archives, recent_commits,
+    retrieve_file_from, retrieve_file_from_encrypted, scan_project, set_password_check, tags_by_commit, index_locked,
+    unlock_index, unlock_needs_password, entry_secrets, apply_merge, pull_project, ArchiveInfo, Config, ExcludeSet,
+    Index, Keyring, Manifest, MergeResult, PasswordCheck, ProjectSummary, PullOutcome, RemoteStatus, Resolution, SecretReport, SecretScan, TrackMode, TrackedFile,
 };
 use egui::Color32;
-use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -22,42 +23,6 @@ use syntect::easy::HighlightLines;
 use crate::state::*;
 use crate::theme::Colors;
 
-/// V2 index format (entries field)
-#[derive(Debug, Deserialize)]
-struct V2Index {
-    #[serde(default)]
-    entries: HashMap<PathBuf, V2FileEntry>,
-}
-
-/// V2 file entry format
-#[derive(Debug, Deserialize)]
-struct V2FileEntry {
-    hash: String,
-    size: u64,
-    #[serde(default)]
-    encrypted: bool,
-}
-
-/// Legacy v1 index format (files field)
-#[derive(Debug, Deserialize)]
-struct LegacyIndex {
-    #[serde(default)]
-    files: HashMap<PathBuf, LegacyFileEntry>,
-}
-
-/// Legacy v1 file entry format
-#[derive(Debug, Deserialize)]
-struct LegacyFileEntry {
-    #[allow(dead_code)]
-    path: PathBuf,
-    hash: String,
-    #[allow(dead_code)]
-    last_modified: u64,
-    size: u64,
-    #[serde(default)]
-    encrypted: bool,
-}
-
 /// Application state
 pub struct GuiApp {
     pub mode: Mode,
@@ -86,6 +51,9 @@ pub struct GuiApp {
     pub commits: Vec<CommitInfo>,
     pub commit_selected: Option<usize>,
     pub selected_commit: Option<usize>,
+    pub archives: Vec<ArchiveInfo>,
+    pub archive_selected: Option<usize>,
+    pub selected_archive: Option<usize>,
     pub restore_files: Vec<RestoreFile>,
     pub restore_selected: HashSet<usize>,
     pub restore_file_selected: Option<usize>,
@@ -125,11 +93,20 @@ pub struct GuiApp {
     // Recursive add state
     pub recursive_preview: Option<RecursivePreviewState>,
 
+    // Pull conflict resolution state
+    pub merge_dialog: Option<MergeDialogState>,
+
     // Password prompt state
     pub password_prompt_visible: bool,
     pub password_input: String,
     pub password_purpose: PasswordPurpose,
+    /// First entry of a new password, while it is typed again to confirm
+    pub password_confirm: Option<String>,
     pub encryption_password: Option<SecretString>,
+    /// Projects the password is known to match
+    password_checked: HashSet<String>,
+    /// Password command running in the background, for the prompt's purpose
+    password_receiver: Option<Receiver<(String, anyhow::Result<SecretString>)>>,
 
     // Git remote status per project
     pub project_remote_status: HashMap<String, RemoteStatus>,
@@ -179,6 +156,9 @@ impl GuiApp {
             commits: Vec::new(),
             commit_selected: None,
             selected_commit: None,
+            archives: Vec::new(),
+            archive_selected: None,
+            selected_archive: None,
             restore_files: Vec::new(),
             restore_selected: HashSet::new(),
             restore_file_selected: None,
@@ -201,10 +181,14 @@ impl GuiApp {
             entering_commit_msg: false,
             commit_msg_input: String::new(),
             recursive_preview: None,
+            merge_dialog: None,
             password_prompt_visible: false,
             password_input: String::new(),
             password_purpose: PasswordPurpose::default(),
+            password_confirm: None,
             encryption_password: None,
+            password_checked: HashSet::new(),
+            password_receiver: None,
             project_remote_status: HashMap::new(),
             viewer_visible: false,
             viewer_content: Vec::new(),
@@ -252,7 +236,6 @@ impl GuiApp {
 
                         self.backup_projects.push(BackupProject {
                             name,
-                            path,
                             commit_count,
                             last_backup,
                         });
@@ -307,13 +290,30 @@ impl GuiApp {
             if let Ok(commits) = recent_commits(&project_dir, 100) {
                 self.commits = commits.into_iter().map(CommitInfo::from).collect();
             }
+            let mut tags = tags_by_commit(&project_dir).unwrap_or_default();
+            for commit in &mut self.commits {
+                commit.tags = tags.remove(&commit.hash).unwrap_or_default();
+            }
         }
+        self.load_commit_stats(project_name);
 
         if !self.commits.is_empty() {
             self.commit_selected = Some(0);
         }
     }
 
+    /// Diff each listed commit against its parent, once the index can be read
+    fn load_commit_stats(&mut self, project_name: &str) {
+        if !matches!(index_locked(&self.config, project_name), Ok(false)) {
+            return;
+        }
+        let hashes: Vec<&str> = self.commits.iter().map(|c| c.hash.as_str()).collect();
+        let stats = commit_diffs(&self.config, project_name, &hashes).unwrap_or_default();
+        for (commit, stat) in self.commits.iter_mut().zip(stats) {
+            commit.stat = stat;
+        }
+    }
+
     pub fn select_backup_project(&mut self) {
         if let Some(idx) = self.backup_project_selected {
             if let Some(project) = self.backup_projects.get(idx) {
@@ -330,6 +330,73 @@ impl GuiApp {
         self.selected_backup_project = None;
         self.commits.clear();
         self.selected_commit = None;
+        self.archives.clear();
+        self.selected_archive = None;
+    }
+
+    pub fn show_archives(&mut self) {
+        let project_name = match &self.selected_backup_project {
+            Some(n) => n.clone(),
+            None => return,
+        };
+
+        self.archives = list_archives(&self.config, &project_name).unwrap_or_default();
+        self.archive_selected = if self.archives.is_empty() { None } else { Some(0) };
+        self.selected_archive = None;
+        self.restore_view = RestoreView::Archives;
+    }
+
+    pub fn select_archive(&mut self) {
+        if let Some(i) = self.archive_selected {
+            if i < self.archives.len() {
+                self.selected_archive = Some(i);
+                self.load_archive_files(i);
+                self.restore_view = RestoreView::Files;
+                self.restore_selected.clear();
+                if !self.restore_files.is_empty() {
+                    self.restore_file_selected = Some(0);
+                }
+            }
+        }
+    }
+
+    pub fn back_to_archives(&mut self) {
+        self.restore_view = RestoreView::Archives;
+        self.restore_files.clear();
+        self.restore_selected.clear();
+        if let Some(idx) = self.selected_archive {
+            self.archive_selected = Some(idx);
+        }
+        self.selected_archive = None;
+    }
+
+    /// Go back from the file list to wherever it was opened from
+    pub fn back_from_restore_files(&mut self) {
+        if self.selected_archive.is_some() {
+            self.back_to_archives();
+        } else {
+            self.back_to_commits();
+        }
+    }
+
+    pub fn load_archive_files(&mut self, archive_idx: usize) {
+        self.restore_files.clear();
+
+        let archive_path = match self.archives.get(archive_idx) {
+            Some(a) => a.path.clone(),
+            None => return,
+        };
+
+        match dmcore::archive::list_entries(&archive_path) {
+            Ok(entries) => {
+                for entry in entries {
+                    self.add_restore_file(entry.path, entry.hash, entry.size, false, Some(entry.name));
+                }
+            }
+            Err(e) => {
+                self.message = Some((format!("Failed to read archive: {}", e), true));
+            }
+        }
     }
 
     pub fn refresh_projects(&mut self) {
@@ -341,21 +408,22 @@ impl GuiApp {
 
         for name in names {
             if let Some(project) = self.manifest.get_project(&name) {
-                let results = scan_project(project, &self.index);
+                let exclude = ExcludeSet::for_project(&self.config, project);
+                let results = scan_project(project, &self.index, &exclude);
                 let summary = ProjectSummary::from_results(&results);
                 let expanded = self.expanded_projects.contains(&name);
                 let remote_status = self.project_remote_status.get(&name).cloned();
 
                 let files: Vec<DisplayFile> = results
                     .iter()
-                    .zip(project.files.iter())
-                    .map(|(r, tracked)| DisplayFile {
+                    .map(|r| DisplayFile {
                         path: r.path.clone(),
+                        entry: r.pattern.clone().unwrap_or_else(|| r.path.clone()),
                         abs_path: expand_path(&r.path),
                         status: r.status,
                         size: r.current_size,
                         track_mode: r.track_mode,
-                        encrypted: tracked.encrypted,
+                        encrypted: r.encrypted,
                     })
                     .collect();
 
@@ -367,7 +435,7 @@ impl GuiApp {
                     new_files: summary.new,
                     missing: summary.missing,
                     expanded,
-                    remote_status: remote_status.clone(),
+                    remote_status,
                 });
 
                 if expanded {
@@ -375,6 +443,7 @@ impl GuiApp {
                         self.visible_items.push(ProjectViewItem::File {
                             project_name: name.clone(),
                             path: file.path.clone(),
+                            entry: file.entry.clone(),
                             abs_path: file.abs_path.clone(),
                             status: file.status,
                             size: file.size,
@@ -384,17 +453,7 @@ impl GuiApp {
                     }
                 }
 
-                self.projects.push(DisplayProject {
-                    name: name.clone(),
-                    file_count: project.file_count(),
-                    synced: summary.synced,
-                    drifted: summary.drifted,
-                    new_files: summary.new,
-                    missing: summary.missing,
-                    expanded,
-                    files,
-                    remote_status,
-                });
+                self.projects.push(DisplayProject { name: name.clone() });
             }
         }
 
@@ -451,7 +510,12 @@ impl GuiApp {
                         self.manifest
                             .projects
                             .iter()
-                            .filter(|(_, p)| p.files.iter().any(|f| f.path == contracted))
+                            .filter(|(_, p)| {
+                                p.entry_for(&path).is_some_and(|f| {
+                                    !f.expands()
+                                        || !ExcludeSet::for_project(&self.config, p).is_excluded(&path)
+                                })
+                            })
                             .map(|(name, _)| name.clone())
                             .collect()
                     };
@@ -480,7 +544,7 @@ impl GuiApp {
         }
     }
 
-    pub fn enter_directory(&mut self, path: &PathBuf) {
+    pub fn enter_directory(&mut self, path: &Path) {
         if path.is_dir() {
             let previous_dir = self.browse_dir.clone();
             self.browse_dir = if path.ends_with("..") {
@@ -489,7 +553,7 @@ impl GuiApp {
                     .unwrap_or(&self.browse_dir)
                     .to_path_buf()
             } else {
-                path.clone()
+                path.to_path_buf()
             };
             self.refresh_browse();
 
@@ -568,9 +632,9 @@ impl GuiApp {
         let (project_name, file_path) = match self.selected_item() {
             Some(ProjectViewItem::File {
                 project_name,
-                path,
+                entry,
                 ..
-            }) => (project_name.clone(), path.clone()),
+            }) => (project_name.clone(), entry.clone()),
             _ => {
                 self.message = Some(("Select a file to toggle encryption".to_string(), true));
                 return;
@@ -584,6 +648,7 @@ impl GuiApp {
                 let state = if file.encrypted { "enabled" } else { "disabled" };
                 self.message = Some((format!("Encryption {} (saves on exit)", state), false));
                 self.refresh_projects();
+                self.migrate_project_encryption(&project_name);
             }
         }
     }
@@ -613,6 +678,55 @@ impl GuiApp {
                 false,
             ));
             self.refresh_projects();
+            self.migrate_project_encryption(&project_name);
+        }
+    }
+
+    /// Re-encrypt or decrypt stored blobs after an encryption toggle
+    fn migrate_project_encryption(&mut self, project_name: &str) {
+        // Nothing stored yet, the next backup stores it the right way
+        let stored = Index::load_for_project(&self.config, project_name).unwrap_or_default();
+        if stored.is_empty() {
+            return;
+        }
+
+        let project = match self.manifest.get_project(project_name) {
+            Some(p) => p.clone(),
+            None => return,
+        };
+        if migration_needs_password(&self.config, &project) && !self.password_checked_for(project_name) {
+            self.show_password_prompt(PasswordPurpose::Migrate);
+            return;
+        }
+
+        let result = Keyring::for_project(&self.config, &project, self.encryption_password.as_ref())
+            .and_then(|keys| migrate_encryption(&self.config, project_name, &project, &keys));
+        match result {
+            Ok(result) if result.is_empty() => {}
+            Ok(result) => {
+                let history = if result.history_exposed {
+                    format!("; earlier commits still hold the plaintext (dmxcli git {} purge-history)", project_name)
+                } else {
+                    String::new()
+                };
+                self.message = Some((
+                    format!(
+                        "Store updated: {} encrypted, {} decrypted, {} plaintext blobs sealed{}",
+                        result.encrypted,
+                        result.decrypted,
+                        result.sealed.len(),
+                        history
+                    ),
+                    result.history_exposed,
+                ));
+                if let Ok(index) = Index::load_for_project(&self.config, project_name) {
+                    self.index = index;
+                }
+                self.refresh_projects();
+            }
+            Err(e) => {
+                self.message = Some((format!("Failed to migrate encryption: {}", e), true));
+            }
         }
     }
 
@@ -620,9 +734,9 @@ impl GuiApp {
         let (project_name, file_path) = match self.selected_item() {
             Some(ProjectViewItem::File {
                 project_name,
-                path,
+                entry,
                 ..
-            }) => (project_name.clone(), path.clone()),
+            }) => (project_name.clone(), entry.clone()),
             _ => {
                 self.message = Some(("Select a file to toggle track mode".to_string(), true));
                 return;
@@ -648,7 +762,28 @@ impl GuiApp {
         }
     }
 
-    pub fn add_file_to_project(&mut self, path: &PathBuf) -> bool {
+    /// Exclude rules for a project (global patterns plus the project's own)
+    fn exclude_for(&self, project_name: &str) -> ExcludeSet {
+        match self.manifest.get_project(project_name) {
+            Some(project) => ExcludeSet::for_project(&self.config, project),
+            None => ExcludeSet::new(&self.config.exclude),
+        }
+    }
+
+    /// Scan a file about to be added unencrypted, as `secret_scan` says
+    ///
+    /// Returns what was found, and whether the scan refuses the file.
+    fn secrets_for_add(&self, project_name: &str, path: &Path) -> (Vec<SecretReport>, bool) {
+        let project = match self.manifest.get_project(project_name) {
+            Some(p) if self.config.secret_scan != SecretScan::Off => p,
+            _ => return (Vec::new(), false),
+        };
+        let reports = entry_secrets(&self.config, project, &TrackedFile::new(contract_path(path)));
+        let blocked = self.config.secret_scan == SecretScan::Block && !reports.is_empty();
+        (reports, blocked)
+    }
+
+    pub fn add_file_to_project(&mut self, path: &Path) -> bool {
         let project_name = match &self.target_project {
             Some(name) => name.clone(),
             None => {
@@ -664,14 +799,43 @@ impl GuiApp {
             }
         };
 
+        if self.exclude_for(&project_name).is_excluded(path) {
+            self.message = Some((
+                format!("{} is excluded in {}", contract_path(path), project_name),
+                true,
+            ));
+            return false;
+        }
+
+        let (secrets, blocked) = self.secrets_for_add(&project_name, path);
+        if blocked {
+            self.message = Some((
+                format!(
+                    "Not added, possible secrets: {} (encrypt it, or list it in allow_secrets)",
+                    secret_summary(&secrets)
+                ),
+                true,
+            ));
+            return false;
+        }
+
         if let Some(project) = self.manifest.get_project_mut(&project_name) {
             let contracted = contract_path(path);
             if project.add_path_with_mode(&contracted, self.default_track_mode) {
                 self.manifest_dirty = true;
-                self.message = Some((
-                    format!("Added {} to {}", contracted, project_name),
-                    false,
-                ));
+                self.message = Some(if secrets.is_empty() {
+                    (format!("Added {} to {}", contracted, project_name), false)
+                } else {
+                    (
+                        format!(
+                            "Added {} to {}; possible secrets: {} (consider encrypting it)",
+                            contracted,
+                            project_name,
+                            secret_summary(&secrets)
+                        ),
+                        true,
+                    )
+                });
                 self.refresh_projects();
                 let saved_selection = self.browse_selected;
                 self.refresh_browse();
@@ -687,7 +851,7 @@ impl GuiApp {
         false
     }
 
-    pub fn untrack_file(&mut self, path: &PathBuf) -> bool {
+    pub fn untrack_file(&mut self, path: &Path) -> bool {
         let contracted = contract_path(path);
         let mut removed_from = Vec::new();
 
@@ -732,7 +896,7 @@ impl GuiApp {
             None => return,
         };
 
-        if project_needs_password(&project) && self.encryption_password.is_none() {
+        if project_needs_password(&project) && !self.password_checked_for(&project_name) {
             self.show_password_prompt(PasswordPurpose::Backup);
             return;
         }
@@ -754,7 +918,7 @@ impl GuiApp {
             None => return,
         };
 
-        if project_needs_password(&project) && self.encryption_password.is_none() {
+        if project_needs_password(&project) && !self.password_checked_for(&project_name) {
             self.show_password_prompt(PasswordPurpose::Backup);
             return;
         }
@@ -821,30 +985,40 @@ impl GuiApp {
             let result = (|| -> anyhow::Result<String> {
                 init_project_repo(&config, &name)?;
 
+                let keys = Keyring::for_project(&config, &project, password.as_ref())?;
                 let result = backup_project_incremental_encrypted_with_message(
                     &config,
                     &name,
                     &project,
-                    password.as_ref(),
+                    &keys,
                     custom_message.as_deref(),
                 )?;
 
-                Ok(format!(
+                let mut message = format!(
                     "Backed up {} files ({} new, {} unchanged)",
                     result.backed_up + result.unchanged,
                     result.backed_up,
                     result.unchanged
-                ))
+                );
+                if !result.secrets.is_empty() {
+                    message.push_str(&format!(
+                        "; possible secrets: {} (consider encrypting)",
+                        secret_summary(&result.secrets)
+                    ));
+                }
+                Ok(message)
             })();
 
             let op_result = match result {
                 Ok(msg) => OpResult {
                     success: true,
                     message: msg,
+                    conflicts: None,
                 },
                 Err(e) => OpResult {
                     success: false,
                     message: e.to_string(),
+                    conflicts: None,
                 },
             };
 
@@ -852,38 +1026,190 @@ impl GuiApp {
         });
     }
 
-    pub fn selected_project_needs_password(&self) -> bool {
-        let project_name = match self.selected_project_name() {
-            Some(name) => name,
-            None => return false,
+
+    /// Check if the known password matches a project's password check
+    ///
+    /// Without a match, the agent is tried before the caller asks with
+    /// `show_password_prompt`.
+    fn password_checked_for(&mut self, project_name: &str) -> bool {
+        if self.password_checked.contains(project_name) {
+            return true;
+        }
+        let correct = |password: &SecretString| {
+            matches!(check_password(&self.config, project_name, password), Ok(PasswordCheck::Correct))
         };
-        match self.manifest.get_project(&project_name) {
-            Some(p) => project_needs_password(p),
-            None => false,
+        if self.encryption_password.as_ref().is_some_and(correct) {
+            self.password_checked.insert(project_name.to_string());
+            return true;
+        }
+        // The agent may hold the password from another frontend
+        let Some(password) = dmcore::agent::get_password(&self.config, project_name).filter(correct) else {
+            return false;
+        };
+        self.encryption_password = Some(password);
+        self.password_checked.clear();
+        self.password_checked.insert(project_name.to_string());
+        true
+    }
+
+    fn password_project(&self) -> Option<String> {
+        match self.password_purpose {
+            PasswordPurpose::Restore | PasswordPurpose::Unlock => self.selected_backup_project.clone(),
+            PasswordPurpose::Backup | PasswordPurpose::Migrate | PasswordPurpose::Pull => {
+                self.selected_project_name()
+            }
         }
     }
 
+    /// Ask for the password for an operation
+    ///
+    /// A project with a password command runs it in the background first;
+    /// the prompt opens if it has none or the command fails.
     pub fn show_password_prompt(&mut self, purpose: PasswordPurpose) {
+        self.password_purpose = purpose;
+        let project = self
+            .password_project()
+            .and_then(|name| Some((name.clone(), self.manifest.get_project(&name)?.clone())));
+        match project {
+            Some((name, project)) if password_command(&self.config, &project).is_some() => {
+                self.run_password_command(name, project);
+            }
+            _ => self.open_password_prompt(),
+        }
+    }
+
+    fn open_password_prompt(&mut self) {
         self.password_prompt_visible = true;
         self.password_input.clear();
-        self.password_purpose = purpose;
+        self.password_confirm = None;
+    }
+
+    /// Get a project's password from its password command, checked against
+    /// the project's password check, without blocking the window
+    fn run_password_command(&mut self, project_name: String, project: dmcore::Project) {
+        let config = self.config.clone();
+
+        let (tx, rx) = mpsc::channel();
+        self.password_receiver = Some(rx);
+        self.busy = true;
+        self.busy_message = format!("Running password command for {}...", project_name);
+
+        std::thread::spawn(move || {
+            let result = command_password(&config, &project_name, &project).and_then(|password| {
+                let password = password
+                    .ok_or_else(|| anyhow::anyhow!("'{}' has no password command", project_name))?;
+                match check_password(&config, &project_name, &password)? {
+                    PasswordCheck::Correct => {}
+                    PasswordCheck::Wrong => anyhow::bail!(
+                        "Wrong password from password command for '{}'",
+                        project_name
+                    ),
+                    PasswordCheck::Unset => set_password_check(&config, &project_name, &password)?,
+                }
+                Ok(password)
+            });
+            let _ = tx.send((project_name, result));
+        });
+    }
+
+    /// Take the result of a background password command, continuing the
+    /// operation that needed it or falling back to the prompt
+    fn poll_password_command(&mut self) {
+        let Some(ref rx) = self.password_receiver else {
+            return;
+        };
+        match rx.try_recv() {
+            Ok((project_name, result)) => {
+                self.busy = false;
+                self.password_receiver = None;
+                match result {
+                    Ok(password) => {
+                        dmcore::agent::put_password(&self.config, &project_name, &password);
+                        self.encryption_password = Some(password);
+                        self.password_checked.clear();
+                        self.password_checked.insert(project_name);
+                        self.continue_with_password();
+                    }
+                    Err(e) => {
+                        self.message = Some((e.to_string(), true));
+                        self.open_password_prompt();
+                    }
+                }
+            }
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                self.spinner_frame = (self.spinner_frame + 1) % crate::theme::SPINNER_FRAMES.len();
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                self.busy = false;
+                self.password_receiver = None;
+            }
+        }
     }
 
     pub fn cancel_password(&mut self) {
         self.password_prompt_visible = false;
         self.password_input.clear();
+        self.password_confirm = None;
     }
 
+    /// Check the entered password against the project's password check; a
+    /// project's first password has to be typed twice
     pub fn confirm_password(&mut self) {
         if self.password_input.is_empty() {
             self.message = Some(("Password cannot be empty".to_string(), true));
             return;
         }
 
-        self.encryption_password = Some(SecretString::from(self.password_input.clone()));
+        let password = SecretString::from(self.password_input.clone());
+        if let Some(project_name) = self.password_project() {
+            match check_password(&self.config, &project_name, &password) {
+                Ok(PasswordCheck::Correct) => {}
+                Ok(PasswordCheck::Wrong) => {
+                    self.message = Some((
+                        format!("Wrong password: '{}' is encrypted with a different one", project_name),
+                        true,
+                    ));
+                    self.password_input.clear();
+                    return;
+                }
+                Ok(PasswordCheck::Unset) => match self.password_confirm.take() {
+                    None => {
+                        self.password_confirm = Some(self.password_input.clone());
+                        self.password_input.clear();
+                        return;
+                    }
+                    Some(first) if first == self.password_input => {
+                        if let Err(e) = set_password_check(&self.config, &project_name, &password) {
+                            self.message = Some((format!("Cannot save password check: {}", e), true));
+                            return;
+                        }
+                    }
+                    Some(_) => {
+                        self.message = Some(("Passwords do not match, enter it again".to_string(), true));
+                        self.password_input.clear();
+                        return;
+                    }
+                },
+                Err(e) => {
+                    self.message = Some((format!("Cannot check password: {}", e), true));
+                    return;
+                }
+            }
+            dmcore::agent::put_password(&self.config, &project_name, &password);
+            // Other projects are checked again against the new password
+            self.password_checked.clear();
+            self.password_checked.insert(project_name);
+        }
+
+        self.encryption_password = Some(password);
         self.password_input.clear();
         self.password_prompt_visible = false;
 
+        self.continue_with_password();
+    }
+
+    /// Continue the operation the password was asked for
+    fn continue_with_password(&mut self) {
         match self.password_purpose {
             PasswordPurpose::Backup => {
                 let project_name = match self.selected_project_name() {
@@ -899,6 +1225,45 @@ impl GuiApp {
             PasswordPurpose::Restore => {
                 self.perform_restore_with_password();
             }
+            PasswordPurpose::Migrate => {
+                if let Some(name) = self.selected_project_name() {
+                    self.migrate_project_encryption(&name);
+                }
+            }
+            PasswordPurpose::Unlock => {
+                if let Some(commit) = self.selected_commit.and_then(|i| self.commits.get(i)) {
+                    let hash = commit.hash.clone();
+                    self.load_commit_files(&hash);
+                }
+            }
+            PasswordPurpose::Pull => {
+                self.pull_project();
+            }
+        }
+    }
+
+    /// Unlock a project's encrypted index with the known password or the
+    /// identity files, prompting for the password if that is what it needs
+    fn index_unlocked(&mut self, project_name: &str) -> bool {
+        if !matches!(index_locked(&self.config, project_name), Ok(true)) {
+            return true;
+        }
+        let needs_password = unlock_needs_password(&self.config, project_name).unwrap_or(true);
+        if needs_password && !self.password_checked_for(project_name) {
+            self.show_password_prompt(PasswordPurpose::Unlock);
+            return false;
+        }
+        let unlocked = Keyring::load(&self.config, self.encryption_password.as_ref())
+            .and_then(|keys| unlock_index(&self.config, project_name, &keys));
+        match unlocked {
+            Ok(()) => {
+                self.load_commit_stats(project_name);
+                true
+            }
+            Err(e) => {
+                self.message = Some((format!("Cannot unlock index: {}", e), true));
+                false
+            }
         }
     }
 
@@ -920,12 +1285,16 @@ impl GuiApp {
     }
 
     pub fn poll_operation(&mut self) {
+        self.poll_password_command();
         if let Some(ref rx) = self.op_receiver {
             match rx.try_recv() {
                 Ok(result) => {
                     self.busy = false;
                     self.op_receiver = None;
                     self.message = Some((result.message, !result.success));
+                    if result.conflicts.is_some() {
+                        self.merge_dialog = result.conflicts;
+                    }
 
                     if result.success {
                         if let Some(name) = self.selected_project_name() {
@@ -962,10 +1331,11 @@ impl GuiApp {
             None => return,
         };
 
+        let exclude = ExcludeSet::for_project(&self.config, project);
         let mut synced = 0;
-        for file in &project.files {
+        for file in &project.resolved_files(&exclude) {
             let abs_path = file.absolute_path();
-            if abs_path.exists() {
+            if abs_path.exists() && !file.encrypted {
                 if let Ok(hash) = hash_file(&abs_path) {
                     if let Ok((size, modified)) = dmcore::file_metadata(&abs_path) {
                         let entry = dmcore::FileEntry::with_sync_now(hash, size, modified);
@@ -1017,46 +1387,40 @@ impl GuiApp {
             None => return,
         };
 
-        let project_dir = match self.config.project_dir(&project_name) {
-            Ok(d) => d,
-            Err(_) => return,
-        };
-
-        let output = std::process::Command::new("git")
-            .args(["show", &format!("{}:index.json", commit_hash)])
-            .current_dir(&project_dir)
-            .output();
-
-        if let Ok(output) = output {
-            if output.status.success() {
-                let content = String::from_utf8_lossy(&output.stdout);
-
-                if let Ok(v2_index) = serde_json::from_str::<V2Index>(&content) {
-                    if !v2_index.entries.is_empty() {
-                        for (path, entry) in v2_index.entries {
-                            self.add_restore_file(path, entry.hash, entry.size, entry.encrypted);
-                        }
-                        self.restore_files
-                            .sort_by(|a, b| a.display_path.cmp(&b.display_path));
-                        return;
-                    }
-                }
+        if !self.index_unlocked(&project_name) {
+            return;
+        }
 
-                if let Ok(legacy_index) = serde_json::from_str::<LegacyIndex>(&content) {
-                    for (path, entry) in legacy_index.files {
-                        self.add_restore_file(path, entry.hash, entry.size, entry.encrypted);
-                    }
-                    self.restore_files
-                        .sort_by(|a, b| a.display_path.cmp(&b.display_path));
+        match Index::load_at_revision(&self.config, &project_name, commit_hash) {
+            Ok(index) => {
+                // Older blobs of files encrypted since then are sealed too
+                let project = self.manifest.get_project(&project_name).cloned();
+                for (path, entry) in index.entries {
+                    let encrypted = entry.encrypted
+                        || project
+                            .as_ref()
+                            .and_then(|p| p.entry_for(&path))
+                            .is_some_and(|f| f.encrypted);
+                    self.add_restore_file(path, entry.hash, entry.size, encrypted, None);
                 }
-            } else {
+                self.restore_files
+                    .sort_by(|a, b| a.display_path.cmp(&b.display_path));
+            }
+            Err(_) => {
                 self.message = Some(("Failed to load commit index".to_string(), true));
             }
         }
     }
 
-    fn add_restore_file(&mut self, path: PathBuf, hash: String, size: u64, encrypted: bool) {
-        let restore_path = Self::remap_path_to_current_home(&path);
+    fn add_restore_file(
+        &mut self,
+        path: PathBuf,
+        hash: String,
+        size: u64,
+        encrypted: bool,
+        archive_entry: Option<String>,
+    ) {
+        let restore_path = dmcore::remap::localize(&self.config.path_remap, &path);
 
         let display_path = if let Some(home) = dirs::home_dir() {
             if let Ok(rel) = restore_path.strip_prefix(home) {
@@ -1078,7 +1442,6 @@ impl GuiApp {
         };
 
         self.restore_files.push(RestoreFile {
-            path,
             restore_path,
             display_path,
             hash,
@@ -1086,56 +1449,10 @@ impl GuiApp {
             exists_locally,
             local_differs,
             encrypted,
+            archive_entry,
         });
     }
 
-    fn remap_path_to_current_home(path: &PathBuf) -> PathBuf {
-        let current_home = match dirs::home_dir() {
-            Some(h) => h,
-            None => return path.clone(),
-        };
-
-        let path_str = path.to_string_lossy();
-
-        if path_str.starts_with("~/") {
-            return current_home.join(&path_str[2..]);
-        }
-
-        if path_str.starts_with("/home/") {
-            if let Some(rest) = path_str.strip_prefix("/home/") {
-                if let Some(slash_pos) = rest.find('/') {
-                    let old_user = &rest[..slash_pos];
-                    let rel_path = &rest[slash_pos + 1..];
-
-                    if let Some(current_user) = current_home.file_name() {
-                        let current_user = current_user.to_string_lossy();
-                        if old_user != current_user.as_ref() {
-                            return current_home.join(rel_path);
-                        }
-                    }
-                }
-            }
-        }
-
-        if path_str.starts_with("/Users/") {
-            if let Some(rest) = path_str.strip_prefix("/Users/") {
-                if let Some(slash_pos) = rest.find('/') {
-                    let old_user = &rest[..slash_pos];
-                    let rel_path = &rest[slash_pos + 1..];
-
-                    if let Some(current_user) = current_home.file_name() {
-                        let current_user = current_user.to_string_lossy();
-                        if old_user != current_user.as_ref() {
-                            return current_home.join(rel_path);
-                        }
-                    }
-                }
-            }
-        }
-
-        path.clone()
-    }
-
     pub fn show_restore_confirm(&mut self) {
         let indices: Vec<usize> = if self.restore_selected.is_empty() {
             self.restore_file_selected.into_iter().collect()
@@ -1160,14 +1477,9 @@ impl GuiApp {
 
         self.restore_confirm = RestoreConfirmState {
             visible: true,
-            destination: RestoreDestination::Original,
-            custom_path: String::new(),
-            entering_path: false,
             files_to_restore: indices,
             will_overwrite,
             selected_idx: 0,
-            scroll_offset: 0,
-            preview_mode: RestorePreviewMode::FileList,
         };
     }
 
@@ -1183,12 +1495,27 @@ impl GuiApp {
             return;
         }
 
-        // Check if any file needs password
-        let needs_password = indices
-            .iter()
-            .any(|&i| self.restore_files.get(i).map(|f| f.encrypted).unwrap_or(false));
+        // Check if any file needs password (blobs encrypted to recipients
+        // are opened with the identity files instead)
+        let store_dir = self
+            .selected_backup_project
+            .as_ref()
+            .and_then(|name| self.config.project_store_dir(name).ok());
+        let needs_password = indices.iter().any(|&i| {
+            self.restore_files.get(i).is_some_and(|f| {
+                f.encrypted
+                    && f.archive_entry.is_none()
+                    && store_dir
+                        .as_ref()
+                        .is_some_and(|dir| object_needs_password(dir, &f.hash))
+            })
+        });
 
-        if needs_password && self.encryption_password.is_none() {
+        let checked = match self.selected_backup_project.clone() {
+            Some(name) => self.password_checked_for(&name),
+            None => self.encryption_password.is_some(),
+        };
+        if needs_password && !checked {
             self.show_password_prompt(PasswordPurpose::Restore);
             return;
         }
@@ -1213,8 +1540,18 @@ impl GuiApp {
             Err(_) => return,
         };
 
+        let keys = match Keyring::load(&self.config, self.encryption_password.as_ref()) {
+            Ok(keys) => keys,
+            Err(e) => {
+                self.message = Some((format!("Failed to load keys: {}", e), true));
+                return;
+            }
+        };
+
         let mut restored = 0;
         let mut errors = 0;
+        // Archive entries are written in one pass over the archive afterwards
+        let mut archive_entries: HashMap<String, PathBuf> = HashMap::new();
 
         for &idx in &indices {
             if let Some(file) = self.restore_files.get(idx) {
@@ -1223,12 +1560,17 @@ impl GuiApp {
                     let _ = fs::create_dir_all(parent);
                 }
 
+                if let Some(name) = &file.archive_entry {
+                    archive_entries.insert(name.clone(), file.restore_path.clone());
+                    continue;
+                }
+
                 let result = if file.encrypted {
                     retrieve_file_from_encrypted(
                         &store_dir,
                         &file.hash,
                         &file.restore_path,
-                        self.encryption_password.as_ref(),
+                        &keys,
                         true,
                     )
                 } else {
@@ -1242,6 +1584,12 @@ impl GuiApp {
             }
         }
 
+        if !archive_entries.is_empty() {
+            let written = self.retrieve_archive_entries(&archive_entries).unwrap_or(0);
+            restored += written;
+            errors += archive_entries.len() - written;
+        }
+
         if errors > 0 {
             self.message = Some((
                 format!("Restored {} files, {} errors", restored, errors),
@@ -1252,6 +1600,22 @@ impl GuiApp {
         }
 
         self.restore_selected.clear();
+
+        // Refresh to update local_differs status
+        if let Some(archive_idx) = self.selected_archive {
+            self.load_archive_files(archive_idx);
+        }
+    }
+
+    /// Write entries of the selected archive to their destinations in one
+    /// pass, returning how many were written
+    fn retrieve_archive_entries(&self, dests: &HashMap<String, PathBuf>) -> anyhow::Result<usize> {
+        let archive = match self.selected_archive.and_then(|i| self.archives.get(i)) {
+            Some(a) => a,
+            None => return Ok(0),
+        };
+
+        Ok(dmcore::archive::write_entries(&archive.path, dests)?.len())
     }
 
     pub fn create_project(&mut self) {
@@ -1347,10 +1711,12 @@ impl GuiApp {
                 Ok(msg) => OpResult {
                     success: true,
                     message: msg,
+                    conflicts: None,
                 },
                 Err(e) => OpResult {
                     success: false,
                     message: e.to_string(),
+                    conflicts: None,
                 },
             };
 
@@ -1358,6 +1724,11 @@ impl GuiApp {
         });
     }
 
+    /// Pull the selected project in the background, merging its index file
+    /// by file
+    ///
+    /// Conflicting files open the merge dialog; nothing is merged until
+    /// each has a chosen version.
     pub fn pull_project(&mut self) {
         let project_name = match self.selected_project_name() {
             Some(name) => name,
@@ -1366,9 +1737,21 @@ impl GuiApp {
                 return;
             }
         };
+        let project = match self.manifest.get_project(&project_name) {
+            Some(p) => p.clone(),
+            None => return,
+        };
+
+        // A sealed index is read to merge and sealed again afterwards
+        let sealed = project.encrypt_index || project.encrypt_manifest;
+        if sealed && project_needs_password(&project) && !self.password_checked_for(&project_name) {
+            self.show_password_prompt(PasswordPurpose::Pull);
+            return;
+        }
 
         let config = self.config.clone();
         let name = project_name.clone();
+        let password = self.encryption_password.clone();
 
         let (tx, rx) = mpsc::channel();
         self.op_receiver = Some(rx);
@@ -1376,20 +1759,35 @@ impl GuiApp {
         self.busy_message = format!("Pulling {}...", project_name);
 
         std::thread::spawn(move || {
-            let result = (|| -> anyhow::Result<String> {
-                let project_dir = config.project_dir(&name)?;
-                dmcore::pull(&project_dir)?;
-                Ok("Pull successful".to_string())
+            let result = (|| -> anyhow::Result<(String, Option<MergeDialogState>)> {
+                let keys = Keyring::for_project(&config, &project, password.as_ref())?;
+                if index_locked(&config, &name)? {
+                    unlock_index(&config, &name, &keys)?;
+                }
+                Ok(match pull_project(&config, &name, &project, &keys)? {
+                    PullOutcome::UpToDate => ("Already up to date".to_string(), None),
+                    PullOutcome::Merged(result) => (merge_message(&name, &result), None),
+                    PullOutcome::Conflicts(plan) => (
+                        format!("{} file(s) changed on both sides; choose a version of each", plan.merge.conflicts.len()),
+                        Some(MergeDialogState {
+                            project: name.clone(),
+                            plan,
+                            resolutions: HashMap::new(),
+                        }),
+                    ),
+                })
             })();
 
             let op_result = match result {
-                Ok(msg) => OpResult {
+                Ok((msg, conflicts)) => OpResult {
                     success: true,
                     message: msg,
+                    conflicts,
                 },
                 Err(e) => OpResult {
                     success: false,
-                    message: e.to_string(),
+                    message: format!("Pull failed: {}", e),
+                    conflicts: None,
                 },
             };
 
@@ -1397,6 +1795,41 @@ impl GuiApp {
         });
     }
 
+    /// Choose a version for every conflicting file in the merge dialog
+    pub fn resolve_all_conflicts(&mut self, resolution: Resolution) {
+        if let Some(dialog) = &mut self.merge_dialog {
+            for conflict in &dialog.plan.merge.conflicts {
+                dialog.resolutions.insert(conflict.path.clone(), resolution);
+            }
+        }
+    }
+
+    /// Merge with the versions chosen in the merge dialog
+    pub fn apply_merge_dialog(&mut self) {
+        let Some(dialog) = self.merge_dialog.take() else {
+            return;
+        };
+        let Some(project) = self.manifest.get_project(&dialog.project).cloned() else {
+            return;
+        };
+
+        let result = Keyring::for_project(&self.config, &project, self.encryption_password.as_ref())
+            .and_then(|keys| apply_merge(&self.config, &dialog.project, &project, &dialog.plan, &dialog.resolutions, &keys));
+        match result {
+            Ok(result) => self.message = Some((merge_message(&dialog.project, &result), false)),
+            Err(e) => self.message = Some((format!("Merge failed: {}", e), true)),
+        }
+        self.refresh_projects();
+        self.scan_backup_projects();
+    }
+
+    /// Close the merge dialog without merging
+    pub fn cancel_merge_dialog(&mut self) {
+        if self.merge_dialog.take().is_some() {
+            self.message = Some(("Merge cancelled; nothing was changed".to_string(), false));
+        }
+    }
+
     pub fn cycle_target_project(&mut self) {
         let names: Vec<_> = self.manifest.projects.keys().cloned().collect();
         if names.is_empty() {
@@ -1583,9 +2016,15 @@ impl GuiApp {
             if let Some(file) = self.browse_files.get(idx) {
                 if file.is_dir && file.name != ".." {
                     let source_dir = file.path.clone();
+                    let target = self
+                        .target_project
+                        .clone()
+                        .or_else(|| self.projects.first().map(|p| p.name.clone()))
+                        .unwrap_or_default();
+                    let exclude = self.exclude_for(&target);
                     let mut preview_files = Vec::new();
 
-                    self.scan_directory_recursive(&source_dir, &mut preview_files);
+                    self.scan_directory_recursive(&source_dir, &exclude, &mut preview_files);
 
                     if preview_files.is_empty() {
                         self.message = Some(("No files found in directory".to_string(), true));
@@ -1605,19 +2044,24 @@ impl GuiApp {
         }
     }
 
-    fn scan_directory_recursive(&self, dir: &Path, files: &mut Vec<PreviewFile>) {
+    fn scan_directory_recursive(
+        &self,
+        dir: &Path,
+        exclude: &ExcludeSet,
+        files: &mut Vec<PreviewFile>,
+    ) {
         if let Ok(entries) = fs::read_dir(dir) {
             for entry in entries.filter_map(|e| e.ok()) {
                 let path = entry.path();
 
-                // Skip hidden files and common ignored directories
+                // Skip hidden files and anything excluded
                 let name = entry.file_name().to_string_lossy().to_string();
-                if name.starts_with('.') || name == "node_modules" || name == "target" {
+                if name.starts_with('.') || exclude.is_excluded(&path) {
                     continue;
                 }
 
                 if path.is_dir() {
-                    self.scan_directory_recursive(&path, files);
+                    self.scan_directory_recursive(&path, exclude, files);
                 } else if path.is_file() {
                     let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
                     let display_path = if let Some(home) = dirs::home_dir() {
@@ -1663,29 +2107,73 @@ impl GuiApp {
             }
         };
 
+        // Scan first, leaving out files the secret scan refuses
+        let mut candidates = Vec::new();
+        let mut refused = 0;
+        for idx in &preview.selected_files {
+            if let Some(file) = preview.preview_files.get(*idx) {
+                let (secrets, blocked) = self.secrets_for_add(&project_name, &file.path);
+                if blocked {
+                    refused += 1;
+                    continue;
+                }
+                candidates.push((contract_path(&file.path), file.track_mode, !secrets.is_empty()));
+            }
+        }
+
         let mut added = 0;
+        let mut flagged = 0;
         if let Some(project) = self.manifest.get_project_mut(&project_name) {
-            for idx in &preview.selected_files {
-                if let Some(file) = preview.preview_files.get(*idx) {
-                    let contracted = contract_path(&file.path);
-                    if project.add_path_with_mode(&contracted, file.track_mode) {
-                        added += 1;
-                    }
+            for (contracted, track_mode, has_secrets) in candidates {
+                if project.add_path_with_mode(&contracted, track_mode) {
+                    added += 1;
+                    flagged += usize::from(has_secrets);
                 }
             }
         }
 
+        let secrets_note = added_secrets_note(flagged, refused);
         if added > 0 {
             self.manifest_dirty = true;
-            self.message = Some((format!("Added {} files to {}", added, project_name), false));
+            self.message = Some((
+                format!("Added {} files to {}{}", added, project_name, secrets_note),
+                flagged + refused > 0,
+            ));
             self.refresh_projects();
             self.refresh_browse();
         } else {
-            self.message = Some(("No new files added".to_string(), false));
+            self.message = Some((format!("No new files added{}", secrets_note), refused > 0));
         }
     }
 }
 
+/// Summaries of secret scan findings, for a one-line message
+fn secret_summary(reports: &[SecretReport]) -> String {
+    reports.iter().map(|r| r.summary()).collect::<Vec<_>>().join("; ")
+}
+
+/// What the secret scan did to a batch of added files, as a message suffix
+fn added_secrets_note(flagged: usize, refused: usize) -> String {
+    let mut note = String::new();
+    if flagged > 0 {
+        note.push_str(&format!("; {} with possible secrets (consider encrypting them)", flagged));
+    }
+    if refused > 0 {
+        note.push_str(&format!("; {} refused for possible secrets", refused));
+    }
+    note
+}
+
+/// What a finished merge did, for the status message
+fn merge_message(project_name: &str, result: &MergeResult) -> String {
+    let how = if result.fast_forward { "Fast-forwarded" } else { "Merged" };
+    if result.from_theirs.is_empty() {
+        format!("{} {}", how, project_name)
+    } else {
+        format!("{} {}; restore {} file(s) to apply them here", how, project_name, result.from_theirs.len())
+    }
+}
+
 /// Convert syntect style to egui Color32
 fn syntect_to_egui_color(style: SyntectStyle) -> Color32 {
     Color32::from_rgb(style.foreground.r, style.foreground.g, style.foreground.b)
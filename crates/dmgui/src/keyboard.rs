@@ -2,6 +2,9 @@
 //!
 //! Processes keyboard shortcuts and navigation.
 
+// Key checks and their bounds guards are kept as separate `if`s for readability.
+#![allow(clippy::collapsible_if)]
+
 use crate::app::GuiApp;
 use crate::state::{Mode, RestoreView};
 use egui::{Context, Key};
@@ -20,6 +23,7 @@ pub fn handle_keyboard(app: &mut GuiApp, ctx: &Context) {
         || app.entering_commit_msg
         || app.password_prompt_visible
         || app.restore_confirm.visible
+        || app.merge_dialog.is_some()
         || app.viewer_visible
         || app.show_help
         || app.show_about
@@ -376,6 +380,11 @@ fn handle_restore_keyboard(app: &mut GuiApp, i: &egui::InputState) {
                 app.select_commit();
             }
 
+            // Archive backups
+            if i.key_pressed(Key::A) {
+                app.show_archives();
+            }
+
             // Back
             if i.key_pressed(Key::ArrowLeft)
                 || i.key_pressed(Key::H)
@@ -384,6 +393,45 @@ fn handle_restore_keyboard(app: &mut GuiApp, i: &egui::InputState) {
                 app.back_to_backup_projects();
             }
         }
+        RestoreView::Archives => {
+            let list_len = app.archives.len();
+
+            // Navigation
+            if i.key_pressed(Key::J) || i.key_pressed(Key::ArrowDown) {
+                if let Some(sel) = app.archive_selected {
+                    app.archive_selected = Some((sel + 1).min(list_len.saturating_sub(1)));
+                } else if list_len > 0 {
+                    app.archive_selected = Some(0);
+                }
+            }
+            if i.key_pressed(Key::K) || i.key_pressed(Key::ArrowUp) {
+                if let Some(sel) = app.archive_selected {
+                    app.archive_selected = Some(sel.saturating_sub(1));
+                } else if list_len > 0 {
+                    app.archive_selected = Some(0);
+                }
+            }
+
+            // Select archive
+            if i.key_pressed(Key::Enter) || i.key_pressed(Key::ArrowRight) || i.key_pressed(Key::L)
+            {
+                app.select_archive();
+            }
+
+            // Refresh
+            if i.key_pressed(Key::R) {
+                app.show_archives();
+            }
+
+            // Back to commits
+            if i.key_pressed(Key::ArrowLeft)
+                || i.key_pressed(Key::H)
+                || i.key_pressed(Key::Backspace)
+                || i.key_pressed(Key::C)
+            {
+                app.restore_view = RestoreView::Commits;
+            }
+        }
         RestoreView::Files => {
             let list_len = app.restore_files.len();
 
@@ -436,7 +484,7 @@ fn handle_restore_keyboard(app: &mut GuiApp, i: &egui::InputState) {
                 || i.key_pressed(Key::H)
                 || i.key_pressed(Key::Backspace)
             {
-                app.back_to_commits();
+                app.back_from_restore_files();
             }
         }
     }
@@ -2,8 +2,8 @@
 //!
 //! Contains enums and structs for managing GUI state.
 
-use dmcore::{FileStatus, RemoteStatus, TrackMode};
-use std::collections::HashSet;
+use dmcore::{FileStatus, MergePlan, RemoteStatus, Resolution, TrackMode};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 /// GUI mode (tab)
@@ -45,13 +45,14 @@ impl Mode {
     }
 }
 
-/// Restore view state (three-level: projects then commits then files)
+/// Restore view state (three-level: projects then commits or archives then files)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum RestoreView {
     #[default]
     Projects, // Viewing available backup projects (scanned from disk)
     Commits,  // Viewing commit list for selected project
-    Files,    // Viewing files from selected commit
+    Archives, // Viewing archive backups for selected project
+    Files,    // Viewing files from selected commit or archive
 }
 
 /// Purpose of the password prompt
@@ -60,31 +61,18 @@ pub enum PasswordPurpose {
     #[default]
     Backup,
     Restore,
-}
-
-/// Restore destination mode
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum RestoreDestination {
-    #[default]
-    Original, // Restore to original location
-    Custom,   // Restore to custom location (user enters path)
-}
-
-/// What to view in restore preview
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum RestorePreviewMode {
-    #[default]
-    FileList, // Show file list
-    Backup,   // View backup file content
-    Local,    // View local file content
-    Diff,     // View diff between backup and local
+    /// Re-encrypt stored blobs after toggling encryption
+    Migrate,
+    /// Unlock an encrypted index to browse its history
+    Unlock,
+    /// Reseal the merged index when pulling
+    Pull,
 }
 
 /// Information about a backup project found on disk
 #[derive(Debug, Clone)]
 pub struct BackupProject {
     pub name: String,
-    pub path: PathBuf,
     pub commit_count: usize,
     pub last_backup: Option<String>, // Date of most recent commit
 }
@@ -93,20 +81,16 @@ pub struct BackupProject {
 #[derive(Debug, Clone, Default)]
 pub struct RestoreConfirmState {
     pub visible: bool,
-    pub destination: RestoreDestination,
-    pub custom_path: String,
-    pub entering_path: bool,
     pub files_to_restore: Vec<usize>, // Indices into restore_files
     pub will_overwrite: usize,        // Count of files that will be overwritten
     pub selected_idx: usize,          // Selected file in the list
-    pub scroll_offset: usize,         // Scroll offset for file list
-    pub preview_mode: RestorePreviewMode, // Current view mode
 }
 
 /// A displayable file entry
 #[derive(Debug, Clone)]
 pub struct DisplayFile {
     pub path: String,
+    pub entry: String, // Manifest entry (the path itself, or the glob it matched)
     pub abs_path: PathBuf,
     pub status: FileStatus,
     pub size: Option<u64>,
@@ -114,18 +98,10 @@ pub struct DisplayFile {
     pub encrypted: bool,
 }
 
-/// A displayable project entry
+/// A displayable project entry (name used for target_project cycling)
 #[derive(Debug, Clone)]
 pub struct DisplayProject {
     pub name: String,
-    pub file_count: usize,
-    pub synced: usize,
-    pub drifted: usize,
-    pub new_files: usize,
-    pub missing: usize,
-    pub expanded: bool,
-    pub files: Vec<DisplayFile>,
-    pub remote_status: Option<RemoteStatus>,
 }
 
 /// An item in the project view (either a project header or a file)
@@ -144,6 +120,7 @@ pub enum ProjectViewItem {
     File {
         project_name: String,
         path: String,
+        entry: String,
         abs_path: PathBuf,
         status: FileStatus,
         size: Option<u64>,
@@ -172,7 +149,6 @@ impl BrowseFile {
 /// A file that can be restored from a specific commit
 #[derive(Debug, Clone)]
 pub struct RestoreFile {
-    pub path: PathBuf,         // Original path from backup
     pub restore_path: PathBuf, // Path to restore to (may be remapped)
     pub display_path: String,
     pub hash: String,
@@ -180,6 +156,7 @@ pub struct RestoreFile {
     pub exists_locally: bool,
     pub local_differs: bool, // True if local file has different hash
     pub encrypted: bool,     // Whether file was stored encrypted
+    pub archive_entry: Option<String>, // Entry name when restoring from an archive
 }
 
 /// File entry for recursive preview
@@ -200,10 +177,31 @@ pub struct RecursivePreviewState {
     pub selected_idx: usize,
 }
 
+/// A pull whose files conflict, waiting for a version of each to be chosen
+pub struct MergeDialogState {
+    pub project: String,
+    pub plan: MergePlan,
+    pub resolutions: HashMap<PathBuf, Resolution>,
+}
+
+impl MergeDialogState {
+    /// Number of conflicting files without a chosen version
+    pub fn unresolved(&self) -> usize {
+        self.plan
+            .merge
+            .conflicts
+            .iter()
+            .filter(|c| !self.resolutions.contains_key(&c.path))
+            .count()
+    }
+}
+
 /// Result from background operation
 pub struct OpResult {
     pub success: bool,
     pub message: String,
+    /// A pull that stopped on conflicting files
+    pub conflicts: Option<MergeDialogState>,
 }
 
 /// A line in the file viewer with syntax highlighting
@@ -220,6 +218,10 @@ pub struct CommitInfo {
     pub short_hash: String,
     pub date: String,
     pub message: String,
+    /// What the commit changed, once the index can be read
+    pub stat: Option<dmcore::IndexDiff>,
+    /// Names of tags on this commit
+    pub tags: Vec<String>,
 }
 
 impl From<dmcore::CommitInfo> for CommitInfo {
@@ -229,6 +231,8 @@ impl From<dmcore::CommitInfo> for CommitInfo {
             short_hash: c.short_hash,
             date: c.date,
             message: c.message,
+            stat: None,
+            tags: Vec::new(),
         }
     }
 }
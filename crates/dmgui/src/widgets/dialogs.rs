@@ -3,7 +3,8 @@
 //! Modal dialogs for various actions.
 
 use crate::app::GuiApp;
-use crate::theme::Colors;
+use crate::theme::{format_size, Colors};
+use dmcore::{contract_path, Resolution};
 use egui::{self, RichText, TextEdit};
 
 /// Render all modal dialogs
@@ -16,6 +17,7 @@ pub fn render_dialogs(app: &mut GuiApp, ctx: &egui::Context) {
     render_commit_message_dialog(app, ctx);
     render_password_dialog(app, ctx);
     render_restore_confirm_dialog(app, ctx);
+    render_merge_dialog(app, ctx);
 }
 
 fn render_help_dialog(app: &mut GuiApp, ctx: &egui::Context) {
@@ -60,7 +62,7 @@ fn render_help_dialog(app: &mut GuiApp, ctx: &egui::Context) {
                 ui.label("g            Refresh git status");
                 ui.label("G            Set git remote URL");
                 ui.label("p            Push to remote");
-                ui.label("P            Pull from remote");
+                ui.label("P            Pull from remote, choosing versions of conflicting files");
                 ui.label("n            New project");
                 ui.label("D            Delete project");
                 ui.label("r            Refresh");
@@ -81,6 +83,7 @@ fn render_help_dialog(app: &mut GuiApp, ctx: &egui::Context) {
                 ui.label(RichText::new("Restore Tab").color(Colors::YELLOW).strong());
                 ui.label("Enter/→/l    Select item / Enter view");
                 ui.label("←/h/Bksp     Back to previous view");
+                ui.label("a            Archive backups (backup list)");
                 ui.label("Space        Toggle file selection");
                 ui.label("a            Select all files");
                 ui.label("d            Deselect all files");
@@ -351,6 +354,9 @@ fn render_password_dialog(app: &mut GuiApp, ctx: &egui::Context) {
     let title = match app.password_purpose {
         crate::state::PasswordPurpose::Backup => "Encryption Password",
         crate::state::PasswordPurpose::Restore => "Decryption Password",
+        crate::state::PasswordPurpose::Migrate => "Encryption Password",
+        crate::state::PasswordPurpose::Unlock => "Decryption Password",
+        crate::state::PasswordPurpose::Pull => "Decryption Password",
     };
 
     egui::Window::new(title)
@@ -358,7 +364,11 @@ fn render_password_dialog(app: &mut GuiApp, ctx: &egui::Context) {
         .resizable(false)
         .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
         .show(ctx, |ui| {
-            ui.label("Enter password for encrypted files:");
+            if app.password_confirm.is_some() {
+                ui.label("Enter the new password again to confirm:");
+            } else {
+                ui.label("Enter password for encrypted files:");
+            }
             ui.add_space(10.0);
 
             let response = ui.add(
@@ -470,3 +480,97 @@ fn render_restore_confirm_dialog(app: &mut GuiApp, ctx: &egui::Context) {
         }
     });
 }
+
+fn render_merge_dialog(app: &mut GuiApp, ctx: &egui::Context) {
+    let Some(dialog) = app.merge_dialog.as_mut() else {
+        return;
+    };
+
+    let version = |entry: &Option<dmcore::FileEntry>| match entry {
+        Some(e) => {
+            let date = chrono::DateTime::from_timestamp(e.modified as i64, 0)
+                .map(|d| d.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            format!("{}, {}", format_size(e.size), date)
+        }
+        None => "deleted".to_string(),
+    };
+
+    let mut action = None;
+    let mut cancel = false;
+    egui::Window::new("Pull Conflicts")
+        .collapsible(false)
+        .resizable(true)
+        .default_size([700.0, 400.0])
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "Merging {} into {}: {} file(s) from there, {} merged line by line",
+                dialog.plan.theirs_ref,
+                dialog.project,
+                dialog.plan.merge.from_theirs.len(),
+                dialog.plan.text_merges.len()
+            ));
+            ui.label(
+                RichText::new("These files changed differently on both sides; choose a version of each:")
+                    .color(Colors::YELLOW),
+            );
+            ui.add_space(8.0);
+
+            egui::ScrollArea::vertical().max_height(260.0).show(ui, |ui| {
+                egui::Grid::new("merge_conflicts").striped(true).show(ui, |ui| {
+                    for conflict in &dialog.plan.merge.conflicts {
+                        ui.label(contract_path(&conflict.path));
+                        let chosen = dialog.resolutions.get(&conflict.path).copied();
+                        for (resolution, label) in [
+                            (Resolution::Ours, format!("Ours ({})", version(&conflict.ours))),
+                            (Resolution::Theirs, format!("Theirs ({})", version(&conflict.theirs))),
+                            (Resolution::Newest, "Newest".to_string()),
+                        ] {
+                            if ui.radio(chosen == Some(resolution), label).clicked() {
+                                dialog.resolutions.insert(conflict.path.clone(), resolution);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                ui.label("All:");
+                if ui.button("Ours").clicked() {
+                    action = Some(Some(Resolution::Ours));
+                }
+                if ui.button("Theirs").clicked() {
+                    action = Some(Some(Resolution::Theirs));
+                }
+                if ui.button("Newest").clicked() {
+                    action = Some(Some(Resolution::Newest));
+                }
+            });
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                let unresolved = dialog.unresolved();
+                let merge = ui.add_enabled(unresolved == 0, egui::Button::new("Merge"));
+                if merge.clicked() {
+                    action = Some(None);
+                }
+                if ui.button("Cancel").clicked() {
+                    cancel = true;
+                }
+                if unresolved > 0 {
+                    ui.label(RichText::new(format!("{} unresolved", unresolved)).color(Colors::DARK_GRAY));
+                }
+            });
+        });
+
+    match action {
+        Some(Some(resolution)) => app.resolve_all_conflicts(resolution),
+        Some(None) => app.apply_merge_dialog(),
+        None => {}
+    }
+    if cancel || ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+        app.cancel_merge_dialog();
+    }
+}
@@ -180,7 +180,7 @@ pub fn render_file_browser(app: &mut GuiApp, ui: &mut egui::Ui) {
                         ui.painter().text(
                             egui::pos2(x, y),
                             egui::Align2::LEFT_CENTER,
-                            &format_size(*s),
+                            format_size(*s),
                             font.clone(),
                             Colors::DARK_GRAY,
                         );
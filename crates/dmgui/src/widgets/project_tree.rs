@@ -142,7 +142,7 @@ pub fn render_project_tree(app: &mut GuiApp, ui: &mut egui::Ui) {
                             ui.painter().text(
                                 egui::pos2(x, y),
                                 egui::Align2::LEFT_CENTER,
-                                &format!("[{}]", git_text),
+                                format!("[{}]", git_text),
                                 font.clone(),
                                 git_color,
                             );
@@ -209,6 +209,7 @@ pub fn render_project_tree(app: &mut GuiApp, ui: &mut egui::Ui) {
                     ProjectViewItem::File {
                         project_name: _,
                         path,
+                        entry: _,
                         abs_path: _,
                         status,
                         size,
@@ -277,7 +278,7 @@ pub fn render_project_tree(app: &mut GuiApp, ui: &mut egui::Ui) {
                             ui.painter().text(
                                 egui::pos2(x, y),
                                 egui::Align2::LEFT_CENTER,
-                                &format_size(*s),
+                                format_size(*s),
                                 font.clone(),
                                 Colors::DARK_GRAY,
                             );
@@ -1,10 +1,11 @@
 //! Restore view widget for the Restore tab
 //!
-//! Three-level navigation: projects -> commits -> files
+//! Three-level navigation: projects -> commits (or archives) -> files
 
 use crate::app::GuiApp;
 use crate::state::RestoreView;
 use crate::theme::{format_size, Colors};
+use dmcore::{contract_path, ChangeKind, IndexDiff};
 use egui::{self, RichText};
 
 /// Render the restore view
@@ -12,6 +13,7 @@ pub fn render_restore_view(app: &mut GuiApp, ui: &mut egui::Ui) {
     match app.restore_view {
         RestoreView::Projects => render_backup_projects(app, ui),
         RestoreView::Commits => render_commits(app, ui),
+        RestoreView::Archives => render_archives(app, ui),
         RestoreView::Files => render_restore_files(app, ui),
     }
 }
@@ -106,7 +108,7 @@ fn render_backup_projects(app: &mut GuiApp, ui: &mut egui::Ui) {
                 ui.painter().text(
                     egui::pos2(x, y),
                     egui::Align2::LEFT_CENTER,
-                    &format!("{} backups", commit_count),
+                    format!("{} backups", commit_count),
                     font.clone(),
                     Colors::CYAN,
                 );
@@ -117,7 +119,7 @@ fn render_backup_projects(app: &mut GuiApp, ui: &mut egui::Ui) {
                     ui.painter().text(
                         egui::pos2(x, y),
                         egui::Align2::LEFT_CENTER,
-                        &format!("Last: {}", date),
+                        format!("Last: {}", date),
                         font.clone(),
                         Colors::DARK_GRAY,
                     );
@@ -169,6 +171,12 @@ fn render_commits(app: &mut GuiApp, ui: &mut egui::Ui) {
         if let Some(name) = &app.selected_backup_project {
             ui.label(RichText::new(format!("Project: {}", name)).color(Colors::YELLOW).strong());
         }
+
+        ui.separator();
+
+        if ui.button("📦 Archives").clicked() {
+            app.show_archives();
+        }
     });
     ui.add_space(5.0);
 
@@ -194,6 +202,8 @@ fn render_commits(app: &mut GuiApp, ui: &mut egui::Ui) {
                 c.short_hash.clone(),
                 c.date.clone(),
                 c.message.clone(),
+                c.tags.clone(),
+                c.stat.clone(),
                 is_selected,
             )
         })
@@ -205,7 +215,7 @@ fn render_commits(app: &mut GuiApp, ui: &mut egui::Ui) {
     egui::ScrollArea::vertical()
         .id_salt("commits_scroll")
         .show(ui, |ui| {
-            for (i, short_hash, date, message, is_selected) in &items {
+            for (i, short_hash, date, message, tags, stat, is_selected) in &items {
                 let bg_color = if *is_selected {
                     Colors::SELECTION_BG
                 } else {
@@ -261,8 +271,20 @@ fn render_commits(app: &mut GuiApp, ui: &mut egui::Ui) {
                 );
                 x += 160.0;
 
+                // Tags
+                for tag in tags {
+                    let rect = ui.painter().text(
+                        egui::pos2(x, y),
+                        egui::Align2::LEFT_CENTER,
+                        format!("[{}]", tag),
+                        font.clone(),
+                        Colors::MAGENTA,
+                    );
+                    x = rect.right() + 8.0;
+                }
+
                 // Message
-                ui.painter().text(
+                let message_rect = ui.painter().text(
                     egui::pos2(x, y),
                     egui::Align2::LEFT_CENTER,
                     message,
@@ -270,6 +292,25 @@ fn render_commits(app: &mut GuiApp, ui: &mut egui::Ui) {
                     Colors::WHITE,
                 );
 
+                // Change counts and size delta
+                let row_response = match stat {
+                    Some(stat) => {
+                        let mut x = message_rect.right() + 16.0;
+                        for (text, color) in commit_stat_parts(stat) {
+                            let rect = ui.painter().text(
+                                egui::pos2(x, y),
+                                egui::Align2::LEFT_CENTER,
+                                text,
+                                font.clone(),
+                                color,
+                            );
+                            x = rect.right() + 6.0;
+                        }
+                        row_response.on_hover_text(commit_stat_tooltip(stat))
+                    }
+                    None => row_response,
+                };
+
                 // Handle clicks
                 if row_response.clicked() {
                     new_selection = Some(*i);
@@ -304,21 +345,218 @@ fn render_commits(app: &mut GuiApp, ui: &mut egui::Ui) {
     }
 }
 
-fn render_restore_files(app: &mut GuiApp, ui: &mut egui::Ui) {
+/// `+added ~modified -removed (size delta)` counts for a commit row
+fn commit_stat_parts(stat: &IndexDiff) -> Vec<(String, egui::Color32)> {
+    let mut parts: Vec<(String, egui::Color32)> = [
+        (ChangeKind::Added, "+", Colors::GREEN),
+        (ChangeKind::Modified, "~", Colors::YELLOW),
+        (ChangeKind::Removed, "-", Colors::RED),
+    ]
+    .into_iter()
+    .filter(|(kind, _, _)| stat.count(*kind) > 0)
+    .map(|(kind, sign, color)| (format!("{}{}", sign, stat.count(kind)), color))
+    .collect();
+    let delta = stat.size_delta();
+    let sign = if delta < 0 { "-" } else { "+" };
+    parts.push((format!("({}{})", sign, format_size(delta.unsigned_abs())), Colors::DARK_GRAY));
+    parts
+}
+
+/// The files a commit changed, for the row tooltip
+fn commit_stat_tooltip(stat: &IndexDiff) -> String {
+    const MAX_FILES: usize = 20;
+
+    let mut lines: Vec<String> = stat
+        .changed()
+        .take(MAX_FILES)
+        .map(|f| {
+            let mark = match f.kind {
+                ChangeKind::Added => "A",
+                ChangeKind::Removed => "D",
+                _ => "M",
+            };
+            format!("{} {}", mark, contract_path(&f.path))
+        })
+        .collect();
+    let changed = stat.changed().count();
+    if changed > MAX_FILES {
+        lines.push(format!("... and {} more", changed - MAX_FILES));
+    }
+    lines.push(format!("{}, {} unchanged", stat.summary(), stat.count(ChangeKind::Unchanged)));
+    lines.join("\n")
+}
+
+fn render_archives(app: &mut GuiApp, ui: &mut egui::Ui) {
     // Navigation toolbar
     ui.horizontal(|ui| {
         if ui.button("⬅ Back to Backups").clicked() {
-            app.back_to_commits();
+            app.restore_view = RestoreView::Commits;
+        }
+
+        ui.separator();
+
+        if let Some(name) = &app.selected_backup_project {
+            ui.label(RichText::new(format!("Project: {}", name)).color(Colors::YELLOW).strong());
+        }
+
+        ui.separator();
+
+        if ui.button("🔄 Refresh").clicked() {
+            app.show_archives();
+        }
+    });
+    ui.add_space(5.0);
+
+    ui.label(
+        RichText::new("Archive Backups - Select an archive to view its files")
+            .color(Colors::DARK_GRAY),
+    );
+    ui.add_space(5.0);
+
+    if app.archives.is_empty() {
+        ui.label(RichText::new("No archive backups found for this project.").color(Colors::DARK_GRAY));
+        return;
+    }
+
+    let items: Vec<_> = app
+        .archives
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let is_selected = app.archive_selected == Some(i);
+            (
+                i,
+                a.format.extension(),
+                a.size,
+                a.name.clone(),
+                is_selected,
+            )
+        })
+        .collect();
+
+    let mut new_selection: Option<usize> = None;
+    let mut double_clicked: Option<usize> = None;
+
+    egui::ScrollArea::vertical()
+        .id_salt("archives_scroll")
+        .show(ui, |ui| {
+            for (i, extension, size, name, is_selected) in &items {
+                let bg_color = if *is_selected {
+                    Colors::SELECTION_BG
+                } else {
+                    egui::Color32::TRANSPARENT
+                };
+
+                let (row_rect, _) = ui.allocate_exact_size(
+                    egui::vec2(ui.available_width(), 20.0),
+                    egui::Sense::hover(),
+                );
+
+                let row_response = ui.interact(
+                    row_rect,
+                    egui::Id::new(("archive_row", *i)),
+                    egui::Sense::click(),
+                );
+
+                if *is_selected || row_response.hovered() {
+                    let color = if *is_selected {
+                        bg_color
+                    } else {
+                        Colors::HOVER_BG
+                    };
+                    ui.painter().rect_filled(row_rect, 0.0, color);
+                }
+
+                let mut x = row_rect.left() + 4.0;
+                let y = row_rect.center().y;
+                let font = egui::FontId::monospace(13.0);
+
+                // Format
+                ui.painter().text(
+                    egui::pos2(x, y),
+                    egui::Align2::LEFT_CENTER,
+                    extension,
+                    font.clone(),
+                    Colors::YELLOW,
+                );
+                x += 70.0;
+
+                // Size
+                ui.painter().text(
+                    egui::pos2(x, y),
+                    egui::Align2::LEFT_CENTER,
+                    format_size(*size),
+                    font.clone(),
+                    Colors::CYAN,
+                );
+                x += 80.0;
+
+                // File name
+                ui.painter().text(
+                    egui::pos2(x, y),
+                    egui::Align2::LEFT_CENTER,
+                    name,
+                    font.clone(),
+                    Colors::WHITE,
+                );
+
+                // Handle clicks
+                if row_response.clicked() {
+                    new_selection = Some(*i);
+                }
+                if row_response.double_clicked() {
+                    double_clicked = Some(*i);
+                }
+
+                // Context menu
+                row_response.context_menu(|ui| {
+                    if ui.button("View Files").clicked() {
+                        double_clicked = Some(*i);
+                        ui.close_menu();
+                    }
+                });
+
+                // Scroll to selected
+                if app.archive_selected == Some(*i) {
+                    row_response.scroll_to_me(Some(egui::Align::Center));
+                }
+            }
+        });
+
+    // Apply selection
+    if let Some(i) = new_selection {
+        app.archive_selected = Some(i);
+    }
+
+    // Handle double-click
+    if double_clicked.is_some() {
+        app.select_archive();
+    }
+}
+
+fn render_restore_files(app: &mut GuiApp, ui: &mut egui::Ui) {
+    // Navigation toolbar
+    ui.horizontal(|ui| {
+        let back_label = if app.selected_archive.is_some() {
+            "⬅ Back to Archives"
+        } else {
+            "⬅ Back to Backups"
+        };
+        if ui.button(back_label).clicked() {
+            app.back_from_restore_files();
         }
 
         ui.separator();
 
-        // Show commit info
-        let commit_info = app
-            .selected_commit
-            .and_then(|i| app.commits.get(i))
-            .map(|c| format!("{} - {}", c.short_hash, c.message))
-            .unwrap_or_else(|| "Unknown".to_string());
+        // Show commit or archive info
+        let commit_info = match app.selected_archive {
+            Some(i) => app.archives.get(i).map(|a| a.name.clone()),
+            None => app
+                .selected_commit
+                .and_then(|i| app.commits.get(i))
+                .map(|c| format!("{} - {}", c.short_hash, c.message)),
+        }
+        .unwrap_or_else(|| "Unknown".to_string());
         ui.label(RichText::new(&commit_info).color(Colors::CYAN).strong());
     });
     ui.add_space(5.0);
@@ -356,14 +594,13 @@ fn render_restore_files(app: &mut GuiApp, ui: &mut egui::Ui) {
         } else {
             app.restore_selected.len()
         };
-        if count > 0 {
-            if ui
+        if count > 0
+            && ui
                 .button(RichText::new(format!("Restore {} files", count)).color(Colors::GREEN))
                 .clicked()
             {
                 app.show_restore_confirm();
             }
-        }
     });
     ui.add_space(5.0);
 
@@ -477,7 +714,7 @@ fn render_restore_files(app: &mut GuiApp, ui: &mut egui::Ui) {
                 ui.painter().text(
                     egui::pos2(x, y),
                     egui::Align2::LEFT_CENTER,
-                    &format_size(*size),
+                    format_size(*size),
                     font.clone(),
                     Colors::DARK_GRAY,
                 );
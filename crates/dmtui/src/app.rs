@@ -4,14 +4,16 @@
 
 use age::secrecy::SecretString;
 use dmcore::{
-    backup_archive, backup_project_incremental_encrypted_with_message, contract_path, expand_path,
-    get_remote_status, hash_file, init_project_repo, list_archives, project_needs_password,
-    recent_commits, retrieve_file_from, retrieve_file_from_encrypted, scan_project, CommitInfo,
-    Config, FileStatus, Index, Manifest, ProjectSummary, RemoteStatus, TrackMode,
+    backup_project_incremental_encrypted_with_message, check_password, command_password, commit_diffs,
+    contract_path, expand_path, get_remote_status, hash_file, init_project_repo, list_archives, migrate_encryption,
+    migration_needs_password, object_needs_password, password_command, project_needs_password, recent_commits,
+    retrieve_file_from, retrieve_file_from_encrypted, scan_project, set_password_check, tags_by_commit, index_locked,
+    unlock_index, unlock_needs_password, entry_secrets, file_history, version_needs_password, version_text,
+    diff_lines, apply_merge, pull_project, ArchiveInfo, DiffTag, CommitInfo, Config, ExcludeSet, FileStatus, FileVersion, Index, IndexDiff,
+    Keyring, Manifest, MergePlan, MergeResult, PasswordCheck, ProjectSummary, PullOutcome, RemoteStatus, Resolution, SecretReport, SecretScan, TrackMode, TrackedFile,
 };
 use ratatui::style::{Color, Modifier, Style};
 use ratatui::widgets::ListState;
-use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -20,42 +22,6 @@ use syntect::highlighting::{Style as SyntectStyle, ThemeSet};
 use syntect::parsing::SyntaxSet;
 use syntect::easy::HighlightLines;
 
-/// V2 index format (entries field)
-#[derive(Debug, Deserialize)]
-struct V2Index {
-    #[serde(default)]
-    entries: HashMap<PathBuf, V2FileEntry>,
-}
-
-/// V2 file entry format
-#[derive(Debug, Deserialize)]
-struct V2FileEntry {
-    hash: String,
-    size: u64,
-    #[serde(default)]
-    encrypted: bool,
-}
-
-/// Legacy v1 index format (files field)
-#[derive(Debug, Deserialize)]
-struct LegacyIndex {
-    #[serde(default)]
-    files: HashMap<PathBuf, LegacyFileEntry>,
-}
-
-/// Legacy v1 file entry format
-#[derive(Debug, Deserialize)]
-struct LegacyFileEntry {
-    #[allow(dead_code)]
-    path: PathBuf,
-    hash: String,
-    #[allow(dead_code)]
-    last_modified: u64,
-    size: u64,
-    #[serde(default)]
-    encrypted: bool,
-}
-
 /// Spinner frames for busy indicator
 pub const SPINNER_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
 
@@ -89,19 +55,20 @@ impl Mode {
     }
 }
 
-/// Restore view state (three-level: projects then commits then files)
+/// Restore view state (three-level: projects then commits or archives then files)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RestoreView {
     Projects, // Viewing available backup projects (scanned from disk)
     Commits,  // Viewing commit list for selected project
-    Files,    // Viewing files from selected commit
+    Archives, // Viewing archive backups for selected project
+    Files,    // Viewing files from selected commit or archive
+    Timeline, // Viewing the backed-up versions of one file
 }
 
 /// Information about a backup project found on disk
 #[derive(Debug, Clone)]
 pub struct BackupProject {
     pub name: String,
-    pub path: PathBuf,
     pub commit_count: usize,
     pub last_backup: Option<String>, // Date of most recent commit
 }
@@ -112,6 +79,14 @@ pub enum PasswordPurpose {
     #[default]
     Backup,
     Restore,
+    /// Re-encrypt stored blobs after toggling encryption
+    Migrate,
+    /// Unlock an encrypted index to browse its history
+    Unlock,
+    /// Read encrypted versions of a file in the timeline
+    History,
+    /// Reseal the merged index when pulling
+    Pull,
 }
 
 /// Restore destination mode
@@ -150,6 +125,7 @@ pub struct RestoreConfirmState {
 #[derive(Debug, Clone)]
 pub struct DisplayFile {
     pub path: String,
+    pub entry: String, // Manifest entry (the path itself, or the glob it matched)
     pub abs_path: PathBuf,
     pub status: FileStatus,
     pub size: Option<u64>,
@@ -181,6 +157,7 @@ pub enum ProjectViewItem {
     File {
         project_name: String,
         path: String,
+        entry: String,
         abs_path: PathBuf,
         status: FileStatus,
         size: Option<u64>,
@@ -198,7 +175,6 @@ pub struct OpResult {
 /// A file that can be restored from a specific commit
 #[derive(Debug, Clone)]
 pub struct RestoreFile {
-    pub path: PathBuf,         // Original path from backup
     pub restore_path: PathBuf, // Path to restore to (may be remapped)
     pub display_path: String,
     pub hash: String,
@@ -206,6 +182,7 @@ pub struct RestoreFile {
     pub exists_locally: bool,
     pub local_differs: bool, // True if local file has different hash
     pub encrypted: bool,     // Whether file was stored encrypted
+    pub archive_entry: Option<String>, // Entry name when restoring from an archive
 }
 
 /// File entry for recursive preview
@@ -219,6 +196,26 @@ pub struct PreviewFile {
 
 /// State for recursive add preview
 #[derive(Debug, Clone)]
+/// A pull whose files conflict, waiting for a version of each to be chosen
+pub struct MergeDialogState {
+    pub project: String,
+    pub plan: MergePlan,
+    pub resolutions: HashMap<PathBuf, Resolution>,
+    pub list_state: ListState,
+}
+
+impl MergeDialogState {
+    /// Number of conflicting files without a chosen version
+    pub fn unresolved(&self) -> usize {
+        self.plan
+            .merge
+            .conflicts
+            .iter()
+            .filter(|c| !self.resolutions.contains_key(&c.path))
+            .count()
+    }
+}
+
 pub struct RecursivePreviewState {
     pub source_dir: PathBuf,
     pub preview_files: Vec<PreviewFile>,
@@ -230,7 +227,6 @@ pub struct RecursivePreviewState {
 #[derive(Debug, Clone)]
 pub struct ViewerLine {
     pub spans: Vec<(String, Style)>, // Text segments with ratatui styling
-    pub file_header: bool,           // True if this is a file separator line
 }
 
 /// Application state
@@ -259,10 +255,20 @@ pub struct App {
     pub selected_backup_project: Option<String>,  // Currently selected backup project name
     pub commits: Vec<CommitInfo>,
     pub commit_list_state: ListState,
+    pub commit_stats: Vec<Option<IndexDiff>>, // Per commit, what it changed (empty while the index is locked)
+    pub commit_tags: HashMap<String, Vec<String>>, // Tag names by commit hash
     pub selected_commit: Option<usize>,
+    pub archives: Vec<ArchiveInfo>,
+    pub archive_list_state: ListState,
+    pub selected_archive: Option<usize>,
     pub restore_files: Vec<RestoreFile>,
     pub restore_list_state: ListState,
     pub restore_selected: HashSet<usize>, // Multi-select for restore
+    pub timeline: Vec<FileVersion>,       // Versions of one file, newest first
+    pub timeline_list_state: ListState,
+    pub timeline_path: Option<PathBuf>,
+    pub timeline_mark: Option<usize>, // Version marked as the old side of a diff
+    timeline_view_pending: bool,      // Whether the password prompt was for viewing rather than diffing
 
     // UI state
     pub message: Option<(String, bool)>, // (message, is_error)
@@ -300,6 +306,9 @@ pub struct App {
     // Recursive add state
     pub recursive_preview: Option<RecursivePreviewState>,
 
+    // Pull conflict resolution state
+    pub merge_dialog: Option<MergeDialogState>,
+
     // Track mode for adding files
     #[allow(dead_code)]
     pub default_track_mode: TrackMode,
@@ -308,7 +317,13 @@ pub struct App {
     pub password_prompt_visible: bool,
     pub password_input: String,
     pub password_purpose: PasswordPurpose,
+    /// First entry of a new password, while it is typed again to confirm
+    pub password_confirm: Option<String>,
     pub encryption_password: Option<SecretString>,
+    /// Projects the password is known to match
+    password_checked: HashSet<String>,
+    /// Password command running in the background, for the prompt's purpose
+    password_receiver: Option<Receiver<(String, anyhow::Result<SecretString>)>>,
 
     // Git remote status per project
     pub project_remote_status: HashMap<String, RemoteStatus>,
@@ -370,10 +385,20 @@ impl App {
             selected_backup_project: None,
             commits: Vec::new(),
             commit_list_state: ListState::default(),
+            commit_stats: Vec::new(),
+            commit_tags: HashMap::new(),
             selected_commit: None,
+            archives: Vec::new(),
+            archive_list_state: ListState::default(),
+            selected_archive: None,
             restore_files: Vec::new(),
             restore_list_state: ListState::default(),
             restore_selected: HashSet::new(),
+            timeline: Vec::new(),
+            timeline_list_state: ListState::default(),
+            timeline_path: None,
+            timeline_mark: None,
+            timeline_view_pending: false,
             message: None,
             should_quit: false,
             show_help: false,
@@ -394,11 +419,15 @@ impl App {
             entering_commit_msg: false,
             commit_msg_input: String::new(),
             recursive_preview: None,
+            merge_dialog: None,
             default_track_mode: TrackMode::Both,
             password_prompt_visible: false,
             password_input: String::new(),
             password_purpose: PasswordPurpose::default(),
+            password_confirm: None,
             encryption_password: None,
+            password_checked: HashSet::new(),
+            password_receiver: None,
             project_remote_status: HashMap::new(),
             viewer_visible: false,
             viewer_content: Vec::new(),
@@ -448,7 +477,6 @@ impl App {
 
                         self.backup_projects.push(BackupProject {
                             name,
-                            path,
                             commit_count,
                             last_backup,
                         });
@@ -510,7 +538,9 @@ impl App {
             if let Ok(commits) = recent_commits(&project_dir, 100) {
                 self.commits = commits;
             }
+            self.commit_tags = tags_by_commit(&project_dir).unwrap_or_default();
         }
+        self.load_commit_stats(project_name);
 
         // Select first commit if available
         if !self.commits.is_empty() {
@@ -518,6 +548,16 @@ impl App {
         }
     }
 
+    /// Diff each listed commit against its parent, once the index can be read
+    fn load_commit_stats(&mut self, project_name: &str) {
+        self.commit_stats = if matches!(index_locked(&self.config, project_name), Ok(false)) {
+            let hashes: Vec<&str> = self.commits.iter().map(|c| c.hash.as_str()).collect();
+            commit_diffs(&self.config, project_name, &hashes).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+    }
+
     /// Select a backup project and load its commits
     pub fn select_backup_project(&mut self) {
         if let Some(idx) = self.backup_project_list_state.selected() {
@@ -536,6 +576,69 @@ impl App {
         self.selected_backup_project = None;
         self.commits.clear();
         self.selected_commit = None;
+        self.archives.clear();
+        self.selected_archive = None;
+    }
+
+    /// Switch from the commit list to the project's archive backups
+    pub fn show_archives(&mut self) {
+        let project_name = match &self.selected_backup_project {
+            Some(n) => n.clone(),
+            None => return,
+        };
+
+        self.archives = list_archives(&self.config, &project_name).unwrap_or_default();
+        self.archive_list_state
+            .select(if self.archives.is_empty() { None } else { Some(0) });
+        self.selected_archive = None;
+        self.restore_view = RestoreView::Archives;
+    }
+
+    /// Select an archive and load its files for restore
+    pub fn select_archive(&mut self) {
+        if let Some(i) = self.archive_list_state.selected() {
+            if i < self.archives.len() {
+                self.selected_archive = Some(i);
+                self.load_archive_files(i);
+                self.restore_view = RestoreView::Files;
+                self.restore_selected.clear();
+                if !self.restore_files.is_empty() {
+                    self.restore_list_state.select(Some(0));
+                }
+            }
+        }
+    }
+
+    /// Go back to archive list from file view
+    pub fn back_to_archives(&mut self) {
+        self.restore_view = RestoreView::Archives;
+        self.restore_files.clear();
+        self.restore_selected.clear();
+        if let Some(idx) = self.selected_archive {
+            self.archive_list_state.select(Some(idx));
+        }
+        self.selected_archive = None;
+    }
+
+    /// Load the files stored in an archive
+    pub fn load_archive_files(&mut self, archive_idx: usize) {
+        self.restore_files.clear();
+
+        let archive_path = match self.archives.get(archive_idx) {
+            Some(a) => a.path.clone(),
+            None => return,
+        };
+
+        match dmcore::archive::list_entries(&archive_path) {
+            Ok(entries) => {
+                for entry in entries {
+                    self.add_restore_file(entry.path, entry.hash, entry.size, false, Some(entry.name));
+                }
+            }
+            Err(e) => {
+                self.message = Some((format!("Failed to read archive: {}", e), true));
+            }
+        }
     }
 
     /// Refresh the projects list and build visible items
@@ -548,21 +651,22 @@ impl App {
 
         for name in names {
             if let Some(project) = self.manifest.get_project(&name) {
-                let results = scan_project(project, &self.index);
+                let exclude = ExcludeSet::for_project(&self.config, project);
+                let results = scan_project(project, &self.index, &exclude);
                 let summary = ProjectSummary::from_results(&results);
                 let expanded = self.expanded_projects.contains(&name);
 
-                // Build file list with encryption status
+                // Build file list with encryption status (glob entries expanded)
                 let files: Vec<DisplayFile> = results
                     .iter()
-                    .zip(project.files.iter())
-                    .map(|(r, tracked)| DisplayFile {
+                    .map(|r| DisplayFile {
                         path: r.path.clone(),
+                        entry: r.pattern.clone().unwrap_or_else(|| r.path.clone()),
                         abs_path: expand_path(&r.path),
                         status: r.status,
                         size: r.current_size,
                         track_mode: r.track_mode,
-                        encrypted: tracked.encrypted,
+                        encrypted: r.encrypted,
                     })
                     .collect();
 
@@ -580,6 +684,7 @@ impl App {
                         self.visible_items.push(ProjectViewItem::File {
                             project_name: name.clone(),
                             path: file.path.clone(),
+                            entry: file.entry.clone(),
                             abs_path: file.abs_path.clone(),
                             status: file.status,
                             size: file.size,
@@ -657,11 +762,16 @@ impl App {
                             .map(|(name, _)| name.clone())
                             .collect()
                     } else {
-                        // For files, exact match
+                        // For files, exact match or glob match (minus excludes)
                         self.manifest
                             .projects
                             .iter()
-                            .filter(|(_, p)| p.files.iter().any(|f| f.path == contracted))
+                            .filter(|(_, p)| {
+                                p.entry_for(&path).is_some_and(|f| {
+                                    !f.expands()
+                                        || !ExcludeSet::for_project(&self.config, p).is_excluded(&path)
+                                })
+                            })
                             .map(|(name, _)| name.clone())
                             .collect()
                     };
@@ -693,7 +803,7 @@ impl App {
     }
 
     /// Navigate into a directory
-    pub fn enter_directory(&mut self, path: &PathBuf) {
+    pub fn enter_directory(&mut self, path: &Path) {
         if path.is_dir() {
             let previous_dir = self.browse_dir.clone();
             self.browse_dir = if path.ends_with("..") {
@@ -702,7 +812,7 @@ impl App {
                     .unwrap_or(&self.browse_dir)
                     .to_path_buf()
             } else {
-                path.clone()
+                path.to_path_buf()
             };
             self.refresh_browse();
 
@@ -786,9 +896,9 @@ impl App {
         let (project_name, file_path) = match self.selected_item() {
             Some(ProjectViewItem::File {
                 project_name,
-                path,
+                entry,
                 ..
-            }) => (project_name.clone(), path.clone()),
+            }) => (project_name.clone(), entry.clone()),
             _ => {
                 self.message = Some(("Select a file to toggle encryption".to_string(), true));
                 return;
@@ -802,6 +912,7 @@ impl App {
                 let state = if file.encrypted { "enabled" } else { "disabled" };
                 self.message = Some((format!("Encryption {} (saves on exit)", state), false));
                 self.refresh_projects();
+                self.migrate_project_encryption(&project_name);
             }
         }
     }
@@ -833,6 +944,63 @@ impl App {
                 false,
             ));
             self.refresh_projects();
+            self.migrate_project_encryption(&project_name);
+        }
+    }
+
+    /// Identity files plus the password, if known, for reading encrypted blobs
+    fn keyring(&self) -> anyhow::Result<Keyring> {
+        Keyring::load(&self.config, self.encryption_password.as_ref())
+    }
+
+    /// Re-encrypt or decrypt stored blobs after an encryption toggle
+    ///
+    /// Asks for the password first if it is needed and not known yet, so no plaintext
+    /// copy of a newly encrypted file is left in the store.
+    fn migrate_project_encryption(&mut self, project_name: &str) {
+        // Nothing stored yet, the next backup stores it the right way
+        let stored = Index::load_for_project(&self.config, project_name).unwrap_or_default();
+        if stored.is_empty() {
+            return;
+        }
+
+        let project = match self.manifest.get_project(project_name) {
+            Some(p) => p.clone(),
+            None => return,
+        };
+        if migration_needs_password(&self.config, &project) && !self.password_checked_for(project_name) {
+            self.show_password_prompt(PasswordPurpose::Migrate);
+            return;
+        }
+
+        let result = Keyring::for_project(&self.config, &project, self.encryption_password.as_ref())
+            .and_then(|keys| migrate_encryption(&self.config, project_name, &project, &keys));
+        match result {
+            Ok(result) if result.is_empty() => {}
+            Ok(result) => {
+                let history = if result.history_exposed {
+                    format!("; earlier commits still hold the plaintext (dmxcli git {} purge-history)", project_name)
+                } else {
+                    String::new()
+                };
+                self.message = Some((
+                    format!(
+                        "Store updated: {} encrypted, {} decrypted, {} plaintext blobs sealed{}",
+                        result.encrypted,
+                        result.decrypted,
+                        result.sealed.len(),
+                        history
+                    ),
+                    result.history_exposed,
+                ));
+                if let Ok(index) = Index::load_for_project(&self.config, project_name) {
+                    self.index = index;
+                }
+                self.refresh_projects();
+            }
+            Err(e) => {
+                self.message = Some((format!("Failed to migrate encryption: {}", e), true));
+            }
         }
     }
 
@@ -841,9 +1009,9 @@ impl App {
         let (project_name, file_path) = match self.selected_item() {
             Some(ProjectViewItem::File {
                 project_name,
-                path,
+                entry,
                 ..
-            }) => (project_name.clone(), path.clone()),
+            }) => (project_name.clone(), entry.clone()),
             _ => {
                 self.message = Some(("Select a file to toggle track mode".to_string(), true));
                 return;
@@ -869,8 +1037,29 @@ impl App {
         }
     }
 
+    /// Exclude rules for a project (global patterns plus the project's own)
+    fn exclude_for(&self, project_name: &str) -> ExcludeSet {
+        match self.manifest.get_project(project_name) {
+            Some(project) => ExcludeSet::for_project(&self.config, project),
+            None => ExcludeSet::new(&self.config.exclude),
+        }
+    }
+
+    /// Scan a file about to be added unencrypted, as `secret_scan` says
+    ///
+    /// Returns what was found, and whether the scan refuses the file.
+    fn secrets_for_add(&self, project_name: &str, path: &Path) -> (Vec<SecretReport>, bool) {
+        let project = match self.manifest.get_project(project_name) {
+            Some(p) if self.config.secret_scan != SecretScan::Off => p,
+            _ => return (Vec::new(), false),
+        };
+        let reports = entry_secrets(&self.config, project, &TrackedFile::new(contract_path(path)));
+        let blocked = self.config.secret_scan == SecretScan::Block && !reports.is_empty();
+        (reports, blocked)
+    }
+
     /// Add a file to the target project
-    pub fn add_file_to_project(&mut self, path: &PathBuf) -> bool {
+    pub fn add_file_to_project(&mut self, path: &Path) -> bool {
         let project_name = match &self.target_project {
             Some(name) => name.clone(),
             None => {
@@ -887,14 +1076,43 @@ impl App {
             }
         };
 
+        if self.exclude_for(&project_name).is_excluded(path) {
+            self.message = Some((
+                format!("{} is excluded in {}", contract_path(path), project_name),
+                true,
+            ));
+            return false;
+        }
+
+        let (secrets, blocked) = self.secrets_for_add(&project_name, path);
+        if blocked {
+            self.message = Some((
+                format!(
+                    "Not added, possible secrets: {} (encrypt it, or list it in allow_secrets)",
+                    secret_summary(&secrets)
+                ),
+                true,
+            ));
+            return false;
+        }
+
         if let Some(project) = self.manifest.get_project_mut(&project_name) {
             let contracted = contract_path(path);
             if project.add_path_with_mode(&contracted, self.default_track_mode) {
                 self.manifest_dirty = true;
-                self.message = Some((
-                    format!("Added {} to {}", contracted, project_name),
-                    false,
-                ));
+                self.message = Some(if secrets.is_empty() {
+                    (format!("Added {} to {}", contracted, project_name), false)
+                } else {
+                    (
+                        format!(
+                            "Added {} to {}; possible secrets: {} (consider encrypting it)",
+                            contracted,
+                            project_name,
+                            secret_summary(&secrets)
+                        ),
+                        true,
+                    )
+                });
                 self.refresh_projects();
                 // Preserve cursor position when refreshing browse
                 let saved_selection = self.browse_list_state.selected();
@@ -912,7 +1130,7 @@ impl App {
     }
 
     /// Remove a file from all projects (untrack)
-    pub fn untrack_file(&mut self, path: &PathBuf) -> bool {
+    pub fn untrack_file(&mut self, path: &Path) -> bool {
         let contracted = contract_path(path);
         let mut removed_from = Vec::new();
 
@@ -961,7 +1179,7 @@ impl App {
         };
 
         // Check if project needs encryption password
-        if project_needs_password(&project) && self.encryption_password.is_none() {
+        if project_needs_password(&project) && !self.password_checked_for(&project_name) {
             self.show_password_prompt(PasswordPurpose::Backup);
             return;
         }
@@ -986,7 +1204,7 @@ impl App {
         };
 
         // Check if project needs encryption password first
-        if project_needs_password(&project) && self.encryption_password.is_none() {
+        if project_needs_password(&project) && !self.password_checked_for(&project_name) {
             self.show_password_prompt(PasswordPurpose::Backup);
             return;
         }
@@ -1061,20 +1279,28 @@ impl App {
                 init_project_repo(&config, &name)?;
 
                 // Backup with encryption support to project-specific store
+                let keys = Keyring::for_project(&config, &project, password.as_ref())?;
                 let result = backup_project_incremental_encrypted_with_message(
                     &config,
                     &name,
                     &project,
-                    password.as_ref(),
+                    &keys,
                     custom_message.as_deref(),
                 )?;
 
-                Ok(format!(
+                let mut message = format!(
                     "Backed up {} files ({} new, {} unchanged)",
                     result.backed_up + result.unchanged,
                     result.backed_up,
                     result.unchanged
-                ))
+                );
+                if !result.secrets.is_empty() {
+                    message.push_str(&format!(
+                        "; possible secrets: {} (consider encrypting)",
+                        secret_summary(&result.secrets)
+                    ));
+                }
+                Ok(message)
             })();
 
             let op_result = match result {
@@ -1092,104 +1318,199 @@ impl App {
         });
     }
 
-    /// Backup selected project to an archive (tar.gz, zip, or 7z)
-    pub fn backup_project_archive(&mut self) {
-        let project_name = match self.selected_project_name() {
-            Some(name) => name,
-            None => {
-                self.message = Some(("No project selected".to_string(), true));
-                return;
-            }
+
+    /// Check if the known password matches a project's password check
+    ///
+    /// A password entered for one project may not be the one another
+    /// project is encrypted with. Without a match, the agent is tried before
+    /// the caller asks with `show_password_prompt`.
+    fn password_checked_for(&mut self, project_name: &str) -> bool {
+        if self.password_checked.contains(project_name) {
+            return true;
+        }
+        let correct = |password: &SecretString| {
+            matches!(check_password(&self.config, project_name, password), Ok(PasswordCheck::Correct))
         };
+        if self.encryption_password.as_ref().is_some_and(correct) {
+            self.password_checked.insert(project_name.to_string());
+            return true;
+        }
+        // The agent may hold the password from another frontend
+        let Some(password) = dmcore::agent::get_password(&self.config, project_name).filter(correct) else {
+            return false;
+        };
+        self.encryption_password = Some(password);
+        self.password_checked.clear();
+        self.password_checked.insert(project_name.to_string());
+        true
+    }
 
-        let project = match self.manifest.get_project(&project_name) {
-            Some(p) => p.clone(),
-            None => {
-                self.message = Some(("Project not found".to_string(), true));
-                return;
+    /// Project the password prompt is for
+    fn password_project(&self) -> Option<String> {
+        match self.password_purpose {
+            PasswordPurpose::Restore | PasswordPurpose::Unlock | PasswordPurpose::History => {
+                self.selected_backup_project.clone()
             }
-        };
+            PasswordPurpose::Backup | PasswordPurpose::Migrate | PasswordPurpose::Pull => {
+                self.selected_project_name()
+            }
+        }
+    }
+
+    /// Ask for the password for an operation
+    ///
+    /// A project with a password command runs it in the background first,
+    /// like other long operations; the prompt opens if it has none or the
+    /// command fails.
+    pub fn show_password_prompt(&mut self, purpose: PasswordPurpose) {
+        self.password_purpose = purpose;
+        let project = self
+            .password_project()
+            .and_then(|name| Some((name.clone(), self.manifest.get_project(&name)?.clone())));
+        match project {
+            Some((name, project)) if password_command(&self.config, &project).is_some() => {
+                self.run_password_command(name, project);
+            }
+            _ => self.open_password_prompt(),
+        }
+    }
+
+    fn open_password_prompt(&mut self) {
+        self.password_prompt_visible = true;
+        self.password_input.clear();
+        self.password_confirm = None;
+    }
 
+    /// Get a project's password from its password command, checked against
+    /// the project's password check, without blocking the UI
+    fn run_password_command(&mut self, project_name: String, project: dmcore::Project) {
         let config = self.config.clone();
-        let name = project_name.clone();
-        let format = config.default_archive_format;
 
         let (tx, rx) = mpsc::channel();
-        self.op_receiver = Some(rx);
+        self.password_receiver = Some(rx);
         self.busy = true;
-        self.busy_message = format!("Creating {} archive for {}...", format.extension(), project_name);
+        self.busy_message = format!("Running password command for {}...", project_name);
 
         std::thread::spawn(move || {
-            let result = backup_archive(&config, &name, &project, format);
-
-            let op_result = match result {
-                Ok(path) => OpResult {
-                    success: true,
-                    message: format!(
-                        "Archive created: {}",
-                        path.file_name()
-                            .map(|n| n.to_string_lossy().to_string())
-                            .unwrap_or_else(|| path.display().to_string())
+            let result = command_password(&config, &project_name, &project).and_then(|password| {
+                let password = password
+                    .ok_or_else(|| anyhow::anyhow!("'{}' has no password command", project_name))?;
+                match check_password(&config, &project_name, &password)? {
+                    PasswordCheck::Correct => {}
+                    PasswordCheck::Wrong => anyhow::bail!(
+                        "Wrong password from password command for '{}'",
+                        project_name
                     ),
-                },
-                Err(e) => OpResult {
-                    success: false,
-                    message: e.to_string(),
-                },
-            };
-
-            let _ = tx.send(op_result);
+                    PasswordCheck::Unset => set_password_check(&config, &project_name, &password)?,
+                }
+                Ok(password)
+            });
+            let _ = tx.send((project_name, result));
         });
     }
 
-    /// List archive backups for the selected project
-    pub fn list_project_archives(&self) -> Vec<dmcore::ArchiveInfo> {
-        let project_name = match self.selected_project_name() {
-            Some(name) => name,
-            None => return Vec::new(),
-        };
-
-        list_archives(&self.config, &project_name).unwrap_or_default()
-    }
-
-    /// Check if selected project needs a password
-    pub fn selected_project_needs_password(&self) -> bool {
-        let project_name = match self.selected_project_name() {
-            Some(name) => name,
-            None => return false,
+    /// Take the result of a background password command, continuing the
+    /// operation that needed it or falling back to the prompt
+    fn poll_password_command(&mut self) {
+        let Some(ref rx) = self.password_receiver else {
+            return;
         };
-        match self.manifest.get_project(&project_name) {
-            Some(p) => project_needs_password(p),
-            None => false,
+        match rx.try_recv() {
+            Ok((project_name, result)) => {
+                self.busy = false;
+                self.password_receiver = None;
+                match result {
+                    Ok(password) => {
+                        dmcore::agent::put_password(&self.config, &project_name, &password);
+                        self.encryption_password = Some(password);
+                        self.password_checked.clear();
+                        self.password_checked.insert(project_name);
+                        self.continue_with_password();
+                    }
+                    Err(e) => {
+                        self.message = Some((e.to_string(), true));
+                        self.open_password_prompt();
+                    }
+                }
+            }
+            Err(std::sync::mpsc::TryRecvError::Empty) => {
+                self.spinner_frame = (self.spinner_frame + 1) % SPINNER_FRAMES.len();
+            }
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                self.busy = false;
+                self.password_receiver = None;
+            }
         }
     }
 
-    /// Show password prompt for backup or restore
-    pub fn show_password_prompt(&mut self, purpose: PasswordPurpose) {
-        self.password_prompt_visible = true;
-        self.password_input.clear();
-        self.password_purpose = purpose;
-    }
-
     /// Cancel password prompt
     pub fn cancel_password(&mut self) {
         self.password_prompt_visible = false;
         self.password_input.clear();
+        self.password_confirm = None;
     }
 
     /// Confirm password entry
+    ///
+    /// The password is checked against the project's password check. A
+    /// project's first password has to be typed twice.
     pub fn confirm_password(&mut self) {
         if self.password_input.is_empty() {
             self.message = Some(("Password cannot be empty".to_string(), true));
             return;
         }
 
+        let password = SecretString::from(self.password_input.clone());
+        if let Some(project_name) = self.password_project() {
+            match check_password(&self.config, &project_name, &password) {
+                Ok(PasswordCheck::Correct) => {}
+                Ok(PasswordCheck::Wrong) => {
+                    self.message = Some((
+                        format!("Wrong password: '{}' is encrypted with a different one", project_name),
+                        true,
+                    ));
+                    self.password_input.clear();
+                    return;
+                }
+                Ok(PasswordCheck::Unset) => match self.password_confirm.take() {
+                    None => {
+                        self.password_confirm = Some(self.password_input.clone());
+                        self.password_input.clear();
+                        return;
+                    }
+                    Some(first) if first == self.password_input => {
+                        if let Err(e) = set_password_check(&self.config, &project_name, &password) {
+                            self.message = Some((format!("Cannot save password check: {}", e), true));
+                            return;
+                        }
+                    }
+                    Some(_) => {
+                        self.message = Some(("Passwords do not match, enter it again".to_string(), true));
+                        self.password_input.clear();
+                        return;
+                    }
+                },
+                Err(e) => {
+                    self.message = Some((format!("Cannot check password: {}", e), true));
+                    return;
+                }
+            }
+            dmcore::agent::put_password(&self.config, &project_name, &password);
+            // Other projects are checked again against the new password
+            self.password_checked.clear();
+            self.password_checked.insert(project_name);
+        }
+
         // Store the password
-        self.encryption_password = Some(SecretString::from(self.password_input.clone()));
+        self.encryption_password = Some(password);
         self.password_input.clear();
         self.password_prompt_visible = false;
 
-        // Continue with the operation
+        self.continue_with_password();
+    }
+
+    /// Continue the operation the password was asked for
+    fn continue_with_password(&mut self) {
         match self.password_purpose {
             PasswordPurpose::Backup => {
                 // Re-trigger backup now that we have the password
@@ -1207,6 +1528,188 @@ impl App {
                 // Re-trigger restore
                 self.perform_restore_with_password();
             }
+            PasswordPurpose::Migrate => {
+                // Re-encrypt the blobs of the project that was toggled
+                if let Some(name) = self.selected_project_name() {
+                    self.migrate_project_encryption(&name);
+                }
+            }
+            PasswordPurpose::Unlock => {
+                // Load the commit that needed the index
+                if let Some(commit) = self.selected_commit.and_then(|i| self.commits.get(i)) {
+                    let hash = commit.hash.clone();
+                    self.load_commit_files(&hash);
+                    if !self.restore_files.is_empty() {
+                        self.restore_list_state.select(Some(0));
+                    }
+                }
+            }
+            PasswordPurpose::History => {
+                // Redo the timeline action that needed the password
+                if self.timeline_view_pending {
+                    self.view_timeline_version();
+                } else {
+                    self.diff_timeline_versions();
+                }
+            }
+            PasswordPurpose::Pull => {
+                self.pull_selected_project();
+            }
+        }
+    }
+
+    /// Pull the selected project, merging its index file by file
+    ///
+    /// Conflicting files open the merge dialog; nothing is merged until
+    /// each has a chosen version.
+    pub fn pull_selected_project(&mut self) {
+        let Some(project_name) = self.selected_project_name() else {
+            self.message = Some(("No project selected".to_string(), true));
+            return;
+        };
+        let Some(project) = self.manifest.get_project(&project_name).cloned() else {
+            return;
+        };
+        match self.config.project_dir(&project_name) {
+            Ok(dir) if dmcore::is_git_repo(&dir) => {}
+            _ => {
+                self.message = Some(("No git repo for project. Backup first.".to_string(), true));
+                return;
+            }
+        }
+
+        // A sealed index is read to merge and sealed again afterwards
+        let sealed = project.encrypt_index || project.encrypt_manifest;
+        if sealed && project_needs_password(&project) && !self.password_checked_for(&project_name) {
+            self.show_password_prompt(PasswordPurpose::Pull);
+            return;
+        }
+
+        let result = Keyring::for_project(&self.config, &project, self.encryption_password.as_ref()).and_then(|keys| {
+            if index_locked(&self.config, &project_name)? {
+                unlock_index(&self.config, &project_name, &keys)?;
+            }
+            pull_project(&self.config, &project_name, &project, &keys)
+        });
+        match result {
+            Ok(PullOutcome::UpToDate) => {
+                self.message = Some(("Already up to date".to_string(), false));
+            }
+            Ok(PullOutcome::Merged(result)) => self.merge_finished(&project_name, &result),
+            Ok(PullOutcome::Conflicts(plan)) => {
+                let mut list_state = ListState::default();
+                list_state.select(Some(0));
+                self.message = Some((
+                    format!("{} file(s) changed on both sides; choose a version of each", plan.merge.conflicts.len()),
+                    true,
+                ));
+                self.merge_dialog = Some(MergeDialogState {
+                    project: project_name,
+                    plan,
+                    resolutions: HashMap::new(),
+                    list_state,
+                });
+            }
+            Err(e) => self.message = Some((format!("Pull failed: {}", e), true)),
+        }
+        self.refresh_remote_status();
+        self.scan_backup_projects();
+    }
+
+    /// Report a finished merge
+    fn merge_finished(&mut self, project_name: &str, result: &MergeResult) {
+        let how = if result.fast_forward { "Fast-forwarded" } else { "Merged" };
+        let restore = if result.from_theirs.is_empty() {
+            String::new()
+        } else {
+            format!("; restore {} file(s) to apply them here", result.from_theirs.len())
+        };
+        self.message = Some((format!("{} {}{}", how, project_name, restore), false));
+    }
+
+    /// Move the selection in the merge dialog
+    pub fn merge_dialog_move(&mut self, delta: isize) {
+        if let Some(dialog) = &mut self.merge_dialog {
+            let len = dialog.plan.merge.conflicts.len();
+            if len == 0 {
+                return;
+            }
+            let i = dialog.list_state.selected().unwrap_or(0) as isize + delta;
+            dialog.list_state.select(Some(i.clamp(0, len as isize - 1) as usize));
+        }
+    }
+
+    /// Choose a version for the selected conflicting file, or for all
+    pub fn resolve_conflict(&mut self, resolution: Resolution, all: bool) {
+        if let Some(dialog) = &mut self.merge_dialog {
+            let selected = dialog.list_state.selected().unwrap_or(0);
+            for (i, conflict) in dialog.plan.merge.conflicts.iter().enumerate() {
+                if all || i == selected {
+                    dialog.resolutions.insert(conflict.path.clone(), resolution);
+                }
+            }
+            if !all {
+                self.merge_dialog_move(1);
+            }
+        }
+    }
+
+    /// Merge with the chosen versions
+    pub fn apply_merge_dialog(&mut self) {
+        let Some(dialog) = &self.merge_dialog else {
+            return;
+        };
+        let unresolved = dialog.unresolved();
+        if unresolved > 0 {
+            self.message = Some((format!("{} file(s) still need a version", unresolved), true));
+            return;
+        }
+        let Some(dialog) = self.merge_dialog.take() else {
+            return;
+        };
+        let Some(project) = self.manifest.get_project(&dialog.project).cloned() else {
+            return;
+        };
+
+        let result = Keyring::for_project(&self.config, &project, self.encryption_password.as_ref())
+            .and_then(|keys| apply_merge(&self.config, &dialog.project, &project, &dialog.plan, &dialog.resolutions, &keys));
+        match result {
+            Ok(result) => self.merge_finished(&dialog.project, &result),
+            Err(e) => self.message = Some((format!("Merge failed: {}", e), true)),
+        }
+        self.refresh_remote_status();
+        self.scan_backup_projects();
+    }
+
+    /// Close the merge dialog without merging
+    pub fn cancel_merge_dialog(&mut self) {
+        if self.merge_dialog.take().is_some() {
+            self.message = Some(("Merge cancelled; nothing was changed".to_string(), false));
+        }
+    }
+
+    /// Unlock a project's encrypted index with the known password or the
+    /// identity files, prompting for the password if that is what it needs
+    fn index_unlocked(&mut self, project_name: &str) -> bool {
+        if !matches!(index_locked(&self.config, project_name), Ok(true)) {
+            return true;
+        }
+        let needs_password = unlock_needs_password(&self.config, project_name).unwrap_or(true);
+        if needs_password && !self.password_checked_for(project_name) {
+            self.show_password_prompt(PasswordPurpose::Unlock);
+            return false;
+        }
+        let unlocked = Keyring::load(&self.config, self.encryption_password.as_ref())
+            .and_then(|keys| unlock_index(&self.config, project_name, &keys));
+        match unlocked {
+            Ok(()) => {
+                self.load_commit_stats(project_name);
+                true
+            }
+            Err(e) => {
+                self.message = Some((format!("Cannot unlock index: {}", e), true));
+                false
+            }
         }
     }
 
@@ -1235,6 +1738,7 @@ impl App {
 
     /// Poll for background operation completion
     pub fn poll_operation(&mut self) {
+        self.poll_password_command();
         if let Some(ref rx) = self.op_receiver {
             match rx.try_recv() {
                 Ok(result) => {
@@ -1282,10 +1786,11 @@ impl App {
             None => return,
         };
 
+        let exclude = ExcludeSet::for_project(&self.config, project);
         let mut synced = 0;
-        for file in &project.files {
+        for file in &project.resolved_files(&exclude) {
             let abs_path = file.absolute_path();
-            if abs_path.exists() {
+            if abs_path.exists() && !file.encrypted {
                 if let Ok(hash) = hash_file(&abs_path) {
                     if let Ok((size, modified)) = dmcore::file_metadata(&abs_path) {
                         let entry = dmcore::FileEntry::with_sync_now(hash, size, modified);
@@ -1332,61 +1837,234 @@ impl App {
         self.selected_commit = None;
     }
 
+    /// Show the backed-up versions of the selected file
+    pub fn open_timeline(&mut self) {
+        let Some(file) = self.restore_list_state.selected().and_then(|i| self.restore_files.get(i)) else {
+            return;
+        };
+        if file.archive_entry.is_some() {
+            self.message = Some(("Archive files have no version history".to_string(), true));
+            return;
+        }
+        let (path, display_path, hash) = (file.restore_path.clone(), file.display_path.clone(), file.hash.clone());
+        let Some(project_name) = self.selected_backup_project.clone() else {
+            return;
+        };
+
+        match file_history(&self.config, &project_name, &path) {
+            Ok(versions) if versions.is_empty() => {
+                self.message = Some((format!("No versions of {} found", display_path), true));
+            }
+            Ok(versions) => {
+                // Start at the version of the commit being browsed
+                let browsed = self.selected_commit.and_then(|i| self.commits.get(i)).map(|c| c.date.clone());
+                let current = versions
+                    .iter()
+                    .position(|v| v.hash == hash && browsed.as_ref().is_none_or(|date| v.commit.date <= *date));
+                self.timeline = versions;
+                self.timeline_path = Some(path);
+                self.timeline_mark = None;
+                self.timeline_list_state.select(Some(current.unwrap_or(0)));
+                self.restore_view = RestoreView::Timeline;
+            }
+            Err(e) => self.message = Some((format!("Cannot read history: {}", e), true)),
+        }
+    }
+
+    /// Go back to the file list from the timeline
+    pub fn back_to_files(&mut self) {
+        self.restore_view = RestoreView::Files;
+        self.timeline.clear();
+        self.timeline_path = None;
+        self.timeline_mark = None;
+    }
+
+    /// Mark the selected version as the old side of diffs, or unmark it
+    pub fn toggle_timeline_mark(&mut self) {
+        let Some(selected) = self.timeline_list_state.selected() else {
+            return;
+        };
+        self.timeline_mark = if self.timeline_mark == Some(selected) { None } else { Some(selected) };
+    }
+
+    /// Keys to read timeline versions with, prompting for the password if
+    /// one of them needs it
+    fn timeline_keys(&mut self, project_name: &str, versions: &[usize]) -> Option<Keyring> {
+        let needs_password = versions.iter().filter_map(|&i| self.timeline.get(i)).any(|version| {
+            version_needs_password(&self.config, project_name, version).unwrap_or(false)
+        });
+        if needs_password && !self.password_checked_for(project_name) {
+            self.show_password_prompt(PasswordPurpose::History);
+            return None;
+        }
+        match self.keyring() {
+            Ok(keys) => Some(keys),
+            Err(e) => {
+                self.message = Some((format!("Cannot load keys: {}", e), true));
+                None
+            }
+        }
+    }
+
+    /// Diff the selected version against the marked one, or against the
+    /// version before it
+    pub fn diff_timeline_versions(&mut self) {
+        let Some(selected) = self.timeline_list_state.selected() else {
+            return;
+        };
+        let other = match self.timeline_mark {
+            Some(mark) if mark != selected => mark,
+            _ => selected + 1,
+        };
+        if other >= self.timeline.len() {
+            self.message = Some((
+                "This is the first version; mark another one with Space to compare".to_string(),
+                true,
+            ));
+            return;
+        }
+        // The list is newest first, so the higher index is the older version
+        let (old, new) = (selected.max(other), selected.min(other));
+        let Some(project_name) = self.selected_backup_project.clone() else {
+            return;
+        };
+
+        self.timeline_view_pending = false;
+        let Some(keys) = self.timeline_keys(&project_name, &[old, new]) else {
+            return;
+        };
+        let (old_version, new_version) = (&self.timeline[old], &self.timeline[new]);
+        let texts = version_text(&self.config, &project_name, old_version, &keys)
+            .and_then(|old_text| Ok((old_text, version_text(&self.config, &project_name, new_version, &keys)?)));
+        match texts {
+            Ok((old_text, new_text)) => {
+                let title = format!(
+                    "v{} ({}) → v{} ({})",
+                    self.timeline.len() - old,
+                    old_version.commit.short_hash,
+                    self.timeline.len() - new,
+                    new_version.commit.short_hash
+                );
+                self.show_version_diff(&old_text, &new_text, &title);
+            }
+            Err(e) => self.message = Some((e.to_string(), true)),
+        }
+    }
+
+    /// View the content of the selected version
+    pub fn view_timeline_version(&mut self) {
+        let (Some(selected), Some(path)) = (self.timeline_list_state.selected(), self.timeline_path.clone()) else {
+            return;
+        };
+        let Some(project_name) = self.selected_backup_project.clone() else {
+            return;
+        };
+
+        self.timeline_view_pending = true;
+        let Some(keys) = self.timeline_keys(&project_name, &[selected]) else {
+            return;
+        };
+        let version = &self.timeline[selected];
+        match version_text(&self.config, &project_name, version, &keys) {
+            Ok(text) => {
+                self.viewer_content = self.highlight_content(&text, &path);
+                self.viewer_title = format!(
+                    "{} v{} ({})",
+                    contract_path(&path),
+                    self.timeline.len() - selected,
+                    version.commit.short_hash
+                );
+                self.viewer_scroll = 0;
+                self.viewer_visible = true;
+            }
+            Err(e) => self.message = Some((e.to_string(), true)),
+        }
+    }
+
+    /// Show a diff between two versions in the viewer, as hunks with context
+    fn show_version_diff(&mut self, old: &str, new: &str, title: &str) {
+        let hunks = dmcore::diff::hunks(&diff_lines(old, new), dmcore::diff::CONTEXT_LINES);
+
+        let mut viewer_lines = vec![ViewerLine {
+            spans: vec![(
+                format!("Diff: {}", title),
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            )],
+        }];
+        if hunks.is_empty() {
+            viewer_lines.push(ViewerLine {
+                spans: vec![("No differences in content".to_string(), Style::default().fg(Color::DarkGray))],
+            });
+        }
+        for hunk in &hunks {
+            viewer_lines.push(ViewerLine {
+                spans: vec![(hunk.header(), Style::default().fg(Color::Cyan))],
+            });
+            for line in &hunk.lines {
+                let color = match line.tag {
+                    DiffTag::Equal => Color::DarkGray,
+                    DiffTag::Delete => Color::Red,
+                    DiffTag::Insert => Color::Green,
+                };
+                viewer_lines.push(ViewerLine {
+                    spans: vec![(format!("{} {}", line.prefix(), line.text), Style::default().fg(color))],
+                });
+            }
+        }
+
+        self.viewer_content = viewer_lines;
+        self.viewer_scroll = 0;
+        self.viewer_title = format!("Diff: {}", title);
+        self.viewer_visible = true;
+    }
+
     /// Load files from a specific commit's index
     pub fn load_commit_files(&mut self, commit_hash: &str) {
         self.restore_files.clear();
 
-        // Get project directory for the selected backup project
+        // Get the selected backup project
         let project_name = match &self.selected_backup_project {
             Some(n) => n.clone(),
             None => return,
         };
 
-        let project_dir = match self.config.project_dir(&project_name) {
-            Ok(d) => d,
-            Err(_) => return,
-        };
-
-        // Get index.json content at this commit
-        let output = std::process::Command::new("git")
-            .args(["show", &format!("{}:index.json", commit_hash)])
-            .current_dir(&project_dir)
-            .output();
-
-        if let Ok(output) = output {
-            if output.status.success() {
-                let content = String::from_utf8_lossy(&output.stdout);
-
-                // Try V2 format first (entries field)
-                if let Ok(v2_index) = serde_json::from_str::<V2Index>(&content) {
-                    if !v2_index.entries.is_empty() {
-                        for (path, entry) in v2_index.entries {
-                            self.add_restore_file(path, entry.hash, entry.size, entry.encrypted);
-                        }
-                        self.restore_files
-                            .sort_by(|a, b| a.display_path.cmp(&b.display_path));
-                        return;
-                    }
-                }
+        if !self.index_unlocked(&project_name) {
+            return;
+        }
 
-                // Fall back to legacy v1 format (files field)
-                if let Ok(legacy_index) = serde_json::from_str::<LegacyIndex>(&content) {
-                    for (path, entry) in legacy_index.files {
-                        self.add_restore_file(path, entry.hash, entry.size, entry.encrypted);
-                    }
-                    self.restore_files
-                        .sort_by(|a, b| a.display_path.cmp(&b.display_path));
+        // Load index.json as of this commit (current or v1 format)
+        match Index::load_at_revision(&self.config, &project_name, commit_hash) {
+            Ok(index) => {
+                // Older blobs of files encrypted since then are sealed too
+                let project = self.manifest.get_project(&project_name).cloned();
+                for (path, entry) in index.entries {
+                    let encrypted = entry.encrypted
+                        || project
+                            .as_ref()
+                            .and_then(|p| p.entry_for(&path))
+                            .is_some_and(|f| f.encrypted);
+                    self.add_restore_file(path, entry.hash, entry.size, encrypted, None);
                 }
-            } else {
+                self.restore_files
+                    .sort_by(|a, b| a.display_path.cmp(&b.display_path));
+            }
+            Err(_) => {
                 self.message = Some(("Failed to load commit index".to_string(), true));
             }
         }
     }
 
     /// Helper to add a file to the restore list
-    fn add_restore_file(&mut self, path: PathBuf, hash: String, size: u64, encrypted: bool) {
+    fn add_restore_file(
+        &mut self,
+        path: PathBuf,
+        hash: String,
+        size: u64,
+        encrypted: bool,
+        archive_entry: Option<String>,
+    ) {
         // Remap path to current home directory if needed
-        let restore_path = Self::remap_path_to_current_home(&path);
+        let restore_path = dmcore::remap::localize(&self.config.path_remap, &path);
 
         let display_path = if let Some(home) = dirs::home_dir() {
             if let Ok(rel) = restore_path.strip_prefix(home) {
@@ -1409,7 +2087,6 @@ impl App {
         };
 
         self.restore_files.push(RestoreFile {
-            path,
             restore_path,
             display_path,
             hash,
@@ -1417,67 +2094,10 @@ impl App {
             exists_locally,
             local_differs,
             encrypted,
+            archive_entry,
         });
     }
 
-    /// Remap a path from an old home directory to the current one
-    ///
-    /// This handles cases where backups were made on a different machine
-    /// or with a different username.
-    fn remap_path_to_current_home(path: &PathBuf) -> PathBuf {
-        let current_home = match dirs::home_dir() {
-            Some(h) => h,
-            None => return path.clone(),
-        };
-
-        let path_str = path.to_string_lossy();
-
-        // Handle ~ prefix (expand to current home)
-        if path_str.starts_with("~/") {
-            return current_home.join(&path_str[2..]);
-        }
-
-        // Handle /home/username/ pattern
-        if path_str.starts_with("/home/") {
-            // Extract the username from the path
-            if let Some(rest) = path_str.strip_prefix("/home/") {
-                if let Some(slash_pos) = rest.find('/') {
-                    let old_user = &rest[..slash_pos];
-                    let rel_path = &rest[slash_pos + 1..];
-
-                    // Get current username from home dir
-                    if let Some(current_user) = current_home.file_name() {
-                        let current_user = current_user.to_string_lossy();
-                        if old_user != current_user.as_ref() {
-                            // Remap to current home
-                            return current_home.join(rel_path);
-                        }
-                    }
-                }
-            }
-        }
-
-        // Handle /Users/username/ pattern (macOS)
-        if path_str.starts_with("/Users/") {
-            if let Some(rest) = path_str.strip_prefix("/Users/") {
-                if let Some(slash_pos) = rest.find('/') {
-                    let old_user = &rest[..slash_pos];
-                    let rel_path = &rest[slash_pos + 1..];
-
-                    if let Some(current_user) = current_home.file_name() {
-                        let current_user = current_user.to_string_lossy();
-                        if old_user != current_user.as_ref() {
-                            return current_home.join(rel_path);
-                        }
-                    }
-                }
-            }
-        }
-
-        // No remapping needed
-        path.clone()
-    }
-
     /// Show restore confirmation dialog
     pub fn show_restore_confirm(&mut self) {
         let indices: Vec<usize> = if self.restore_selected.is_empty() {
@@ -1561,14 +2181,12 @@ impl App {
         // Create a temp file to retrieve the backup content
         let temp_path = std::env::temp_dir().join(format!("dmtui_preview_{}", file.hash));
 
-        let result = if file.encrypted {
-            retrieve_file_from_encrypted(
-                &store_dir,
-                &file.hash,
-                &temp_path,
-                self.encryption_password.as_ref(),
-                true,
-            )
+        let result = if let Some(name) = &file.archive_entry {
+            self.retrieve_archive_entry(name, &temp_path)
+        } else if file.encrypted {
+            self.keyring().and_then(|keys| {
+                retrieve_file_from_encrypted(&store_dir, &file.hash, &temp_path, &keys, true)
+            })
         } else {
             retrieve_file_from(&store_dir, &file.hash, &temp_path)
         };
@@ -1641,14 +2259,12 @@ impl App {
         // Retrieve backup to temp file
         let temp_path = std::env::temp_dir().join(format!("dmtui_diff_{}", file.hash));
 
-        let result = if file.encrypted {
-            retrieve_file_from_encrypted(
-                &store_dir,
-                &file.hash,
-                &temp_path,
-                self.encryption_password.as_ref(),
-                true,
-            )
+        let result = if let Some(name) = &file.archive_entry {
+            self.retrieve_archive_entry(name, &temp_path)
+        } else if file.encrypted {
+            self.keyring().and_then(|keys| {
+                retrieve_file_from_encrypted(&store_dir, &file.hash, &temp_path, &keys, true)
+            })
         } else {
             retrieve_file_from(&store_dir, &file.hash, &temp_path)
         };
@@ -1699,35 +2315,30 @@ impl App {
                 format!("Diff: {} (backup vs local)", filename),
                 Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
             )],
-            file_header: true,
         });
         viewer_lines.push(ViewerLine {
             spans: vec![(
                 "─".repeat(60),
                 Style::default().fg(Color::DarkGray),
             )],
-            file_header: false,
         });
         viewer_lines.push(ViewerLine {
             spans: vec![
                 ("- ".to_string(), Style::default().fg(Color::Red)),
                 ("lines only in backup".to_string(), Style::default().fg(Color::Red)),
             ],
-            file_header: false,
         });
         viewer_lines.push(ViewerLine {
             spans: vec![
                 ("+ ".to_string(), Style::default().fg(Color::Green)),
                 ("lines only in local".to_string(), Style::default().fg(Color::Green)),
             ],
-            file_header: false,
         });
         viewer_lines.push(ViewerLine {
             spans: vec![(
                 "─".repeat(60),
                 Style::default().fg(Color::DarkGray),
             )],
-            file_header: false,
         });
 
         // Simple line-by-line diff using LCS-like approach
@@ -1749,7 +2360,6 @@ impl App {
                                 format!("  {}", backup_lines[bi]),
                                 Style::default().fg(Color::DarkGray),
                             )],
-                            file_header: false,
                         });
                     } else {
                         in_diff_section = false;
@@ -1769,7 +2379,6 @@ impl App {
                                     format!("  {}", ctx_line),
                                     Style::default().fg(Color::DarkGray),
                                 )],
-                                file_header: false,
                             });
                         }
                         context_queue.clear();
@@ -1789,7 +2398,6 @@ impl App {
                                 format!("- {}", backup_lines[bi]),
                                 Style::default().fg(Color::Red),
                             )],
-                            file_header: false,
                         });
                         bi += 1;
                     } else if backup_in_local && !local_in_backup {
@@ -1799,7 +2407,6 @@ impl App {
                                 format!("+ {}", local_lines[li]),
                                 Style::default().fg(Color::Green),
                             )],
-                            file_header: false,
                         });
                         li += 1;
                     } else {
@@ -1809,14 +2416,12 @@ impl App {
                                 format!("- {}", backup_lines[bi]),
                                 Style::default().fg(Color::Red),
                             )],
-                            file_header: false,
                         });
                         viewer_lines.push(ViewerLine {
                             spans: vec![(
                                 format!("+ {}", local_lines[li]),
                                 Style::default().fg(Color::Green),
                             )],
-                            file_header: false,
                         });
                         bi += 1;
                         li += 1;
@@ -1831,7 +2436,6 @@ impl App {
                                 format!("  {}", ctx_line),
                                 Style::default().fg(Color::DarkGray),
                             )],
-                            file_header: false,
                         });
                     }
                     context_queue.clear();
@@ -1842,7 +2446,6 @@ impl App {
                         format!("- {}", backup_lines[bi]),
                         Style::default().fg(Color::Red),
                     )],
-                    file_header: false,
                 });
                 bi += 1;
             } else {
@@ -1854,7 +2457,6 @@ impl App {
                                 format!("  {}", ctx_line),
                                 Style::default().fg(Color::DarkGray),
                             )],
-                            file_header: false,
                         });
                     }
                     context_queue.clear();
@@ -1865,7 +2467,6 @@ impl App {
                         format!("+ {}", local_lines[li]),
                         Style::default().fg(Color::Green),
                     )],
-                    file_header: false,
                 });
                 li += 1;
             }
@@ -1922,14 +2523,27 @@ impl App {
             }
         }
 
-        // Check if password needed
-        let needs_password = self
-            .restore_confirm
-            .files_to_restore
-            .iter()
-            .any(|&i| self.restore_files.get(i).map(|f| f.encrypted).unwrap_or(false));
+        // Check if password needed (blobs encrypted to recipients are opened
+        // with the identity files instead)
+        let store_dir = self
+            .selected_backup_project
+            .as_ref()
+            .and_then(|name| self.config.project_store_dir(name).ok());
+        let needs_password = self.restore_confirm.files_to_restore.iter().any(|&i| {
+            self.restore_files.get(i).is_some_and(|f| {
+                f.encrypted
+                    && f.archive_entry.is_none()
+                    && store_dir
+                        .as_ref()
+                        .is_some_and(|dir| object_needs_password(dir, &f.hash))
+            })
+        });
 
-        if needs_password && self.encryption_password.is_none() {
+        let checked = match self.selected_backup_project.clone() {
+            Some(name) => self.password_checked_for(&name),
+            None => self.encryption_password.is_some(),
+        };
+        if needs_password && !checked {
             // Store indices before showing password prompt
             self.restore_selected = self.restore_confirm.files_to_restore.iter().cloned().collect();
             self.show_password_prompt(PasswordPurpose::Restore);
@@ -2020,8 +2634,18 @@ impl App {
             None
         };
 
+        let keys = match self.keyring() {
+            Ok(keys) => keys,
+            Err(e) => {
+                self.message = Some((format!("Failed to load keys: {}", e), true));
+                return;
+            }
+        };
+
         let mut restored = 0;
         let mut errors = 0;
+        // Archive entries are written in one pass over the archive afterwards
+        let mut archive_entries: HashMap<String, PathBuf> = HashMap::new();
 
         for i in indices {
             if i >= self.restore_files.len() {
@@ -2045,21 +2669,25 @@ impl App {
 
             // Create parent directory if needed
             if let Some(parent) = dest_path.parent() {
-                if !parent.exists() {
-                    if let Err(_) = fs::create_dir_all(parent) {
+                if !parent.exists()
+                    && fs::create_dir_all(parent).is_err() {
                         errors += 1;
                         continue;
                     }
-                }
             }
 
-            // Use encrypted or regular retrieve from project-specific store
+            if let Some(name) = &file.archive_entry {
+                archive_entries.insert(name.clone(), dest_path);
+                continue;
+            }
+
+            // Encrypted or regular retrieve from project-specific store
             let result = if file.encrypted {
                 retrieve_file_from_encrypted(
                     &store_dir,
                     &file.hash,
                     &dest_path,
-                    self.encryption_password.as_ref(),
+                    &keys,
                     true,
                 )
             } else {
@@ -2073,6 +2701,12 @@ impl App {
             }
         }
 
+        if !archive_entries.is_empty() {
+            let written = self.retrieve_archive_entries(&archive_entries).unwrap_or(0);
+            restored += written;
+            errors += archive_entries.len() - written;
+        }
+
         self.restore_selected.clear();
 
         if errors > 0 {
@@ -2085,12 +2719,41 @@ impl App {
         }
 
         // Refresh to update local_differs status
-        if let Some(commit_idx) = self.selected_commit {
+        if let Some(archive_idx) = self.selected_archive {
+            self.load_archive_files(archive_idx);
+        } else if let Some(commit_idx) = self.selected_commit {
             let hash = self.commits[commit_idx].hash.clone();
             self.load_commit_files(&hash);
         }
     }
 
+    /// Write an entry of the selected archive to `dest`
+    fn retrieve_archive_entry(&self, name: &str, dest: &Path) -> anyhow::Result<bool> {
+        let archive = match self.selected_archive.and_then(|i| self.archives.get(i)) {
+            Some(a) => a,
+            None => return Ok(false),
+        };
+
+        match dmcore::archive::read_entry(&archive.path, name)? {
+            Some(content) => {
+                fs::write(dest, content)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Write entries of the selected archive to their destinations in one
+    /// pass, returning how many were written
+    fn retrieve_archive_entries(&self, dests: &HashMap<String, PathBuf>) -> anyhow::Result<usize> {
+        let archive = match self.selected_archive.and_then(|i| self.archives.get(i)) {
+            Some(a) => a,
+            None => return Ok(0),
+        };
+
+        Ok(dmcore::archive::write_entries(&archive.path, dests)?.len())
+    }
+
     /// Toggle file selection for restore
     pub fn toggle_restore_select(&mut self) {
         if let Some(i) = self.restore_list_state.selected() {
@@ -2381,9 +3044,15 @@ impl App {
 
         let dir = file.path.clone();
 
-        // Scan directory recursively
+        // Scan directory recursively, honoring the target project's excludes
+        let target = self
+            .target_project
+            .clone()
+            .or_else(|| self.projects.first().map(|p| p.name.clone()))
+            .unwrap_or_default();
+        let exclude = self.exclude_for(&target);
         let mut preview_files = Vec::new();
-        self.scan_dir_recursive(&dir, &mut preview_files);
+        self.scan_dir_recursive(&dir, &exclude, &mut preview_files);
 
         if preview_files.is_empty() {
             self.message = Some(("No files found in directory".to_string(), true));
@@ -2408,7 +3077,7 @@ impl App {
     }
 
     /// Recursively scan a directory for files
-    fn scan_dir_recursive(&self, dir: &PathBuf, files: &mut Vec<PreviewFile>) {
+    fn scan_dir_recursive(&self, dir: &Path, exclude: &ExcludeSet, files: &mut Vec<PreviewFile>) {
         let entries = match std::fs::read_dir(dir) {
             Ok(e) => e,
             Err(_) => return,
@@ -2418,19 +3087,13 @@ impl App {
             let path = entry.path();
             let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
 
-            // Skip hidden files and common ignored dirs
-            if name.starts_with('.') {
-                continue;
-            }
-            if matches!(
-                name,
-                "node_modules" | "__pycache__" | "target" | ".git"
-            ) {
+            // Skip hidden files and anything excluded
+            if name.starts_with('.') || exclude.is_excluded(&path) {
                 continue;
             }
 
             if path.is_dir() {
-                self.scan_dir_recursive(&path, files);
+                self.scan_dir_recursive(&path, exclude, files);
             } else if path.is_file() {
                 let display_path = if let Some(home) = dirs::home_dir() {
                     if let Ok(rel) = path.strip_prefix(&home) {
@@ -2478,6 +3141,22 @@ impl App {
             }
         };
 
+        // Scan first, leaving out files the secret scan refuses
+        let mut candidates = Vec::new();
+        let mut refused = 0;
+        for idx in &preview.selected_files {
+            if let Some(file) = preview.preview_files.get(*idx) {
+                let (secrets, blocked) = self.secrets_for_add(&project_name, &file.path);
+                if blocked {
+                    refused += 1;
+                    continue;
+                }
+                // Create a TrackedFile with the file's track mode
+                let tracked = TrackedFile::with_mode(contract_path(&file.path), file.track_mode);
+                candidates.push((tracked, !secrets.is_empty()));
+            }
+        }
+
         let project = match self.manifest.get_project_mut(&project_name) {
             Some(p) => p,
             None => {
@@ -2487,22 +3166,20 @@ impl App {
         };
 
         let mut added = 0;
-        for idx in &preview.selected_files {
-            if let Some(file) = preview.preview_files.get(*idx) {
-                let contracted = contract_path(&file.path);
-                // Create a TrackedFile with the file's track mode
-                let tracked = dmcore::TrackedFile::with_mode(&contracted, file.track_mode);
-                if project.add_file(tracked) {
-                    added += 1;
-                }
+        let mut flagged = 0;
+        for (tracked, has_secrets) in candidates {
+            if project.add_file(tracked) {
+                added += 1;
+                flagged += usize::from(has_secrets);
             }
         }
 
+        let secrets_note = added_secrets_note(flagged, refused);
         if added > 0 {
             self.manifest_dirty = true;
             self.message = Some((
-                format!("Added {} files to {}", added, project_name),
-                false,
+                format!("Added {} files to {}{}", added, project_name, secrets_note),
+                flagged + refused > 0,
             ));
             self.refresh_projects();
             // Preserve cursor position when refreshing browse
@@ -2513,7 +3190,7 @@ impl App {
                 self.browse_list_state.select(Some(idx.min(max_idx)));
             }
         } else {
-            self.message = Some(("No new files added".to_string(), false));
+            self.message = Some((format!("No new files added{}", secrets_note), refused > 0));
         }
     }
 
@@ -2688,7 +3365,6 @@ impl App {
             Ok(c) => c,
             Err(_) => return vec![ViewerLine {
                 spans: vec![("Unable to read file".to_string(), Style::default().fg(Color::Red))],
-                file_header: false,
             }],
         };
 
@@ -2731,7 +3407,6 @@ impl App {
             let header = format!("─────────────── {} ───────────────", file_name);
             result.push(ViewerLine {
                 spans: vec![(header, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))],
-                file_header: true,
             });
 
             // Add file content
@@ -2743,7 +3418,6 @@ impl App {
             // Add blank line between files
             result.push(ViewerLine {
                 spans: vec![("".to_string(), Style::default())],
-                file_header: false,
             });
         }
 
@@ -2774,7 +3448,6 @@ impl App {
                             (text.to_string(), syntect_to_ratatui_style(style))
                         })
                         .collect(),
-                    file_header: false,
                 }
             })
             .collect()
@@ -2816,7 +3489,7 @@ impl App {
 }
 
 /// Sort config files: numeric prefix first, then alphabetically
-pub fn sort_config_files(files: &mut Vec<PathBuf>) {
+pub fn sort_config_files(files: &mut [PathBuf]) {
     files.sort_by(|a, b| {
         let a_name = a.file_name().unwrap_or_default().to_string_lossy();
         let b_name = b.file_name().unwrap_or_default().to_string_lossy();
@@ -2844,6 +3517,23 @@ pub fn sort_config_files(files: &mut Vec<PathBuf>) {
     });
 }
 
+/// Summaries of secret scan findings, for a one-line message
+fn secret_summary(reports: &[SecretReport]) -> String {
+    reports.iter().map(|r| r.summary()).collect::<Vec<_>>().join("; ")
+}
+
+/// What the secret scan did to a batch of added files, as a message suffix
+fn added_secrets_note(flagged: usize, refused: usize) -> String {
+    let mut note = String::new();
+    if flagged > 0 {
+        note.push_str(&format!("; {} with possible secrets (consider encrypting them)", flagged));
+    }
+    if refused > 0 {
+        note.push_str(&format!("; {} refused for possible secrets", refused));
+    }
+    note
+}
+
 /// Convert syntect style to ratatui style
 fn syntect_to_ratatui_style(style: &SyntectStyle) -> Style {
     let fg = Color::Rgb(style.foreground.r, style.foreground.g, style.foreground.b);
@@ -12,7 +12,7 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use dmcore::FileStatus;
+use dmcore::{contract_path, ChangeKind, FileEntry, FileStatus, IndexDiff, Resolution};
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
@@ -237,6 +237,24 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut A
                 continue;
             }
 
+            // Pull conflict resolution mode
+            if app.merge_dialog.is_some() {
+                match key.code {
+                    KeyCode::Down | KeyCode::Char('j') => app.merge_dialog_move(1),
+                    KeyCode::Up | KeyCode::Char('k') => app.merge_dialog_move(-1),
+                    KeyCode::Char('o') => app.resolve_conflict(Resolution::Ours, false),
+                    KeyCode::Char('t') => app.resolve_conflict(Resolution::Theirs, false),
+                    KeyCode::Char('n') => app.resolve_conflict(Resolution::Newest, false),
+                    KeyCode::Char('O') => app.resolve_conflict(Resolution::Ours, true),
+                    KeyCode::Char('T') => app.resolve_conflict(Resolution::Theirs, true),
+                    KeyCode::Char('N') => app.resolve_conflict(Resolution::Newest, true),
+                    KeyCode::Enter => app.apply_merge_dialog(),
+                    KeyCode::Esc | KeyCode::Char('q') => app.cancel_merge_dialog(),
+                    _ => {}
+                }
+                continue;
+            }
+
             // Password prompt mode
             if app.password_prompt_visible {
                 match key.code {
@@ -423,44 +441,38 @@ const PAGE_SIZE: usize = 10;
 
 fn handle_projects_keys(app: &mut App, key: KeyCode) {
     match key {
-        KeyCode::Down | KeyCode::Char('j') => {
-            if !app.visible_items.is_empty() {
+        KeyCode::Down | KeyCode::Char('j')
+            if !app.visible_items.is_empty() => {
                 let i = app.project_list_state.selected().unwrap_or(0);
                 let next = (i + 1).min(app.visible_items.len() - 1);
                 app.project_list_state.select(Some(next));
             }
-        }
-        KeyCode::Up | KeyCode::Char('k') => {
-            if !app.visible_items.is_empty() {
+        KeyCode::Up | KeyCode::Char('k')
+            if !app.visible_items.is_empty() => {
                 let i = app.project_list_state.selected().unwrap_or(0);
                 let prev = i.saturating_sub(1);
                 app.project_list_state.select(Some(prev));
             }
-        }
-        KeyCode::PageDown => {
-            if !app.visible_items.is_empty() {
+        KeyCode::PageDown
+            if !app.visible_items.is_empty() => {
                 let i = app.project_list_state.selected().unwrap_or(0);
                 let next = (i + PAGE_SIZE).min(app.visible_items.len() - 1);
                 app.project_list_state.select(Some(next));
             }
-        }
-        KeyCode::PageUp => {
-            if !app.visible_items.is_empty() {
+        KeyCode::PageUp
+            if !app.visible_items.is_empty() => {
                 let i = app.project_list_state.selected().unwrap_or(0);
                 let prev = i.saturating_sub(PAGE_SIZE);
                 app.project_list_state.select(Some(prev));
             }
-        }
-        KeyCode::Home => {
-            if !app.visible_items.is_empty() {
+        KeyCode::Home
+            if !app.visible_items.is_empty() => {
                 app.project_list_state.select(Some(0));
             }
-        }
-        KeyCode::End => {
-            if !app.visible_items.is_empty() {
+        KeyCode::End
+            if !app.visible_items.is_empty() => {
                 app.project_list_state.select(Some(app.visible_items.len() - 1));
             }
-        }
         KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
             app.toggle_selected_project();
         }
@@ -527,23 +539,8 @@ fn handle_projects_keys(app: &mut App, key: KeyCode) {
             }
         }
         KeyCode::Char('P') => {
-            // Pull from remote (project-specific)
-            if let Some(name) = app.selected_project_name() {
-                if let Ok(project_dir) = app.config.project_dir(&name) {
-                    if dmcore::is_git_repo(&project_dir) {
-                        match dmcore::pull(&project_dir) {
-                            Ok(msg) => app.message = Some((msg, false)),
-                            Err(e) => app.message = Some((e.to_string(), true)),
-                        }
-                        app.refresh_remote_status();
-                        app.scan_backup_projects();
-                    } else {
-                        app.message = Some(("No git repo for project. Backup first.".to_string(), true));
-                    }
-                }
-            } else {
-                app.message = Some(("No project selected".to_string(), true));
-            }
+            // Pull from remote, merging the index (project-specific)
+            app.pull_selected_project();
         }
         KeyCode::Char('v') => {
             app.open_viewer();
@@ -554,44 +551,38 @@ fn handle_projects_keys(app: &mut App, key: KeyCode) {
 
 fn handle_add_keys(app: &mut App, key: KeyCode) {
     match key {
-        KeyCode::Down | KeyCode::Char('j') => {
-            if !app.browse_files.is_empty() {
+        KeyCode::Down | KeyCode::Char('j')
+            if !app.browse_files.is_empty() => {
                 let i = app.browse_list_state.selected().unwrap_or(0);
                 let next = (i + 1).min(app.browse_files.len() - 1);
                 app.browse_list_state.select(Some(next));
             }
-        }
-        KeyCode::Up | KeyCode::Char('k') => {
-            if !app.browse_files.is_empty() {
+        KeyCode::Up | KeyCode::Char('k')
+            if !app.browse_files.is_empty() => {
                 let i = app.browse_list_state.selected().unwrap_or(0);
                 let prev = i.saturating_sub(1);
                 app.browse_list_state.select(Some(prev));
             }
-        }
-        KeyCode::PageDown => {
-            if !app.browse_files.is_empty() {
+        KeyCode::PageDown
+            if !app.browse_files.is_empty() => {
                 let i = app.browse_list_state.selected().unwrap_or(0);
                 let next = (i + PAGE_SIZE).min(app.browse_files.len() - 1);
                 app.browse_list_state.select(Some(next));
             }
-        }
-        KeyCode::PageUp => {
-            if !app.browse_files.is_empty() {
+        KeyCode::PageUp
+            if !app.browse_files.is_empty() => {
                 let i = app.browse_list_state.selected().unwrap_or(0);
                 let prev = i.saturating_sub(PAGE_SIZE);
                 app.browse_list_state.select(Some(prev));
             }
-        }
-        KeyCode::Home => {
-            if !app.browse_files.is_empty() {
+        KeyCode::Home
+            if !app.browse_files.is_empty() => {
                 app.browse_list_state.select(Some(0));
             }
-        }
-        KeyCode::End => {
-            if !app.browse_files.is_empty() {
+        KeyCode::End
+            if !app.browse_files.is_empty() => {
                 app.browse_list_state.select(Some(app.browse_files.len() - 1));
             }
-        }
         KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
             if let Some(idx) = app.browse_list_state.selected() {
                 if let Some(file) = app.browse_files.get(idx) {
@@ -671,44 +662,38 @@ fn handle_restore_keys(app: &mut App, key: KeyCode) {
     match app.restore_view {
         RestoreView::Projects => {
             match key {
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if !app.backup_projects.is_empty() {
+                KeyCode::Down | KeyCode::Char('j')
+                    if !app.backup_projects.is_empty() => {
                         let i = app.backup_project_list_state.selected().unwrap_or(0);
                         let next = (i + 1).min(app.backup_projects.len() - 1);
                         app.backup_project_list_state.select(Some(next));
                     }
-                }
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if !app.backup_projects.is_empty() {
+                KeyCode::Up | KeyCode::Char('k')
+                    if !app.backup_projects.is_empty() => {
                         let i = app.backup_project_list_state.selected().unwrap_or(0);
                         let prev = i.saturating_sub(1);
                         app.backup_project_list_state.select(Some(prev));
                     }
-                }
-                KeyCode::PageDown => {
-                    if !app.backup_projects.is_empty() {
+                KeyCode::PageDown
+                    if !app.backup_projects.is_empty() => {
                         let i = app.backup_project_list_state.selected().unwrap_or(0);
                         let next = (i + PAGE_SIZE).min(app.backup_projects.len() - 1);
                         app.backup_project_list_state.select(Some(next));
                     }
-                }
-                KeyCode::PageUp => {
-                    if !app.backup_projects.is_empty() {
+                KeyCode::PageUp
+                    if !app.backup_projects.is_empty() => {
                         let i = app.backup_project_list_state.selected().unwrap_or(0);
                         let prev = i.saturating_sub(PAGE_SIZE);
                         app.backup_project_list_state.select(Some(prev));
                     }
-                }
-                KeyCode::Home => {
-                    if !app.backup_projects.is_empty() {
+                KeyCode::Home
+                    if !app.backup_projects.is_empty() => {
                         app.backup_project_list_state.select(Some(0));
                     }
-                }
-                KeyCode::End => {
-                    if !app.backup_projects.is_empty() {
+                KeyCode::End
+                    if !app.backup_projects.is_empty() => {
                         app.backup_project_list_state.select(Some(app.backup_projects.len() - 1));
                     }
-                }
                 KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
                     // Select project and view its commits
                     app.select_backup_project();
@@ -723,44 +708,38 @@ fn handle_restore_keys(app: &mut App, key: KeyCode) {
         }
         RestoreView::Commits => {
             match key {
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if !app.commits.is_empty() {
+                KeyCode::Down | KeyCode::Char('j')
+                    if !app.commits.is_empty() => {
                         let i = app.commit_list_state.selected().unwrap_or(0);
                         let next = (i + 1).min(app.commits.len() - 1);
                         app.commit_list_state.select(Some(next));
                     }
-                }
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if !app.commits.is_empty() {
+                KeyCode::Up | KeyCode::Char('k')
+                    if !app.commits.is_empty() => {
                         let i = app.commit_list_state.selected().unwrap_or(0);
                         let prev = i.saturating_sub(1);
                         app.commit_list_state.select(Some(prev));
                     }
-                }
-                KeyCode::PageDown => {
-                    if !app.commits.is_empty() {
+                KeyCode::PageDown
+                    if !app.commits.is_empty() => {
                         let i = app.commit_list_state.selected().unwrap_or(0);
                         let next = (i + PAGE_SIZE).min(app.commits.len() - 1);
                         app.commit_list_state.select(Some(next));
                     }
-                }
-                KeyCode::PageUp => {
-                    if !app.commits.is_empty() {
+                KeyCode::PageUp
+                    if !app.commits.is_empty() => {
                         let i = app.commit_list_state.selected().unwrap_or(0);
                         let prev = i.saturating_sub(PAGE_SIZE);
                         app.commit_list_state.select(Some(prev));
                     }
-                }
-                KeyCode::Home => {
-                    if !app.commits.is_empty() {
+                KeyCode::Home
+                    if !app.commits.is_empty() => {
                         app.commit_list_state.select(Some(0));
                     }
-                }
-                KeyCode::End => {
-                    if !app.commits.is_empty() {
+                KeyCode::End
+                    if !app.commits.is_empty() => {
                         app.commit_list_state.select(Some(app.commits.len() - 1));
                     }
-                }
                 KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
                     // Select commit and view its files
                     app.select_commit();
@@ -769,6 +748,10 @@ fn handle_restore_keys(app: &mut App, key: KeyCode) {
                     // Go back to projects
                     app.back_to_backup_projects();
                 }
+                KeyCode::Char('a') => {
+                    // Switch to archive backups
+                    app.show_archives();
+                }
                 KeyCode::Char('r') => {
                     // Refresh commits
                     if let Some(name) = app.selected_backup_project.clone() {
@@ -779,53 +762,89 @@ fn handle_restore_keys(app: &mut App, key: KeyCode) {
                 _ => {}
             }
         }
+        RestoreView::Archives => {
+            match key {
+                KeyCode::Down | KeyCode::Char('j')
+                    if !app.archives.is_empty() => {
+                        let i = app.archive_list_state.selected().unwrap_or(0);
+                        let next = (i + 1).min(app.archives.len() - 1);
+                        app.archive_list_state.select(Some(next));
+                    }
+                KeyCode::Up | KeyCode::Char('k')
+                    if !app.archives.is_empty() => {
+                        let i = app.archive_list_state.selected().unwrap_or(0);
+                        let prev = i.saturating_sub(1);
+                        app.archive_list_state.select(Some(prev));
+                    }
+                KeyCode::Home
+                    if !app.archives.is_empty() => {
+                        app.archive_list_state.select(Some(0));
+                    }
+                KeyCode::End
+                    if !app.archives.is_empty() => {
+                        app.archive_list_state.select(Some(app.archives.len() - 1));
+                    }
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                    // Select archive and view its files
+                    app.select_archive();
+                }
+                KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace | KeyCode::Char('c') => {
+                    // Go back to commits
+                    app.restore_view = RestoreView::Commits;
+                }
+                KeyCode::Char('r') => {
+                    // Refresh archives
+                    app.show_archives();
+                    app.message = Some(("Refreshed".to_string(), false));
+                }
+                _ => {}
+            }
+        }
         RestoreView::Files => {
             match key {
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if !app.restore_files.is_empty() {
+                KeyCode::Down | KeyCode::Char('j')
+                    if !app.restore_files.is_empty() => {
                         let i = app.restore_list_state.selected().unwrap_or(0);
                         let next = (i + 1).min(app.restore_files.len() - 1);
                         app.restore_list_state.select(Some(next));
                     }
-                }
-                KeyCode::Up | KeyCode::Char('k') => {
-                    if !app.restore_files.is_empty() {
+                KeyCode::Up | KeyCode::Char('k')
+                    if !app.restore_files.is_empty() => {
                         let i = app.restore_list_state.selected().unwrap_or(0);
                         let prev = i.saturating_sub(1);
                         app.restore_list_state.select(Some(prev));
                     }
-                }
-                KeyCode::PageDown => {
-                    if !app.restore_files.is_empty() {
+                KeyCode::PageDown
+                    if !app.restore_files.is_empty() => {
                         let i = app.restore_list_state.selected().unwrap_or(0);
                         let next = (i + PAGE_SIZE).min(app.restore_files.len() - 1);
                         app.restore_list_state.select(Some(next));
                     }
-                }
-                KeyCode::PageUp => {
-                    if !app.restore_files.is_empty() {
+                KeyCode::PageUp
+                    if !app.restore_files.is_empty() => {
                         let i = app.restore_list_state.selected().unwrap_or(0);
                         let prev = i.saturating_sub(PAGE_SIZE);
                         app.restore_list_state.select(Some(prev));
                     }
-                }
-                KeyCode::Home => {
-                    if !app.restore_files.is_empty() {
+                KeyCode::Home
+                    if !app.restore_files.is_empty() => {
                         app.restore_list_state.select(Some(0));
                     }
-                }
-                KeyCode::End => {
-                    if !app.restore_files.is_empty() {
+                KeyCode::End
+                    if !app.restore_files.is_empty() => {
                         app.restore_list_state.select(Some(app.restore_files.len() - 1));
                     }
-                }
                 KeyCode::Enter | KeyCode::Char('R') => {
                     // Restore selected file(s)
                     app.perform_restore();
                 }
                 KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => {
-                    // Go back to commits
-                    app.back_to_commits();
+                    // Go back to commits or archives
+                    if app.selected_archive.is_some() {
+                        app.back_to_archives();
+                    } else {
+                        app.back_to_commits();
+                    }
                 }
                 KeyCode::Char(' ') => {
                     // Toggle selection
@@ -839,7 +858,9 @@ fn handle_restore_keys(app: &mut App, key: KeyCode) {
                 }
                 KeyCode::Char('r') => {
                     // Refresh files
-                    if let Some(idx) = app.selected_commit {
+                    if let Some(idx) = app.selected_archive {
+                        app.load_archive_files(idx);
+                    } else if let Some(idx) = app.selected_commit {
                         let hash = app.commits[idx].hash.clone();
                         app.load_commit_files(&hash);
                     }
@@ -848,6 +869,10 @@ fn handle_restore_keys(app: &mut App, key: KeyCode) {
                 KeyCode::Char('v') => {
                     app.open_viewer();
                 }
+                KeyCode::Char('t') => {
+                    // Show the file's versions
+                    app.open_timeline();
+                }
                 KeyCode::Char('a') => {
                     // Select all
                     app.select_all_restore();
@@ -859,6 +884,57 @@ fn handle_restore_keys(app: &mut App, key: KeyCode) {
                 _ => {}
             }
         }
+        RestoreView::Timeline => {
+            match key {
+                KeyCode::Down | KeyCode::Char('j')
+                    if !app.timeline.is_empty() => {
+                        let i = app.timeline_list_state.selected().unwrap_or(0);
+                        let next = (i + 1).min(app.timeline.len() - 1);
+                        app.timeline_list_state.select(Some(next));
+                    }
+                KeyCode::Up | KeyCode::Char('k')
+                    if !app.timeline.is_empty() => {
+                        let i = app.timeline_list_state.selected().unwrap_or(0);
+                        let prev = i.saturating_sub(1);
+                        app.timeline_list_state.select(Some(prev));
+                    }
+                KeyCode::PageDown
+                    if !app.timeline.is_empty() => {
+                        let i = app.timeline_list_state.selected().unwrap_or(0);
+                        let next = (i + PAGE_SIZE).min(app.timeline.len() - 1);
+                        app.timeline_list_state.select(Some(next));
+                    }
+                KeyCode::PageUp
+                    if !app.timeline.is_empty() => {
+                        let i = app.timeline_list_state.selected().unwrap_or(0);
+                        let prev = i.saturating_sub(PAGE_SIZE);
+                        app.timeline_list_state.select(Some(prev));
+                    }
+                KeyCode::Home
+                    if !app.timeline.is_empty() => {
+                        app.timeline_list_state.select(Some(0));
+                    }
+                KeyCode::End
+                    if !app.timeline.is_empty() => {
+                        app.timeline_list_state.select(Some(app.timeline.len() - 1));
+                    }
+                KeyCode::Char(' ') => {
+                    // Mark the old side of the next diff
+                    app.toggle_timeline_mark();
+                }
+                KeyCode::Enter | KeyCode::Char('d') => {
+                    app.diff_timeline_versions();
+                }
+                KeyCode::Char('v') => {
+                    app.view_timeline_version();
+                }
+                KeyCode::Left | KeyCode::Char('h') | KeyCode::Backspace => {
+                    // Go back to files
+                    app.back_to_files();
+                }
+                _ => {}
+            }
+        }
     }
 }
 
@@ -949,6 +1025,11 @@ fn ui(f: &mut Frame, app: &mut App) {
         render_delete_confirm(f, app);
     }
 
+    // Pull conflict resolution overlay
+    if app.merge_dialog.is_some() {
+        render_merge_dialog(f, app);
+    }
+
     // Restore confirmation overlay
     if app.restore_confirm.visible {
         render_restore_confirm(f, app);
@@ -1055,7 +1136,7 @@ fn render_projects(f: &mut Frame, app: &mut App, area: Rect) {
                 };
 
                 let size_str = size
-                    .map(|s| format_size(s))
+                    .map(format_size)
                     .unwrap_or_else(|| "-".to_string());
 
                 // Track mode indicator: [G]=Git, [B]=Backup, [+]=Both
@@ -1209,7 +1290,9 @@ fn render_restore(f: &mut Frame, app: &mut App, area: Rect) {
     match app.restore_view {
         RestoreView::Projects => render_restore_projects(f, app, area),
         RestoreView::Commits => render_restore_commits(f, app, area),
+        RestoreView::Archives => render_restore_archives(f, app, area),
         RestoreView::Files => render_restore_files(f, app, area),
+        RestoreView::Timeline => render_restore_timeline(f, app, area),
     }
 }
 
@@ -1293,7 +1376,8 @@ fn render_restore_commits(f: &mut Frame, app: &mut App, area: Rect) {
     let items: Vec<ListItem> = app
         .commits
         .iter()
-        .map(|commit| {
+        .enumerate()
+        .map(|(i, commit)| {
             // Parse date to show only date and time
             let date_short = if commit.date.len() > 19 {
                 &commit.date[..19]
@@ -1301,14 +1385,23 @@ fn render_restore_commits(f: &mut Frame, app: &mut App, area: Rect) {
                 &commit.date
             };
 
-            let line = Line::from(vec![
+            let mut line = Line::from(vec![
                 Span::styled(
                     format!("{} ", commit.short_hash),
                     Style::default().fg(Color::Yellow),
                 ),
                 Span::styled(format!("{} ", date_short), Style::default().fg(Color::Cyan)),
-                Span::raw(&commit.message),
             ]);
+            for tag in app.commit_tags.get(&commit.hash).into_iter().flatten() {
+                line.spans.push(Span::styled(
+                    format!("[{}] ", tag),
+                    Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+                ));
+            }
+            line.spans.push(Span::raw(&commit.message));
+            if let Some(Some(diff)) = app.commit_stats.get(i) {
+                line.spans.extend(commit_stat_spans(diff));
+            }
 
             ListItem::new(line)
         })
@@ -1336,6 +1429,138 @@ fn render_restore_commits(f: &mut Frame, app: &mut App, area: Rect) {
     f.render_stateful_widget(list, area, &mut app.commit_list_state);
 }
 
+/// Compact `+added ~modified -removed (size delta)` counts for a commit
+fn commit_stat_spans(diff: &IndexDiff) -> Vec<Span<'static>> {
+    let mut spans = vec![Span::raw("  ")];
+    for (kind, sign, color) in [
+        (ChangeKind::Added, "+", Color::Green),
+        (ChangeKind::Modified, "~", Color::Yellow),
+        (ChangeKind::Removed, "-", Color::Red),
+    ] {
+        let count = diff.count(kind);
+        if count > 0 {
+            spans.push(Span::styled(format!("{}{} ", sign, count), Style::default().fg(color)));
+        }
+    }
+    let delta = diff.size_delta();
+    let sign = if delta < 0 { "-" } else { "+" };
+    spans.push(Span::styled(
+        format!("({}{})", sign, format_size(delta.unsigned_abs())),
+        Style::default().fg(Color::DarkGray),
+    ));
+    spans
+}
+
+fn render_restore_archives(f: &mut Frame, app: &mut App, area: Rect) {
+    let project_name = app.selected_backup_project.clone().unwrap_or_default();
+
+    if app.archives.is_empty() {
+        let msg = Paragraph::new("No archive backups found. Create one with the archive backup action.")
+            .style(Style::default().fg(Color::DarkGray))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!(" {} - Archives ", project_name)),
+            );
+        f.render_widget(msg, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = app
+        .archives
+        .iter()
+        .map(|archive| {
+            let line = Line::from(vec![
+                Span::styled(
+                    format!("{:<7} ", archive.format.extension()),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    format!("{:>8}  ", format_size(archive.size)),
+                    Style::default().fg(Color::Cyan),
+                ),
+                Span::raw(&archive.name),
+            ]);
+
+            ListItem::new(line)
+        })
+        .collect();
+
+    let title = format!(
+        " {} - {} archives (Enter=select, Backspace=back) ",
+        project_name,
+        app.archives.len()
+    );
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+
+    f.render_stateful_widget(list, area, &mut app.archive_list_state);
+}
+
+fn render_restore_timeline(f: &mut Frame, app: &mut App, area: Rect) {
+    let path = app.timeline_path.as_deref().map(contract_path).unwrap_or_default();
+    let count = app.timeline.len();
+
+    let items: Vec<ListItem> = app
+        .timeline
+        .iter()
+        .enumerate()
+        .map(|(i, version)| {
+            let date_short = if version.commit.date.len() > 19 {
+                &version.commit.date[..19]
+            } else {
+                &version.commit.date
+            };
+            let marker = if app.timeline_mark == Some(i) { "* " } else { "  " };
+
+            let line = Line::from(vec![
+                Span::styled(marker, Style::default().fg(Color::Magenta)),
+                Span::styled(format!("v{:<3} ", count - i), Style::default().fg(Color::White)),
+                Span::styled(
+                    format!("{} ", version.commit.short_hash),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(format!("{} ", date_short), Style::default().fg(Color::Cyan)),
+                Span::styled(
+                    format!("{:>8} ", format_size(version.size)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(&version.commit.message),
+            ]);
+
+            ListItem::new(line)
+        })
+        .collect();
+
+    let title = format!(" {} - {} versions (Space=mark, Enter=diff, Backspace=back) ", path, count);
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+
+    f.render_stateful_widget(list, area, &mut app.timeline_list_state);
+}
+
 fn render_restore_files(f: &mut Frame, app: &mut App, area: Rect) {
     // Get the selected backup project name for the title
     let project_name = app.selected_backup_project.clone().unwrap_or_default();
@@ -1393,11 +1618,14 @@ fn render_restore_files(f: &mut Frame, app: &mut App, area: Rect) {
         })
         .collect();
 
-    let commit_info = app
-        .selected_commit
-        .and_then(|i| app.commits.get(i))
-        .map(|c| format!("{} - {}", c.short_hash, c.message))
-        .unwrap_or_else(|| "Unknown".to_string());
+    let commit_info = match app.selected_archive {
+        Some(i) => app.archives.get(i).map(|a| a.name.clone()),
+        None => app
+            .selected_commit
+            .and_then(|i| app.commits.get(i))
+            .map(|c| format!("{} - {}", c.short_hash, c.message)),
+    }
+    .unwrap_or_else(|| "Unknown".to_string());
 
     let title = if project_name.is_empty() {
         format!(" {} (Enter=restore, Backspace=back) ", commit_info)
@@ -1432,10 +1660,12 @@ fn render_status_bar(f: &mut Frame, app: &mut App, area: Rect) {
             Mode::Add => "↑↓:select  Enter:open/add  h:parent  ~:home  ?:help  q:quit",
             Mode::Restore => match app.restore_view {
                 RestoreView::Projects => "↑↓:select  Enter:view backups  r:refresh  ?:help  q:quit",
-                RestoreView::Commits => "↑↓:select  Enter:view files  h:back  r:refresh  ?:help",
+                RestoreView::Commits => "↑↓:select  Enter:view files  a:archives  h:back  r:refresh  ?:help",
+                RestoreView::Archives => "↑↓:select  Enter:view files  h/c:commits  r:refresh  ?:help",
                 RestoreView::Files => {
-                    "↑↓:nav  Space:select  a:all  d:none  Enter:restore  v:view  h:back  ?:help"
+                    "↑↓:nav  Space:select  a:all  d:none  Enter:restore  v:view  t:timeline  h:back  ?:help"
                 }
+                RestoreView::Timeline => "↑↓:nav  Space:mark  Enter/d:diff  v:view  h:back  ?:help",
             },
         };
         (help.to_string(), Style::default().fg(Color::Cyan))
@@ -1639,7 +1869,7 @@ fn render_help(f: &mut Frame, _app: &App) {
  g          Refresh git status
  G          Set git remote
  p          Push to remote
- P          Pull from remote
+ P          Pull from remote (conflicts: o/t/n per file, O/T/N all)
 
  ADD FILES TAB
  ───────────────────────────
@@ -1670,9 +1900,16 @@ fn render_help(f: &mut Frame, _app: &App) {
  RESTORE - COMMITS
  ───────────────────────────
  Enter/→/l  View files in backup
+ a          Show archive backups
  ←/h/Bksp   Back to projects
  r          Refresh
 
+ RESTORE - ARCHIVES
+ ───────────────────────────
+ Enter/→/l  View files in archive
+ ←/h/c      Back to commits
+ r          Refresh
+
  RESTORE - FILES
  ───────────────────────────
  Space      Toggle selection
@@ -1680,9 +1917,17 @@ fn render_help(f: &mut Frame, _app: &App) {
  d          Deselect all
  Enter/R    Restore (confirm)
  v          View file content
- ←/h/Bksp   Back to commits
+ t          Show file timeline
+ ←/h/Bksp   Back to commits/archives
  r          Refresh
 
+ RESTORE - TIMELINE
+ ───────────────────────────
+ Space      Mark version to compare
+ Enter/d    Diff with marked/previous
+ v          View version content
+ ←/h/Bksp   Back to files
+
  RESTORE CONFIRMATION
  ───────────────────────────
  ↑/k ↓/j    Navigate files
@@ -2036,6 +2281,96 @@ fn render_recursive_preview(f: &mut Frame, app: &mut App) {
     f.render_widget(footer, chunks[2]);
 }
 
+fn render_merge_dialog(f: &mut Frame, app: &mut App) {
+    let Some(dialog) = app.merge_dialog.as_mut() else {
+        return;
+    };
+    let area = centered_rect(80, 70, f.area());
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .split(area);
+
+    let version = |entry: &Option<FileEntry>| match entry {
+        Some(e) => {
+            let date = chrono::DateTime::from_timestamp(e.modified as i64, 0)
+                .map(|d| d.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            format!("{:>8} {}", format_size(e.size), date)
+        }
+        None => format!("{:>25}", "deleted"),
+    };
+
+    let items: Vec<ListItem> = dialog
+        .plan
+        .merge
+        .conflicts
+        .iter()
+        .map(|conflict| {
+            let chosen = dialog.resolutions.get(&conflict.path).map(|r| conflict.side(*r));
+            let (badge, color) = match chosen {
+                Some(Resolution::Theirs) => ("theirs", Color::Cyan),
+                Some(_) => ("ours  ", Color::Green),
+                None => ("  ?   ", Color::Yellow),
+            };
+            let highlight = |side: Resolution| {
+                if chosen == Some(side) {
+                    Style::default().fg(Color::White)
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                }
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("[{}] ", badge), Style::default().fg(color)),
+                Span::styled(format!("ours {}  ", version(&conflict.ours)), highlight(Resolution::Ours)),
+                Span::styled(format!("theirs {}  ", version(&conflict.theirs)), highlight(Resolution::Theirs)),
+                Span::raw(contract_path(&conflict.path)),
+            ]))
+        })
+        .collect();
+
+    let header_text = vec![
+        Line::from(vec![
+            Span::raw(" Merging "),
+            Span::styled(dialog.plan.theirs_ref.clone(), Style::default().fg(Color::Cyan)),
+            Span::raw(" into "),
+            Span::styled(dialog.project.clone(), Style::default().fg(Color::Yellow)),
+        ]),
+        Line::from(vec![
+            Span::raw(format!(
+                " {} file(s) from there, {} merged line by line, ",
+                dialog.plan.merge.from_theirs.len(),
+                dialog.plan.text_merges.len()
+            )),
+            Span::styled(
+                format!("{} unresolved", dialog.unresolved()),
+                Style::default().fg(if dialog.unresolved() > 0 { Color::Yellow } else { Color::Green }),
+            ),
+        ]),
+    ];
+    let header = Paragraph::new(header_text)
+        .block(Block::default().borders(Borders::ALL).title(" Pull Conflicts "))
+        .style(Style::default().bg(Color::Black));
+
+    f.render_widget(ratatui::widgets::Clear, area);
+    f.render_widget(header, chunks[0]);
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL))
+        .highlight_style(Style::default().bg(Color::DarkGray))
+        .style(Style::default().bg(Color::Black));
+    f.render_stateful_widget(list, chunks[1], &mut dialog.list_state);
+
+    let footer = Paragraph::new(" o/t/n: ours/theirs/newest  O/T/N: all  Enter: merge  Esc: cancel")
+        .style(Style::default().fg(Color::Cyan).bg(Color::Black));
+    f.render_widget(footer, chunks[2]);
+}
+
 fn render_delete_confirm(f: &mut Frame, app: &App) {
     let area = centered_rect(50, 20, f.area());
 
@@ -2295,11 +2630,20 @@ fn render_password_prompt(f: &mut Frame, app: &App) {
     let title = match app.password_purpose {
         PasswordPurpose::Backup => " Encryption Password ",
         PasswordPurpose::Restore => " Decryption Password ",
+        PasswordPurpose::Migrate => " Encryption Password ",
+        PasswordPurpose::Unlock => " Decryption Password ",
+        PasswordPurpose::History => " Decryption Password ",
+        PasswordPurpose::Pull => " Decryption Password ",
     };
 
     let description = match app.password_purpose {
+        _ if app.password_confirm.is_some() => "  Enter the new password again to confirm:",
         PasswordPurpose::Backup => "  Enter password to encrypt files:",
         PasswordPurpose::Restore => "  Enter password to decrypt files:",
+        PasswordPurpose::Migrate => "  Enter password to re-encrypt stored files:",
+        PasswordPurpose::Unlock => "  Enter password to unlock the encrypted index:",
+        PasswordPurpose::History => "  Enter password to decrypt file versions:",
+        PasswordPurpose::Pull => "  Enter password to merge the encrypted index:",
     };
 
     // Mask the password with asterisks